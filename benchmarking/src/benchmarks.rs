@@ -1,8 +1,15 @@
 extern crate test;
 use core_sdk::board_representation::game_state::GameState;
+use core_sdk::search::cache::Cache;
+use core_sdk::search::searcher::{search_move, InterThreadCommunicationSystem};
+use core_sdk::search::smp::SMP_STRATEGIES;
+use core_sdk::search::timecontrol::TimeControl;
 use extended_sdk::openings::load_db_until;
 use rand::Rng;
 use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::time::Instant;
 
 pub const BENCHMARKING_POSITIONS: &str = "benchmarking_positions.txt";
 pub const BENCHMARKING_POSITIONS_AMOUNT: usize = 1000;
@@ -38,6 +45,40 @@ pub fn load_benchmarking_positions() -> Vec<GameState> {
     states
 }
 
+//Searches every position with each SMP strategy and prints the reports, together with the total
+//time and nodes each strategy needed
+pub fn compare_smp_strategies(states: &[GameState], threads: usize, depth: usize) {
+    for &strategy in SMP_STRATEGIES.iter() {
+        let itcs = Arc::new(InterThreadCommunicationSystem::default_with_output(
+            io::sink(),
+        ));
+        itcs.uci_options_mut().smp_strategy = strategy;
+        InterThreadCommunicationSystem::update_thread_count(&itcs, threads);
+        itcs.set_cache(Cache::with_size_threaded(64, threads));
+        let before_time = Instant::now();
+        let mut nodes = 0;
+        for state in states.iter() {
+            search_move(
+                Arc::clone(&itcs),
+                depth as i16,
+                state.clone(),
+                Vec::new(),
+                TimeControl::Infinite,
+            );
+            println!("{}", itcs.smp_report());
+            nodes += itcs.get_nodes_sum();
+            itcs.cache().clear_threaded(threads);
+        }
+        println!(
+            "{}: {}ms, {} nodes",
+            strategy,
+            before_time.elapsed().as_millis(),
+            nodes
+        );
+        InterThreadCommunicationSystem::update_thread_count(&itcs, 0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::load_benchmarking_positions;
@@ -73,7 +114,7 @@ mod tests {
             for i in 0..BENCHMARKING_POSITIONS_AMOUNT {
                 attack_container.write_state(&states[i]);
                 movegen::generate_moves(&states[i], false, &mut movelist, &attack_container);
-                sum += movelist.move_list.len();
+                sum += movelist.len();
             }
             sum
         });
@@ -1,6 +1,21 @@
 #![feature(test)]
 pub mod benchmarks;
 
+use benchmarks::{compare_smp_strategies, load_benchmarking_positions};
+use std::env;
+
 fn main() {
     println!("Welcome to the benchmarks!");
+    let args: Vec<String> = env::args().collect();
+    //smp <threads> <depth> <positions>
+    if args.len() > 1 && args[1] == "smp" {
+        let parse = |index: usize, default: usize| {
+            args.get(index)
+                .map(|arg| arg.parse::<usize>().expect("Invalid argument!"))
+                .unwrap_or(default)
+        };
+        let states = load_benchmarking_positions();
+        let amount = parse(4, 20).min(states.len());
+        compare_smp_strategies(&states[..amount], parse(2, 4), parse(3, 10));
+    }
 }
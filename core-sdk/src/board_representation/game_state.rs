@@ -39,6 +39,62 @@ impl Display for GameResult {
         write!(formatter, "{}", res_str)
     }
 }
+#[derive(PartialEq, Debug)]
+pub enum SanError {
+    Empty,
+    InvalidSyntax(String),
+    IllegalMove(String),
+    AmbiguousMove(String),
+}
+impl Display for SanError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        match self {
+            SanError::Empty => write!(formatter, "Empty move"),
+            SanError::InvalidSyntax(san) => write!(formatter, "Invalid move syntax: {}", san),
+            SanError::IllegalMove(san) => write!(formatter, "Illegal move: {}", san),
+            SanError::AmbiguousMove(san) => write!(formatter, "Ambiguous move: {}", san),
+        }
+    }
+}
+impl std::error::Error for SanError {}
+
+#[derive(PartialEq, Debug)]
+pub enum FenError {
+    MissingFields(String),
+    InvalidBoard(String),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidMoveCounter(String),
+    IllegalPosition(String),
+}
+impl Display for FenError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        match self {
+            FenError::MissingFields(fen) => {
+                write!(formatter, "Invalid FEN, missing fields: {}", fen)
+            }
+            FenError::InvalidBoard(board) => write!(formatter, "Invalid FEN board: {}", board),
+            FenError::InvalidSideToMove(side) => {
+                write!(formatter, "Invalid FEN side to move: {}", side)
+            }
+            FenError::InvalidCastling(castling) => {
+                write!(formatter, "Invalid FEN castling rights: {}", castling)
+            }
+            FenError::InvalidEnPassant(square) => {
+                write!(formatter, "Invalid FEN en passant square: {}", square)
+            }
+            FenError::InvalidMoveCounter(counter) => {
+                write!(formatter, "Invalid FEN move counter: {}", counter)
+            }
+            FenError::IllegalPosition(fen) => {
+                write!(formatter, "The side not to move is in check: {}", fen)
+            }
+        }
+    }
+}
+impl std::error::Error for FenError {}
+
 #[derive(PartialEq, Clone, Debug, Copy)]
 pub enum GameMoveType {
     Quiet,
@@ -160,43 +216,51 @@ impl GameMove {
             _ => panic!("Captured piece type  called on a capture"),
         }
     }
-    pub fn string_to_move(desc: &str) -> (usize, usize, Option<PieceType>) {
-        let mut chars = desc.chars();
-        let from_file = match chars.next() {
-            Some(s) => char_to_file(s),
-            _ => {
-                panic!("Invalid move desc!");
-            }
-        };
-        let from_rank = match chars.next() {
-            Some(s) => char_to_rank(s),
-            _ => {
-                panic!("Invalid move desc!");
-            }
-        };
-        let to_file = match chars.next() {
-            Some(s) => char_to_file(s),
-            _ => {
-                panic!("Invalid move desc!");
-            }
-        };
-        let to_rank = match chars.next() {
-            Some(s) => char_to_rank(s),
-            _ => {
-                panic!("Invalid move desc!");
-            }
+    pub fn string_to_move(
+        desc: &str,
+    ) -> std::result::Result<(usize, usize, Option<PieceType>), SanError> {
+        let chars: Vec<char> = desc.trim().chars().collect();
+        let is_file = |c: char| ('a'..='h').contains(&c);
+        let is_rank = |c: char| ('1'..='8').contains(&c);
+        if chars.is_empty() {
+            return Err(SanError::Empty);
+        }
+        if chars.len() < 4
+            || chars.len() > 5
+            || !is_file(chars[0])
+            || !is_rank(chars[1])
+            || !is_file(chars[2])
+            || !is_rank(chars[3])
+        {
+            return Err(SanError::InvalidSyntax(desc.to_owned()));
+        }
+        let promotion = match chars.get(4) {
+            Some(c) if "QRBNqrbn".contains(*c) => Some(char_to_promotion_piecetype(*c)),
+            Some(_) => return Err(SanError::InvalidSyntax(desc.to_owned())),
+            None => None,
         };
-        if desc.len() == 5 {
-            return (
-                from_file + 8 * from_rank,
-                to_file + 8 * to_rank,
-                Some(char_to_promotion_piecetype(match chars.next() {
-                    Some(s) => s,
-                    _ => panic!("Invalid move desc!"),
-                })),
-            );
-        }
-        (from_file + 8 * from_rank, to_file + 8 * to_rank, None)
+        Ok((
+            char_to_file(chars[0]) + 8 * char_to_rank(chars[1]),
+            char_to_file(chars[2]) + 8 * char_to_rank(chars[3]),
+            promotion,
+        ))
+    }
+
+    //Long algebraic notation as used by UCI, e.g. e2e4 or e7e8q. Only legal moves are found
+    pub fn from_uci(game_state: &GameState, desc: &str) -> std::result::Result<GameMove, SanError> {
+        let (from, to, promotion) = GameMove::string_to_move(desc)?;
+        let mut movelist = MoveList::default();
+        let agsi = GameStateAttackContainer::from_state(game_state);
+        generate_moves(game_state, false, &mut movelist, &agsi);
+        let found = movelist.game_moves(game_state).find(|mv| {
+            mv.from as usize == from
+                && mv.to as usize == to
+                && match mv.move_type {
+                    GameMoveType::Promotion(piece, _) => promotion == Some(piece),
+                    _ => true,
+                }
+        });
+        found.ok_or_else(|| SanError::IllegalMove(desc.trim().to_owned()))
     }
 
     pub fn to_san(self, game_state: &GameState) -> String {
@@ -222,8 +286,7 @@ impl GameMove {
             //Check for disambiguities
             let mut file_needed = false;
             let mut rank_needed = false;
-            for gmv in movelist.move_list.iter() {
-                let other_mv = gmv.0;
+            for other_mv in movelist.game_moves(game_state) {
                 if other_mv.piece_type == self.piece_type
                     && other_mv.to == self.to
                     && other_mv.from != self.from
@@ -275,6 +338,127 @@ impl GameMove {
         }
         res_str
     }
+
+    //Accepts SAN and LAN with optional check markers and annotation glyphs, castling written
+    //with O or 0 and figurine pieces. A LAN move without piece letter may move any piece
+    pub fn from_san(game_state: &GameState, san: &str) -> std::result::Result<GameMove, SanError> {
+        let mut chars: Vec<char> = san
+            .trim()
+            .chars()
+            .map(|c| match c {
+                '\u{2654}' | '\u{265A}' => 'K',
+                '\u{2655}' | '\u{265B}' => 'Q',
+                '\u{2656}' | '\u{265C}' => 'R',
+                '\u{2657}' | '\u{265D}' => 'B',
+                '\u{2658}' | '\u{265E}' => 'N',
+                '\u{2659}' | '\u{265F}' => 'P',
+                _ => c,
+            })
+            .collect();
+        if chars.ends_with(&['e', '.', 'p', '.']) {
+            chars.truncate(chars.len() - 4);
+        }
+        while let Some(c) = chars.last() {
+            if "+#!?\u{203C}\u{2047}\u{2048}\u{2049} ".contains(*c) {
+                chars.pop();
+            } else {
+                break;
+            }
+        }
+        if chars.is_empty() {
+            return Err(SanError::Empty);
+        }
+        let invalid = || SanError::InvalidSyntax(san.to_owned());
+
+        let mut movelist = MoveList::default();
+        let agsi = GameStateAttackContainer::from_state(game_state);
+        generate_moves(game_state, false, &mut movelist, &agsi);
+        let castle: String = chars.iter().map(|c| c.to_ascii_uppercase()).collect();
+        let candidates: Vec<GameMove> = if castle == "O-O" || castle == "0-0" {
+            movelist
+                .game_moves(game_state)
+                .filter(|mv| mv.move_type == GameMoveType::Castle && mv.to > mv.from)
+                .collect()
+        } else if castle == "O-O-O" || castle == "0-0-0" {
+            movelist
+                .game_moves(game_state)
+                .filter(|mv| mv.move_type == GameMoveType::Castle && mv.to < mv.from)
+                .collect()
+        } else {
+            let piece_type = match chars[0] {
+                'K' => Some(PieceType::King),
+                'Q' => Some(PieceType::Queen),
+                'R' => Some(PieceType::Rook),
+                'B' => Some(PieceType::Bishop),
+                'N' => Some(PieceType::Knight),
+                'P' => Some(PieceType::Pawn),
+                _ => None,
+            };
+            if piece_type.is_some() {
+                chars.remove(0);
+            }
+            //A promotion piece directly follows the rank of the target square
+            let mut promotion = None;
+            if let Some(c) = chars.last().copied() {
+                if "QRBNqrbn".contains(c) && chars.len() >= 2 {
+                    let before = chars[chars.len() - 2];
+                    if before.is_ascii_digit() || before == '=' {
+                        promotion = Some(char_to_promotion_piecetype(c));
+                        chars.pop();
+                    }
+                }
+            }
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+            let squares: Vec<char> = chars.into_iter().filter(|c| !"x-:".contains(*c)).collect();
+            let is_file = |c: char| ('a'..='h').contains(&c);
+            let is_rank = |c: char| ('1'..='8').contains(&c);
+            if squares.len() < 2 || squares.len() > 4 {
+                return Err(invalid());
+            }
+            let (to_file, to_rank) = (squares[squares.len() - 2], squares[squares.len() - 1]);
+            if !is_file(to_file) || !is_rank(to_rank) {
+                return Err(invalid());
+            }
+            let to = (char_to_file(to_file) + 8 * char_to_rank(to_rank)) as u8;
+            let mut from_file = None;
+            let mut from_rank = None;
+            for &c in squares[..squares.len() - 2].iter() {
+                if is_file(c) && from_file.is_none() && from_rank.is_none() {
+                    from_file = Some(char_to_file(c) as u8);
+                } else if is_rank(c) && from_rank.is_none() {
+                    from_rank = Some(char_to_rank(c) as u8);
+                } else {
+                    return Err(invalid());
+                }
+            }
+            //Without piece letter only a full from square leaves the piece open
+            let piece_type = match piece_type {
+                None if from_file.is_none() || from_rank.is_none() => Some(PieceType::Pawn),
+                _ => piece_type,
+            };
+            movelist
+                .game_moves(game_state)
+                .filter(|mv| {
+                    mv.to == to
+                        && piece_type.map(|p| p == mv.piece_type).unwrap_or(true)
+                        && from_file.map(|f| f == mv.from % 8).unwrap_or(true)
+                        && from_rank.map(|r| r == mv.from / 8).unwrap_or(true)
+                        && promotion
+                            == match mv.move_type {
+                                GameMoveType::Promotion(p, _) => Some(p),
+                                _ => None,
+                            }
+                })
+                .collect()
+        };
+        match candidates.len() {
+            0 => Err(SanError::IllegalMove(san.to_owned())),
+            1 => Ok(candidates[0]),
+            _ => Err(SanError::AmbiguousMove(san.to_owned())),
+        }
+    }
 }
 
 impl Debug for GameMove {
@@ -300,6 +484,152 @@ impl Debug for GameMove {
     }
 }
 
+//Compact move: from square in bits 10-15, to square in bits 4-9 and the move type in bits 0-3
+//Move generation, the move lists, killer and counter moves and the TT store moves this way.
+//Moves leave the move orderer as GameMove: making them, SEE, the history tables, the PV and the
+//tracer need the moving and captured piece, which a Move only knows together with the board
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Move(pub u16);
+
+//Order of the promotion pieces in both promotion ranges
+const PROMOTION_PIECES: [PieceType; 4] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+];
+
+impl Move {
+    pub const QUIET: u16 = 1;
+    pub const CASTLE: u16 = 2;
+    pub const PROMOTION: u16 = 3;
+    pub const CAPTURE: u16 = 7;
+    pub const EN_PASSANT: u16 = 8;
+    pub const CAPTURE_PROMOTION: u16 = 9;
+
+    #[inline(always)]
+    pub fn new(from: usize, to: usize, flag: u16) -> Move {
+        Move((from as u16) << 10 | (to as u16) << 4 | flag)
+    }
+
+    #[inline(always)]
+    pub fn promotion(from: usize, to: usize, piece: PieceType, capture: bool) -> Move {
+        let index = match piece {
+            PieceType::Queen => 0,
+            PieceType::Rook => 1,
+            PieceType::Bishop => 2,
+            PieceType::Knight => 3,
+            _ => panic!("Invalid promotion!"),
+        };
+        if capture {
+            Move::new(from, to, Move::CAPTURE_PROMOTION + index)
+        } else {
+            Move::new(from, to, Move::PROMOTION + index)
+        }
+    }
+
+    #[inline(always)]
+    pub fn from_square(self) -> usize {
+        (self.0 >> 10) as usize
+    }
+
+    #[inline(always)]
+    pub fn to_square(self) -> usize {
+        (self.0 >> 4 & 63) as usize
+    }
+
+    #[inline(always)]
+    fn flag(self) -> u16 {
+        self.0 & 15
+    }
+
+    #[inline(always)]
+    pub fn is_capture(self) -> bool {
+        let flag = self.flag();
+        flag == Move::CAPTURE || flag == Move::EN_PASSANT || flag >= Move::CAPTURE_PROMOTION
+    }
+
+    #[inline(always)]
+    pub fn is_en_passant(self) -> bool {
+        self.flag() == Move::EN_PASSANT
+    }
+
+    #[inline(always)]
+    pub fn promotion_piece(self) -> Option<PieceType> {
+        let flag = self.flag();
+        if flag >= Move::CAPTURE_PROMOTION {
+            Some(PROMOTION_PIECES[(flag - Move::CAPTURE_PROMOTION) as usize])
+        } else if (Move::PROMOTION..Move::CAPTURE).contains(&flag) {
+            Some(PROMOTION_PIECES[(flag - Move::PROMOTION) as usize])
+        } else {
+            None
+        }
+    }
+
+    //Piece types are read from the board. Moves from the TT may be trash, missing pieces decode to kings
+    #[inline(always)]
+    pub fn to_game_move(self, game_state: &GameState) -> GameMove {
+        let from = self.from_square();
+        let to = self.to_square();
+        let piece_type = game_state.piece_type_on(game_state.color_to_move, from);
+        let captured = || game_state.piece_type_on(1 - game_state.color_to_move, to);
+        let flag = self.flag();
+        let move_type = match flag {
+            Move::QUIET => GameMoveType::Quiet,
+            Move::CASTLE => GameMoveType::Castle,
+            Move::CAPTURE => GameMoveType::Capture(captured()),
+            Move::EN_PASSANT => GameMoveType::EnPassant,
+            _ if flag >= Move::CAPTURE_PROMOTION => {
+                GameMoveType::Promotion(self.promotion_piece().unwrap(), Some(captured()))
+            }
+            _ => GameMoveType::Promotion(self.promotion_piece().unwrap(), None),
+        };
+        GameMove {
+            from: from as u8,
+            to: to as u8,
+            piece_type,
+            move_type,
+        }
+    }
+}
+
+impl From<GameMove> for Move {
+    #[inline(always)]
+    fn from(mv: GameMove) -> Self {
+        let (from, to) = (mv.from as usize, mv.to as usize);
+        match mv.move_type {
+            GameMoveType::Quiet => Move::new(from, to, Move::QUIET),
+            GameMoveType::Castle => Move::new(from, to, Move::CASTLE),
+            GameMoveType::Capture(_) => Move::new(from, to, Move::CAPTURE),
+            GameMoveType::EnPassant => Move::new(from, to, Move::EN_PASSANT),
+            GameMoveType::Promotion(piece, captured) => {
+                Move::promotion(from, to, piece, captured.is_some())
+            }
+        }
+    }
+}
+
+impl Debug for Move {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        let (from, to) = (self.from_square(), self.to_square());
+        write!(
+            formatter,
+            "{}{}{}{}",
+            file_to_string(from % 8),
+            from / 8 + 1,
+            file_to_string(to % 8),
+            to / 8 + 1
+        )?;
+        match self.promotion_piece() {
+            Some(PieceType::Queen) => write!(formatter, "q"),
+            Some(PieceType::Rook) => write!(formatter, "r"),
+            Some(PieceType::Bishop) => write!(formatter, "b"),
+            Some(PieceType::Knight) => write!(formatter, "n"),
+            _ => Ok(()),
+        }
+    }
+}
+
 fn char_to_promotion_piecetype(c: char) -> PieceType {
     match c {
         'q' | 'Q' => PieceType::Queen,
@@ -390,203 +720,122 @@ pub struct GameState {
 }
 
 impl GameState {
+    //For FENs known to be valid, external input goes through try_from_fen
     pub fn from_fen(fen: &str) -> GameState {
-        let vec: Vec<&str> = fen.trim().split(' ').collect();
-        if vec.len() < 4 {
-            panic!("Invalid FEN");
+        GameState::try_from_fen(fen).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    //Castling rights the pieces contradict and en passant squares no pawn can have skipped are
+    //dropped, everything move generation can't handle is an error
+    pub fn try_from_fen(fen: &str) -> std::result::Result<GameState, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 || fields.len() == 5 {
+            return Err(FenError::MissingFields(fen.to_owned()));
         }
-        //Parse through FEN
         //Pieces
-        let pieces: Vec<&str> = vec[0].split('/').collect();
-        if pieces.len() != 8 {
-            panic!("Invalid FEN");
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::InvalidBoard(fields[0].to_owned()));
         }
-        //Iterate over all 8 ranks
         let mut pieces_arr: [[u64; 2]; 6] = [[0u64; 2]; 6];
-        for (rank, rank_str) in pieces.iter().enumerate().take(8) {
+        for (rank, rank_str) in ranks.iter().enumerate() {
             let mut file: usize = 0;
-            let mut rank_str_idx: usize = 0;
-            while file < 8 {
-                let idx = (7 - rank) * 8 + file;
-                let next_char = rank_str.chars().nth(rank_str_idx);
-                rank_str_idx += 1;
-                match next_char {
-                    Some(x) => match x {
-                        'P' => {
-                            pieces_arr[PAWN][WHITE] |= 1u64 << idx;
-                            file += 1;
-                        }
-                        'p' => {
-                            pieces_arr[PAWN][BLACK] |= 1u64 << idx;
-                            file += 1;
-                        }
-                        'N' => {
-                            pieces_arr[KNIGHT][WHITE] |= 1u64 << idx;
-                            file += 1;
-                        }
-                        'n' => {
-                            pieces_arr[KNIGHT][BLACK] |= 1u64 << idx;
-                            file += 1;
-                        }
-                        'B' => {
-                            pieces_arr[BISHOP][WHITE] |= 1u64 << idx;
-                            file += 1;
-                        }
-                        'b' => {
-                            pieces_arr[BISHOP][BLACK] |= 1u64 << idx;
-                            file += 1;
-                        }
-                        'R' => {
-                            pieces_arr[ROOK][WHITE] |= 1u64 << idx;
-                            file += 1;
-                        }
-                        'r' => {
-                            pieces_arr[ROOK][BLACK] |= 1u64 << idx;
-                            file += 1;
-                        }
-                        'Q' => {
-                            pieces_arr[QUEEN][WHITE] |= 1u64 << idx;
-                            file += 1;
-                        }
-                        'q' => {
-                            pieces_arr[QUEEN][BLACK] |= 1u64 << idx;
-                            file += 1;
-                        }
-                        'K' => {
-                            pieces_arr[KING][WHITE] |= 1u64 << idx;
-                            file += 1;
-                        }
-                        'k' => {
-                            pieces_arr[KING][BLACK] |= 1u64 << idx;
-                            file += 1;
-                        }
-                        '1' => {
-                            file += 1;
-                        }
-                        '2' => {
-                            file += 2;
-                        }
-                        '3' => {
-                            file += 3;
-                        }
-                        '4' => {
-                            file += 4;
-                        }
-                        '5' => {
-                            file += 5;
-                        }
-                        '6' => {
-                            file += 6;
-                        }
-                        '7' => {
-                            file += 7;
-                        }
-                        '8' => {
-                            file += 8;
-                        }
-                        _ => {
-                            panic!("Invalid FEN");
-                        }
-                    },
-                    None => panic!("Invalid FEN"),
+            for c in rank_str.chars() {
+                if file >= 8 {
+                    return Err(FenError::InvalidBoard(fields[0].to_owned()));
                 }
+                let idx = (7 - rank) * 8 + file;
+                let (piece, side) = match c {
+                    '1'..='8' => {
+                        file += c as usize - '0' as usize;
+                        continue;
+                    }
+                    'P' => (PAWN, WHITE),
+                    'p' => (PAWN, BLACK),
+                    'N' => (KNIGHT, WHITE),
+                    'n' => (KNIGHT, BLACK),
+                    'B' => (BISHOP, WHITE),
+                    'b' => (BISHOP, BLACK),
+                    'R' => (ROOK, WHITE),
+                    'r' => (ROOK, BLACK),
+                    'Q' => (QUEEN, WHITE),
+                    'q' => (QUEEN, BLACK),
+                    'K' => (KING, WHITE),
+                    'k' => (KING, BLACK),
+                    _ => return Err(FenError::InvalidBoard(fields[0].to_owned())),
+                };
+                pieces_arr[piece][side] |= 1u64 << idx;
+                file += 1;
             }
+            if file != 8 {
+                return Err(FenError::InvalidBoard(fields[0].to_owned()));
+            }
+        }
+        if pieces_arr[KING][WHITE].count_ones() != 1 || pieces_arr[KING][BLACK].count_ones() != 1 {
+            return Err(FenError::InvalidBoard(fields[0].to_owned()));
         }
 
         //Side to move
-        let color_to_move = match vec[1] {
-            "w" => 0,
-            "b" => 1,
-            _ => panic!("Invalid FEN!"),
+        let color_to_move = match fields[1] {
+            "w" => WHITE,
+            "b" => BLACK,
+            _ => return Err(FenError::InvalidSideToMove(fields[1].to_owned())),
         };
 
         //Castling-Abilities
-        let castle_white_kingside = vec[2].contains('K');
-        let castle_white_queenside = vec[2].contains('Q');
-        let castle_black_kingside = vec[2].contains('k');
-        let castle_black_queenside = vec[2].contains('q');
+        if fields[2] != "-" && !fields[2].chars().all(|c| "KQkq".contains(c)) {
+            return Err(FenError::InvalidCastling(fields[2].to_owned()));
+        }
+        let can_castle = |side: usize, king: usize, rook: usize| {
+            pieces_arr[KING][side] & 1u64 << king != 0u64
+                && pieces_arr[ROOK][side] & 1u64 << rook != 0u64
+        };
+        let castle_white_kingside = fields[2].contains('K') && can_castle(WHITE, 4, 7);
+        let castle_white_queenside = fields[2].contains('Q') && can_castle(WHITE, 4, 0);
+        let castle_black_kingside = fields[2].contains('k') && can_castle(BLACK, 60, 63);
+        let castle_black_queenside = fields[2].contains('q') && can_castle(BLACK, 60, 56);
 
         //En passant target square
-        let en_passant: u64 = if vec[3] != "-" {
-            let mut idx: usize = 0usize;
-            let file = vec[3].chars().next();
-            let rank = vec[3].chars().nth(1);
-            match file {
-                Some(x) => match x {
-                    'a' | 'A' => {}
-                    'b' | 'B' => {
-                        idx += 1;
-                    }
-                    'c' | 'C' => {
-                        idx += 2;
-                    }
-                    'd' | 'D' => {
-                        idx += 3;
-                    }
-                    'e' | 'E' => {
-                        idx += 4;
-                    }
-                    'f' | 'F' => {
-                        idx += 5;
-                    }
-                    'g' | 'G' => {
-                        idx += 6;
-                    }
-                    'h' | 'H' => {
-                        idx += 7;
-                    }
-                    _ => {
-                        panic!("Invalid FEN!");
-                    }
-                },
-                None => {
-                    panic!("Invalid FEN!");
-                }
+        let en_passant: u64 = if fields[3] != "-" {
+            let square: Vec<char> = fields[3].to_lowercase().chars().collect();
+            if square.len() != 2
+                || !('a'..='h').contains(&square[0])
+                || !('1'..='8').contains(&square[1])
+            {
+                return Err(FenError::InvalidEnPassant(fields[3].to_owned()));
             }
-            match rank {
-                Some(x) => match x {
-                    '1' => {}
-                    '2' => {
-                        idx += 8;
-                    }
-                    '3' => {
-                        idx += 16;
-                    }
-                    '4' => {
-                        idx += 24;
-                    }
-                    '5' => {
-                        idx += 32;
-                    }
-                    '6' => {
-                        idx += 40;
-                    }
-                    '7' => {
-                        idx += 48;
-                    }
-                    '8' => {
-                        idx += 56;
-                    }
-                    _ => {
-                        panic!("Invalid FEN!");
-                    }
-                },
-                None => {
-                    panic!("Invalid FEN!");
-                }
+            let idx = char_to_file(square[0]) + 8 * char_to_rank(square[1]);
+            //The pawn which just moved two squares must be in front of it, its origin empty
+            let (pawn, origin, rank) = if color_to_move == WHITE {
+                (idx as isize - 8, idx as isize + 8, 5)
+            } else {
+                (idx as isize + 8, idx as isize - 8, 2)
+            };
+            let occupied = pieces_arr
+                .iter()
+                .fold(0u64, |acc, piece| acc | piece[WHITE] | piece[BLACK]);
+            if idx / 8 == rank
+                && pieces_arr[PAWN][1 - color_to_move] & 1u64 << pawn != 0u64
+                && occupied & (1u64 << idx | 1u64 << origin) == 0u64
+            {
+                1u64 << idx
+            } else {
+                0u64
             }
-            1u64 << idx
         } else {
             0u64
         };
-        let (half_moves, full_moves) = if vec.len() > 4 {
-            (
-                vec[4].parse().expect("unable to parse half moves"),
-                vec[5].parse().expect("unable to parse full moves"),
-            )
-        } else {
-            (0, 1)
+
+        //Move counters are optional, but come in pairs
+        let counter = |field: Option<&&str>, default: usize| match field {
+            Some(value) => value
+                .parse::<usize>()
+                .map_err(|_| FenError::InvalidMoveCounter((*value).to_owned())),
+            None => Ok(default),
         };
+        let half_moves = counter(fields.get(4), 0)?;
+        let full_moves = counter(fields.get(5), 1)?;
+
         let hash = GameState::calculate_zobrist_hash(
             color_to_move,
             pieces_arr,
@@ -604,7 +853,7 @@ impl GameState {
         let p_w = crate::evaluation::psqt_evaluation::psqt(true, &pieces_arr, &mut _eval);
         let p_b = crate::evaluation::psqt_evaluation::psqt(false, &pieces_arr, &mut _eval);
         let phase = Phase::from_pieces(&pieces_arr);
-        GameState {
+        let state = GameState {
             color_to_move,
             pieces: pieces_arr,
             castle_white_kingside,
@@ -617,7 +866,15 @@ impl GameState {
             hash,
             psqt: p_w - p_b,
             phase,
+        };
+        //The side not to move can not be in check
+        let attack_container = GameStateAttackContainer::from_state(&state);
+        if attack_container.attacks_sum[color_to_move] & state.pieces[KING][1 - color_to_move]
+            != 0u64
+        {
+            return Err(FenError::IllegalPosition(fen.to_owned()));
         }
+        Ok(state)
     }
 
     pub fn get_piece_on(&self, shift: i32) -> &str {
@@ -892,6 +1149,25 @@ impl GameState {
         self.pieces[KING][side].trailing_zeros() as usize
     }
 
+    //Kings are assumed on squares without any other piece of the side
+    #[inline(always)]
+    pub fn piece_type_on(&self, side: usize, square: usize) -> PieceType {
+        let board = 1u64 << square;
+        if self.pieces[PAWN][side] & board != 0u64 {
+            PieceType::Pawn
+        } else if self.pieces[KNIGHT][side] & board != 0u64 {
+            PieceType::Knight
+        } else if self.pieces[BISHOP][side] & board != 0u64 {
+            PieceType::Bishop
+        } else if self.pieces[ROOK][side] & board != 0u64 {
+            PieceType::Rook
+        } else if self.pieces[QUEEN][side] & board != 0u64 {
+            PieceType::Queen
+        } else {
+            PieceType::King
+        }
+    }
+
     #[inline(always)]
     pub fn has_non_pawns(&self, side: usize) -> bool {
         self.pieces[BISHOP][side] != 0u64
@@ -901,23 +1177,36 @@ impl GameState {
     }
 
     pub fn gives_check(&self, mv: GameMove) -> bool {
-        if mv.move_type == GameMoveType::Castle {
-            return false; // In theory a castle move can give_check, but it is too much hasssle to compute that
-        }
-        //We also ignore en passant discovered checks here
+        let side = self.color_to_move;
         let mut occ_board = self.get_all_pieces();
         occ_board ^= 1u64 << mv.from;
         occ_board |= 1u64 << mv.to;
-        let king_position = self.king_square(1 - self.color_to_move);
+        let mut rook_likes = self.pieces[ROOK][side] | self.pieces[QUEEN][side];
+        match mv.move_type {
+            GameMoveType::Castle => {
+                //The rook gives check from its new square, or a line opens behind the king
+                let (rook_from, rook_to) = match mv.to {
+                    6 => (7, 5),
+                    2 => (0, 3),
+                    62 => (63, 61),
+                    _ => (56, 59),
+                };
+                let rook_move = 1u64 << rook_from | 1u64 << rook_to;
+                occ_board ^= rook_move;
+                rook_likes ^= rook_move;
+            }
+            GameMoveType::EnPassant => {
+                //The captured pawn may have blocked a line to the king
+                occ_board ^= 1u64 << if side == WHITE { mv.to - 8 } else { mv.to + 8 };
+            }
+            _ => {}
+        }
+        let king_position = self.king_square(1 - side);
         let bishop_like_attack = bishop_attack(king_position, occ_board);
         let rook_like_attack = rook_attack(king_position, occ_board);
-        //CHeck discovered check
-        if bishop_like_attack
-            & (self.pieces[BISHOP][self.color_to_move] | self.pieces[QUEEN][self.color_to_move])
-            != 0u64
-            || rook_like_attack
-                & (self.pieces[ROOK][self.color_to_move] | self.pieces[QUEEN][self.color_to_move])
-                != 0u64
+        //Check discovered check
+        if bishop_like_attack & (self.pieces[BISHOP][side] | self.pieces[QUEEN][side]) != 0u64
+            || rook_like_attack & rook_likes != 0u64
         {
             return true;
         }
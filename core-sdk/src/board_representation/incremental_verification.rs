@@ -0,0 +1,199 @@
+//Checks for the fields make_move updates incrementally (hash, psqt and phase) against
+//their values computed from scratch, a random game fuzzer driving those checks and
+//statistics on how often the transposition table would confuse two positions.
+use crate::board_representation::game_state::{GameMove, GameState};
+use crate::board_representation::game_state_attack_container::GameStateAttackContainer;
+use crate::evaluation::phase::Phase;
+use crate::evaluation::psqt_evaluation::psqt;
+use crate::evaluation::EvaluationResult;
+use crate::move_generation::makemove::{make_move, make_nullmove};
+use crate::perft::legal_moves;
+use crate::search::cache::{CacheBucket, CacheEntry};
+use crate::search::in_check;
+use rand::prelude::*;
+use std::collections::HashMap;
+
+pub fn verify_incremental(g: &GameState) -> Result<(), String> {
+    let hash = GameState::calculate_zobrist_hash(
+        g.color_to_move,
+        g.pieces,
+        g.castle_white_kingside,
+        g.castle_white_queenside,
+        g.castle_black_kingside,
+        g.castle_black_queenside,
+        g.en_passant,
+    );
+    if g.hash != hash {
+        return Err(format!(
+            "hash is {:x}, recomputed {:x} in {}",
+            g.hash,
+            hash,
+            g.to_fen()
+        ));
+    }
+    let mut _eval = EvaluationResult {
+        final_eval: 0,
+        #[cfg(feature = "texel-tuning")]
+        trace: crate::evaluation::trace::Trace::default(),
+    };
+    let psqt_score = psqt(true, &g.pieces, &mut _eval) - psqt(false, &g.pieces, &mut _eval);
+    if g.psqt != psqt_score {
+        return Err(format!(
+            "psqt is {:?}, recomputed {:?} in {}",
+            g.psqt,
+            psqt_score,
+            g.to_fen()
+        ));
+    }
+    let phase = Phase::from_pieces(&g.pieces);
+    if g.phase.material_score != phase.material_score
+        || (g.phase.phase - phase.phase).abs() > f64::EPSILON
+    {
+        return Err(format!(
+            "phase is {} ({}), recomputed {} ({}) in {}",
+            g.phase.phase,
+            g.phase.material_score,
+            phase.phase,
+            phase.material_score,
+            g.to_fen()
+        ));
+    }
+    Ok(())
+}
+
+//Called by make_move and make_nullmove with the verify-incremental feature.
+//Release builds skip the verification, since the assertion could not fire anyway
+#[inline(always)]
+pub fn debug_assert_incremental(old: &GameState, mv: Option<GameMove>, new: &GameState) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    if let Err(e) = verify_incremental(new) {
+        panic!(
+            "{} after {} from {}",
+            e,
+            mv.map(|mv| format!("{:?}", mv))
+                .unwrap_or_else(|| "nullmove".to_owned()),
+            old.to_fen()
+        );
+    }
+}
+
+//Plays random legal games, sprinkled with null moves, and verifies every position reached.
+//Returns the number of positions checked.
+pub fn fuzz_random_games(seed: u64, games: usize, max_plies: usize) -> Result<usize, String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut checked = 0;
+    for game in 0..games {
+        let mut g = GameState::standard();
+        let mut line: Vec<String> = Vec::new();
+        for _ in 0..max_plies {
+            let moves = legal_moves(&g);
+            if moves.is_empty() {
+                break;
+            }
+            let attack_container = GameStateAttackContainer::from_state(&g);
+            g = if !in_check(&g, &attack_container) && rng.gen_range(0, 10) == 0 {
+                line.push("null".to_owned());
+                make_nullmove(&g)
+            } else {
+                let mv = moves[rng.gen_range(0, moves.len())];
+                line.push(format!("{:?}", mv));
+                make_move(&g, mv)
+            };
+            verify_incremental(&g).map_err(|e| {
+                format!(
+                    "game {} (seed {}) after {}: {}",
+                    game,
+                    seed,
+                    line.join(" "),
+                    e
+                )
+            })?;
+            checked += 1;
+        }
+    }
+    Ok(checked)
+}
+
+#[derive(Default, Debug)]
+pub struct CollisionStatistics {
+    pub positions: usize,
+    //Pairs of distinct positions sharing all 64 bits of the hash
+    pub hash_collisions: usize,
+    //Pairs sharing the upper 48 bits, the part CacheEntry keeps and validate_hash compares
+    pub key_collisions: usize,
+    //Key collisions which also map to the same bucket, i.e. probes returning the wrong entry
+    pub false_hits: usize,
+    //What independent uniform keys would give for key_collisions
+    pub expected_key_collisions: f64,
+}
+
+//Samples distinct positions from random games and counts the pairs the transposition table
+//of the given size could not tell apart
+pub fn collision_statistics(
+    seed: u64,
+    positions: usize,
+    max_plies: usize,
+    hash_size: usize,
+) -> CollisionStatistics {
+    let buckets = (1024 * 1024 * hash_size / std::mem::size_of::<CacheBucket>()).max(1);
+    let mut rng = StdRng::seed_from_u64(seed);
+    //Positions are identified by what the hash encodes: pieces, side to move, castling, en passant
+    let mut seen: HashMap<String, u64> = HashMap::with_capacity(positions);
+    while seen.len() < positions {
+        let mut g = GameState::standard();
+        for _ in 0..max_plies {
+            let moves = legal_moves(&g);
+            if moves.is_empty() || seen.len() >= positions {
+                break;
+            }
+            g = make_move(&g, moves[rng.gen_range(0, moves.len())]);
+            let fen = g.to_fen();
+            let identity = fen.split(' ').take(4).collect::<Vec<_>>().join(" ");
+            seen.insert(identity, g.hash);
+        }
+    }
+
+    let mut by_key: HashMap<u64, Vec<u64>> = HashMap::with_capacity(seen.len());
+    for &hash in seen.values() {
+        by_key.entry(hash >> 16).or_default().push(hash);
+    }
+    let mut stats = CollisionStatistics {
+        positions: seen.len(),
+        expected_key_collisions: seen.len() as f64 * (seen.len() as f64 - 1.) / 2. / 2f64.powi(48),
+        ..CollisionStatistics::default()
+    };
+    for hashes in by_key.values().filter(|hashes| hashes.len() > 1) {
+        for (i, &first) in hashes.iter().enumerate() {
+            let entry = CacheEntry {
+                upper_hash: first >> 16,
+                mv: 1,
+                ..CacheEntry::invalid()
+            };
+            for &second in &hashes[i + 1..] {
+                if !entry.validate_hash(second) {
+                    continue;
+                }
+                stats.key_collisions += 1;
+                if first == second {
+                    stats.hash_collisions += 1;
+                }
+                if first as usize % buckets == second as usize % buckets {
+                    stats.false_hits += 1;
+                }
+            }
+        }
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_games_stay_consistent() {
+        assert!(fuzz_random_games(7, 200, 150).unwrap() > 0);
+    }
+}
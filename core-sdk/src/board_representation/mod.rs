@@ -1,3 +1,5 @@
 pub mod game_state;
 pub mod game_state_attack_container;
+pub mod incremental_verification;
+pub mod rules;
 pub mod zobrist_hashing;
@@ -0,0 +1,190 @@
+//FIDE rules ending a game, shared by the engine, the referee and the PGN writer
+use crate::board_representation::game_state::*;
+use crate::board_representation::game_state_attack_container::GameStateAttackContainer;
+use crate::move_generation::movegen::{generate_moves, MoveList};
+use std::fmt::{Display, Formatter, Result};
+
+const LIGHT_SQUARES: u64 = 0x55AA_55AA_55AA_55AA;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum GameEndReason {
+    Checkmate,
+    Stalemate,
+    DeadPosition,
+    FivefoldRepetition,
+    SeventyFiveMoveRule,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+}
+
+impl GameEndReason {
+    //Threefold repetition and the fifty-move rule end the game only if a player claims the draw
+    pub fn needs_claim(self) -> bool {
+        matches!(
+            self,
+            GameEndReason::ThreefoldRepetition | GameEndReason::FiftyMoveRule
+        )
+    }
+}
+
+impl Display for GameEndReason {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        write!(
+            formatter,
+            "{}",
+            match self {
+                GameEndReason::Checkmate => "checkmate",
+                GameEndReason::Stalemate => "stalemate",
+                GameEndReason::DeadPosition => "dead position",
+                GameEndReason::FivefoldRepetition => "fivefold repetition",
+                GameEndReason::SeventyFiveMoveRule => "seventy-five-move rule",
+                GameEndReason::ThreefoldRepetition => "threefold repetition",
+                GameEndReason::FiftyMoveRule => "fifty-move rule",
+            }
+        )
+    }
+}
+
+//K vs K, KB vs K, KN vs K and positions where all bishops stand on squares of one color
+pub fn is_dead_position(game_state: &GameState) -> bool {
+    if game_state.pieces[PAWN][WHITE]
+        | game_state.pieces[PAWN][BLACK]
+        | game_state.pieces[ROOK][WHITE]
+        | game_state.pieces[ROOK][BLACK]
+        | game_state.pieces[QUEEN][WHITE]
+        | game_state.pieces[QUEEN][BLACK]
+        != 0u64
+    {
+        return false;
+    }
+    let knights = game_state.pieces[KNIGHT][WHITE] | game_state.pieces[KNIGHT][BLACK];
+    let bishops = game_state.pieces[BISHOP][WHITE] | game_state.pieces[BISHOP][BLACK];
+    (knights | bishops).count_ones() <= 1
+        || knights == 0u64 && (bishops & LIGHT_SQUARES == 0u64 || bishops & !LIGHT_SQUARES == 0u64)
+}
+
+#[inline(always)]
+pub fn mate_or_stalemate(
+    game_state: &GameState,
+    has_legal_moves: bool,
+    in_check: bool,
+) -> (GameResult, Option<GameEndReason>) {
+    if has_legal_moves {
+        (GameResult::Ingame, None)
+    } else if !in_check {
+        (GameResult::Draw, Some(GameEndReason::Stalemate))
+    } else if game_state.color_to_move == WHITE {
+        (GameResult::BlackWin, Some(GameEndReason::Checkmate))
+    } else {
+        (GameResult::WhiteWin, Some(GameEndReason::Checkmate))
+    }
+}
+
+//A checkmate delivered with the move reaching the fifty- or seventy-five-move limit stands
+pub fn is_checkmate(game_state: &GameState) -> bool {
+    let attack_container = GameStateAttackContainer::from_state(game_state);
+    let agsi = generate_moves(
+        game_state,
+        false,
+        &mut MoveList::default(),
+        &attack_container,
+    );
+    agsi.stm_incheck && !agsi.stm_haslegalmove
+}
+
+//Repetitions is how often the current position occurred before in the game
+pub fn game_result(
+    game_state: &GameState,
+    has_legal_moves: bool,
+    in_check: bool,
+    repetitions: usize,
+) -> (GameResult, Option<GameEndReason>) {
+    let result = mate_or_stalemate(game_state, has_legal_moves, in_check);
+    if result.0 != GameResult::Ingame {
+        return result;
+    }
+    let reason = if is_dead_position(game_state) {
+        GameEndReason::DeadPosition
+    } else if repetitions >= 4 {
+        GameEndReason::FivefoldRepetition
+    } else if game_state.half_moves >= 150 {
+        GameEndReason::SeventyFiveMoveRule
+    } else if repetitions >= 2 {
+        GameEndReason::ThreefoldRepetition
+    } else if game_state.half_moves >= 100 {
+        GameEndReason::FiftyMoveRule
+    } else {
+        return (GameResult::Ingame, None);
+    };
+    (GameResult::Draw, Some(reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result_of(fen: &str, repetitions: usize) -> (GameResult, Option<GameEndReason>) {
+        let game_state = GameState::from_fen(fen);
+        let attack_container = GameStateAttackContainer::from_state(&game_state);
+        let agsi = generate_moves(
+            &game_state,
+            false,
+            &mut MoveList::default(),
+            &attack_container,
+        );
+        game_result(
+            &game_state,
+            agsi.stm_haslegalmove,
+            agsi.stm_incheck,
+            repetitions,
+        )
+    }
+
+    #[test]
+    fn fide_rules() {
+        let draw = |reason| (GameResult::Draw, Some(reason));
+        assert_eq!(
+            result_of("8/8/4k3/8/8/8/4K3/8 w - - 0 1", 0),
+            draw(GameEndReason::DeadPosition)
+        );
+        assert_eq!(
+            result_of("8/8/4k3/8/8/8/4K3/6N1 w - - 0 1", 0),
+            draw(GameEndReason::DeadPosition)
+        );
+        assert_eq!(
+            result_of("8/8/4kb2/8/8/8/1B2K3/2B5 w - - 0 1", 0),
+            draw(GameEndReason::DeadPosition)
+        );
+        assert_eq!(
+            result_of("8/8/4k3/8/8/8/4K3/2BB4 w - - 0 1", 0),
+            (GameResult::Ingame, None)
+        );
+        assert_eq!(
+            result_of("8/8/4k3/8/8/8/4K3/2N2n2 w - - 0 1", 0),
+            (GameResult::Ingame, None)
+        );
+        let fen = "8/8/4k3/8/2R5/8/4K3/8 w - - 0 40";
+        assert_eq!(result_of(fen, 1), (GameResult::Ingame, None));
+        assert_eq!(result_of(fen, 2), draw(GameEndReason::ThreefoldRepetition));
+        assert_eq!(result_of(fen, 4), draw(GameEndReason::FivefoldRepetition));
+        assert_eq!(
+            result_of("8/8/4k3/8/2R5/8/4K3/8 w - - 100 90", 0),
+            draw(GameEndReason::FiftyMoveRule)
+        );
+        assert_eq!(
+            result_of("8/8/4k3/8/2R5/8/4K3/8 w - - 150 120", 2),
+            draw(GameEndReason::SeventyFiveMoveRule)
+        );
+        //Mate on the hundredth ply
+        let fen = "7k/6Q1/6K1/8/8/8/8/8 b - - 100 90";
+        assert!(is_checkmate(&GameState::from_fen(fen)));
+        assert_eq!(
+            result_of(fen, 0),
+            (GameResult::WhiteWin, Some(GameEndReason::Checkmate))
+        );
+        assert_eq!(
+            result_of("7k/5Q2/6K1/8/8/8/8/8 b - - 100 90", 0),
+            draw(GameEndReason::Stalemate)
+        );
+    }
+}
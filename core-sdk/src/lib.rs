@@ -6,16 +6,19 @@ pub mod bitboards;
 pub mod board_representation;
 pub mod evaluation;
 pub mod move_generation;
+pub mod perft;
 pub mod search;
 
 use crate::board_representation::game_state::GameState;
 use crate::move_generation::makemove::make_move;
 use crate::move_generation::movegen;
 use crate::search::cache::DEFAULT_HASH_SIZE;
+use crate::search::parameters::SearchParams;
 use crate::search::reserved_memory::{ReservedAttackContainer, ReservedMoveList};
 use crate::search::searcher::{
     InterThreadCommunicationSystem, DEFAULT_SKIP_RATIO, DEFAULT_THREADS,
 };
+use crate::search::smp::{SMPStrategy, DEFAULT_SMP_STRATEGY};
 use crate::search::timecontrol::DEFAULT_MOVE_OVERHEAD;
 use std::sync::Arc;
 #[cfg(not(target_arch = "wasm32"))]
@@ -30,6 +33,8 @@ pub struct UCIOptions {
     pub move_overhead: u64,
     pub debug_print: bool,
     pub skip_ratio: usize,
+    pub smp_strategy: SMPStrategy,
+    pub search_params: SearchParams,
 }
 impl Default for UCIOptions {
     fn default() -> Self {
@@ -39,6 +44,8 @@ impl Default for UCIOptions {
             move_overhead: DEFAULT_MOVE_OVERHEAD,
             debug_print: false,
             skip_ratio: DEFAULT_SKIP_RATIO,
+            smp_strategy: DEFAULT_SMP_STRATEGY,
+            search_params: SearchParams::default(),
         }
     }
 }
@@ -56,12 +63,12 @@ pub fn perft_div(g: &GameState, depth: usize) -> u64 {
         &mut movelist.move_lists[depth],
         &attack_container.attack_containers[depth],
     );
-    let len = movelist.move_lists[depth].move_list.len();
+    let len = movelist.move_lists[depth].len();
     for i in 0..len {
-        let gmv = movelist.move_lists[depth].move_list[i];
-        let next_g = make_move(&g, gmv.0);
+        let mv = movelist.move_lists[depth].moves()[i].0;
+        let next_g = make_move(&g, mv.to_game_move(g));
         let res = perft(&next_g, depth - 1, &mut movelist, &mut attack_container);
-        println!("{:?}: {}", gmv.0, res);
+        println!("{:?}: {}", mv, res);
         count += res;
     }
     println!("{}", count);
@@ -89,7 +96,7 @@ pub fn perft(
             &mut movelist.move_lists[depth],
             &attack_container.attack_containers[depth],
         );
-        movelist.move_lists[depth].move_list.len() as u64
+        movelist.move_lists[depth].len() as u64
     } else {
         if depth == 0 {
             return 1;
@@ -101,9 +108,9 @@ pub fn perft(
             &mut movelist.move_lists[depth],
             &attack_container.attack_containers[depth],
         );
-        let len = movelist.move_lists[depth].move_list.len();
+        let len = movelist.move_lists[depth].len();
         for i in 0..len {
-            let mv = movelist.move_lists[depth].move_list[i].0;
+            let mv = movelist.move_lists[depth].moves()[i].0.to_game_move(g);
             res += perft(&make_move(&g, mv), depth - 1, movelist, attack_container);
         }
         res
@@ -164,7 +171,7 @@ const BENCHMARKING_POSITIONS: [&str; 50] = [
 pub fn bench(depth: usize) {
     let itcs = Arc::new(InterThreadCommunicationSystem::default());
     InterThreadCommunicationSystem::update_thread_count(&itcs, 1);
-    *itcs.cache() = search::cache::Cache::with_size_threaded(8, 1);
+    itcs.set_cache(search::cache::Cache::with_size_threaded(8, 1));
     let before_time = Instant::now();
     let mut nodes = 0;
     for position in BENCHMARKING_POSITIONS.iter() {
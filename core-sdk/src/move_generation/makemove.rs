@@ -1,6 +1,8 @@
 use crate::board_representation::game_state::{
     GameMove, GameMoveType, GameState, PieceType, WHITE,
 };
+#[cfg(feature = "verify-incremental")]
+use crate::board_representation::incremental_verification::debug_assert_incremental;
 use crate::board_representation::zobrist_hashing::ZOBRIST_KEYS;
 use crate::evaluation::psqt_evaluation::psqt_toggle_piece;
 
@@ -57,7 +59,7 @@ pub fn make_nullmove(g: &GameState) -> GameState {
     let full_moves = g.full_moves + g.color_to_move;
     let mut hash = g.hash ^ ZOBRIST_KEYS.side_to_move;
     enpassant_hash(g.en_passant, en_passant, &mut hash);
-    GameState {
+    let res = GameState {
         color_to_move,
         pieces,
         castle_white_kingside: g.castle_white_kingside,
@@ -70,7 +72,10 @@ pub fn make_nullmove(g: &GameState) -> GameState {
         hash,
         psqt: g.psqt,
         phase: g.phase.clone(),
-    }
+    };
+    #[cfg(feature = "verify-incremental")]
+    debug_assert_incremental(g, None, &res);
+    res
 }
 
 #[inline(always)]
@@ -267,7 +272,7 @@ pub fn make_move(g: &GameState, mv: GameMove) -> GameState {
     } else {
         0
     };
-    GameState {
+    let res = GameState {
         color_to_move,
         pieces,
         castle_white_kingside,
@@ -280,5 +285,8 @@ pub fn make_move(g: &GameState, mv: GameMove) -> GameState {
         hash,
         psqt,
         phase,
-    }
+    };
+    #[cfg(feature = "verify-incremental")]
+    debug_assert_incremental(g, Some(mv), &res);
+    res
 }
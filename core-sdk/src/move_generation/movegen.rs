@@ -5,7 +5,7 @@ use crate::bitboards::bitboards::constants::{
     ROOK_RAYS,
 };
 use crate::board_representation::game_state::{
-    GameMove, GameMoveType, GameState, PieceType, BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK, WHITE,
+    GameMove, GameState, Move, PieceType, BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK, WHITE,
 };
 use crate::board_representation::game_state_attack_container::{
     GameStateAttackContainer, MGSA_BISHOP, MGSA_KNIGHT, MGSA_QUEEN, MGSA_ROOKS,
@@ -164,10 +164,7 @@ pub fn add_pin_moves_to_movelist(
     capture_mask: u64,
     enemy_pinner: u64,
     pinned_piece_position: usize,
-    moving_piece_type: PieceType,
     pinner_position: usize,
-    enemy_queens: u64,
-    other_pinner_piece_type: PieceType,
 ) -> bool {
     let pin_quiet_targets = ray_to_king & push_mask & !(1u64 << pinned_piece_position);
     let pin_capture_possible = (capture_mask & enemy_pinner) != 0u64;
@@ -177,8 +174,7 @@ pub fn add_pin_moves_to_movelist(
             legal_moves,
             pinned_piece_position,
             pin_quiet_targets,
-            moving_piece_type,
-            GameMoveType::Quiet,
+            Move::QUIET,
         );
     }
     if pin_capture_possible {
@@ -186,12 +182,7 @@ pub fn add_pin_moves_to_movelist(
             legal_moves,
             pinned_piece_position,
             pinner_position,
-            moving_piece_type,
-            GameMoveType::Capture(if enemy_pinner & enemy_queens != 0u64 {
-                PieceType::Queen
-            } else {
-                other_pinner_piece_type
-            }),
+            Move::CAPTURE,
         );
     }
     haslegalmove
@@ -199,7 +190,6 @@ pub fn add_pin_moves_to_movelist(
 
 #[inline(always)]
 pub fn add_king_moves_to_movelist(
-    g: &GameState,
     legal_moves: &mut MoveList,
     only_captures: bool,
     stm_legal_kingmoves: u64,
@@ -210,23 +200,11 @@ pub fn add_king_moves_to_movelist(
     let quiets = stm_legal_kingmoves & !captures;
     while captures != 0u64 {
         let capture_index = captures.trailing_zeros() as usize;
-        add_move_to_movelist(
-            legal_moves,
-            stm_king_index,
-            capture_index,
-            PieceType::King,
-            GameMoveType::Capture(find_captured_piece_type(g, capture_index)),
-        );
+        add_move_to_movelist(legal_moves, stm_king_index, capture_index, Move::CAPTURE);
         captures ^= 1u64 << capture_index;
     }
     if !only_captures {
-        add_moves_to_movelist(
-            legal_moves,
-            stm_king_index,
-            quiets,
-            PieceType::King,
-            GameMoveType::Quiet,
-        );
+        add_moves_to_movelist(legal_moves, stm_king_index, quiets, Move::QUIET);
     }
 }
 
@@ -252,21 +230,15 @@ pub fn add_pawn_moves_to_movelist(
         let from_board = 1u64 << from_index;
         if from_board & pinned_pieces == 0u64 {
             stm_haslegalmove = true;
-            let mv_type = if is_capture {
-                GameMoveType::Capture(find_captured_piece_type(g, pawn_index))
+            let flag = if is_capture {
+                Move::CAPTURE
             } else {
-                GameMoveType::Quiet
+                Move::QUIET
             };
             if is_promotion {
-                add_promotion_move_to_movelist(legal_moves, from_index, pawn_index, mv_type);
+                add_promotion_move_to_movelist(legal_moves, from_index, pawn_index, flag);
             } else {
-                add_move_to_movelist(
-                    legal_moves,
-                    from_index,
-                    pawn_index,
-                    PieceType::Pawn,
-                    mv_type,
-                )
+                add_move_to_movelist(legal_moves, from_index, pawn_index, flag)
             }
         }
         target_board ^= pawn;
@@ -309,13 +281,7 @@ pub fn add_normal_moves_to_movelist(
             stm_haslegalmove |= captures != 0u64;
             while captures != 0u64 {
                 let capture_index = captures.trailing_zeros() as usize;
-                add_move_to_movelist(
-                    legal_moves,
-                    piece_index,
-                    capture_index,
-                    piece_type,
-                    GameMoveType::Capture(find_captured_piece_type(g, capture_index)),
-                );
+                add_move_to_movelist(legal_moves, piece_index, capture_index, Move::CAPTURE);
                 captures ^= 1u64 << capture_index;
             }
 
@@ -323,13 +289,7 @@ pub fn add_normal_moves_to_movelist(
                 let quiets = piece_target & push_mask & empty_squares;
                 stm_haslegalmove |= quiets != 0u64;
                 if !only_captures {
-                    add_moves_to_movelist(
-                        legal_moves,
-                        piece_index,
-                        quiets,
-                        piece_type,
-                        GameMoveType::Quiet,
-                    );
+                    add_moves_to_movelist(legal_moves, piece_index, quiets, Move::QUIET);
                 }
             }
         }
@@ -344,51 +304,19 @@ pub fn add_promotion_move_to_movelist(
     legal_moves: &mut MoveList,
     from_square: usize,
     to_square: usize,
-    move_type: GameMoveType,
+    flag: u16,
 ) {
-    let new_types = if let GameMoveType::Capture(x) = move_type {
-        (
-            GameMoveType::Promotion(PieceType::Queen, Some(x)),
-            GameMoveType::Promotion(PieceType::Rook, Some(x)),
-            GameMoveType::Promotion(PieceType::Bishop, Some(x)),
-            GameMoveType::Promotion(PieceType::Knight, Some(x)),
-        )
-    } else {
-        (
-            GameMoveType::Promotion(PieceType::Queen, None),
-            GameMoveType::Promotion(PieceType::Rook, None),
-            GameMoveType::Promotion(PieceType::Bishop, None),
-            GameMoveType::Promotion(PieceType::Knight, None),
-        )
-    };
-    add_move_to_movelist(
-        legal_moves,
-        from_square,
-        to_square,
-        PieceType::Pawn,
-        new_types.0,
-    );
-    add_move_to_movelist(
-        legal_moves,
-        from_square,
-        to_square,
-        PieceType::Pawn,
-        new_types.1,
-    );
-    add_move_to_movelist(
-        legal_moves,
-        from_square,
-        to_square,
-        PieceType::Pawn,
-        new_types.2,
-    );
-    add_move_to_movelist(
-        legal_moves,
-        from_square,
-        to_square,
-        PieceType::Pawn,
-        new_types.3,
-    );
+    let capture = flag == Move::CAPTURE;
+    for piece in [
+        PieceType::Queen,
+        PieceType::Rook,
+        PieceType::Bishop,
+        PieceType::Knight,
+    ]
+    .iter()
+    {
+        legal_moves.add_move(Move::promotion(from_square, to_square, *piece, capture));
+    }
 }
 
 #[inline(always)]
@@ -396,18 +324,11 @@ pub fn add_moves_to_movelist(
     legal_moves: &mut MoveList,
     from_square: usize,
     mut target_board: u64,
-    piece_type: PieceType,
-    move_type: GameMoveType,
+    flag: u16,
 ) {
     while target_board != 0u64 {
         let target_square = target_board.trailing_zeros() as usize;
-        add_move_to_movelist(
-            legal_moves,
-            from_square,
-            target_square,
-            piece_type,
-            move_type,
-        );
+        add_move_to_movelist(legal_moves, from_square, target_square, flag);
         target_board ^= 1u64 << target_square;
     }
 }
@@ -417,15 +338,9 @@ pub fn add_move_to_movelist(
     legal_moves: &mut MoveList,
     from_square: usize,
     to_square: usize,
-    piece_type: PieceType,
-    move_type: GameMoveType,
+    flag: u16,
 ) {
-    legal_moves.add_move(GameMove {
-        from: from_square as u8,
-        to: to_square as u8,
-        move_type,
-        piece_type,
-    });
+    legal_moves.add_move(Move::new(from_square, to_square, flag));
 }
 
 #[inline(always)]
@@ -459,27 +374,71 @@ pub struct AdditionalGameStateInformation {
     pub stm_haslegalmove: bool,
 }
 
-pub const MAX_MOVES: usize = 128;
+//No position has more than 218 legal moves
+pub const MAX_MOVES: usize = 256;
 
 pub struct MoveList {
-    pub move_list: Vec<GradedMove>,
+    moves: [GradedMove; MAX_MOVES],
+    len: usize,
 }
 impl Default for MoveList {
     fn default() -> Self {
-        let move_list = Vec::with_capacity(MAX_MOVES);
-        MoveList { move_list }
+        MoveList {
+            moves: [GradedMove(Move(0), None); MAX_MOVES],
+            len: 0,
+        }
     }
 }
 
 impl MoveList {
     #[inline(always)]
-    pub fn add_move(&mut self, mv: GameMove) {
-        self.move_list.push(GradedMove(mv, None));
+    pub fn add_move(&mut self, mv: Move) {
+        self.push(GradedMove(mv, None));
     }
 
     #[inline(always)]
-    pub fn find_move(&self, mv: GameMove, contains: bool) -> usize {
-        for (index, mvs) in self.move_list.iter().enumerate() {
+    pub fn push(&mut self, gmv: GradedMove) {
+        self.moves[self.len] = gmv;
+        self.len += 1;
+    }
+
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline(always)]
+    pub fn moves(&self) -> &[GradedMove] {
+        &self.moves[..self.len]
+    }
+
+    #[inline(always)]
+    pub fn moves_mut(&mut self) -> &mut [GradedMove] {
+        &mut self.moves[..self.len]
+    }
+
+    //Does not keep the order of the remaining moves
+    #[inline(always)]
+    pub fn remove(&mut self, index: usize) -> GradedMove {
+        let gmv = self.moves[index];
+        self.len -= 1;
+        self.moves[index] = self.moves[self.len];
+        gmv
+    }
+
+    #[inline(always)]
+    pub fn find_move(&self, mv: Move, contains: bool) -> usize {
+        for (index, mvs) in self.moves().iter().enumerate() {
             if mvs.0 == mv {
                 return index;
             }
@@ -487,25 +446,32 @@ impl MoveList {
         if contains {
             panic!("Type 2 error")
         }
-        self.move_list.len()
+        self.len
     }
 
     #[inline(always)]
-    pub fn highest_score(&mut self) -> Option<(usize, GradedMove)> {
-        let mut best_index = self.move_list.len();
-        let mut best_score = -1_000_000_000.;
-        for (index, gmv) in self.move_list.iter().enumerate() {
-            if gmv.1.is_some() && gmv.1.unwrap() > best_score {
-                best_index = index;
-                best_score = gmv.1.unwrap();
+    pub fn highest_score(&self) -> Option<(usize, GradedMove)> {
+        let mut best_index = self.len;
+        let mut best_score = i32::MIN;
+        for (index, gmv) in self.moves().iter().enumerate() {
+            if let Some(score) = gmv.1 {
+                if best_index == self.len || score > best_score {
+                    best_index = index;
+                    best_score = score;
+                }
             }
         }
-        if best_index == self.move_list.len() {
+        if best_index == self.len {
             None
         } else {
-            Some((best_index, self.move_list[best_index]))
+            Some((best_index, self.moves[best_index]))
         }
     }
+
+    //The moves in the format of the old API, with the pieces taken from the position they were generated in
+    pub fn game_moves<'a>(&'a self, g: &'a GameState) -> impl Iterator<Item = GameMove> + 'a {
+        self.moves().iter().map(move |gmv| gmv.0.to_game_move(g))
+    }
 }
 
 pub fn generate_moves(
@@ -517,7 +483,7 @@ pub fn generate_moves(
     //----------------------------------------------------------------------
     //**********************************************************************
     //1. General bitboards and variable initialization
-    movelist.move_list.clear();
+    movelist.clear();
 
     let side = g.color_to_move;
     let enemy = 1 - side;
@@ -538,7 +504,6 @@ pub fn generate_moves(
         attack_container.king_attacks[side] & !attack_container.attacks_sum[enemy] & !side_pieces;
     stm_haslegalmove |= stm_legal_kingmoves != 0u64;
     add_king_moves_to_movelist(
-        g,
         movelist,
         only_captures,
         stm_legal_kingmoves,
@@ -616,10 +581,7 @@ pub fn generate_moves(
                     capture_mask,
                     enemy_rook,
                     pinned_piece_position,
-                    PieceType::Queen,
                     enemy_rook_position,
-                    g.pieces[QUEEN][enemy],
-                    PieceType::Rook,
                 );
             } else if pinned_piece & g.pieces[ROOK][side] != 0u64 {
                 //Add possible rook pushes
@@ -631,10 +593,7 @@ pub fn generate_moves(
                     capture_mask,
                     enemy_rook,
                     pinned_piece_position,
-                    PieceType::Rook,
                     enemy_rook_position,
-                    g.pieces[QUEEN][enemy],
-                    PieceType::Rook,
                 );
             } else if pinned_piece & side_pawns != 0u64 {
                 //Add possible pawn pushes
@@ -657,8 +616,7 @@ pub fn generate_moves(
                         movelist,
                         pinned_piece_position,
                         stm_pawn_pin_single_push | stm_pawn_pin_double_push,
-                        PieceType::Pawn,
-                        GameMoveType::Quiet,
+                        Move::QUIET,
                     )
                 }
             }
@@ -696,10 +654,7 @@ pub fn generate_moves(
                     capture_mask,
                     enemy_bishop,
                     pinned_piece_position,
-                    PieceType::Queen,
                     enemy_bishop_position,
-                    g.pieces[QUEEN][enemy],
-                    PieceType::Bishop,
                 );
             } else if pinned_piece & g.pieces[BISHOP][side] != 0u64 {
                 //Add possible bishop pushes
@@ -711,10 +666,7 @@ pub fn generate_moves(
                     capture_mask,
                     enemy_bishop,
                     pinned_piece_position,
-                    PieceType::Bishop,
                     enemy_bishop_position,
-                    g.pieces[QUEEN][enemy],
-                    PieceType::Bishop,
                 );
             } else if pinned_piece & side_pawns != 0u64 {
                 //Add possible pawn captures
@@ -735,11 +687,7 @@ pub fn generate_moves(
                         movelist,
                         pinned_piece_position,
                         enemy_bishop_position,
-                        GameMoveType::Capture(if enemy_bishop & g.pieces[QUEEN][enemy] != 0u64 {
-                            PieceType::Queen
-                        } else {
-                            PieceType::Bishop
-                        }),
+                        Move::CAPTURE,
                     );
                 }
                 let stm_pawn_pin_nonpromotion_capture =
@@ -750,12 +698,7 @@ pub fn generate_moves(
                         movelist,
                         pinned_piece_position,
                         enemy_bishop_position,
-                        PieceType::Pawn,
-                        GameMoveType::Capture(if enemy_bishop & g.pieces[QUEEN][enemy] != 0u64 {
-                            PieceType::Queen
-                        } else {
-                            PieceType::Bishop
-                        }),
+                        Move::CAPTURE,
                     );
                 }
                 //En passants
@@ -767,8 +710,7 @@ pub fn generate_moves(
                         movelist,
                         pinned_piece_position,
                         stm_pawn_pin_enpassant.trailing_zeros() as usize,
-                        PieceType::Pawn,
-                        GameMoveType::EnPassant,
+                        Move::EN_PASSANT,
                     );
                 }
             }
@@ -886,13 +828,7 @@ pub fn generate_moves(
             == 0u64
         {
             stm_haslegalmove = true;
-            add_move_to_movelist(
-                movelist,
-                pawn_from,
-                pawn_index,
-                PieceType::Pawn,
-                GameMoveType::EnPassant,
-            );
+            add_move_to_movelist(movelist, pawn_from, pawn_index, Move::EN_PASSANT);
         }
     }
     //5.4 East captures (normal capture, promotion capture, en passant)
@@ -951,13 +887,7 @@ pub fn generate_moves(
             == 0u64
         {
             stm_haslegalmove = true;
-            add_move_to_movelist(
-                movelist,
-                pawn_from,
-                pawn_index,
-                PieceType::Pawn,
-                GameMoveType::EnPassant,
-            );
+            add_move_to_movelist(movelist, pawn_from, pawn_index, Move::EN_PASSANT);
         }
     }
 
@@ -1032,12 +962,7 @@ pub fn generate_moves(
             {
                 stm_haslegalmove = true;
                 if !only_captures {
-                    movelist.add_move(GameMove {
-                        from: g.king_square(side) as u8,
-                        to: 6,
-                        move_type: GameMoveType::Castle,
-                        piece_type: PieceType::King,
-                    });
+                    movelist.add_move(Move::new(g.king_square(side), 6, Move::CASTLE));
                 }
             }
             if g.castle_white_queenside
@@ -1047,12 +972,7 @@ pub fn generate_moves(
             {
                 stm_haslegalmove = true;
                 if !only_captures {
-                    movelist.add_move(GameMove {
-                        from: g.king_square(side) as u8,
-                        to: 2,
-                        move_type: GameMoveType::Castle,
-                        piece_type: PieceType::King,
-                    });
+                    movelist.add_move(Move::new(g.king_square(side), 2, Move::CASTLE));
                 }
             }
         } else {
@@ -1062,12 +982,7 @@ pub fn generate_moves(
             {
                 stm_haslegalmove = true;
                 if !only_captures {
-                    movelist.add_move(GameMove {
-                        from: g.king_square(side) as u8,
-                        to: 62,
-                        move_type: GameMoveType::Castle,
-                        piece_type: PieceType::King,
-                    });
+                    movelist.add_move(Move::new(g.king_square(side), 62, Move::CASTLE));
                 }
             }
             if g.castle_black_queenside
@@ -1077,12 +992,7 @@ pub fn generate_moves(
             {
                 stm_haslegalmove = true;
                 if !only_captures {
-                    movelist.add_move(GameMove {
-                        from: g.king_square(side) as u8,
-                        to: 58,
-                        move_type: GameMoveType::Castle,
-                        piece_type: PieceType::King,
-                    });
+                    movelist.add_move(Move::new(g.king_square(side), 58, Move::CASTLE));
                 }
             }
         }
@@ -0,0 +1,494 @@
+use super::movegen::{
+    add_move_to_movelist, add_moves_to_movelist, add_pawn_moves_to_movelist, bishop_attack,
+    double_push_pawn_targets, get_checkers, pawn_east_targets, pawn_west_targets, rook_attack,
+    single_push_pawn_targets, xray_bishop_attacks, xray_rook_attacks, MoveList,
+};
+use crate::bitboards::bitboards::constants::{
+    square, BISHOP_RAYS, FREEFIELD_BISHOP_ATTACKS, FREEFIELD_ROOK_ATTACKS, KNIGHT_ATTACKS, RANKS,
+    ROOK_RAYS,
+};
+use crate::board_representation::game_state::{
+    GameState, Move, BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK, WHITE,
+};
+use crate::board_representation::game_state_attack_container::{
+    GameStateAttackContainer, MGSA_BISHOP, MGSA_KNIGHT, MGSA_QUEEN, MGSA_ROOKS,
+};
+
+//Staged pseudo-legal movegen for the search
+//King moves and castles are only generated if they are legal
+//Every other move has to pass is_legal before it is made
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GenType {
+    //Captures, capture-promotions and en passants. Not in check only
+    Captures,
+    //Quiet moves, quiet promotions and castles. Not in check only
+    Quiets,
+    //All moves resolving a check. In check only
+    Evasions,
+    //Quiet moves giving check, without promotions and castles. Not in check only
+    QuietChecks,
+}
+
+//Appends the moves to the movelist
+pub fn generate_pseudo_legal_moves(
+    g: &GameState,
+    gen_type: GenType,
+    movelist: &mut MoveList,
+    attack_container: &GameStateAttackContainer,
+) {
+    let side = g.color_to_move;
+    let enemy = 1 - side;
+    let side_pieces = g.get_pieces_from_side(side);
+    let enemy_pieces = g.get_pieces_from_side(enemy);
+    let all_pieces = side_pieces | enemy_pieces;
+    let empty_squares = !all_pieces;
+    let king_square = g.king_square(side);
+    let promotion_rank = RANKS[if side == WHITE { 7 } else { 0 }];
+
+    //1. Target squares
+    let (mut capture_mask, mut push_mask) = match gen_type {
+        GenType::Captures => (enemy_pieces, 0u64),
+        GenType::Quiets | GenType::QuietChecks => (0u64, empty_squares),
+        GenType::Evasions => (enemy_pieces, empty_squares),
+    };
+
+    //2. Safe king moves
+    let king_targets = attack_container.king_attacks[side] & !attack_container.attacks_sum[enemy];
+    if gen_type != GenType::QuietChecks {
+        add_moves_to_movelist(
+            movelist,
+            king_square,
+            king_targets & capture_mask,
+            Move::CAPTURE,
+        );
+        add_moves_to_movelist(movelist, king_square, king_targets & push_mask, Move::QUIET);
+    }
+
+    //3. Check evasions: capture a single checker or block a sliding one
+    if gen_type == GenType::Evasions {
+        let check_board = get_checkers(g, false);
+        debug_assert!(check_board != 0u64);
+        if check_board.count_ones() > 1 {
+            return;
+        }
+        capture_mask &= check_board;
+        let checker_square = check_board.trailing_zeros() as usize;
+        push_mask &= if check_board
+            & (g.pieces[BISHOP][enemy] | g.pieces[ROOK][enemy] | g.pieces[QUEEN][enemy])
+            == 0u64
+        {
+            0u64
+        } else if check_board & FREEFIELD_ROOK_ATTACKS[king_square] != 0u64 {
+            ROOK_RAYS[king_square][checker_square]
+        } else {
+            BISHOP_RAYS[king_square][checker_square]
+        };
+    }
+
+    //4. Quiet checks: direct checks per piece type and pieces discovering a check
+    let (check_squares, discoverers) = if gen_type == GenType::QuietChecks {
+        let enemy_king = g.pieces[KING][enemy];
+        let enemy_king_square = g.king_square(enemy);
+        let bishop_checks = bishop_attack(enemy_king_square, all_pieces);
+        let rook_checks = rook_attack(enemy_king_square, all_pieces);
+        (
+            [
+                pawn_west_targets(enemy, enemy_king) | pawn_east_targets(enemy, enemy_king),
+                KNIGHT_ATTACKS[enemy_king_square],
+                bishop_checks,
+                rook_checks,
+                bishop_checks | rook_checks,
+            ],
+            discovered_check_candidates(g, enemy_king_square, all_pieces, side_pieces),
+        )
+    } else {
+        ([!0u64; 5], 0u64)
+    };
+
+    //5. Pawns
+    let pawns = g.pieces[PAWN][side];
+    let single_pushes = single_push_pawn_targets(side, pawns, empty_squares) & push_mask;
+    let double_pushes = double_push_pawn_targets(side, pawns, empty_squares) & push_mask;
+    if gen_type == GenType::QuietChecks {
+        add_pawn_moves(
+            g,
+            movelist,
+            single_pushes & !promotion_rank & check_squares[PAWN],
+            8,
+            false,
+        );
+        add_pawn_moves(g, movelist, double_pushes & check_squares[PAWN], 16, false);
+    } else {
+        add_pawn_moves(g, movelist, single_pushes & promotion_rank, 8, true);
+        add_pawn_moves(g, movelist, single_pushes & !promotion_rank, 8, false);
+        add_pawn_moves(g, movelist, double_pushes, 16, false);
+    }
+    let west_captures = attack_container.pawn_west_attacks[side] & capture_mask;
+    let east_captures = attack_container.pawn_east_attacks[side] & capture_mask;
+    add_pawn_captures(g, movelist, west_captures & promotion_rank, 7, true);
+    add_pawn_captures(g, movelist, west_captures & !promotion_rank, 7, false);
+    add_pawn_captures(g, movelist, east_captures & promotion_rank, 9, true);
+    add_pawn_captures(g, movelist, east_captures & !promotion_rank, 9, false);
+    //The pawn captured en passant has to be a target
+    let en_passant_victim = if side == WHITE {
+        g.en_passant >> 8
+    } else {
+        g.en_passant << 8
+    };
+    if en_passant_victim & capture_mask != 0u64 {
+        for (attacks, shift) in [
+            (attack_container.pawn_west_attacks[side], 7),
+            (attack_container.pawn_east_attacks[side], 9),
+        ]
+        .iter()
+        {
+            if attacks & g.en_passant != 0u64 {
+                let to = g.en_passant.trailing_zeros() as usize;
+                let from = if side == WHITE {
+                    to - shift
+                } else {
+                    to + shift
+                };
+                add_move_to_movelist(movelist, from, to, Move::EN_PASSANT);
+            }
+        }
+    }
+
+    //6. Knights, bishops, rooks and queens
+    for (piece, attack_index) in [
+        (KNIGHT, MGSA_KNIGHT),
+        (BISHOP, MGSA_BISHOP),
+        (ROOK, MGSA_ROOKS),
+        (QUEEN, MGSA_QUEEN),
+    ]
+    .iter()
+    {
+        let mut piece_board = g.pieces[*piece][side];
+        let mut index = 0;
+        while piece_board != 0u64 {
+            let from = piece_board.trailing_zeros() as usize;
+            let targets = attack_container.attack[*attack_index][side][index];
+            add_moves_to_movelist(movelist, from, targets & capture_mask, Move::CAPTURE);
+            add_moves_to_movelist(
+                movelist,
+                from,
+                targets & push_mask & check_squares[*piece],
+                Move::QUIET,
+            );
+            piece_board ^= 1u64 << from;
+            index += 1;
+        }
+    }
+
+    //7. Discovered checks of pieces not already checking directly
+    let mut discoverers = discoverers;
+    while discoverers != 0u64 {
+        let from = discoverers.trailing_zeros() as usize;
+        let from_board = 1u64 << from;
+        let (targets, piece) = if from == king_square {
+            (king_targets, None)
+        } else if from_board & pawns != 0u64 {
+            (
+                (single_push_pawn_targets(side, from_board, empty_squares)
+                    | double_push_pawn_targets(side, from_board, empty_squares))
+                    & !promotion_rank,
+                Some(PAWN),
+            )
+        } else if from_board & g.pieces[KNIGHT][side] != 0u64 {
+            (KNIGHT_ATTACKS[from], Some(KNIGHT))
+        } else if from_board & g.pieces[BISHOP][side] != 0u64 {
+            (bishop_attack(from, all_pieces), Some(BISHOP))
+        } else if from_board & g.pieces[ROOK][side] != 0u64 {
+            (rook_attack(from, all_pieces), Some(ROOK))
+        } else {
+            //A queen would already give check through the line it blocks
+            (0u64, None)
+        };
+        let mut targets =
+            targets & push_mask & !piece.map(|piece| check_squares[piece]).unwrap_or(0u64);
+        while targets != 0u64 {
+            let to = targets.trailing_zeros() as usize;
+            if discovers_check(g, from, to, all_pieces) {
+                add_move_to_movelist(movelist, from, to, Move::QUIET);
+            }
+            targets ^= 1u64 << to;
+        }
+        discoverers ^= from_board;
+    }
+
+    //8. Castling
+    if gen_type == GenType::Quiets
+        && attack_container.attacks_sum[enemy] & (1u64 << king_square) == 0u64
+    {
+        let blocked = all_pieces | attack_container.attacks_sum[enemy];
+        let (kingside, queenside, base) = if side == WHITE {
+            (g.castle_white_kingside, g.castle_white_queenside, 0)
+        } else {
+            (g.castle_black_kingside, g.castle_black_queenside, 56)
+        };
+        if kingside && blocked & (square(base + 5) | square(base + 6)) == 0u64 {
+            movelist.add_move(Move::new(king_square, base + 6, Move::CASTLE));
+        }
+        if queenside
+            && (blocked & (square(base + 2) | square(base + 3)) | all_pieces & square(base + 1))
+                == 0u64
+        {
+            movelist.add_move(Move::new(king_square, base + 2, Move::CASTLE));
+        }
+    }
+}
+
+//Exact for pseudo-legal moves from generate_pseudo_legal_moves, which already resolve checks
+#[inline(always)]
+pub fn is_legal(g: &GameState, mv: Move) -> bool {
+    let side = g.color_to_move;
+    let enemy = 1 - side;
+    let king_square = g.king_square(side);
+    let from = mv.from_square();
+    if from == king_square {
+        return true;
+    }
+    let to = mv.to_square();
+    let captured = if mv.is_en_passant() {
+        if side == WHITE {
+            to - 8
+        } else {
+            to + 8
+        }
+    } else {
+        to
+    };
+    let vacated = 1u64 << from | 1u64 << captured;
+    //Only a piece on a line between the king and an enemy slider can be pinned
+    let enemy_rooks = (g.pieces[ROOK][enemy] | g.pieces[QUEEN][enemy]) & !(1u64 << captured);
+    let enemy_bishops = (g.pieces[BISHOP][enemy] | g.pieces[QUEEN][enemy]) & !(1u64 << captured);
+    let rook_line = FREEFIELD_ROOK_ATTACKS[king_square] & vacated != 0u64
+        && FREEFIELD_ROOK_ATTACKS[king_square] & enemy_rooks != 0u64;
+    let bishop_line = FREEFIELD_BISHOP_ATTACKS[king_square] & vacated != 0u64
+        && FREEFIELD_BISHOP_ATTACKS[king_square] & enemy_bishops != 0u64;
+    if !rook_line && !bishop_line {
+        return true;
+    }
+    let occupancy = g.get_all_pieces() & !vacated | 1u64 << to;
+    !(rook_line && rook_attack(king_square, occupancy) & enemy_rooks != 0u64
+        || bishop_line && bishop_attack(king_square, occupancy) & enemy_bishops != 0u64)
+}
+
+#[inline(always)]
+fn add_pawn_moves(
+    g: &GameState,
+    movelist: &mut MoveList,
+    targets: u64,
+    shift: usize,
+    is_promotion: bool,
+) {
+    add_pawn_moves_to_movelist(g, movelist, targets, shift, false, is_promotion, 0u64);
+}
+
+#[inline(always)]
+fn add_pawn_captures(
+    g: &GameState,
+    movelist: &mut MoveList,
+    targets: u64,
+    shift: usize,
+    is_promotion: bool,
+) {
+    add_pawn_moves_to_movelist(g, movelist, targets, shift, true, is_promotion, 0u64);
+}
+
+//Pieces of the side to move standing alone between one of its sliders and the enemy king
+#[inline(always)]
+fn discovered_check_candidates(
+    g: &GameState,
+    enemy_king_square: usize,
+    all_pieces: u64,
+    side_pieces: u64,
+) -> u64 {
+    let side = g.color_to_move;
+    let mut candidates = 0u64;
+    let mut rooks = xray_rook_attacks(
+        rook_attack(enemy_king_square, all_pieces),
+        all_pieces,
+        side_pieces,
+        enemy_king_square,
+    ) & (g.pieces[ROOK][side] | g.pieces[QUEEN][side]);
+    while rooks != 0u64 {
+        let rook_square = rooks.trailing_zeros() as usize;
+        candidates |= ROOK_RAYS[enemy_king_square][rook_square] & side_pieces;
+        rooks ^= 1u64 << rook_square;
+    }
+    let mut bishops = xray_bishop_attacks(
+        bishop_attack(enemy_king_square, all_pieces),
+        all_pieces,
+        side_pieces,
+        enemy_king_square,
+    ) & (g.pieces[BISHOP][side] | g.pieces[QUEEN][side]);
+    while bishops != 0u64 {
+        let bishop_square = bishops.trailing_zeros() as usize;
+        candidates |= BISHOP_RAYS[enemy_king_square][bishop_square] & side_pieces;
+        bishops ^= 1u64 << bishop_square;
+    }
+    candidates
+}
+
+#[inline(always)]
+fn discovers_check(g: &GameState, from: usize, to: usize, all_pieces: u64) -> bool {
+    let side = g.color_to_move;
+    let enemy_king_square = g.king_square(1 - side);
+    let occupancy = all_pieces & !(1u64 << from) | 1u64 << to;
+    let sliders = !(1u64 << from);
+    rook_attack(enemy_king_square, occupancy)
+        & (g.pieces[ROOK][side] | g.pieces[QUEEN][side])
+        & sliders
+        != 0u64
+        || bishop_attack(enemy_king_square, occupancy)
+            & (g.pieces[BISHOP][side] | g.pieces[QUEEN][side])
+            & sliders
+            != 0u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_representation::game_state::GameMoveType;
+    use crate::move_generation::makemove::make_move;
+    use crate::move_generation::movegen::generate_moves;
+
+    const POSITIONS: [&str; 8] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+        "8/8/8/R2pP2k/8/8/8/4K3 w - d6 0 1",
+    ];
+
+    fn legal_moves(g: &GameState) -> Vec<u16> {
+        let mut movelist = MoveList::default();
+        generate_moves(
+            g,
+            false,
+            &mut movelist,
+            &GameStateAttackContainer::from_state(g),
+        );
+        let mut moves: Vec<u16> = movelist.moves().iter().map(|gmv| (gmv.0).0).collect();
+        moves.sort();
+        moves
+    }
+
+    fn staged_moves(g: &GameState, gen_types: &[GenType]) -> Vec<u16> {
+        let mut movelist = MoveList::default();
+        let attack_container = GameStateAttackContainer::from_state(g);
+        for gen_type in gen_types.iter() {
+            generate_pseudo_legal_moves(g, *gen_type, &mut movelist, &attack_container);
+        }
+        let mut moves: Vec<u16> = movelist
+            .moves()
+            .iter()
+            .filter(|gmv| is_legal(g, gmv.0))
+            .map(|gmv| (gmv.0).0)
+            .collect();
+        moves.sort();
+        moves
+    }
+
+    fn staged_perft(g: &GameState, depth: usize) -> u64 {
+        let gen_types: &[GenType] = if get_checkers(g, true) != 0u64 {
+            &[GenType::Evasions]
+        } else {
+            &[GenType::Captures, GenType::Quiets]
+        };
+        let mut movelist = MoveList::default();
+        let attack_container = GameStateAttackContainer::from_state(g);
+        for gen_type in gen_types.iter() {
+            generate_pseudo_legal_moves(g, *gen_type, &mut movelist, &attack_container);
+        }
+        let mut nodes = 0;
+        for gmv in movelist.moves() {
+            if is_legal(g, gmv.0) {
+                nodes += if depth == 1 {
+                    1
+                } else {
+                    staged_perft(&make_move(g, gmv.0.to_game_move(g)), depth - 1)
+                };
+            }
+        }
+        nodes
+    }
+
+    //Every stage of every position along the way has to match the legal generator
+    fn compare_stages(g: &GameState, depth: usize) {
+        let legal = legal_moves(g);
+        for mv in legal.iter() {
+            let mv = Move(*mv).to_game_move(g);
+            let checks = get_checkers(&make_move(g, mv), true) != 0u64;
+            assert_eq!(g.gives_check(mv), checks, "{} {:?}", g.to_fen(), mv);
+        }
+        if get_checkers(g, true) != 0u64 {
+            assert_eq!(
+                staged_moves(g, &[GenType::Evasions]),
+                legal,
+                "{}",
+                g.to_fen()
+            );
+        } else {
+            let captures = staged_moves(g, &[GenType::Captures]);
+            let quiets = staged_moves(g, &[GenType::Quiets]);
+            let mut all = [&captures[..], &quiets[..]].concat();
+            all.sort();
+            assert_eq!(all, legal, "{}", g.to_fen());
+            assert!(captures.iter().all(|mv| Move(*mv).is_capture()));
+            assert!(quiets.iter().all(|mv| !Move(*mv).is_capture()));
+            for mv in staged_moves(g, &[GenType::QuietChecks]) {
+                assert!(quiets.contains(&mv), "{}", g.to_fen());
+                let mv = Move(mv).to_game_move(g);
+                assert!(get_checkers(&make_move(g, mv), true) != 0u64);
+            }
+            //Quiet moves giving check, except castles and promotions
+            for mv in quiets.iter() {
+                let mv = Move(*mv);
+                let gmv = mv.to_game_move(g);
+                if mv.promotion_piece().is_none()
+                    && gmv.move_type != GameMoveType::Castle
+                    && get_checkers(&make_move(g, gmv), true) != 0u64
+                {
+                    assert!(
+                        staged_moves(g, &[GenType::QuietChecks]).contains(&mv.0),
+                        "{} {:?}",
+                        g.to_fen(),
+                        mv
+                    );
+                }
+            }
+        }
+        if depth > 1 {
+            for mv in legal {
+                compare_stages(&make_move(g, Move(mv).to_game_move(g)), depth - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn staged_perft_test() {
+        let expected = [
+            (POSITIONS[0], 4, 197_281),
+            (POSITIONS[1], 3, 97_862),
+            (POSITIONS[2], 5, 674_624),
+            (POSITIONS[3], 4, 422_333),
+            (POSITIONS[4], 3, 62_379),
+            (POSITIONS[5], 3, 89_890),
+        ];
+        for (fen, depth, nodes) in expected.iter() {
+            assert_eq!(staged_perft(&GameState::from_fen(fen), *depth), *nodes);
+        }
+    }
+
+    #[test]
+    fn stages_match_legal_moves() {
+        for fen in POSITIONS.iter() {
+            compare_stages(&GameState::from_fen(fen), 2);
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//Perft tooling to find bugs in the move generation
+use crate::board_representation::game_state::{GameMove, GameState, PAWN};
+use crate::board_representation::game_state_attack_container::GameStateAttackContainer;
+use crate::move_generation::makemove::make_move;
+use crate::move_generation::movegen::{self, MoveList};
+use crate::search::reserved_memory::{ReservedAttackContainer, ReservedMoveList};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+pub const DEFAULT_PERFT_HASH_SIZE: usize = 64; //IN MB
+
+#[derive(Copy, Clone)]
+pub struct PerftOptions {
+    pub hash_size: usize,
+    pub threads: usize,
+}
+impl Default for PerftOptions {
+    fn default() -> Self {
+        PerftOptions {
+            hash_size: DEFAULT_PERFT_HASH_SIZE,
+            threads: 1,
+        }
+    }
+}
+
+//Lockless shared table: the key is stored xored with the data, so torn writes never match
+pub struct PerftTable {
+    entries: Vec<[AtomicU64; 2]>,
+}
+impl PerftTable {
+    pub fn with_size(mb: usize) -> Self {
+        let len = mb * 1024 * 1024 / 16;
+        let mut entries = Vec::with_capacity(len);
+        for _ in 0..len {
+            entries.push([AtomicU64::new(0), AtomicU64::new(0)]);
+        }
+        PerftTable { entries }
+    }
+
+    #[inline(always)]
+    fn index(&self, hash: u64, depth: usize) -> usize {
+        ((hash ^ (depth as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)) % self.entries.len() as u64)
+            as usize
+    }
+
+    pub fn probe(&self, hash: u64, depth: usize) -> Option<u64> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let entry = &self.entries[self.index(hash, depth)];
+        let data = entry[1].load(Ordering::Relaxed);
+        if entry[0].load(Ordering::Relaxed) ^ data == hash && (data & 0xFF) as usize == depth {
+            Some(data >> 8)
+        } else {
+            None
+        }
+    }
+
+    pub fn store(&self, hash: u64, depth: usize, nodes: u64) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let entry = &self.entries[self.index(hash, depth)];
+        let data = nodes << 8 | depth as u64;
+        entry[0].store(hash ^ data, Ordering::Relaxed);
+        entry[1].store(data, Ordering::Relaxed);
+    }
+}
+
+pub fn legal_moves(g: &GameState) -> Vec<GameMove> {
+    let mut movelist = MoveList::default();
+    let attack_container = GameStateAttackContainer::from_state(g);
+    movegen::generate_moves(g, false, &mut movelist, &attack_container);
+    movelist.game_moves(g).collect()
+}
+
+fn count_nodes(
+    g: &GameState,
+    depth: usize,
+    table: &PerftTable,
+    movelist: &mut ReservedMoveList,
+    attack_container: &mut ReservedAttackContainer,
+) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    if let Some(nodes) = table.probe(g.hash, depth) {
+        return nodes;
+    }
+    attack_container.attack_containers[depth].write_state(g);
+    movegen::generate_moves(
+        g,
+        false,
+        &mut movelist.move_lists[depth],
+        &attack_container.attack_containers[depth],
+    );
+    let len = movelist.move_lists[depth].len();
+    //Bulk counting: the leaves are not made, they are only counted
+    if depth == 1 {
+        return len as u64;
+    }
+    let mut nodes = 0;
+    for i in 0..len {
+        let mv = movelist.move_lists[depth].moves()[i].0.to_game_move(g);
+        nodes += count_nodes(
+            &make_move(g, mv),
+            depth - 1,
+            table,
+            movelist,
+            attack_container,
+        );
+    }
+    table.store(g.hash, depth, nodes);
+    nodes
+}
+
+//Node count below every root move, the root moves are split among the threads
+pub fn divide(g: &GameState, depth: usize, options: PerftOptions) -> Vec<(GameMove, u64)> {
+    divide_with_table(
+        g,
+        depth,
+        options,
+        &Arc::new(PerftTable::with_size(options.hash_size)),
+    )
+}
+
+fn divide_with_table(
+    g: &GameState,
+    depth: usize,
+    options: PerftOptions,
+    table: &Arc<PerftTable>,
+) -> Vec<(GameMove, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    let root_moves = Arc::new(legal_moves(g));
+    let next_move = Arc::new(AtomicUsize::new(0));
+    let work = {
+        let g = g.clone();
+        let root_moves = Arc::clone(&root_moves);
+        let next_move = Arc::clone(&next_move);
+        let table = Arc::clone(table);
+        move || {
+            let mut movelist = ReservedMoveList::default();
+            let mut attack_container = ReservedAttackContainer::default();
+            let mut results = Vec::new();
+            loop {
+                let index = next_move.fetch_add(1, Ordering::Relaxed);
+                if index >= root_moves.len() {
+                    break results;
+                }
+                let next_g = make_move(&g, root_moves[index]);
+                let nodes = count_nodes(
+                    &next_g,
+                    depth - 1,
+                    &table,
+                    &mut movelist,
+                    &mut attack_container,
+                );
+                results.push((index, nodes));
+            }
+        }
+    };
+    //Helper threads are only spawned if asked for, so this also runs without thread support
+    let handles: Vec<thread::JoinHandle<Vec<(usize, u64)>>> = (1..options.threads)
+        .map(|_| thread::spawn(work.clone()))
+        .collect();
+    let mut results = work();
+    for handle in handles {
+        results.extend(handle.join().expect("Perft thread panicked!"));
+    }
+    let mut divide: Vec<(GameMove, u64)> = root_moves.iter().map(|mv| (*mv, 0)).collect();
+    for (index, nodes) in results {
+        divide[index].1 = nodes;
+    }
+    divide
+}
+
+pub fn count(g: &GameState, depth: usize, options: PerftOptions) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    divide(g, depth, options)
+        .iter()
+        .map(|(_, nodes)| nodes)
+        .sum()
+}
+
+//Move counters are ignored and the en passant square only counts if a pawn could capture there
+fn position_key(g: &GameState) -> String {
+    let fen = g.to_fen();
+    let fields: Vec<&str> = fen.split(' ').collect();
+    let pawns = g.pieces[PAWN][g.color_to_move];
+    let pawn_attacks = movegen::pawn_east_targets(g.color_to_move, pawns)
+        | movegen::pawn_west_targets(g.color_to_move, pawns);
+    let en_passant = if g.en_passant & pawn_attacks != 0u64 {
+        fields[3]
+    } else {
+        "-"
+    };
+    format!("{} {} {} {}", fields[0], fields[1], fields[2], en_passant)
+}
+
+//Reference node counts in the usual perft EPD format: <fen> ;D1 20 ;D2 400
+#[derive(Default)]
+pub struct PerftReference {
+    counts: HashMap<String, HashMap<usize, u64>>,
+}
+impl PerftReference {
+    pub fn parse(epd: &str) -> Result<Self, String> {
+        let mut reference = PerftReference::default();
+        for line in epd.lines().map(|line| line.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split(';');
+            let fen = fields.next().unwrap().trim();
+            //The move counters are optional in EPD
+            let fen = if fen.split_whitespace().count() == 4 {
+                format!("{} 0 1", fen)
+            } else {
+                fen.to_owned()
+            };
+            let g = GameState::try_from_fen(&fen).map_err(|e| e.to_string())?;
+            let counts = reference.counts.entry(position_key(&g)).or_default();
+            for field in fields {
+                let field = field.trim();
+                let mut parts = field.split_whitespace();
+                let depth = parts
+                    .next()
+                    .filter(|depth| depth.starts_with('D'))
+                    .and_then(|depth| depth[1..].parse::<usize>().ok());
+                let nodes = parts.next().and_then(|nodes| nodes.parse::<u64>().ok());
+                match (depth, nodes) {
+                    (Some(depth), Some(nodes)) => {
+                        counts.insert(depth, nodes);
+                    }
+                    _ => return Err(format!("Invalid perft count \"{}\" in: {}", field, line)),
+                }
+            }
+        }
+        Ok(reference)
+    }
+
+    pub fn nodes(&self, g: &GameState, depth: usize) -> Option<u64> {
+        self.counts
+            .get(&position_key(g))
+            .and_then(|counts| counts.get(&depth))
+            .copied()
+    }
+}
+
+pub struct PerftDifference {
+    //Moves from the root to the deepest position whose count differs
+    pub path: Vec<GameMove>,
+    pub fen: String,
+    pub depth: usize,
+    pub expected: u64,
+    pub found: u64,
+    pub divide: Vec<(GameMove, u64)>,
+}
+
+//Descends into the first move whose count differs from the reference, as long as the reference knows the position
+pub fn diff(
+    g: &GameState,
+    depth: usize,
+    reference: &PerftReference,
+    options: PerftOptions,
+) -> Result<Option<PerftDifference>, String> {
+    let expected = reference
+        .nodes(g, depth)
+        .ok_or_else(|| format!("No reference count at depth {} for {}", depth, g.to_fen()))?;
+    let table = Arc::new(PerftTable::with_size(options.hash_size));
+    let mut g = g.clone();
+    let mut depth = depth;
+    let mut path = Vec::new();
+    let mut expected = expected;
+    let mut divide = divide_with_table(&g, depth, options, &table);
+    let mut found = if depth == 0 {
+        1
+    } else {
+        divide.iter().map(|(_, nodes)| nodes).sum()
+    };
+    if found == expected {
+        return Ok(None);
+    }
+    'narrow: while depth > 1 {
+        for (mv, nodes) in divide.iter() {
+            let next_g = make_move(&g, *mv);
+            if let Some(child_expected) = reference.nodes(&next_g, depth - 1) {
+                if child_expected != *nodes {
+                    path.push(*mv);
+                    expected = child_expected;
+                    found = *nodes;
+                    depth -= 1;
+                    divide = divide_with_table(&next_g, depth, options, &table);
+                    g = next_g;
+                    continue 'narrow;
+                }
+            }
+        }
+        break;
+    }
+    Ok(Some(PerftDifference {
+        path,
+        fen: g.to_fen(),
+        depth,
+        expected,
+        found,
+        divide,
+    }))
+}
+
+//Reference lines for the position and every position up to plies moves deep, to be diffed against later
+pub fn reference_epd(g: &GameState, depth: usize, plies: usize, options: PerftOptions) -> String {
+    let table = Arc::new(PerftTable::with_size(options.hash_size));
+    let mut seen = HashSet::new();
+    let mut epd = String::new();
+    let mut positions = vec![g.clone()];
+    for ply in 0..=plies.min(depth) {
+        let mut next_positions = Vec::new();
+        for position in positions {
+            if !seen.insert(position_key(&position)) {
+                continue;
+            }
+            let divide = divide_with_table(&position, depth - ply, options, &table);
+            let nodes: u64 = if depth == ply {
+                1
+            } else {
+                divide.iter().map(|(_, nodes)| nodes).sum()
+            };
+            epd.push_str(&format!(
+                "{} ;D{} {}\n",
+                position_key(&position),
+                depth - ply,
+                nodes
+            ));
+            if ply < plies {
+                next_positions.extend(divide.iter().map(|(mv, _)| make_move(&position, *mv)));
+            }
+        }
+        positions = next_positions;
+    }
+    epd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn hashed_threaded_perft() {
+        let g = GameState::from_fen(KIWIPETE);
+        let options = PerftOptions {
+            hash_size: 1,
+            threads: 3,
+        };
+        assert_eq!(count(&g, 4, options), 4_085_603);
+        let unhashed = PerftOptions {
+            hash_size: 0,
+            threads: 1,
+        };
+        assert_eq!(count(&g, 3, unhashed), 97_862);
+        let divide = divide(&g, 2, options);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2039);
+    }
+
+    #[test]
+    fn diff_narrows_down() {
+        let g = GameState::standard();
+        let options = PerftOptions::default();
+        let epd = reference_epd(&g, 3, 2, options);
+        let reference = PerftReference::parse(&epd).unwrap();
+        assert_eq!(reference.nodes(&g, 3), Some(8902));
+        assert!(diff(&g, 3, &reference, options).unwrap().is_none());
+
+        //Pretend 1. e4 e5 has one more move
+        let e4 = legal_moves(&g)
+            .into_iter()
+            .find(|mv| format!("{:?}", mv) == "e2e4")
+            .unwrap();
+        let after_e4 = make_move(&g, e4);
+        let e5 = legal_moves(&after_e4)
+            .into_iter()
+            .find(|mv| format!("{:?}", mv) == "e7e5")
+            .unwrap();
+        let after_e5 = make_move(&after_e4, e5);
+        let mut epd = String::new();
+        for line in reference_epd(&g, 3, 2, options).lines() {
+            let key = line.split(" ;").next().unwrap().to_owned();
+            let keys = [&g, &after_e4, &after_e5]
+                .iter()
+                .map(|position| position_key(position))
+                .collect::<Vec<String>>();
+            if keys.contains(&key) {
+                let (fen, count) = line.split_at(line.rfind(' ').unwrap());
+                epd.push_str(&format!(
+                    "{} {}\n",
+                    fen,
+                    count.trim().parse::<u64>().unwrap() + 1
+                ));
+            } else {
+                epd.push_str(&format!("{}\n", line));
+            }
+        }
+        let reference = PerftReference::parse(&epd).unwrap();
+        let difference = diff(&g, 3, &reference, options).unwrap().unwrap();
+        assert_eq!(difference.path, vec![e4, e5]);
+        assert_eq!(difference.depth, 1);
+        assert_eq!(difference.found, legal_moves(&after_e5).len() as u64);
+        assert_eq!(difference.expected, difference.found + 1);
+        assert!(PerftReference::parse("8/8/8/8/8/8/8/K6k w - - ;D1 x").is_err());
+    }
+}
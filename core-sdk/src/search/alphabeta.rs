@@ -4,28 +4,30 @@ use super::*;
 use super::{MATE_SCORE, MAX_SEARCH_DEPTH, STANDARD_SCORE};
 use crate::evaluation::eval_game_state;
 use crate::move_generation::makemove::{make_move, make_nullmove};
+use crate::search::cache::CacheEntry;
+use crate::search::heuristics::{history_bonus, MAX_CAPTURES_TRIED};
 use crate::search::moveordering::{MoveOrderer, NORMAL_STAGES};
+use crate::search::parameters::SearchParams;
 use crate::search::searcher::Thread;
+use crate::search::smp::{abdada_defer, SMPStrategy, ABDADA_MIN_DEPTH};
+use crate::search::tracer::TraceDecision;
+
+pub fn principal_variation_search(p: CombinedSearchParameters, thread: &mut Thread) -> i16 {
+    thread.tracer.enter(&p, false);
+    let score = search_node(p, thread);
+    thread.tracer.exit(score, &thread.played_moves);
+    score
+}
 
-pub const FUTILITY_MARGIN: i16 = 90;
-pub const FUTILITY_DEPTH: i16 = 6;
-pub const STATIC_NULL_MOVE_MARGIN: i16 = 120;
-pub const STATIC_NULL_MOVE_DEPTH: i16 = 5;
-pub const NULL_MOVE_PRUNING_DEPTH: i16 = 3;
-pub const HISTORY_PRUNING_DEPTH: i16 = 2;
-pub const HISTORY_PRUNING_THRESHOLD: isize = 0;
-pub const SEE_PRUNING_DEPTH: i16 = 6;
-pub const SEE_PRUNING_CAPTURE_MULT: f64 = -23.;
-pub const SEE_PRUNING_QUIET_MULT: f64 = -23.;
-
-pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut Thread) -> i16 {
+fn search_node(mut p: CombinedSearchParameters, thread: &mut Thread) -> i16 {
     //Step 0. Prepare variables
     thread.search_statistics.add_normal_node(p.current_depth);
     clear_pv(p.current_depth, thread);
     let root = p.current_depth == 0;
     let is_pv_node = p.beta - p.alpha > 1;
+    let params = thread.uci_options.search_params;
     //Step 1. Check timeout and if stop flag is set, if we are main thread
-    if thread.search_statistics.nodes_searched % 4096 == 0 {
+    if thread.search_statistics.nodes_searched % thread.checkup_interval == 0 {
         checkup(thread)
     }
     if thread.search_statistics.nodes_searched % 8192 == 0 {
@@ -36,11 +38,13 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
         );
     }
     if thread.self_stop {
+        thread.tracer.decision(TraceDecision::Stopped);
         return STANDARD_SCORE;
     }
 
     //Step 2. Max Search depth reached
     if let SearchInstruction::StopSearching(res) = max_depth(&p, thread) {
+        thread.tracer.decision(TraceDecision::MaxDepth);
         return res;
     }
 
@@ -48,10 +52,12 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
     if !root {
         if let SearchInstruction::StopSearching(res) = check_for_draw(p.game_state, &thread.history)
         {
+            thread.tracer.decision(TraceDecision::Draw);
             return res;
         }
         //Mate distance pruning
         if let SearchInstruction::StopSearching(res) = mate_distance_pruning(&mut p) {
+            thread.tracer.decision(TraceDecision::MateDistance);
             return res;
         }
     }
@@ -73,6 +79,7 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
     if p.depth_left <= 0 {
         debug_assert_eq!(p.depth_left, 0);
         thread.search_statistics.add_q_root();
+        thread.tracer.decision(TraceDecision::Quiescence);
         return q_search(p, thread);
     }
 
@@ -82,7 +89,7 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
     //Step 8. TT Lookup
     let mut static_evaluation = None;
     let mut tt_move: Option<GameMove> = None;
-    if let SearchInstruction::StopSearching(res) = thread.itcs.cache().lookup(
+    if let SearchInstruction::StopSearching(res) = thread.cache.lookup(
         &p,
         &mut static_evaluation,
         &mut tt_move,
@@ -92,6 +99,8 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
         {
             thread.search_statistics.add_cache_hit_aj_replace_ns();
         }
+        thread.tracer.tt_hit();
+        thread.tracer.decision(TraceDecision::TTCutoff);
         return res;
     }
     #[cfg(feature = "search-statistics")]
@@ -100,13 +109,19 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
             thread.search_statistics.add_cache_hit_ns();
         }
     }
+    if tt_move.is_some() {
+        thread.tracer.tt_hit();
+    }
     thread
         .history
         .push(p.game_state.hash, p.game_state.half_moves == 0);
 
     //Step 9. Static Eval if needed
-    let prunable = !is_pv_node && !incheck;
+    let prunable = !is_pv_node && !incheck && p.excluded_move.is_none();
     make_eval(&p, thread, &mut static_evaluation, prunable);
+    if let Some(eval) = static_evaluation {
+        thread.tracer.static_eval(eval * p.color);
+    }
 
     //Step 10. Prunings
     if prunable {
@@ -114,12 +129,14 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
         if let SearchInstruction::StopSearching(res) =
             static_null_move_pruning(&p, thread, static_evaluation)
         {
+            thread.tracer.decision(TraceDecision::StaticNullMove);
             return res;
         }
         //Step 10.2 Null Move Forward Pruning
         if let SearchInstruction::StopSearching(res) =
             null_move_pruning(&p, thread, static_evaluation)
         {
+            thread.tracer.decision(TraceDecision::NullMove);
             return res;
         }
     }
@@ -129,29 +146,64 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
         if let SearchInstruction::StopSearching(res) =
             internal_iterative_deepening(&p, thread, &mut tt_move)
         {
+            thread.tracer.decision(TraceDecision::Stopped);
             return res;
         }
     }
 
     //Step 12. Futil Pruning and margin preparation
-    let futil_margin = prepare_futility_pruning(&p, static_evaluation);
+    let futil_margin = prepare_futility_pruning(&p, &params, static_evaluation);
+
+    //Step 13. Singular extension. The TT move is extended if all other moves fail low against a margin
+    // below its score. If they fail high even against beta, multi-cut prunes the node
+    let mut singular_move = None;
+    if let Some(mv) = tt_move {
+        if !root && p.excluded_move.is_none() && p.depth_left >= params.singular_extension_depth {
+            if let SearchInstruction::StopSearching(res) =
+                singular_extension(&p, thread, mv, &mut singular_move)
+            {
+                thread.tracer.decision(if thread.self_stop {
+                    TraceDecision::Stopped
+                } else {
+                    TraceDecision::MultiCut
+                });
+                return res;
+            }
+        }
+    }
 
     //Step 14. Iterate through all moves
     let mut current_max_score = STANDARD_SCORE;
     let mut index: usize = 0;
     let mut quiets_tried: usize = 0;
+    let mut captures_tried: usize = 0;
     let mut move_orderer = MoveOrderer {
         stage: 0,
         stages: &NORMAL_STAGES,
         gen_only_captures: false,
         has_legal_move: false,
     };
+    //ABDADA: Moves searched by other threads are deferred until all other moves are searched
+    let abdada = thread.uci_options.smp_strategy == SMPStrategy::ABDADA
+        && thread.uci_options.threads > 1
+        && p.depth_left >= ABDADA_MIN_DEPTH;
+    thread.deferred_moves[p.current_depth].clear();
+    let mut deferred_index = 0;
     loop {
-        let mv = move_orderer.next(thread, &p, pv_table_move, tt_move);
+        let mut mv = move_orderer.next(thread, &p, pv_table_move, tt_move);
+        if mv.is_none() {
+            mv = thread.deferred_moves[p.current_depth]
+                .get(deferred_index)
+                .copied();
+            deferred_index += 1;
+        }
         if mv.is_none() {
             break;
         }
         let (mv, move_score) = mv.unwrap(); //Move score is only set for bad_capture
+        if Some(mv) == p.excluded_move || (root && !thread.root_moves.contains(&mv)) {
+            continue;
+        }
 
         //Step 14.4. UCI Reporting at root
         //uci_report_move(&p, su, &mv, index);
@@ -169,10 +221,10 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
             && is_quiet_move
             && current_max_score > MATED_IN_MAX
             && (futil_margin <= p.alpha
-                || p.depth_left <= HISTORY_PRUNING_DEPTH
+                || p.depth_left <= params.history_pruning_depth
                     && thread.history_score[p.game_state.color_to_move][mv.from as usize]
                         [mv.to as usize]
-                        < HISTORY_PRUNING_THRESHOLD)
+                        < params.history_pruning_threshold as isize)
             && p.game_state.has_non_pawns(p.game_state.color_to_move)
             && !gives_check
         {
@@ -182,26 +234,33 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
                 {
                     thread.search_statistics.add_futil_pruning();
                 }
+                thread
+                    .tracer
+                    .pruned(&p, mv, TraceDecision::FutilityPruned, &thread.played_moves);
                 index += 1;
                 continue;
             }
             //Step 14.6. History Pruning. Skip quiet moves in low depths if they are below threshold
-            if p.depth_left <= HISTORY_PRUNING_DEPTH
+            if p.depth_left <= params.history_pruning_depth
                 && thread.history_score[p.game_state.color_to_move][mv.from as usize]
                     [mv.to as usize]
-                    < HISTORY_PRUNING_THRESHOLD
+                    < params.history_pruning_threshold as isize
             {
                 #[cfg(feature = "search-statistics")]
                 {
                     thread.search_statistics.add_history_pruned();
                 }
+                thread
+                    .tracer
+                    .pruned(&p, mv, TraceDecision::HistoryPruned, &thread.played_moves);
                 index += 1;
                 continue;
             }
             //Step 14.7 SEE Pruning. Skip quiet moves which have negative SEE Score on low depths
-            if p.depth_left <= SEE_PRUNING_DEPTH && false {
+            if p.depth_left <= params.see_pruning_depth && false {
                 let see_value = 0.;
-                if see_value < SEE_PRUNING_QUIET_MULT * (p.depth_left as f64 + 3.) {
+                if see_value < f64::from(params.see_pruning_quiet_mult) * (p.depth_left as f64 + 3.)
+                {
                     index += 1;
                     continue;
                 }
@@ -209,18 +268,24 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
         } else if !root
             && isc
             && current_max_score > MATED_IN_MAX
-            && p.depth_left <= SEE_PRUNING_DEPTH
-            && move_score < SEE_PRUNING_CAPTURE_MULT * p.depth_left as f64 * p.depth_left as f64
+            && p.depth_left <= params.see_pruning_depth
+            && move_score
+                < i32::from(params.see_pruning_capture_mult)
+                    * i32::from(p.depth_left)
+                    * i32::from(p.depth_left)
             && p.game_state.has_non_pawns(p.game_state.color_to_move)
             && !gives_check
         {
+            thread
+                .tracer
+                .pruned(&p, mv, TraceDecision::SEEPruned, &thread.played_moves);
             index += 1;
             continue;
         }
         //Step 14.7. Late move reductions. Compute reduction based on move type, node type and depth
         let reduction = if p.depth_left > 2
             && !incheck
-            && (!isc || move_score < 0.)
+            && (!isc || move_score < 0)
             && index >= 2
             && (!root || index >= 5)
         {
@@ -230,18 +295,33 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
         };
 
         let next_state = make_move(p.game_state, mv);
+        if abdada && abdada_defer(&thread.cache, next_state.hash, index, deferred_index > 0) {
+            thread.deferred_moves[p.current_depth].push((mv, move_score));
+            continue;
+        }
+        if abdada {
+            thread.cache.set_busy(next_state.hash, true);
+        }
         //Step 14.8. Search the moves
+        thread.played_moves[p.current_depth] = Some(mv);
+        let new_depth = if Some(mv) == singular_move {
+            p.depth_left
+        } else {
+            p.depth_left - 1
+        };
+        let nodes_before = thread.search_statistics.nodes_searched;
         let mut following_score: i16;
         if p.depth_left <= 2 || !is_pv_node || index == 0 {
             //Step 14.8.1 Full move window. This is done in pv nodes when index == 0 or depth left <= 2, e.g. the first move. If we are in a pv node,
             // reduction is 0 and we really search the full window (without research). Else we are in a zero window, and the full window search is just
             // zero window again (with reduction). If the reduced zero window search raises alpha, research without reduction
             debug_assert!(!is_pv_node || reduction == 0);
+            thread.tracer.reduce(reduction);
             following_score = -principal_variation_search(
                 CombinedSearchParameters::from(
                     -p.beta,
                     -p.alpha,
-                    p.depth_left - 1 - reduction,
+                    new_depth - reduction,
                     &next_state,
                     -p.color,
                     p.current_depth + 1,
@@ -253,7 +333,7 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
                     CombinedSearchParameters::from(
                         -p.beta,
                         -p.alpha,
-                        p.depth_left - 1,
+                        new_depth,
                         &next_state,
                         -p.color,
                         p.current_depth + 1,
@@ -264,11 +344,12 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
         } else {
             //We are in a pv node and search with zero window all moves except the first (and with reduction). If
             // the reduced zero window search raises alpha, research
+            thread.tracer.reduce(reduction);
             following_score = -principal_variation_search(
                 CombinedSearchParameters::from(
                     -p.alpha - 1,
                     -p.alpha,
-                    p.depth_left - 1 - reduction,
+                    new_depth - reduction,
                     &next_state,
                     -p.color,
                     p.current_depth + 1,
@@ -280,7 +361,7 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
                     CombinedSearchParameters::from(
                         -p.beta,
                         -p.alpha,
-                        p.depth_left - 1,
+                        new_depth,
                         &next_state,
                         -p.color,
                         p.current_depth + 1,
@@ -290,9 +371,20 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
             }
         }
 
+        if abdada {
+            thread.cache.set_busy(next_state.hash, false);
+        }
+        let move_nodes = thread.search_statistics.nodes_searched - nodes_before;
+        if root {
+            thread.root_nodes += move_nodes;
+        }
+
         //Step 14.9. Update principal variation if move raised current best moves score (does not have to raise alpha)
         // Also update UCI pv
         if following_score > current_max_score && !thread.self_stop {
+            if root {
+                thread.best_move_nodes = move_nodes;
+            }
             thread.pv_table[p.current_depth].pv[0] = Some(mv);
             current_max_score = following_score;
             concatenate_pv(p.current_depth, thread);
@@ -315,11 +407,19 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
             {
                 thread.search_statistics.add_normal_node_beta_cutoff(index);
             }
+            thread.tracer.decision(TraceDecision::BetaCutoff);
             if !isc {
                 update_quiet_cutoff(&p, thread, mv, quiets_tried);
+            } else {
+                update_capture_cutoff(&p, thread, mv, captures_tried);
             }
             break;
-        } else if !isc {
+        } else if isc {
+            if captures_tried < MAX_CAPTURES_TRIED {
+                thread.captures_tried[p.current_depth][captures_tried] = Some(mv);
+                captures_tried += 1;
+            }
+        } else {
             //Step 14.12 Move does not cause beta cutoff, add to quiet moves tried and update butterfly heuristic
             thread.quiets_tried[p.current_depth][quiets_tried] = Some(mv);
             quiets_tried += 1;
@@ -332,6 +432,10 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
     }
 
     thread.history.pop();
+    //The excluded move may have been the only legal one
+    if p.excluded_move.is_some() && current_max_score == STANDARD_SCORE {
+        return p.alpha;
+    }
 
     debug_assert!(
         !move_orderer.has_legal_move || current_max_score > STANDARD_SCORE || thread.self_stop
@@ -352,7 +456,7 @@ pub fn principal_variation_search(mut p: CombinedSearchParameters, thread: &mut
 
     //Step 16. Make TT Entry
     if !thread.self_stop {
-        thread.itcs.cache().insert(
+        thread.cache.insert(
             &p,
             thread.pv_table[p.current_depth].pv[0].expect("Can't unwrap move for TT"),
             current_max_score,
@@ -440,10 +544,12 @@ pub fn make_eval(
     static_evaluation: &mut Option<i16>,
     prunable: bool,
 ) {
+    let params = &thread.uci_options.search_params;
     if static_evaluation.is_none()
         && (prunable
-            && (p.depth_left <= STATIC_NULL_MOVE_DEPTH || p.depth_left >= NULL_MOVE_PRUNING_DEPTH)
-            || p.depth_left <= FUTILITY_DEPTH)
+            && (p.depth_left <= params.static_null_move_depth
+                || p.depth_left >= params.null_move_pruning_depth)
+            || p.depth_left <= params.futility_depth)
     {
         let eval_res = eval_game_state(
             p.game_state,
@@ -465,9 +571,10 @@ pub fn static_null_move_pruning(
     thread: &mut Thread,
     static_evaluation: Option<i16>,
 ) -> SearchInstruction {
-    if p.depth_left <= STATIC_NULL_MOVE_DEPTH
+    let params = thread.uci_options.search_params;
+    if p.depth_left <= params.static_null_move_depth
         && static_evaluation.expect("Static null move") * p.color
-            - STATIC_NULL_MOVE_MARGIN * p.depth_left
+            - params.static_null_move_margin * p.depth_left
             >= p.beta
     {
         thread.history.pop();
@@ -477,7 +584,7 @@ pub fn static_null_move_pruning(
         }
         SearchInstruction::StopSearching(
             static_evaluation.expect("Static null move 2") * p.color
-                - STATIC_NULL_MOVE_DEPTH * p.depth_left,
+                - params.static_null_move_depth * p.depth_left,
         )
     } else {
         SearchInstruction::ContinueSearching
@@ -490,11 +597,12 @@ pub fn null_move_pruning(
     thread: &mut Thread,
     static_evaluation: Option<i16>,
 ) -> SearchInstruction {
-    if p.depth_left >= NULL_MOVE_PRUNING_DEPTH
+    if p.depth_left >= thread.uci_options.search_params.null_move_pruning_depth
         && p.game_state.has_non_pawns(p.game_state.color_to_move)
         && static_evaluation.expect("null move static") * p.color >= p.beta
     {
         let nextgs = make_nullmove(p.game_state);
+        thread.played_moves[p.current_depth] = None;
         let rat = -principal_variation_search(
             CombinedSearchParameters::from(
                 -p.beta,
@@ -550,14 +658,76 @@ pub fn internal_iterative_deepening(
     SearchInstruction::ContinueSearching
 }
 
+#[inline(always)]
+pub fn singular_extension(
+    p: &CombinedSearchParameters,
+    thread: &mut Thread,
+    tt_move: GameMove,
+    singular_move: &mut Option<GameMove>,
+) -> SearchInstruction {
+    //The TT score has to be a lower bound of a search which is not much shallower
+    let ce = match thread.cache.probe(p.game_state.hash) {
+        Some(ce) => ce,
+        None => return SearchInstruction::ContinueSearching,
+    };
+    let params = thread.uci_options.search_params;
+    if ce.alpha
+        || (ce.depth as i16) < p.depth_left - params.singular_extension_tt_depth
+        || ce.score.abs() >= -MATED_IN_MAX
+        || CacheEntry::u16_to_mv(ce.mv, p.game_state) != tt_move
+    {
+        return SearchInstruction::ContinueSearching;
+    }
+    let singular_beta = ce.score - params.singular_extension_margin * p.depth_left;
+    thread.history.pop();
+    let score = principal_variation_search(
+        CombinedSearchParameters::from(
+            singular_beta - 1,
+            singular_beta,
+            (p.depth_left - 1) / 2,
+            &p.game_state,
+            p.color,
+            p.current_depth,
+        )
+        .excluding(tt_move),
+        thread,
+    );
+    #[cfg(feature = "search-statistics")]
+    {
+        thread.search_statistics.add_singular_search();
+    }
+    if thread.self_stop {
+        return SearchInstruction::StopSearching(STANDARD_SCORE);
+    }
+    if score < singular_beta {
+        #[cfg(feature = "search-statistics")]
+        {
+            thread.search_statistics.add_singular_extension();
+        }
+        *singular_move = Some(tt_move);
+    } else if singular_beta >= p.beta {
+        //Multi-cut: the TT move and at least one other move fail high
+        #[cfg(feature = "search-statistics")]
+        {
+            thread.search_statistics.add_multi_cut();
+        }
+        return SearchInstruction::StopSearching(singular_beta);
+    }
+    thread
+        .history
+        .push(p.game_state.hash, p.game_state.half_moves == 0);
+    SearchInstruction::ContinueSearching
+}
+
 #[inline(always)]
 pub fn prepare_futility_pruning(
     p: &CombinedSearchParameters,
+    params: &SearchParams,
     static_evaluation: Option<i16>,
 ) -> i16 {
-    let futil_pruning = p.depth_left <= FUTILITY_DEPTH && p.current_depth > 0;
+    let futil_pruning = p.depth_left <= params.futility_depth && p.current_depth > 0;
     if futil_pruning {
-        static_evaluation.expect("Futil pruning") * p.color + p.depth_left * FUTILITY_MARGIN
+        static_evaluation.expect("Futil pruning") * p.color + p.depth_left * params.futility_margin
     } else {
         MATE_SCORE
     }
@@ -628,6 +798,18 @@ pub fn update_quiet_cutoff(
         p.depth_left as isize,
         p.game_state.color_to_move,
     );
+    let color = p.game_state.color_to_move;
+    update_continuation_history(
+        thread,
+        p.current_depth,
+        color,
+        mv,
+        history_bonus(p.depth_left),
+    );
+    if let Some(previous) = thread.previous_move(p.current_depth, 1, color) {
+        thread.counter_moves[previous] = Some(Move::from(mv));
+    }
+    let mv = Move::from(mv);
     if let Some(s) = thread.killer_moves[p.current_depth][0] {
         if mv == s {
             return;
@@ -651,9 +833,42 @@ pub fn decrement_history_quiets(
     depth_left: isize,
     side_to_move: usize,
 ) {
+    let malus = -history_bonus(depth_left as i16);
     for i in 0..quiets_tried {
-        let mv = thread.quiets_tried[current_depth][i].as_ref().unwrap();
+        let mv = thread.quiets_tried[current_depth][i].unwrap();
         thread.history_score[side_to_move][mv.from as usize][mv.to as usize] -=
             depth_left * depth_left;
+        update_continuation_history(thread, current_depth, side_to_move, mv, malus);
+    }
+}
+
+#[inline(always)]
+pub fn update_continuation_history(
+    thread: &mut Thread,
+    current_depth: usize,
+    side_to_move: usize,
+    mv: GameMove,
+    bonus: i32,
+) {
+    for plies in 1..=2 {
+        if let Some(previous) = thread.previous_move(current_depth, plies, side_to_move) {
+            thread.continuation_history[plies - 1].update(previous, mv, side_to_move, bonus);
+        }
+    }
+}
+
+#[inline(always)]
+pub fn update_capture_cutoff(
+    p: &CombinedSearchParameters,
+    thread: &mut Thread,
+    mv: GameMove,
+    captures_tried: usize,
+) {
+    let color = p.game_state.color_to_move;
+    let bonus = history_bonus(p.depth_left);
+    thread.capture_history.update(mv, color, bonus);
+    for i in 0..captures_tried {
+        let tried = thread.captures_tried[p.current_depth][i].unwrap();
+        thread.capture_history.update(tried, color, -bonus);
     }
 }
@@ -1,27 +1,28 @@
-use crate::board_representation::game_state::{
-    GameMove, GameMoveType, GameState, PieceType, BISHOP, KNIGHT, PAWN, QUEEN, ROOK,
-};
+use crate::board_representation::game_state::{GameMove, GameState, Move};
 use crate::search::{CombinedSearchParameters, SearchInstruction};
-use std::cell::UnsafeCell;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+#[cfg(target_arch = "wasm32")]
+type AtomicU64 = atomic::Atomic<u64>;
 
 pub const INVALID_STATIC_EVALUATION: i16 = -32768;
 pub const DEFAULT_HASH_SIZE: usize = 256; //IN MB
 pub const MIN_HASH_SIZE: usize = 0; //IN MB
 pub const MAX_HASH_SIZE: usize = 131_072; //IN MB
 
+//Shared by all search threads without locking, see AtomicCacheEntry
 pub struct Cache {
     pub entries: usize,
     pub buckets: usize,
-    pub cache: UnsafeCell<Vec<CacheBucket>>,
+    pub cache: Vec<CacheBucket>,
 }
 
-unsafe impl std::marker::Sync for Cache {}
-
 impl Cache {
     pub fn with_size_threaded(mb_size: usize, num_threads: usize) -> Self {
-        let buckets = 1024 * 1024 * mb_size / 64;
+        let buckets = 1024 * 1024 * mb_size / std::mem::size_of::<CacheBucket>();
         let entries = buckets * 3;
-        let cache = UnsafeCell::new(Cache::get_init_cache(buckets, num_threads));
+        let cache = Cache::get_init_cache(buckets, num_threads);
         Cache {
             entries,
             buckets,
@@ -34,84 +35,87 @@ impl Cache {
         // This is relevant for events like TCEC, where huge memory is allocated (like 64GB),
         // and a startup time of >1min is unacceptable.
         let mut cache_vec: Vec<CacheBucket> = Vec::with_capacity(buckets);
-        unsafe {
-            cache_vec.set_len(buckets);
-            let chunksize = (buckets + num_threads - 1) / num_threads;
-
-            let mut ptr = cache_vec.as_mut_ptr();
-            // let mut handles = Vec::new();
-
-            for t in 0..num_threads {
-                // The last chunk may be shorter.
-                let this_chunk = chunksize.min(buckets - t * chunksize);
-
-                // circumvent the fact that raw pointers are not Send
-                let w = PtrWrapper { p: ptr.clone() };
-                // handles.push(std::thread::spawn(move || {
-                    let mut inner_ptr = w.p;
-                    for _ in 0..this_chunk {
-                        *inner_ptr = CacheBucket::default();
-                        inner_ptr = inner_ptr.offset(1);
-                    }
-                // }));
-                ptr = ptr.offset(chunksize as isize);
-            }
-
-            // for handle in handles {
-            //     handle
-            //         .join()
-            //         .expect("Could not unwrap handle while initializing the cache!");
-            // }
-        }
+        let chunksize = Cache::chunksize(buckets, num_threads);
+        in_parallel(
+            cache_vec.spare_capacity_mut()[..buckets].chunks_mut(chunksize),
+            |chunk| {
+                for bucket in chunk {
+                    bucket.write(CacheBucket::default());
+                }
+            },
+        );
+        // All buckets were initialized above
+        unsafe { cache_vec.set_len(buckets) };
         cache_vec
     }
 
+    fn chunksize(buckets: usize, num_threads: usize) -> usize {
+        // The last chunk may be shorter.
+        buckets.div_ceil(num_threads.max(1)).max(1)
+    }
+
     pub fn fill_status(&self) -> usize {
         if self.entries < 1000 {
             return 1000;
         }
-        //Count bottom 500 entries
-        let mut counted_entries = 0;
-        let mut full = 0;
-
-        let mut index = 0;
-        while index < unsafe { (&*self.cache.get()).len() } && counted_entries < 500 {
-            let bucket = unsafe { (&*self.cache.get()).get(index).unwrap() };
-            index += 1;
-            full += bucket.fill_status();
-            counted_entries += 3;
-        }
-        //Count upper 500 entries
-        let mut index = unsafe { (&*self.cache.get()).len() - 1 };
-        while counted_entries < 1000 {
-            let bucket = unsafe { (&*self.cache.get()).get(index).unwrap() };
-            debug_assert!(index > 0);
-            full += bucket.fill_status();
-            counted_entries += 3;
-            if index == 0 {
-                break;
-            }
-            index -= 1;
-        }
-        (full as f64 / counted_entries as f64 * 1000.0) as usize
+        //Count bottom 500 and upper 500 entries
+        let sample_buckets = (500 + 2) / 3;
+        let full: usize = self
+            .cache
+            .iter()
+            .take(sample_buckets)
+            .chain(self.cache.iter().rev().take(sample_buckets))
+            .map(CacheBucket::fill_status)
+            .sum();
+        (full as f64 / (sample_buckets * 6) as f64 * 1000.0) as usize
     }
 
     pub fn clear_threaded(&self, num_threads: usize) {
-        unsafe {
-            *self.cache.get() = Cache::get_init_cache(self.buckets, num_threads);
-        }
+        in_parallel(
+            self.cache
+                .chunks(Cache::chunksize(self.buckets, num_threads)),
+            |chunk| chunk.iter().for_each(CacheBucket::clear),
+        );
+    }
+
+    #[inline(always)]
+    fn bucket(&self, hash: u64) -> &CacheBucket {
+        &self.cache[hash as usize % self.buckets]
     }
 
     pub fn age_entry(&self, hash: u64, new_age: u16) {
-        unsafe {
-            (&mut *self.cache.get())
-                .get_unchecked_mut(hash as usize % self.buckets)
-                .age_entry(hash, new_age);
+        self.bucket(hash)
+            .update_entry(hash, |entry| entry.plies_played = new_age);
+    }
+
+    //ABDADA: Only positions which already have an entry can be marked as busy
+    pub fn set_busy(&self, hash: u64, busy: bool) {
+        if self.entries == 0 {
+            return;
+        }
+        self.bucket(hash)
+            .update_entry(hash, |entry| entry.busy = busy);
+    }
+
+    pub fn is_busy(&self, hash: u64) -> bool {
+        if self.entries == 0 {
+            return false;
         }
+        self.bucket(hash)
+            .probe(hash)
+            .map(|entry| entry.busy)
+            .unwrap_or(false)
     }
 
-    pub fn get(&self, hash: u64) -> CacheBucket {
-        unsafe { *(&*self.cache.get()).get_unchecked(hash as usize % self.buckets) }
+    pub fn get(&self, hash: u64) -> &CacheBucket {
+        self.bucket(hash)
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<CacheEntry> {
+        if self.entries == 0 {
+            return None;
+        }
+        self.bucket(hash).probe(hash)
     }
 
     pub fn insert(
@@ -123,22 +127,18 @@ impl Cache {
         root_plies_played: usize,
         static_evaluation: Option<i16>,
     ) {
-        if self.entries == 0 {
+        //Singular extension verification searches share the hash with their node
+        if self.entries == 0 || p.excluded_move.is_some() {
             return;
         }
-        let index = p.game_state.hash as usize % self.buckets;
-        unsafe {
-            (&mut *self.cache.get())
-                .get_unchecked_mut(index)
-                .replace_entry(
-                    p,
-                    mv,
-                    score,
-                    original_alpha,
-                    root_plies_played,
-                    static_evaluation,
-                );
-        };
+        self.bucket(p.game_state.hash).replace_entry(
+            p,
+            mv,
+            score,
+            original_alpha,
+            root_plies_played,
+            static_evaluation,
+        );
     }
 
     pub fn lookup(
@@ -148,7 +148,7 @@ impl Cache {
         tt_move: &mut Option<GameMove>,
         root_plies: usize,
     ) -> SearchInstruction {
-        if self.entries == 0 {
+        if self.entries == 0 || p.excluded_move.is_some() {
             return SearchInstruction::ContinueSearching;
         }
         let ce = self.get(p.game_state.hash).probe(p.game_state.hash);
@@ -175,12 +175,46 @@ impl Cache {
 }
 
 //#[repr(align(64))]
-#[derive(Copy, Clone)]
-pub struct CacheBucket([CacheEntry; 3]);
+#[derive(Default)]
+pub struct CacheBucket([AtomicCacheEntry; 3]);
 
 impl CacheBucket {
+    pub fn load(&self) -> [CacheEntry; 3] {
+        [self.0[0].load(), self.0[1].load(), self.0[2].load()]
+    }
+
+    //Works on a snapshot of the bucket and only writes back the entries that changed.
+    //Like update_entry, this is not atomic over the bucket, see there
     pub fn replace_entry(
-        &mut self,
+        &self,
+        p: &CombinedSearchParameters,
+        mv: GameMove,
+        score: i16,
+        original_alpha: i16,
+        root_plies_played: usize,
+        static_evaluation: Option<i16>,
+    ) -> bool {
+        let before = self.load();
+        let mut entries = before;
+        let res = CacheBucket::replace_in(
+            &mut entries,
+            p,
+            mv,
+            score,
+            original_alpha,
+            root_plies_played,
+            static_evaluation,
+        );
+        for i in 0..3 {
+            if entries[i] != before[i] {
+                self.0[i].store(&entries[i]);
+            }
+        }
+        res
+    }
+
+    fn replace_in(
+        entries: &mut [CacheEntry; 3],
         p: &CombinedSearchParameters,
         mv: GameMove,
         score: i16,
@@ -215,46 +249,46 @@ impl CacheBucket {
             }
         };
 
-        if self.0[0].is_invalid()
-            || self.0[0].plies_played < root_plies_played as u16
-            || self.0[0].validate_hash(p.game_state.hash)
+        if entries[0].is_invalid()
+            || entries[0].plies_played < root_plies_played as u16
+            || entries[0].validate_hash(p.game_state.hash)
         {
-            let res = self.0[0].is_invalid();
-            renew_entry(&mut self.0[0]);
+            let res = entries[0].is_invalid();
+            renew_entry(&mut entries[0]);
             return res;
-        } else if self.0[1].is_invalid()
-            || self.0[1].plies_played < root_plies_played as u16
-            || self.0[1].validate_hash(p.game_state.hash)
+        } else if entries[1].is_invalid()
+            || entries[1].plies_played < root_plies_played as u16
+            || entries[1].validate_hash(p.game_state.hash)
         {
-            let res = self.0[1].is_invalid();
-            renew_entry(&mut self.0[1]);
-            self.0.swap(0, 1);
+            let res = entries[1].is_invalid();
+            renew_entry(&mut entries[1]);
+            entries.swap(0, 1);
             return res;
-        } else if self.0[2].is_invalid()
-            || self.0[2].plies_played < root_plies_played as u16
-            || self.0[2].validate_hash(p.game_state.hash)
+        } else if entries[2].is_invalid()
+            || entries[2].plies_played < root_plies_played as u16
+            || entries[2].validate_hash(p.game_state.hash)
         {
-            let res = self.0[2].is_invalid();
-            renew_entry(&mut self.0[2]);
-            self.0.swap(0, 2);
-            self.0.swap(1, 2);
+            let res = entries[2].is_invalid();
+            renew_entry(&mut entries[2]);
+            entries.swap(0, 2);
+            entries.swap(1, 2);
             return res;
         }
-        let mut min_score = self.0[2].get_score();
+        let mut min_score = entries[2].get_score();
         let mut min_entry = 2;
 
-        if self.0[1].get_score() < min_score {
-            min_score = self.0[1].get_score();
+        if entries[1].get_score() < min_score {
+            min_score = entries[1].get_score();
             min_entry = 1;
         }
-        if self.0[0].get_score() < min_score {
-            min_score = self.0[0].get_score();
+        if entries[0].get_score() < min_score {
+            min_score = entries[0].get_score();
             min_entry = 0;
         }
 
         let new_score = p.depth_left as f64 * if pv_node { 1. } else { 0.7 };
         if new_score >= min_score {
-            write_entry(&mut self.0[min_entry]);
+            write_entry(&mut entries[min_entry]);
         }
         false
     }
@@ -263,49 +297,79 @@ impl CacheBucket {
         if hash == 0u64 {
             return None;
         }
-        if self.0[0].validate_hash(hash) {
-            return Some(self.0[0]);
-        } else if self.0[1].validate_hash(hash) {
-            return Some(self.0[1]);
-        } else if self.0[2].validate_hash(hash) {
-            return Some(self.0[2]);
-        }
-        None
+        self.load()
+            .iter()
+            .find(|entry| entry.validate_hash(hash))
+            .copied()
     }
 
-    pub fn age_entry(&mut self, hash: u64, new_age: u16) {
-        if self.0[0].validate_hash(hash) {
-            self.0[0].plies_played = new_age;
-        } else if self.0[1].validate_hash(hash) {
-            self.0[1].plies_played = new_age;
-        } else if self.0[2].validate_hash(hash) {
-            self.0[2].plies_played = new_age;
+    //Load, update and store are separate steps, so a concurrent write to the same entry may be
+    //lost, in either direction. This is accepted, as for every other cache write: a lost age
+    //update only lets the entry be replaced a little later or earlier, a lost busy flag only
+    //makes ABDADA search a move twice or defer it once more. The xored key keeps such races
+    //from ever producing an entry which validates for the wrong position
+    pub fn update_entry<F: FnOnce(&mut CacheEntry)>(&self, hash: u64, update: F) {
+        for atomic_entry in self.0.iter() {
+            let mut entry = atomic_entry.load();
+            if entry.validate_hash(hash) {
+                update(&mut entry);
+                atomic_entry.store(&entry);
+                return;
+            }
         }
     }
 
     pub fn fill_status(&self) -> usize {
-        (if self.0[0].is_invalid() { 0 } else { 1 })
-            + (if self.0[1].is_invalid() { 0 } else { 1 })
-            + (if self.0[2].is_invalid() { 0 } else { 1 })
+        self.load()
+            .iter()
+            .filter(|entry| !entry.is_invalid())
+            .count()
+    }
+
+    pub fn clear(&self) {
+        self.0.iter().for_each(AtomicCacheEntry::clear);
     }
 }
-impl Default for CacheBucket {
-    fn default() -> Self {
-        CacheBucket([CacheEntry::invalid(); 3])
+
+//Lockless hashing: The key word is stored xored with the data word. An entry torn apart by
+//concurrent writes then fails the hash validation, instead of mixing up two positions
+#[derive(Default)]
+pub struct AtomicCacheEntry {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl AtomicCacheEntry {
+    #[inline(always)]
+    pub fn load(&self) -> CacheEntry {
+        let data = self.data.load(Ordering::Relaxed);
+        let key = self.key.load(Ordering::Relaxed) ^ data;
+        CacheEntry::unpack(key, data)
+    }
+
+    #[inline(always)]
+    pub fn store(&self, entry: &CacheEntry) {
+        let (key, data) = entry.pack();
+        self.key.store(key ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        self.key.store(0, Ordering::Relaxed);
+        self.data.store(0, Ordering::Relaxed);
     }
 }
 
-#[repr(C)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub struct CacheEntry {
     pub alpha: bool,
     pub beta: bool,
     pub pv_node: bool,
+    pub busy: bool, //Some thread is searching the position right now
     pub depth: i8,
     pub plies_played: u16,
     pub score: i16,
-    pub upper_hash: u32,
-    pub lower_hash: u32,
+    pub upper_hash: u64, //Upper 48 bits of the hash
     pub mv: u16,
     pub static_evaluation: i16,
 }
@@ -316,8 +380,7 @@ impl CacheEntry {
     }
 
     pub fn validate_hash(&self, hash: u64) -> bool {
-        (self.upper_hash as u64) == (hash >> 32)
-            && ((self.lower_hash ^ self.mv as u32) as u64) == (hash & 0xFFFF_FFFF)
+        self.upper_hash == (hash >> 16) && !self.is_invalid()
     }
     //I know this is not idiomatic, but it saves memory...
     pub fn is_invalid(&self) -> bool {
@@ -326,7 +389,6 @@ impl CacheEntry {
     pub fn invalid() -> CacheEntry {
         CacheEntry {
             upper_hash: 0,
-            lower_hash: 0,
             depth: 0,
             plies_played: 0,
             score: 0,
@@ -335,8 +397,10 @@ impl CacheEntry {
             mv: 0,
             static_evaluation: INVALID_STATIC_EVALUATION,
             pv_node: false,
+            busy: false,
         }
     }
+    #[allow(clippy::too_many_arguments)]
     pub fn write(
         &mut self,
         hash: u64,
@@ -349,16 +413,15 @@ impl CacheEntry {
         beta: bool,
         mv: GameMove,
     ) {
-        let mv = CacheEntry::mv_to_u16(mv);
-        self.upper_hash = (hash >> 32) as u32;
-        self.lower_hash = (hash & 0xFFFF_FFFF) as u32 ^ mv as u32;
+        self.upper_hash = hash >> 16;
         self.depth = depth as i8;
         self.plies_played = plies_played;
         self.score = score;
         self.alpha = alpha;
         self.beta = beta;
         self.pv_node = pv_node;
-        self.mv = mv;
+        self.busy = false;
+        self.mv = CacheEntry::mv_to_u16(mv);
         self.static_evaluation = if let Some(se) = static_evaluation {
             se
         } else {
@@ -366,168 +429,71 @@ impl CacheEntry {
         };
     }
 
+    //Key word: upper 48 bits of the hash and the age.
+    //Data word: move, score, static evaluation, depth, bound and busy flags
+    pub fn pack(&self) -> (u64, u64) {
+        let key = self.upper_hash << 16 | u64::from(self.plies_played);
+        let data = u64::from(self.mv)
+            | u64::from(self.score as u16) << 16
+            | u64::from(self.static_evaluation as u16) << 32
+            | u64::from(self.depth as u8) << 48
+            | u64::from(self.alpha) << 56
+            | u64::from(self.beta) << 57
+            | u64::from(self.pv_node) << 58
+            | u64::from(self.busy) << 59;
+        (key, data)
+    }
+
+    pub fn unpack(key: u64, data: u64) -> CacheEntry {
+        CacheEntry {
+            alpha: data >> 56 & 1 != 0,
+            beta: data >> 57 & 1 != 0,
+            pv_node: data >> 58 & 1 != 0,
+            busy: data >> 59 & 1 != 0,
+            depth: (data >> 48) as u8 as i8,
+            plies_played: key as u16,
+            score: (data >> 16) as u16 as i16,
+            upper_hash: key >> 16,
+            mv: data as u16,
+            static_evaluation: (data >> 32) as u16 as i16,
+        }
+    }
+
     #[inline(always)]
     pub fn mv_to_u16(mv: GameMove) -> u16 {
-        let mut res = 0;
-        res |= (mv.from as usize) << 10;
-        res |= (mv.to as usize) << 4;
-        res |= match &mv.move_type {
-            GameMoveType::Quiet => 1,
-            GameMoveType::Castle => 2,
-            GameMoveType::Promotion(a, _) => match a {
-                PieceType::Queen => 3,
-                PieceType::Rook => 4,
-                PieceType::Bishop => 5,
-                PieceType::Knight => 6,
-                _ => panic!("Invalid promotion!"),
-            },
-            GameMoveType::Capture(_) => 7,
-            GameMoveType::EnPassant => 8,
-        };
-        res as u16
+        Move::from(mv).0
     }
 
     #[inline(always)]
     pub fn u16_to_mv(mv: u16, game_state: &GameState) -> GameMove {
-        let typ = mv & 15;
-        let from = ((mv & 0xFC00) >> 10) as u8;
-        let from_board = 1u64 << from;
-        let to = ((mv & 0x03F0) >> 4) as u8;
-        let to_board = 1u64 << to;
-        let color_to_move = game_state.color_to_move;
-        let enemy_color = 1 - color_to_move;
-        let piece_type = if (game_state.pieces[PAWN][color_to_move] & from_board) != 0u64 {
-            PieceType::Pawn
-        } else if (game_state.pieces[KNIGHT][color_to_move] & from_board) != 0u64 {
-            PieceType::Knight
-        } else if (game_state.pieces[BISHOP][color_to_move] & from_board) != 0u64 {
-            PieceType::Bishop
-        } else if (game_state.pieces[ROOK][color_to_move] & from_board) != 0u64 {
-            PieceType::Rook
-        } else if (game_state.pieces[QUEEN][color_to_move] & from_board) != 0u64 {
-            PieceType::Queen
-        } else {
-            PieceType::King
-        };
-        if typ == 1 {
-            GameMove {
-                from,
-                to,
-                piece_type,
-                move_type: GameMoveType::Quiet,
-            }
-        } else if typ == 2 {
-            //debug_assert_eq!(piece_type, PieceType::King); //We literally expect TRASH in here.
-            GameMove {
-                from,
-                to,
-                piece_type,
-                move_type: GameMoveType::Castle,
-            }
-        } else {
-            if typ == 8 {
-                return GameMove {
-                    from,
-                    to,
-                    piece_type,
-                    move_type: GameMoveType::EnPassant,
-                };
-            }
-            let captured_piece_type = if (game_state.pieces[PAWN][enemy_color] & to_board) != 0u64 {
-                PieceType::Pawn
-            } else if (game_state.pieces[KNIGHT][enemy_color] & to_board) != 0u64 {
-                PieceType::Knight
-            } else if (game_state.pieces[BISHOP][enemy_color] & to_board) != 0u64 {
-                PieceType::Bishop
-            } else if (game_state.pieces[ROOK][enemy_color] & to_board) != 0u64 {
-                PieceType::Rook
-            } else if (game_state.pieces[QUEEN][enemy_color] & to_board) != 0u64 {
-                PieceType::Queen
-            } else {
-                PieceType::King
-            };
-            if typ == 3 {
-                GameMove {
-                    from,
-                    to,
-                    piece_type,
-                    move_type: GameMoveType::Promotion(
-                        PieceType::Queen,
-                        if captured_piece_type != PieceType::King {
-                            Some(captured_piece_type)
-                        } else {
-                            None
-                        },
-                    ),
-                }
-            } else if typ == 4 {
-                GameMove {
-                    from,
-                    to,
-                    piece_type,
-                    move_type: GameMoveType::Promotion(
-                        PieceType::Rook,
-                        if captured_piece_type != PieceType::King {
-                            Some(captured_piece_type)
-                        } else {
-                            None
-                        },
-                    ),
-                }
-            } else if typ == 5 {
-                GameMove {
-                    from,
-                    to,
-                    piece_type,
-                    move_type: GameMoveType::Promotion(
-                        PieceType::Bishop,
-                        if captured_piece_type != PieceType::King {
-                            Some(captured_piece_type)
-                        } else {
-                            None
-                        },
-                    ),
-                }
-            } else if typ == 6 {
-                GameMove {
-                    from,
-                    to,
-                    piece_type,
-                    move_type: GameMoveType::Promotion(
-                        PieceType::Knight,
-                        if captured_piece_type != PieceType::King {
-                            Some(captured_piece_type)
-                        } else {
-                            None
-                        },
-                    ),
-                }
-            } else {
-                GameMove {
-                    from,
-                    to,
-                    piece_type,
-                    move_type: GameMoveType::Capture(captured_piece_type),
-                }
-            }
-        }
+        Move(mv).to_game_move(game_state)
     }
 }
 
-struct PtrWrapper<T> {
-    pub p: *mut T,
+//Runs f on every chunk, each in its own thread if possible
+fn in_parallel<C: Send, I: Iterator<Item = C>, F: Fn(C) + Sync>(chunks: I, f: F) {
+    #[cfg(not(target_arch = "wasm32"))]
+    std::thread::scope(|scope| {
+        for chunk in chunks {
+            let f = &f;
+            scope.spawn(move || f(chunk));
+        }
+    });
+    #[cfg(target_arch = "wasm32")]
+    chunks.for_each(f);
 }
-unsafe impl<T> Send for PtrWrapper<T> {}
 
 #[cfg(test)]
 mod tests {
-    use super::CacheEntry;
+    use super::{AtomicCacheEntry, Cache, CacheEntry};
     use crate::board_representation::game_state::{GameMove, GameMoveType, GameState, PieceType};
     use crate::move_generation::makemove::make_move;
 
     #[test]
+    //Building the move generation tables takes too long under Miri
+    #[cfg_attr(miri, ignore)]
     fn mv_to_u16_test() {
-        let mut game_state = GameState::from_fen("k4b2/2p1P3/8/3P4/6b1/7P/8/R3K2R w KQ -");
+        let mut game_state = GameState::from_fen("k4b2/p1p1P3/8/3P4/6b1/7P/8/R3K2R w KQ -");
         {
             let h3h4 = GameMove {
                 from: 23,
@@ -730,4 +696,54 @@ mod tests {
             assert_eq!(d5d6res.piece_type, d5d6.piece_type);
         }
     }
+
+    #[test]
+    fn concurrent_entries_never_mix() {
+        let entry_for = |thread: u64| CacheEntry {
+            alpha: thread < 3,
+            beta: thread >= 3,
+            pv_node: false,
+            busy: thread == 2,
+            depth: thread as i8,
+            plies_played: 2 * thread as u16,
+            score: -100 * thread as i16,
+            upper_hash: 0xABCD_0000 + thread,
+            mv: thread as u16,
+            static_evaluation: 10 * thread as i16,
+        };
+        let atomic_entry = std::sync::Arc::new(AtomicCacheEntry::default());
+        let handles: Vec<_> = (1..5)
+            .map(|thread| {
+                let atomic_entry = std::sync::Arc::clone(&atomic_entry);
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        atomic_entry.store(&entry_for(thread));
+                        let loaded = atomic_entry.load();
+                        //A torn entry must not validate for any of the written hashes
+                        for other in 1..5 {
+                            if loaded.validate_hash(entry_for(other).upper_hash << 16) {
+                                assert_eq!(loaded, entry_for(other));
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let mut entry = entry_for(3);
+        let (key, data) = entry.pack();
+        assert_eq!(CacheEntry::unpack(key, data), entry);
+        entry.static_evaluation = super::INVALID_STATIC_EVALUATION;
+        entry.score = -15000;
+        let (key, data) = entry.pack();
+        assert_eq!(CacheEntry::unpack(key, data), entry);
+
+        let cache = Cache::with_size_threaded(1, 3);
+        assert_eq!(cache.buckets, cache.cache.len());
+        cache.cache[7].0[1].store(&entry);
+        cache.clear_threaded(2);
+        assert_eq!(cache.fill_status(), 0);
+    }
 }
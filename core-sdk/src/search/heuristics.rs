@@ -0,0 +1,121 @@
+use crate::board_representation::game_state::GameMove;
+
+//History tables saturate at HISTORY_MAX through gravity updates
+pub const HISTORY_MAX: i32 = 16384;
+pub const HISTORY_BONUS_MAX: i32 = 1200;
+pub const CAPTURE_HISTORY_DIVISOR: i32 = 64;
+pub const MAX_CAPTURES_TRIED: usize = 32;
+
+const PIECE_SQUARES: usize = 12 * 64;
+
+#[inline(always)]
+pub fn history_bonus(depth_left: i16) -> i32 {
+    (i32::from(depth_left) * i32::from(depth_left)).min(HISTORY_BONUS_MAX)
+}
+
+//Gravity: the closer an entry is to HISTORY_MAX, the less a bonus in the same direction moves it
+#[inline(always)]
+pub fn apply_gravity(entry: &mut i16, bonus: i32) {
+    let value = i32::from(*entry);
+    *entry = (value + bonus - value * bonus.abs() / HISTORY_MAX) as i16;
+}
+
+#[inline(always)]
+pub fn piece_square(mv: GameMove, color: usize) -> usize {
+    (color * 6 + mv.piece_type.to_index()) * 64 + mv.to as usize
+}
+
+//Indexed by the previous move's piece/to-square and the current move's piece/to-square
+pub struct ContinuationHistory(Vec<i16>);
+impl ContinuationHistory {
+    #[inline(always)]
+    pub fn get(&self, previous: usize, mv: GameMove, color: usize) -> i16 {
+        self.0[previous * PIECE_SQUARES + piece_square(mv, color)]
+    }
+    #[inline(always)]
+    pub fn update(&mut self, previous: usize, mv: GameMove, color: usize, bonus: i32) {
+        apply_gravity(
+            &mut self.0[previous * PIECE_SQUARES + piece_square(mv, color)],
+            bonus,
+        );
+    }
+    pub fn clear(&mut self) {
+        for entry in self.0.iter_mut() {
+            *entry = 0;
+        }
+    }
+}
+impl Default for ContinuationHistory {
+    fn default() -> Self {
+        ContinuationHistory(vec![0; PIECE_SQUARES * PIECE_SQUARES])
+    }
+}
+
+//Indexed by moving piece, to-square and captured piece
+pub struct CaptureHistory(Vec<i16>);
+impl CaptureHistory {
+    #[inline(always)]
+    fn index(mv: GameMove, color: usize) -> usize {
+        piece_square(mv, color) * 5 + mv.get_captured_piece().to_index()
+    }
+    #[inline(always)]
+    pub fn get(&self, mv: GameMove, color: usize) -> i16 {
+        self.0[CaptureHistory::index(mv, color)]
+    }
+    #[inline(always)]
+    pub fn update(&mut self, mv: GameMove, color: usize, bonus: i32) {
+        apply_gravity(&mut self.0[CaptureHistory::index(mv, color)], bonus);
+    }
+    pub fn clear(&mut self) {
+        for entry in self.0.iter_mut() {
+            *entry = 0;
+        }
+    }
+}
+impl Default for CaptureHistory {
+    fn default() -> Self {
+        CaptureHistory(vec![0; PIECE_SQUARES * 5])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_representation::game_state::{GameMoveType, PieceType};
+
+    #[test]
+    fn gravity_saturates() {
+        let mut entry = 0;
+        for _ in 0..1000 {
+            apply_gravity(&mut entry, history_bonus(30));
+            assert!(i32::from(entry) <= HISTORY_MAX);
+        }
+        assert!(i32::from(entry) > HISTORY_MAX * 9 / 10);
+        for _ in 0..1000 {
+            apply_gravity(&mut entry, -history_bonus(30));
+            assert!(i32::from(entry) >= -HISTORY_MAX);
+        }
+        assert!(i32::from(entry) < -HISTORY_MAX * 9 / 10);
+    }
+
+    #[test]
+    fn continuation_history_is_keyed_by_previous_move() {
+        let mut history = ContinuationHistory::default();
+        let previous = GameMove {
+            from: 12,
+            to: 28,
+            move_type: GameMoveType::Quiet,
+            piece_type: PieceType::Pawn,
+        };
+        let mv = GameMove {
+            from: 57,
+            to: 42,
+            move_type: GameMoveType::Quiet,
+            piece_type: PieceType::Knight,
+        };
+        history.update(piece_square(previous, 0), mv, 1, 100);
+        assert_eq!(history.get(piece_square(previous, 0), mv, 1), 100);
+        assert_eq!(history.get(piece_square(previous, 1), mv, 1), 0);
+        assert_eq!(history.get(piece_square(previous, 0), mv, 0), 0);
+    }
+}
@@ -1,19 +1,24 @@
 pub mod alphabeta;
 pub mod cache;
+pub mod heuristics;
 pub mod history;
 pub mod moveordering;
+pub mod parameters;
 pub mod quiescence;
 pub mod reserved_memory;
 pub mod searcher;
+pub mod smp;
 pub mod statistics;
 pub mod timecontrol;
+pub mod tracer;
 
 use crate::board_representation::game_state::*;
 use crate::board_representation::game_state_attack_container::GameStateAttackContainer;
+use crate::board_representation::rules::{is_checkmate, is_dead_position, mate_or_stalemate};
 use crate::search::searcher::Thread;
-use crate::search::timecontrol::TimeControlInformation;
 use history::History;
 use std::fmt::{Display, Formatter, Result};
+use std::sync::atomic::Ordering;
 
 pub const MAX_SEARCH_DEPTH: usize = 100;
 pub const MATE_SCORE: i16 = 15000;
@@ -31,6 +36,7 @@ pub struct CombinedSearchParameters<'a> {
     pub game_state: &'a GameState,
     pub color: i16,
     pub current_depth: usize,
+    pub excluded_move: Option<GameMove>, //Singular extension verification searches skip this move
 }
 impl<'a> CombinedSearchParameters<'a> {
     pub fn from(
@@ -48,8 +54,14 @@ impl<'a> CombinedSearchParameters<'a> {
             game_state,
             color,
             current_depth,
+            excluded_move: None,
         }
     }
+
+    pub fn excluding(mut self, mv: GameMove) -> Self {
+        self.excluded_move = Some(mv);
+        self
+    }
 }
 pub enum SearchInstruction {
     SkipMove,
@@ -98,7 +110,7 @@ impl Display for PrincipalVariation {
 }
 
 #[derive(Copy, Clone)]
-pub struct GradedMove(pub GameMove, pub Option<f64>);
+pub struct GradedMove(pub Move, pub Option<i32>);
 
 #[inline(always)]
 pub fn leaf_score(game_status: GameResult, color: i16, current_depth: i16) -> i16 {
@@ -124,11 +136,12 @@ pub fn check_for_draw(game_state: &GameState, history: &History) -> SearchInstru
         == 0u64
         && (game_state.pieces[KNIGHT][WHITE] | game_state.pieces[BISHOP][WHITE]).count_ones() <= 1
         && (game_state.pieces[KNIGHT][BLACK] | game_state.pieces[BISHOP][BLACK]).count_ones() <= 1
+        || is_dead_position(game_state)
     {
         return SearchInstruction::StopSearching(0);
     }
 
-    if game_state.half_moves >= 100 {
+    if game_state.half_moves >= 100 && !is_checkmate(game_state) {
         return SearchInstruction::StopSearching(0);
     }
 
@@ -144,17 +157,7 @@ pub fn check_end_condition(
     has_legal_moves: bool,
     in_check: bool,
 ) -> GameResult {
-    if in_check && !has_legal_moves {
-        if game_state.color_to_move == WHITE {
-            return GameResult::BlackWin;
-        } else {
-            return GameResult::WhiteWin;
-        }
-    }
-    if !in_check && !has_legal_moves {
-        return GameResult::Draw;
-    }
-    GameResult::Ingame
+    mate_or_stalemate(game_state, has_legal_moves, in_check).0
 }
 
 #[inline(always)]
@@ -188,31 +191,27 @@ pub fn in_check(game_state: &GameState, attack_container: &GameStateAttackContai
 
 #[inline(always)]
 pub fn checkup(thread: &mut Thread) {
+    //Without a background search (wasm), the main thread has to look for stop commands itself.
+    //Nothing happens unless an input callback was installed
+    if thread.id == 0 {
+        thread.itcs.poll_input();
+    }
+    //The main thread checks the node limit against the published nodes of all threads
+    thread.itcs.update(
+        thread.id,
+        thread.search_statistics.nodes_searched,
+        thread.search_statistics.seldepth,
+    );
     if (thread.id == 0
-        && thread.tc.time_over(
-            thread.itcs.get_time_elapsed(),
-            &TimeControlInformation {
-                high_score_diff: false,
-                time_saved: thread.time_saved,
-                stable_pv: thread
-                    .itcs
-                    .stable_pv
-                    .load(std::sync::atomic::Ordering::Relaxed),
-            },
-            thread.itcs.uci_options().move_overhead,
-        ))
-        || *thread
-            .itcs
-            .timeout_flag
-            .read()
-            .expect("Reading posioned timeoutflag")
+        && ((!thread.itcs.is_pondering()
+            && thread
+                .time_manager
+                .time_over(thread.itcs.get_time_elapsed()))
+            || thread.itcs.node_limit_reached()))
+        || thread.itcs.timeout_flag.load(Ordering::Acquire)
     {
         if thread.id == 0 {
-            *thread
-                .itcs
-                .timeout_flag
-                .write()
-                .expect("Writing poisoned timeoutflag") = true;
+            thread.itcs.timeout_flag.store(true, Ordering::Release);
         }
         thread.self_stop = true;
     }
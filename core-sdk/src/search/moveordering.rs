@@ -1,38 +1,54 @@
-use crate::board_representation::game_state::{GameMove, PieceType};
+use crate::board_representation::game_state::{GameMove, Move, PieceType};
 use crate::move_generation::movegen;
+use crate::move_generation::staged::{generate_pseudo_legal_moves, is_legal, GenType};
+use crate::search::heuristics::{CAPTURE_HISTORY_DIVISOR, HISTORY_MAX};
 use crate::search::moveordering::MoveOrderingStage::{
-    BadCapture, GoodCapture, GoodCaptureInitialization, Killer, PVMove, Quiet, QuietInitialization,
-    TTMove,
+    BadCapture, CounterMove, GoodCapture, GoodCaptureInitialization, Killer, PVMove, Quiet,
+    QuietCheck, QuietCheckInitialization, QuietGeneration, QuietInitialization, TTMove,
 };
 use crate::search::quiescence::{see, PIECE_VALUES};
 use crate::search::searcher::Thread;
-use crate::search::{CombinedSearchParameters, GradedMove};
+use crate::search::smp::ORDERING_NOISE;
+use crate::search::{in_check, CombinedSearchParameters, GradedMove};
+use rand::Rng;
 
 //For MVV-LVA calculation
 pub const ATTACKER_VALUE: [i16; 6] = [0, 1, 2, 3, 4, 5];
 pub const TARGET_VALUE: [i16; 5] = [100, 400, 400, 650, 1100];
+//Keeps quiet scores of frequently cutting moves from overflowing
+const QUIET_SCORE_MAX: usize = 1 << 28;
 
 pub fn mvvlva(mv: GameMove) -> i16 {
     debug_assert!(mv.is_capture());
     TARGET_VALUE[mv.get_captured_piece().to_index()] - ATTACKER_VALUE[mv.piece_type.to_index()]
 }
 
-pub const NORMAL_STAGES: [MoveOrderingStage; 8] = [
+pub const NORMAL_STAGES: [MoveOrderingStage; 10] = [
     PVMove,
     TTMove,
     GoodCaptureInitialization,
     GoodCapture,
+    QuietGeneration,
     Killer,
+    CounterMove,
     QuietInitialization,
     Quiet,
     BadCapture,
 ];
 pub const QUIESCENCE_STAGES: [MoveOrderingStage; 3] =
     [TTMove, GoodCaptureInitialization, GoodCapture];
-pub const QUIESCENCE_IN_CHECK_STAGES: [MoveOrderingStage; 6] = [
+pub const QUIESCENCE_WITH_CHECKS_STAGES: [MoveOrderingStage; 5] = [
     TTMove,
     GoodCaptureInitialization,
     GoodCapture,
+    QuietCheckInitialization,
+    QuietCheck,
+];
+pub const QUIESCENCE_IN_CHECK_STAGES: [MoveOrderingStage; 7] = [
+    TTMove,
+    GoodCaptureInitialization,
+    GoodCapture,
+    QuietGeneration,
     QuietInitialization,
     Quiet,
     BadCapture,
@@ -42,10 +58,14 @@ pub enum MoveOrderingStage {
     TTMove,
     GoodCaptureInitialization,
     GoodCapture,
+    QuietGeneration,
     Killer,
+    CounterMove,
     QuietInitialization,
     Quiet,
     BadCapture,
+    QuietCheckInitialization,
+    QuietCheck,
 }
 pub struct MoveOrderer {
     pub stage: usize,
@@ -54,13 +74,30 @@ pub struct MoveOrderer {
     pub has_legal_move: bool,
 }
 impl MoveOrderer {
+    //Moves from the staged movegen are pseudo-legal, their legality is checked right before they are made
     pub fn next(
         &mut self,
         thread: &mut Thread,
         p: &CombinedSearchParameters,
         pv_table_move: Option<GameMove>,
         tt_move: Option<GameMove>,
-    ) -> Option<(GameMove, f64)> {
+    ) -> Option<(GameMove, i32)> {
+        loop {
+            let (mv, score) = self.next_candidate(thread, p, pv_table_move, tt_move)?;
+            if is_legal(p.game_state, Move::from(mv)) {
+                self.has_legal_move = true;
+                return Some((mv, score));
+            }
+        }
+    }
+
+    fn next_candidate(
+        &mut self,
+        thread: &mut Thread,
+        p: &CombinedSearchParameters,
+        pv_table_move: Option<GameMove>,
+        tt_move: Option<GameMove>,
+    ) -> Option<(GameMove, i32)> {
         if self.stage >= self.stages.len() {
             return None;
         }
@@ -73,9 +110,9 @@ impl MoveOrderer {
                         &thread.attack_container.attack_containers[p.current_depth],
                     )
                 {
-                    Some((pv_table_move.unwrap(), 0.))
+                    Some((pv_table_move.unwrap(), 0))
                 } else {
-                    self.next(thread, p, pv_table_move, tt_move)
+                    self.next_candidate(thread, p, pv_table_move, tt_move)
                 }
             }
             MoveOrderingStage::TTMove => {
@@ -87,79 +124,100 @@ impl MoveOrderer {
                         &thread.attack_container.attack_containers[p.current_depth],
                     )
                 {
-                    Some((tt_move.unwrap(), 0.))
+                    Some((tt_move.unwrap(), 0))
                 } else {
-                    self.next(thread, p, pv_table_move, tt_move)
+                    self.next_candidate(thread, p, pv_table_move, tt_move)
                 }
             }
             MoveOrderingStage::GoodCaptureInitialization => {
                 //Generate moves first!
-                let agsi = movegen::generate_moves(
-                    &p.game_state,
-                    self.gen_only_captures,
-                    &mut thread.movelist.move_lists[p.current_depth],
-                    &thread.attack_container.attack_containers[p.current_depth],
-                );
-                self.has_legal_move = agsi.stm_haslegalmove;
                 let our_mvlist = &mut thread.movelist.move_lists[p.current_depth];
-
-                if let Some(pv_move) = pv_table_move {
-                    let mv_index = our_mvlist.find_move(pv_move, false);
-                    if mv_index < our_mvlist.move_list.len() {
-                        our_mvlist.move_list.remove(mv_index);
-                    }
-                }
-                if let Some(tt_move) = tt_move {
-                    let mv_index = our_mvlist.find_move(tt_move, false);
-                    if mv_index < our_mvlist.move_list.len() {
-                        our_mvlist.move_list.remove(mv_index);
-                    }
+                let attack_container = &thread.attack_container.attack_containers[p.current_depth];
+                if self.gen_only_captures {
+                    //The legal movegen also finds out if there is any legal quiet move
+                    let agsi =
+                        movegen::generate_moves(p.game_state, true, our_mvlist, attack_container);
+                    self.has_legal_move |= agsi.stm_haslegalmove;
+                } else {
+                    our_mvlist.clear();
+                    let gen_type = if in_check(p.game_state, attack_container) {
+                        GenType::Evasions
+                    } else {
+                        GenType::Captures
+                    };
+                    generate_pseudo_legal_moves(
+                        p.game_state,
+                        gen_type,
+                        our_mvlist,
+                        attack_container,
+                    );
                 }
+                remove_moves(our_mvlist, pv_table_move, tt_move);
 
-                //Give any capture move in movelist its MVV-LVA score
-                for mv in our_mvlist.move_list.iter_mut() {
-                    if mv.0.is_capture() {
-                        mv.1 = Some(f64::from(mvvlva(mv.0)));
+                //Give any capture move in movelist its MVV-LVA score, refined by capture history
+                let color = p.game_state.color_to_move;
+                for gmv in our_mvlist.moves_mut().iter_mut() {
+                    if gmv.0.is_capture() {
+                        let mv = gmv.0.to_game_move(p.game_state);
+                        let history = i32::from(thread.capture_history.get(mv, color));
+                        gmv.1 = Some(
+                            (i32::from(mvvlva(mv)) + history / CAPTURE_HISTORY_DIVISOR).max(0),
+                        );
                     }
                 }
 
                 self.stage += 1;
-                self.next(thread, p, None, None)
+                self.next_candidate(thread, p, pv_table_move, tt_move)
             }
             MoveOrderingStage::GoodCapture => {
                 //We now have all of the captures sorted by mvv lva
                 let our_list = &mut thread.movelist.move_lists[p.current_depth];
                 let highest_mvv_lva = our_list.highest_score();
-                if highest_mvv_lva.is_none() || (highest_mvv_lva.unwrap().1).1.unwrap() < 0. {
+                if highest_mvv_lva.is_none() || (highest_mvv_lva.unwrap().1).1.unwrap() < 0 {
                     self.stage += 1;
-                    self.next(thread, p, pv_table_move, tt_move)
+                    self.next_candidate(thread, p, pv_table_move, tt_move)
                 } else {
                     let (gm_index, graded_move) = highest_mvv_lva.unwrap();
-                    our_list.move_list.remove(gm_index);
-                    if PIECE_VALUES[graded_move.0.get_captured_piece().to_index()]
-                        - PIECE_VALUES[graded_move.0.piece_type.to_index()]
+                    our_list.remove(gm_index);
+                    let mv = graded_move.0.to_game_move(p.game_state);
+                    if PIECE_VALUES[mv.get_captured_piece().to_index()]
+                        - PIECE_VALUES[mv.piece_type.to_index()]
                         >= 0
-                        || graded_move.0.piece_type == PieceType::King
+                        || mv.piece_type == PieceType::King
                     {
-                        Some((graded_move.0, 0.))
+                        Some((mv, 0))
                     } else {
                         let see_value = see(
                             p.game_state,
-                            graded_move.0,
+                            mv,
                             self.stages.len() == NORMAL_STAGES.len(),
                             &mut thread.see_buffer,
                         );
                         if see_value >= 0 {
-                            Some((graded_move.0, 0.))
+                            Some((mv, 0))
                         } else {
-                            our_list
-                                .move_list
-                                .push(GradedMove(graded_move.0, Some(f64::from(see_value))));
-                            self.next(thread, p, None, None)
+                            our_list.push(GradedMove(graded_move.0, Some(i32::from(see_value))));
+                            self.next_candidate(thread, p, pv_table_move, tt_move)
                         }
                     }
                 }
             }
+            MoveOrderingStage::QuietGeneration => {
+                self.stage += 1;
+                //Evasions already contain the quiet moves
+                let attack_container = &thread.attack_container.attack_containers[p.current_depth];
+                if !in_check(p.game_state, attack_container) {
+                    let our_list = &mut thread.movelist.move_lists[p.current_depth];
+                    generate_pseudo_legal_moves(
+                        p.game_state,
+                        GenType::Quiets,
+                        our_list,
+                        attack_container,
+                    );
+                    remove_moves(our_list, pv_table_move, tt_move);
+                }
+                self.next_candidate(thread, p, pv_table_move, tt_move)
+            }
             MoveOrderingStage::Killer => {
                 debug_assert!(
                     thread.killer_moves[p.current_depth][0].is_none()
@@ -173,56 +231,113 @@ impl MoveOrderer {
                             .unwrap()
                             .is_capture()
                 );
+                let killers = thread.killer_moves[p.current_depth];
                 let our_list = &mut thread.movelist.move_lists[p.current_depth];
-                let mut found_index = our_list.move_list.len();
-                for (index, gmv) in our_list.move_list.iter().enumerate() {
-                    if gmv.1.is_none()
-                        && (Some(gmv.0) == thread.killer_moves[p.current_depth][0]
-                            || Some(gmv.0) == thread.killer_moves[p.current_depth][1])
-                    {
-                        found_index = index;
-                        break;
-                    }
-                }
-                if found_index < our_list.move_list.len() {
-                    let res = our_list.move_list[found_index].0;
-                    our_list.move_list.remove(found_index);
-                    Some((res, 0.))
+                let found_index = our_list.moves().iter().position(|gmv| {
+                    gmv.1.is_none() && (Some(gmv.0) == killers[0] || Some(gmv.0) == killers[1])
+                });
+                if let Some(index) = found_index {
+                    let res = our_list.remove(index).0;
+                    Some((res.to_game_move(p.game_state), 0))
                 } else {
                     self.stage += 1;
-                    self.next(thread, p, None, None)
+                    self.next_candidate(thread, p, pv_table_move, tt_move)
+                }
+            }
+            MoveOrderingStage::CounterMove => {
+                self.stage += 1;
+                let color = p.game_state.color_to_move;
+                let counter_move = thread
+                    .previous_move(p.current_depth, 1, color)
+                    .and_then(|previous| thread.counter_moves[previous]);
+                if let Some(counter_move) = counter_move {
+                    let our_list = &mut thread.movelist.move_lists[p.current_depth];
+                    if let Some(index) = our_list
+                        .moves()
+                        .iter()
+                        .position(|gmv| gmv.1.is_none() && gmv.0 == counter_move)
+                    {
+                        our_list.remove(index);
+                        return Some((counter_move.to_game_move(p.game_state), 0));
+                    }
                 }
+                self.next_candidate(thread, p, pv_table_move, tt_move)
             }
             MoveOrderingStage::QuietInitialization => {
-                for mv in thread.movelist.move_lists[p.current_depth]
-                    .move_list
+                let color = p.game_state.color_to_move;
+                let previous_moves = [
+                    thread.previous_move(p.current_depth, 1, color),
+                    thread.previous_move(p.current_depth, 2, color),
+                ];
+                let ordering_noise = &mut thread.ordering_noise;
+                for gmv in thread.movelist.move_lists[p.current_depth]
+                    .moves_mut()
                     .iter_mut()
                 {
-                    if mv.1.is_none() {
-                        debug_assert!(!mv.0.is_capture());
-                        mv.1 = Some(
-                            thread.hh_score[p.game_state.color_to_move][mv.0.from as usize]
-                                [mv.0.to as usize] as f64
-                                / thread.bf_score[p.game_state.color_to_move][mv.0.from as usize]
-                                    [mv.0.to as usize] as f64
-                                / 1000.0,
-                        );
+                    if gmv.1.is_none() {
+                        debug_assert!(!gmv.0.is_capture());
+                        let mv = gmv.0.to_game_move(p.game_state);
+                        let noise = if let Some(rng) = ordering_noise.as_mut() {
+                            rng.gen_range(0, ORDERING_NOISE)
+                        } else {
+                            0
+                        };
+                        //Continuation history is shifted into [0, 4 * HISTORY_MAX] so quiet scores stay non-negative
+                        let mut continuation = 2 * HISTORY_MAX;
+                        for (plies, previous) in previous_moves.iter().enumerate() {
+                            if let Some(previous) = previous {
+                                continuation += i32::from(
+                                    thread.continuation_history[plies].get(*previous, mv, color),
+                                );
+                            }
+                        }
+                        //A thousand cutoffs per try weigh as much as the whole continuation history range
+                        let (from, to) = (mv.from as usize, mv.to as usize);
+                        let butterfly = thread.hh_score[color][from][to]
+                            * (4 * HISTORY_MAX) as usize
+                            / (thread.bf_score[color][from][to] * 1000);
+                        gmv.1 = Some(butterfly.min(QUIET_SCORE_MAX) as i32 + continuation + noise);
                     }
                 }
                 self.stage += 1;
-                self.next(thread, p, None, None)
+                self.next_candidate(thread, p, pv_table_move, tt_move)
             }
             MoveOrderingStage::Quiet => {
                 let our_list = &mut thread.movelist.move_lists[p.current_depth];
                 let highest = our_list.highest_score();
                 if let Some((index, gmv)) = highest {
-                    if gmv.1.unwrap() < 0. {
+                    if gmv.1.unwrap() < 0 {
                         self.stage += 1;
-                        return self.next(thread, p, None, None);
+                        return self.next_candidate(thread, p, pv_table_move, tt_move);
                     }
                     debug_assert!(!gmv.0.is_capture());
-                    our_list.move_list.remove(index);
-                    Some((gmv.0, 0.))
+                    our_list.remove(index);
+                    Some((gmv.0.to_game_move(p.game_state), 0))
+                } else {
+                    self.stage = self.stages.len();
+                    None
+                }
+            }
+            MoveOrderingStage::QuietCheckInitialization => {
+                self.stage += 1;
+                generate_pseudo_legal_moves(
+                    p.game_state,
+                    GenType::QuietChecks,
+                    &mut thread.movelist.move_lists[p.current_depth],
+                    &thread.attack_container.attack_containers[p.current_depth],
+                );
+                self.next_candidate(thread, p, pv_table_move, tt_move)
+            }
+            MoveOrderingStage::QuietCheck => {
+                //Bad captures left in the list are graded, quiet checks are not
+                let our_list = &mut thread.movelist.move_lists[p.current_depth];
+                if let Some(index) = our_list.moves().iter().position(|gmv| gmv.1.is_none()) {
+                    let mv = our_list.remove(index).0.to_game_move(p.game_state);
+                    if see(p.game_state, mv, false, &mut thread.see_buffer) >= 0 {
+                        Some((mv, 0))
+                    } else {
+                        self.next_candidate(thread, p, pv_table_move, tt_move)
+                    }
                 } else {
                     self.stage = self.stages.len();
                     None
@@ -233,9 +348,9 @@ impl MoveOrderer {
                 let highest = our_list.highest_score();
                 if let Some((index, gmv)) = highest {
                     debug_assert!(gmv.0.is_capture());
-                    debug_assert!(gmv.1.unwrap() < 0.);
-                    our_list.move_list.remove(index);
-                    Some((gmv.0, gmv.1.unwrap()))
+                    debug_assert!(gmv.1.unwrap() < 0);
+                    our_list.remove(index);
+                    Some((gmv.0.to_game_move(p.game_state), gmv.1.unwrap()))
                 } else {
                     self.stage = self.stages.len();
                     None
@@ -244,3 +359,17 @@ impl MoveOrderer {
         }
     }
 }
+
+//PV and TT moves are searched before the movelist is generated
+fn remove_moves(
+    movelist: &mut movegen::MoveList,
+    pv_table_move: Option<GameMove>,
+    tt_move: Option<GameMove>,
+) {
+    for mv in pv_table_move.iter().chain(tt_move.iter()) {
+        let mv_index = movelist.find_move(Move::from(*mv), false);
+        if mv_index < movelist.len() {
+            movelist.remove(mv_index);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//Search constants, read at runtime so they can be tuned through UCI options
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SearchParams {
+    pub futility_margin: i16,
+    pub futility_depth: i16,
+    pub static_null_move_margin: i16,
+    pub static_null_move_depth: i16,
+    pub null_move_pruning_depth: i16,
+    pub history_pruning_depth: i16,
+    pub history_pruning_threshold: i16,
+    pub see_pruning_depth: i16,
+    pub see_pruning_capture_mult: i16,
+    pub see_pruning_quiet_mult: i16,
+    pub singular_extension_depth: i16,
+    pub singular_extension_tt_depth: i16,
+    pub singular_extension_margin: i16,
+    pub delta_pruning: i16,
+    pub aspiration_delta: i16,
+    //0 or 1, whether the first quiescence ply also tries quiet checks
+    pub quiescence_checks: i16,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        SearchParams {
+            futility_margin: 90,
+            futility_depth: 6,
+            static_null_move_margin: 120,
+            static_null_move_depth: 5,
+            null_move_pruning_depth: 3,
+            history_pruning_depth: 2,
+            history_pruning_threshold: 0,
+            see_pruning_depth: 6,
+            see_pruning_capture_mult: -23,
+            see_pruning_quiet_mult: -23,
+            singular_extension_depth: 8,
+            singular_extension_tt_depth: 3,
+            singular_extension_margin: 2,
+            delta_pruning: 100,
+            aspiration_delta: 14,
+            quiescence_checks: 0,
+        }
+    }
+}
+
+pub struct SearchParamRange {
+    pub name: &'static str,
+    pub min: i16,
+    pub max: i16,
+}
+
+//UCI names and bounds of the parameters
+pub const SEARCH_PARAMS: [SearchParamRange; 16] = [
+    SearchParamRange {
+        name: "FutilityMargin",
+        min: 0,
+        max: 300,
+    },
+    SearchParamRange {
+        name: "FutilityDepth",
+        min: 0,
+        max: 12,
+    },
+    SearchParamRange {
+        name: "StaticNullMoveMargin",
+        min: 0,
+        max: 400,
+    },
+    SearchParamRange {
+        name: "StaticNullMoveDepth",
+        min: 0,
+        max: 12,
+    },
+    SearchParamRange {
+        name: "NullMovePruningDepth",
+        min: 1,
+        max: 12,
+    },
+    SearchParamRange {
+        name: "HistoryPruningDepth",
+        min: 0,
+        max: 8,
+    },
+    SearchParamRange {
+        name: "HistoryPruningThreshold",
+        min: -2000,
+        max: 2000,
+    },
+    SearchParamRange {
+        name: "SEEPruningDepth",
+        min: 0,
+        max: 12,
+    },
+    SearchParamRange {
+        name: "SEEPruningCaptureMult",
+        min: -200,
+        max: 0,
+    },
+    SearchParamRange {
+        name: "SEEPruningQuietMult",
+        min: -200,
+        max: 0,
+    },
+    SearchParamRange {
+        name: "SingularExtensionDepth",
+        min: 4,
+        max: 16,
+    },
+    SearchParamRange {
+        name: "SingularExtensionTTDepth",
+        min: 1,
+        max: 8,
+    },
+    SearchParamRange {
+        name: "SingularExtensionMargin",
+        min: 0,
+        max: 10,
+    },
+    SearchParamRange {
+        name: "DeltaPruning",
+        min: 0,
+        max: 500,
+    },
+    SearchParamRange {
+        name: "AspirationDelta",
+        min: 5,
+        max: 100,
+    },
+    SearchParamRange {
+        name: "QuiescenceChecks",
+        min: 0,
+        max: 1,
+    },
+];
+
+impl SearchParams {
+    //Names are matched case insensitively, like all UCI option names
+    fn field_mut(&mut self, name: &str) -> Option<&mut i16> {
+        match name.to_lowercase().as_str() {
+            "futilitymargin" => Some(&mut self.futility_margin),
+            "futilitydepth" => Some(&mut self.futility_depth),
+            "staticnullmovemargin" => Some(&mut self.static_null_move_margin),
+            "staticnullmovedepth" => Some(&mut self.static_null_move_depth),
+            "nullmovepruningdepth" => Some(&mut self.null_move_pruning_depth),
+            "historypruningdepth" => Some(&mut self.history_pruning_depth),
+            "historypruningthreshold" => Some(&mut self.history_pruning_threshold),
+            "seepruningdepth" => Some(&mut self.see_pruning_depth),
+            "seepruningcapturemult" => Some(&mut self.see_pruning_capture_mult),
+            "seepruningquietmult" => Some(&mut self.see_pruning_quiet_mult),
+            "singularextensiondepth" => Some(&mut self.singular_extension_depth),
+            "singularextensionttdepth" => Some(&mut self.singular_extension_tt_depth),
+            "singularextensionmargin" => Some(&mut self.singular_extension_margin),
+            "deltapruning" => Some(&mut self.delta_pruning),
+            "aspirationdelta" => Some(&mut self.aspiration_delta),
+            "quiescencechecks" => Some(&mut self.quiescence_checks),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<i16> {
+        let mut params = *self;
+        params.field_mut(name).map(|value| *value)
+    }
+
+    //Clamps the value into the bounds of the parameter and returns what was set
+    pub fn set(&mut self, name: &str, value: i64) -> Option<i16> {
+        let range = SEARCH_PARAMS
+            .iter()
+            .find(|range| range.name.eq_ignore_ascii_case(name))?;
+        let value = value.clamp(i64::from(range.min), i64::from(range.max)) as i16;
+        *self.field_mut(name)? = value;
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_param_is_settable() {
+        let defaults = SearchParams::default();
+        for range in SEARCH_PARAMS.iter() {
+            let default = defaults.get(range.name).expect("Param without field!");
+            assert!(range.min <= default && default <= range.max);
+            let mut params = SearchParams::default();
+            assert_eq!(
+                params.set(range.name, i64::from(range.min)),
+                Some(range.min)
+            );
+            assert_eq!(params.get(&range.name.to_uppercase()), Some(range.min));
+            assert_eq!(params.set(range.name, i64::MAX), Some(range.max));
+        }
+        let mut params = SearchParams::default();
+        assert_eq!(params.set("NoSuchParam", 1), None);
+        assert_eq!(params, SearchParams::default());
+    }
+}
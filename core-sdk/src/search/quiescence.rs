@@ -8,28 +8,40 @@ use super::alphabeta::*;
 use super::*;
 use crate::bitboards::bitboards::constants::{KING_ATTACKS, KNIGHT_ATTACKS, RANKS};
 use crate::move_generation::makemove::make_move;
-use crate::search::moveordering::{MoveOrderer, QUIESCENCE_IN_CHECK_STAGES, QUIESCENCE_STAGES};
+use crate::search::moveordering::{
+    MoveOrderer, QUIESCENCE_IN_CHECK_STAGES, QUIESCENCE_STAGES, QUIESCENCE_WITH_CHECKS_STAGES,
+};
+use crate::search::tracer::TraceDecision;
 
-pub const DELTA_PRUNING: i16 = 100;
 pub const PIECE_VALUES: [i16; 6] = [100, 400, 400, 650, 1100, 30000];
 
-pub fn q_search(mut p: CombinedSearchParameters, thread: &mut Thread) -> i16 {
+pub fn q_search(p: CombinedSearchParameters, thread: &mut Thread) -> i16 {
+    thread.tracer.enter(&p, true);
+    let score = q_search_node(p, thread);
+    thread.tracer.exit(score, &thread.played_moves);
+    score
+}
+
+fn q_search_node(mut p: CombinedSearchParameters, thread: &mut Thread) -> i16 {
     //Step 0. Prepare variables
     thread.search_statistics.add_q_node(p.current_depth);
     clear_pv(p.current_depth, thread);
 
     //Step 1. Stop flag set, return immediatly
     if thread.self_stop {
+        thread.tracer.decision(TraceDecision::Stopped);
         return STANDARD_SCORE;
     }
 
     //Step 2. Max search-depth reached
     if let SearchInstruction::StopSearching(res) = max_depth(&p, thread) {
+        thread.tracer.decision(TraceDecision::MaxDepth);
         return res;
     }
 
     //Step 3. Check for draw
     if let SearchInstruction::StopSearching(res) = check_for_draw(p.game_state, &thread.history) {
+        thread.tracer.decision(TraceDecision::Draw);
         return res;
     }
 
@@ -60,11 +72,19 @@ pub fn q_search(mut p: CombinedSearchParameters, thread: &mut Thread) -> i16 {
     };
 
     //Step 6. Preliminary pruning
+    if let Some(stand_pat) = stand_pat {
+        thread.tracer.static_eval(stand_pat);
+    }
     if !incheck {
         if let SearchInstruction::StopSearching(res) = adjust_standpat(&mut p, stand_pat.unwrap()) {
+            thread.tracer.decision(TraceDecision::StandPat);
             return res;
-        } else if let SearchInstruction::StopSearching(res) = delta_pruning(&p, stand_pat.unwrap())
-        {
+        } else if let SearchInstruction::StopSearching(res) = delta_pruning(
+            &p,
+            stand_pat.unwrap(),
+            thread.uci_options.search_params.delta_pruning,
+        ) {
+            thread.tracer.decision(TraceDecision::DeltaPruning);
             return res;
         }
     }
@@ -74,17 +94,21 @@ pub fn q_search(mut p: CombinedSearchParameters, thread: &mut Thread) -> i16 {
     if p.depth_left == 0 {
         if let SearchInstruction::StopSearching(res) =
             thread
-                .itcs
-                .cache()
+                .cache
                 .lookup(&p, &mut None, &mut tt_move, thread.root_plies_played)
         {
             #[cfg(feature = "search-statistics")]
             {
                 thread.search_statistics.add_cache_hit_aj_replace_ns();
             }
+            thread.tracer.tt_hit();
+            thread.tracer.decision(TraceDecision::TTCutoff);
             return res;
         }
     }
+    if tt_move.is_some() {
+        thread.tracer.tt_hit();
+    }
     #[cfg(feature = "search-statistics")]
     {
         if tt_move.is_some() {
@@ -112,6 +136,9 @@ pub fn q_search(mut p: CombinedSearchParameters, thread: &mut Thread) -> i16 {
         stage: 0,
         stages: if incheck {
             &QUIESCENCE_IN_CHECK_STAGES
+        } else if p.depth_left == 0 && thread.uci_options.search_params.quiescence_checks != 0 {
+            //Quiet checks are only tried at the first ply of the quiescence search
+            &QUIESCENCE_WITH_CHECKS_STAGES
         } else {
             &QUIESCENCE_STAGES
         },
@@ -126,17 +153,28 @@ pub fn q_search(mut p: CombinedSearchParameters, thread: &mut Thread) -> i16 {
         }
         let (capture_move, _) = mv.unwrap();
         if !incheck
+            && capture_move.is_capture()
             && !passes_delta_pruning(
                 capture_move,
                 p.game_state.phase.phase,
                 *stand_pat.as_ref().unwrap(),
                 p.alpha,
+                thread.uci_options.search_params.delta_pruning,
             )
         {
+            thread.tracer.pruned(
+                &p,
+                capture_move,
+                TraceDecision::DeltaPruning,
+                &thread.played_moves,
+            );
             continue;
         }
-        debug_assert!(incheck || capture_move.is_capture());
+        debug_assert!(
+            incheck || capture_move.is_capture() || p.game_state.gives_check(capture_move)
+        );
         let next_g = make_move(p.game_state, capture_move);
+        thread.played_moves[p.current_depth] = Some(capture_move);
         //Step 8.4. Search move
         let score = -q_search(
             CombinedSearchParameters::from(
@@ -163,6 +201,7 @@ pub fn q_search(mut p: CombinedSearchParameters, thread: &mut Thread) -> i16 {
             {
                 thread.search_statistics.add_q_beta_cutoff(index);
             }
+            thread.tracer.decision(TraceDecision::BetaCutoff);
             break;
         }
 
@@ -188,7 +227,7 @@ pub fn q_search(mut p: CombinedSearchParameters, thread: &mut Thread) -> i16 {
 
     //Step 10. Make TT entry
     if has_pv && p.depth_left == 0 && !thread.self_stop {
-        thread.itcs.cache().insert(
+        thread.cache.insert(
             &p,
             thread.pv_table[p.current_depth].pv[0].expect("Can't unwrap move for TT in qsearch!"),
             current_max_score,
@@ -218,8 +257,12 @@ pub fn adjust_standpat(p: &mut CombinedSearchParameters, stand_pat: i16) -> Sear
 }
 
 #[inline(always)]
-pub fn delta_pruning(p: &CombinedSearchParameters, stand_pat: i16) -> SearchInstruction {
-    let diff = p.alpha - stand_pat - DELTA_PRUNING;
+pub fn delta_pruning(
+    p: &CombinedSearchParameters,
+    stand_pat: i16,
+    delta_margin: i16,
+) -> SearchInstruction {
+    let diff = p.alpha - stand_pat - delta_margin;
     if diff > 0 && best_move_value(p.game_state) < diff {
         SearchInstruction::StopSearching(stand_pat)
     } else {
@@ -249,7 +292,13 @@ pub fn best_move_value(state: &GameState) -> i16 {
 }
 
 #[inline(always)]
-pub fn passes_delta_pruning(capture_move: GameMove, phase: f64, eval: i16, alpha: i16) -> bool {
+pub fn passes_delta_pruning(
+    capture_move: GameMove,
+    phase: f64,
+    eval: i16,
+    alpha: i16,
+    delta_margin: i16,
+) -> bool {
     if phase == 0.0 || eval >= alpha {
         return true;
     }
@@ -261,7 +310,7 @@ pub fn passes_delta_pruning(capture_move: GameMove, phase: f64, eval: i16, alpha
         GameMoveType::EnPassant => &PieceType::Pawn,
         _ => panic!("No capture!"),
     };
-    eval + captured_piece.to_piece_score().interpolate(phase) + DELTA_PRUNING >= alpha
+    eval + captured_piece.to_piece_score().interpolate(phase) + delta_margin >= alpha
 }
 
 #[inline(always)]
@@ -365,6 +414,7 @@ pub fn attacks_to(game_state: &GameState, square: usize, occ: u64) -> u64 {
 pub fn capture_value(mv: GameMove) -> i16 {
     match &mv.move_type {
         GameMoveType::Capture(c) => piece_value(*c),
+        GameMoveType::Quiet => 0,
         GameMoveType::Promotion(_, b) => match b {
             Some(c) => piece_value(*c),
             _ => panic!("Promotion but no capture"),
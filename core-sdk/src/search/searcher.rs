@@ -1,12 +1,15 @@
 use super::alphabeta::principal_variation_search;
 use super::cache::Cache;
+use super::heuristics::{piece_square, CaptureHistory, ContinuationHistory, MAX_CAPTURES_TRIED};
 use super::history::History;
+use super::smp::SMPReport;
 use super::statistics::SearchStatistics;
-use super::timecontrol::TimeControl;
-use super::GameMove;
+use super::timecontrol::{Clock, SystemClock, TimeControl, TimeManager};
+use super::tracer::SearchTracer;
 use super::PrincipalVariation;
 use super::MATED_IN_MAX;
 use super::MAX_SEARCH_DEPTH;
+use super::{GameMove, Move};
 use crate::board_representation::game_state::{GameState, WHITE};
 //use crate::logging::log;
 use crate::board_representation::game_state_attack_container::GameStateAttackContainer;
@@ -15,26 +18,23 @@ use crate::move_generation::movegen::{generate_moves, MoveList};
 use crate::search::reserved_memory::{ReservedAttackContainer, ReservedMoveList};
 use crate::search::{CombinedSearchParameters, ScoredPrincipalVariation, MATE_SCORE};
 use crate::UCIOptions;
-use std::cell::UnsafeCell;
-use std::io::{self, Write, BufWriter};
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::AtomicUsize;
+use rand::rngs::StdRng;
+use std::collections::VecDeque;
+use std::io::{self, BufWriter, Write};
 use std::sync::atomic::Ordering;
-#[allow(unused)]
+use std::sync::atomic::{AtomicBool, AtomicUsize};
 use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Arc, Mutex, MutexGuard, RwLock};
-#[allow(unused)]
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockWriteGuard};
+#[cfg(not(target_arch = "wasm32"))]
 use std::thread;
 
 // FIXME expects used for error handling of writeln
 #[cfg(not(target_arch = "wasm32"))]
 mod imports {
     pub use std::sync::atomic::AtomicU64;
-    pub use std::time::Instant;
 }
 #[cfg(target_arch = "wasm32")]
 mod imports {
-    pub use fake_instant::FakeClock as Instant;
     pub type AtomicU64 = atomic::Atomic<u64>;
 }
 use imports::*;
@@ -46,9 +46,16 @@ pub const MAX_SKIP_RATIO: usize = 1024;
 pub const DEFAULT_THREADS: usize = 1;
 pub const MAX_THREADS: usize = 65536;
 pub const MIN_THREADS: usize = 1;
+//Nodes between two checkups of a thread. Small node limits are checked more often
+pub const CHECKUP_INTERVAL: u64 = 4096;
+//Threads running a search need a generous stack for the deep recursion
+#[cfg(not(target_arch = "wasm32"))]
+const SEARCH_STACK_SIZE: usize = 32 * 1024 * 1024;
 
-pub(crate) type Output = BufWriter<Box<dyn io::Write>>;
-
+pub(crate) type Output = BufWriter<Box<dyn io::Write + Send>>;
+//Non-blocking source of input lines. Where the search can not run in the background,
+//the main thread polls it during the search
+pub type InputCallback = Box<dyn FnMut() -> Option<String> + Send>;
 
 #[derive(Copy, Clone)]
 pub enum DepthInformation {
@@ -57,119 +64,155 @@ pub enum DepthInformation {
     UnSearched,
 }
 pub struct InterThreadCommunicationSystem {
-    pub uci_options: UnsafeCell<UCIOptions>,
+    pub uci_options: RwLock<UCIOptions>, //Every thread takes a snapshot when the search starts
     pub best_pv: Mutex<ScoredPrincipalVariation>,
-    pub stable_pv: AtomicBool,
     pub depth_info: Mutex<[DepthInformation; MAX_SEARCH_DEPTH]>,
-    pub start_time: RwLock<Instant>, //Only used for reporting
-    pub nodes_searched: UnsafeCell<Vec<AtomicU64>>, // Only used for reporting
-    pub seldepth: AtomicUsize,       // Only used for reporting
-    pub cache: UnsafeCell<Cache>,    //Only used for reporting
+    //Only used for reporting
+    pub time_to_depth: Mutex<[Option<u64>; MAX_SEARCH_DEPTH]>,
+    pub clock: Box<dyn Clock>,
+    pub start_time: RwLock<u64>, //Read from the clock when the search starts
+    pub nodes_searched: RwLock<Arc<[AtomicU64]>>, // Only used for reporting
+    pub seldepth: AtomicUsize,   // Only used for reporting
+    pub cache: RwLock<Arc<Cache>>,
     pub cache_status: AtomicUsize,
-    pub last_cache_status: Mutex<Option<Instant>>,
-    pub timeout_flag: RwLock<bool>,
-    pub saved_time: AtomicU64,
-    pub tx: RwLock<Vec<Sender<ThreadInstruction>>>,
+    pub last_cache_status: Mutex<Option<u64>>,
+    pub timeout_flag: AtomicBool, //Polled on every checkup of every thread
+    pub pondering: AtomicBool, //The clock only starts on ponderhit, the bestmove waits for ponderhit or stop
+    pub node_limit: AtomicU64, //Nodes of all threads together, u64::MAX without limit
+    pub searchmoves: Mutex<Vec<GameMove>>, //Only these root moves are searched, all if none of them is legal
+    pub tx: RwLock<Vec<Sender<ThreadInstruction>>>, //One sender per helper thread
+    pub tx_f: Mutex<Sender<()>>,           //Helper threads signal finished searches and exits
+    pub rx_f: Mutex<Receiver<()>>,
+    pub input_callback: Mutex<Option<InputCallback>>,
+    pub deferred_input: Mutex<VecDeque<String>>, //Input polled during the search, handled afterwards
     pub output: Mutex<Output>,
 }
 
 impl Default for InterThreadCommunicationSystem {
     fn default() -> Self {
-        // let (tx_f, rx_f) = channel();
-        let output = Box::new(io::stdout());
-        InterThreadCommunicationSystem {
-            uci_options: UnsafeCell::new(UCIOptions::default()),
-            best_pv: Mutex::new(ScoredPrincipalVariation::default()),
-            stable_pv: AtomicBool::new(false),
-            depth_info: Mutex::new([DepthInformation::UnSearched; MAX_SEARCH_DEPTH]),
-            nodes_searched: UnsafeCell::new(Vec::new()),
-            seldepth: AtomicUsize::new(0),
-            start_time: RwLock::new(Instant::now()),
-            last_cache_status: Mutex::new(None),
-            cache_status: AtomicUsize::new(0),
-            cache: UnsafeCell::new(Cache::with_size_threaded(0, 1)),
-            timeout_flag: RwLock::new(false),
-            saved_time: AtomicU64::new(0u64),
-            tx: RwLock::new(Vec::new()),
-            output: Mutex::new(BufWriter::new(output)),
-        }
+        InterThreadCommunicationSystem::default_with_output(io::stdout())
     }
 }
 impl InterThreadCommunicationSystem {
-    pub fn default_with_output<O: Write + 'static>(output: O) -> Self {
+    pub fn default_with_output<O: Write + Send + 'static>(output: O) -> Self {
+        InterThreadCommunicationSystem::with_output_and_clock(
+            output,
+            Box::new(SystemClock::default()),
+        )
+    }
+    pub fn with_output_and_clock<O: Write + Send + 'static>(
+        output: O,
+        clock: Box<dyn Clock>,
+    ) -> Self {
+        let (tx_f, rx_f) = channel();
         InterThreadCommunicationSystem {
-            uci_options: UnsafeCell::new(UCIOptions::default()),
+            uci_options: RwLock::new(UCIOptions::default()),
             best_pv: Mutex::new(ScoredPrincipalVariation::default()),
-            stable_pv: AtomicBool::new(false),
             depth_info: Mutex::new([DepthInformation::UnSearched; MAX_SEARCH_DEPTH]),
-            nodes_searched: UnsafeCell::new(Vec::new()),
+            time_to_depth: Mutex::new([None; MAX_SEARCH_DEPTH]),
+            nodes_searched: RwLock::new(Arc::new([])),
             seldepth: AtomicUsize::new(0),
-            start_time: RwLock::new(Instant::now()),
+            start_time: RwLock::new(clock.now()),
+            clock,
             last_cache_status: Mutex::new(None),
             cache_status: AtomicUsize::new(0),
-            cache: UnsafeCell::new(Cache::with_size_threaded(0, 1)),
-            timeout_flag: RwLock::new(false),
-            saved_time: AtomicU64::new(0u64),
+            cache: RwLock::new(Arc::new(Cache::with_size_threaded(0, 1))),
+            timeout_flag: AtomicBool::new(false),
+            pondering: AtomicBool::new(false),
+            node_limit: AtomicU64::new(u64::MAX),
+            searchmoves: Mutex::new(Vec::new()),
             tx: RwLock::new(Vec::new()),
+            tx_f: Mutex::new(tx_f),
+            rx_f: Mutex::new(rx_f),
+            input_callback: Mutex::new(None),
+            deferred_input: Mutex::new(VecDeque::new()),
             output: Mutex::new(BufWriter::new(Box::new(output))),
         }
     }
 
-    pub fn cache(&self) -> &mut Cache {
-        unsafe { self.cache.get().as_mut().unwrap() }
+    pub fn cache(&self) -> Arc<Cache> {
+        Arc::clone(&self.cache.read().unwrap())
+    }
+    //Searches that are already running keep the old cache
+    pub fn set_cache(&self, cache: Cache) {
+        *self.cache.write().unwrap() = Arc::new(cache);
+    }
+    pub fn uci_options(&self) -> UCIOptions {
+        *self.uci_options.read().unwrap()
     }
-    pub fn uci_options(&self) -> &mut UCIOptions {
-        unsafe { self.uci_options.get().as_mut().unwrap() }
+    pub fn uci_options_mut(&self) -> RwLockWriteGuard<'_, UCIOptions> {
+        self.uci_options.write().unwrap()
     }
-    pub fn nodes_searched(&self) -> &mut Vec<AtomicU64> {
-        unsafe { self.nodes_searched.get().as_mut().unwrap() }
+    pub fn nodes_searched(&self) -> Arc<[AtomicU64]> {
+        Arc::clone(&self.nodes_searched.read().unwrap())
     }
 
     pub fn update_thread_count(
         itcs: &Arc<InterThreadCommunicationSystem>,
         new_thread_count: usize,
     ) {
-        // for tx in itcs.tx.read().unwrap().iter() {
-        //     tx.send(ThreadInstruction::Exit)
-        //         .expect("couldn't send exit flag");
-        // }
-        // for _ in 0..itcs.tx.read().unwrap().len() {
-        //     itcs.rx_f.recv().expect("Couldn't receive exit flag!")
-        // }
-        itcs.uci_options().threads = new_thread_count;
+        //Shut down the old helper threads before spawning new ones
+        {
+            let itcs_tx = itcs.tx.read().unwrap();
+            for tx in itcs_tx.iter() {
+                tx.send(ThreadInstruction::Exit)
+                    .expect("Couldn't send exit flag!");
+            }
+            let rx_f = itcs.rx_f.lock().unwrap();
+            for _ in 0..itcs_tx.len() {
+                rx_f.recv().expect("Couldn't receive exit flag!");
+            }
+        }
+        itcs.uci_options_mut().threads = new_thread_count;
         let itcs_tx = &mut *itcs.tx.write().unwrap();
-        let itcs_nodes_searched = itcs.nodes_searched();
         *itcs_tx = Vec::with_capacity(new_thread_count);
-        *itcs_nodes_searched = Vec::with_capacity(new_thread_count);
-        for _ in 0..new_thread_count {
-            itcs_nodes_searched.push(AtomicU64::new(0));
-            //     let (tx, rx) = channel();
-            //     itcs_tx.push(tx);
-            //     let tx_f = itcs.tx_f.clone();
-            //     let self_arc = Arc::clone(&itcs);
-            //     thread::spawn(move ||
-            //     {
-            //         let mut thread = Thread::new(id, self_arc, rx, tx_f);
-            //         thread.run();
-            //     };
+        *itcs.nodes_searched.write().unwrap() =
+            (0..new_thread_count).map(|_| AtomicU64::new(0)).collect();
+        //Thread 0 searches on the thread calling search_move, all others are helpers
+        #[cfg(not(target_arch = "wasm32"))]
+        for id in 1..new_thread_count {
+            itcs_tx.push(Thread::spawn_helper(id, itcs));
         }
     }
 
+    //The start time is read first, a ponderhit resetting it can't make it later than now
     pub fn get_time_elapsed(&self) -> u64 {
-        let now = Instant::now();
-        let dur = now.duration_since(*self.start_time.read().unwrap());
-        (dur.as_secs() as u64) * 1000
+        let start_time = *self.start_time.read().unwrap();
+        self.clock.now().saturating_sub(start_time)
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::SeqCst)
+    }
+
+    //The search goes on with the time control it was started with, counted from now on
+    pub fn ponderhit(&self) {
+        let mut start_time = self.start_time.write().unwrap();
+        if self.pondering.swap(false, Ordering::SeqCst) {
+            *start_time = self.clock.now();
+        }
+    }
+
+    pub fn stop(&self) {
+        self.timeout_flag.store(true, Ordering::Release);
+        self.pondering.store(false, Ordering::SeqCst);
     }
 
     pub fn update(&self, thread_id: usize, nodes_searched: u64, seldepth: usize) {
         let curr_seldepth = self.seldepth.load(Ordering::Relaxed);
         self.seldepth
             .store(curr_seldepth.max(seldepth), Ordering::Relaxed);
-        self.nodes_searched()[thread_id].store(nodes_searched, Ordering::Relaxed);
+        self.nodes_searched.read().unwrap()[thread_id].store(nodes_searched, Ordering::Relaxed);
+    }
+
+    pub fn node_limit_reached(&self) -> bool {
+        self.get_nodes_sum() >= self.node_limit.load(Ordering::Relaxed)
     }
 
     pub fn get_nodes_sum(&self) -> u64 {
-        self.nodes_searched()
+        self.nodes_searched
+            .read()
+            .unwrap()
             .iter()
             .map(|x| x.load(Ordering::Relaxed))
             .sum()
@@ -179,15 +222,39 @@ impl InterThreadCommunicationSystem {
         self.output.lock().unwrap()
     }
 
-    pub fn register_pv(&self, scored_pv: &ScoredPrincipalVariation, no_fail: bool) {
-        let mut curr_best = self.best_pv.lock().unwrap();
-        self.stable_pv.store(false, Ordering::Relaxed);
-        //Update pv stability
-        if let Some(other_mv) = curr_best.pv.pv[0] {
-            if other_mv == scored_pv.pv.pv[0].unwrap() && no_fail {
-                self.stable_pv.store(true, Ordering::Relaxed);
+    pub fn set_input_callback(&self, callback: InputCallback) {
+        *self.input_callback.lock().unwrap() = Some(callback);
+    }
+
+    //Handles stop and isready right away, everything else is deferred until the search is over
+    pub fn poll_input(&self) {
+        let mut callback = self.input_callback.lock().unwrap();
+        if let Some(callback) = callback.as_mut() {
+            while let Some(line) = callback() {
+                match line.trim() {
+                    "stop" => self.stop(),
+                    "ponderhit" => self.ponderhit(),
+                    "isready" => {
+                        let mut output = self.output();
+                        writeln!(output, "readyok").expect("engine output write failed");
+                        output.flush().expect("engine output flush failed");
+                    }
+                    "quit" => {
+                        self.stop();
+                        self.deferred_input.lock().unwrap().push_back(line);
+                    }
+                    _ => self.deferred_input.lock().unwrap().push_back(line),
+                }
             }
         }
+    }
+
+    pub fn next_deferred_input(&self) -> Option<String> {
+        self.deferred_input.lock().unwrap().pop_front()
+    }
+
+    pub fn register_pv(&self, scored_pv: &ScoredPrincipalVariation, no_fail: bool) {
+        let mut curr_best = self.best_pv.lock().unwrap();
         if curr_best.depth < scored_pv.depth
             || (curr_best.depth == scored_pv.depth && curr_best.score < scored_pv.score)
         {
@@ -199,10 +266,10 @@ impl InterThreadCommunicationSystem {
             let elapsed_time = self.get_time_elapsed();
             let fill_status = self.cache().fill_status();
             //if cache_status.is_none()
-                // || Instant::now()
-                //     .duration_since(cache_status.unwrap())
-                //     .as_millis()
-                //     > 200
+            // || Instant::now()
+            //     .duration_since(cache_status.unwrap())
+            //     .as_millis()
+            //     > 200
             // {
             //     *cache_status = Some(Instant::now());
             //     self.cache_status
@@ -246,9 +313,61 @@ impl InterThreadCommunicationSystem {
                 .expect("Could not unwrap pv for bestmove!")
         )
         .expect("engine output write failed");
+        self.output().flush().expect("engine output flush failed");
+    }
+
+    //Depth information for strategies which choose their depths on their own
+    pub fn claim_depth(&self, from_depth: usize, next_depth: usize) -> bool {
+        let mut depth_info = self.depth_info.lock().unwrap();
+        if from_depth > 0 {
+            depth_info[from_depth - 1] = DepthInformation::FullySearched;
+        }
+        if next_depth > MAX_SEARCH_DEPTH {
+            return false;
+        }
+        match depth_info[next_depth - 1] {
+            DepthInformation::UnSearched => {
+                depth_info[next_depth - 1] = DepthInformation::CurrentlySearchedBy(1);
+                true
+            }
+            DepthInformation::CurrentlySearchedBy(other_threads) => {
+                depth_info[next_depth - 1] =
+                    DepthInformation::CurrentlySearchedBy(other_threads + 1);
+                false
+            }
+            DepthInformation::FullySearched => false,
+        }
     }
 
-    pub fn get_next_depth(&self, mut from_depth: usize) -> (usize, bool) {
+    pub fn depth_completed(&self, depth: usize) {
+        let mut time_to_depth = self.time_to_depth.lock().unwrap();
+        if time_to_depth[depth - 1].is_none() {
+            time_to_depth[depth - 1] = Some(self.get_time_elapsed());
+        }
+    }
+
+    pub fn smp_report(&self) -> SMPReport {
+        SMPReport {
+            strategy: self.uci_options().smp_strategy,
+            time_to_depth: self
+                .time_to_depth
+                .lock()
+                .unwrap()
+                .iter()
+                .enumerate()
+                .filter_map(|(depth, time)| time.map(|time| (depth + 1, time)))
+                .collect(),
+            thread_nodes: self
+                .nodes_searched
+                .read()
+                .unwrap()
+                .iter()
+                .map(|nodes| nodes.load(Ordering::Relaxed))
+                .collect(),
+        }
+    }
+
+    pub fn get_next_depth(&self, mut from_depth: usize, options: &UCIOptions) -> (usize, bool) {
         if from_depth == 0 {
             return (1, true);
         }
@@ -263,9 +382,7 @@ impl InterThreadCommunicationSystem {
                     next_depth += 1;
                 }
                 DepthInformation::CurrentlySearchedBy(other_thread) => {
-                    if other_thread as f64
-                        >= self.uci_options().threads as f64 / self.uci_options().skip_ratio as f64
-                    {
+                    if other_thread as f64 >= options.threads as f64 / options.skip_ratio as f64 {
                         next_depth += 1;
                     } else {
                         depth_info[next_depth] =
@@ -284,33 +401,45 @@ impl InterThreadCommunicationSystem {
         (next_depth + 1, main_thread)
     }
 }
-unsafe impl std::marker::Sync for InterThreadCommunicationSystem {}
 pub enum ThreadInstruction {
     Exit,
-    StartSearch(i16, GameState, TimeControl, History, u64),
+    StartSearch(i16, GameState, TimeManager, History, Vec<GameMove>),
 }
 
 pub struct Thread {
     pub id: usize,
     pub itcs: Arc<InterThreadCommunicationSystem>,
     pub root_plies_played: usize,
+    pub root_moves: Vec<GameMove>, //The legal root moves the search is restricted to
+    pub checkup_interval: u64,
     pub history: History,
     pub movelist: ReservedMoveList,
     pub attack_container: ReservedAttackContainer,
     pub pv_table: Vec<PrincipalVariation>,
-    pub killer_moves: [[Option<GameMove>; 2]; MAX_SEARCH_DEPTH],
+    pub killer_moves: [[Option<Move>; 2]; MAX_SEARCH_DEPTH],
     pub quiets_tried: [[Option<GameMove>; 128]; MAX_SEARCH_DEPTH],
     pub hh_score: [[[usize; 64]; 64]; 2],
     pub bf_score: [[[usize; 64]; 64]; 2],
     pub history_score: [[[isize; 64]; 64]; 2],
+    pub played_moves: [Option<GameMove>; MAX_SEARCH_DEPTH], //None for null moves
+    pub counter_moves: [Option<Move>; 12 * 64],
+    pub continuation_history: [ContinuationHistory; 2], //Indexed by the move 1 and 2 plies ago
+    pub capture_history: CaptureHistory,
+    pub captures_tried: [[Option<GameMove>; MAX_CAPTURES_TRIED]; MAX_SEARCH_DEPTH],
     pub see_buffer: Vec<i16>,
     pub search_statistics: SearchStatistics,
-    pub tc: TimeControl, //Only thread 0 takes care of Timecontrol though
-    pub time_saved: u64,
+    pub time_manager: TimeManager, //Only thread 0 takes care of Timecontrol though
+    pub root_nodes: u64,           //Nodes spent on root moves in the current iteration
+    pub best_move_nodes: u64,
     pub self_stop: bool, //This is set when timeout_stop is set(timeout_stop isn't always polled)
     pub current_pv: ScoredPrincipalVariation,
     pub pv_applicable: Vec<u64>, //Hashes of gamestates the pv plays along
     pub main_thread_in_depth: bool,
+    pub cache: Arc<Cache>,
+    pub uci_options: UCIOptions, //Snapshot taken when the search starts
+    pub deferred_moves: Vec<Vec<(GameMove, i32)>>, //ABDADA: moves busy in other threads, per ply
+    pub ordering_noise: Option<StdRng>,
+    pub tracer: SearchTracer, //Only thread 0 traces
 }
 
 impl Thread {
@@ -342,6 +471,21 @@ impl Thread {
             }
         }
     }
+    //Piece/to-square of the move played the given number of plies before the current node
+    #[inline(always)]
+    pub fn previous_move(
+        &self,
+        current_depth: usize,
+        plies: usize,
+        color_to_move: usize,
+    ) -> Option<usize> {
+        if current_depth < plies {
+            return None;
+        }
+        self.played_moves[current_depth - plies]
+            .map(|mv| piece_square(mv, (color_to_move + plies) % 2))
+    }
+
     fn new(id: usize, itcs: Arc<InterThreadCommunicationSystem>) -> Self {
         let mut pv_table = Vec::with_capacity(MAX_SEARCH_DEPTH);
         for i in 0..MAX_SEARCH_DEPTH {
@@ -349,8 +493,9 @@ impl Thread {
         }
         Thread {
             id,
-            itcs,
             root_plies_played: 0,
+            root_moves: Vec::new(),
+            checkup_interval: CHECKUP_INTERVAL,
             history: History::default(),
             movelist: ReservedMoveList::default(),
             attack_container: ReservedAttackContainer::default(),
@@ -360,14 +505,29 @@ impl Thread {
             hh_score: [[[0; 64]; 64]; 2],
             bf_score: [[[1; 64]; 64]; 2],
             history_score: [[[0; 64]; 64]; 2],
+            played_moves: [None; MAX_SEARCH_DEPTH],
+            counter_moves: [None; 12 * 64],
+            continuation_history: [
+                ContinuationHistory::default(),
+                ContinuationHistory::default(),
+            ],
+            capture_history: CaptureHistory::default(),
+            captures_tried: [[None; MAX_CAPTURES_TRIED]; MAX_SEARCH_DEPTH],
             see_buffer: vec![0i16; MAX_SEARCH_DEPTH],
             search_statistics: SearchStatistics::default(),
-            tc: TimeControl::MoveTime(0u64),
-            time_saved: 0u64,
+            time_manager: TimeManager::new(TimeControl::Infinite, &GameState::standard(), 0),
+            root_nodes: 0,
+            best_move_nodes: 0,
             self_stop: false,
             current_pv: ScoredPrincipalVariation::default(),
             pv_applicable: Vec::with_capacity(MAX_SEARCH_DEPTH),
             main_thread_in_depth: false,
+            cache: itcs.cache(),
+            uci_options: itcs.uci_options(),
+            deferred_moves: vec![Vec::new(); MAX_SEARCH_DEPTH],
+            ordering_noise: None,
+            tracer: SearchTracer::default(),
+            itcs,
         }
     }
 
@@ -375,13 +535,15 @@ impl Thread {
         &mut self,
         max_depth: i16,
         state: GameState,
-        tc: TimeControl,
+        time_manager: TimeManager,
         history: History,
-        time_saved: u64,
+        root_moves: Vec<GameMove>,
     ) {
         self.root_plies_played = (state.full_moves - 1) * 2 + state.color_to_move;
         self.history = history;
-        self.time_saved = time_saved;
+        self.root_moves = root_moves;
+        self.checkup_interval =
+            (self.itcs.node_limit.load(Ordering::Relaxed) / 256).clamp(1, CHECKUP_INTERVAL);
         self.pv_applicable.clear();
         self.current_pv = ScoredPrincipalVariation::default();
         self.main_thread_in_depth = false;
@@ -389,30 +551,68 @@ impl Thread {
         self.hh_score = [[[0; 64]; 64]; 2];
         self.bf_score = [[[1; 64]; 64]; 2];
         self.history_score = [[[0; 64]; 64]; 2];
+        self.counter_moves = [None; 12 * 64];
+        for continuation_history in self.continuation_history.iter_mut() {
+            continuation_history.clear();
+        }
+        self.capture_history.clear();
         self.search_statistics = SearchStatistics::default();
-        self.tc = tc;
+        self.time_manager = time_manager;
+        self.cache = self.itcs.cache();
+        self.uci_options = self.itcs.uci_options();
+        self.ordering_noise = self.uci_options.smp_strategy.ordering_noise(self.id);
         self.self_stop = false;
+        if self.id == 0 {
+            self.tracer.start(&state);
+        }
         self.search(max_depth, state);
-        // self.tx.send(()).expect("Error sending finish flag!");
-    }
-
-    // fn run(&mut self) {
-    //     loop {
-    //         let msg: ThreadInstruction = self.rx.try_recv().unwrap();
-    //         match msg {
-    //             ThreadInstruction::Exit => {
-    //                 self.tx.send(()).expect("Error sending exit flag!");
-    //                 break;
-    //             }
-    //             ThreadInstruction::StartSearch(max_depth, state, tc, history, time_saved) => {
-    //                 self.start_search(max_depth, state, tc, history, time_saved)
-    //             }
-    //         }
-    //     }
-    // }
+        self.tracer.finish();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn spawn_helper(
+        id: usize,
+        itcs: &Arc<InterThreadCommunicationSystem>,
+    ) -> Sender<ThreadInstruction> {
+        let (tx, rx) = channel();
+        let tx_f = itcs.tx_f.lock().unwrap().clone();
+        let self_arc = Arc::clone(itcs);
+        thread::Builder::new()
+            .name(format!("helper {}", id))
+            .stack_size(SEARCH_STACK_SIZE)
+            .spawn(move || {
+                let mut thread = Thread::new(id, self_arc);
+                thread.run(rx, tx_f);
+            })
+            .expect("Couldn't spawn helper thread!");
+        tx
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn run(&mut self, rx: Receiver<ThreadInstruction>, tx_f: Sender<()>) {
+        loop {
+            let msg = rx.recv().expect("Couldn't receive thread instruction!");
+            match msg {
+                ThreadInstruction::Exit => {
+                    tx_f.send(()).expect("Error sending exit flag!");
+                    break;
+                }
+                ThreadInstruction::StartSearch(
+                    max_depth,
+                    state,
+                    time_manager,
+                    history,
+                    root_moves,
+                ) => {
+                    self.start_search(max_depth, state, time_manager, history, root_moves);
+                    tx_f.send(()).expect("Error sending finish flag!");
+                }
+            }
+        }
+    }
 
     fn search(&mut self, max_depth: i16, state: GameState) {
-        if self.itcs.uci_options().debug_print {
+        if self.uci_options.debug_print {
             writeln!(
                 self.output(),
                 "info String Thread {} starting the search of state!",
@@ -423,14 +623,20 @@ impl Thread {
         let mut curr_depth = 0;
         let mut previous_score: Option<i16> = None;
         loop {
-            let temp = self.itcs.get_next_depth(curr_depth);
+            let temp = self.uci_options.smp_strategy.next_depth(
+                &self.itcs,
+                self.id,
+                curr_depth,
+                &self.uci_options,
+            );
             curr_depth = temp.0;
             self.main_thread_in_depth = temp.1;
             if curr_depth as i16 > max_depth {
                 break;
             }
+            self.tracer.iteration(curr_depth);
             //Start Aspiration Window
-            if self.itcs.uci_options().debug_print {
+            if self.uci_options.debug_print {
                 writeln!(
                     self.output(),
                     "info String Thread {} starting aspiration window with depth {}",
@@ -443,7 +649,7 @@ impl Thread {
                 ps.abs() / 50
             } else {
                 0
-            } + 14;
+            } + self.uci_options.search_params.aspiration_delta;
             let mut alpha = if curr_depth == 1 {
                 -16000
             } else {
@@ -455,6 +661,8 @@ impl Thread {
                 self.current_pv.score + delta
             };
             loop {
+                self.root_nodes = 0;
+                self.best_move_nodes = 0;
                 principal_variation_search(
                     CombinedSearchParameters::from(
                         alpha,
@@ -496,8 +704,35 @@ impl Thread {
             if self.self_stop {
                 break;
             }
+            self.itcs.depth_completed(curr_depth);
+            if self.id == 0 {
+                if let Some(best_move) = self.current_pv.pv.pv[0] {
+                    self.time_manager.iteration_completed(
+                        best_move,
+                        self.current_pv.score,
+                        self.best_move_nodes as f64 / self.root_nodes.max(1) as f64,
+                    );
+                }
+                let elapsed = self.itcs.get_time_elapsed();
+                let stop = (!self.itcs.is_pondering() && self.time_manager.stop_iterating(elapsed))
+                    || self.itcs.node_limit_reached();
+                if self.uci_options.debug_print {
+                    writeln!(
+                        self.output(),
+                        "info String depth {} time {} scaled optimum {} stop {}",
+                        curr_depth,
+                        elapsed,
+                        self.time_manager.scaled_optimum(),
+                        stop
+                    )
+                    .expect("engine output write failed");
+                }
+                if stop {
+                    break;
+                }
+            }
         }
-        if self.itcs.uci_options().debug_print {
+        if self.uci_options.debug_print {
             writeln!(
                 self.output(),
                 "info String Thread {} stopping the search of state!",
@@ -512,15 +747,29 @@ impl Thread {
             self.search_statistics.seldepth,
         );
         if self.id == 0 {
-            *self
-                .itcs
-                .timeout_flag
-                .write()
-                .expect("Couldn't write to timeout flag") = true;
+            self.itcs.timeout_flag.store(true, Ordering::Release);
         }
     }
 }
 
+//The search runs on a worker thread, so the caller can keep reading input like stop.
+//Everything a stop command could race with is reset before the worker is spawned
+#[cfg(not(target_arch = "wasm32"))]
+pub fn search_move_in_background(
+    itcs: Arc<InterThreadCommunicationSystem>,
+    max_depth: i16,
+    game_state: GameState,
+    history: Vec<GameState>,
+    tc: TimeControl,
+) -> thread::JoinHandle<Option<i16>> {
+    prepare_search(&itcs);
+    thread::Builder::new()
+        .name("search".to_owned())
+        .stack_size(SEARCH_STACK_SIZE)
+        .spawn(move || run_search(itcs, max_depth, game_state, history, tc))
+        .expect("Couldn't spawn search thread!")
+}
+
 pub fn search_move(
     itcs: Arc<InterThreadCommunicationSystem>,
     max_depth: i16,
@@ -528,23 +777,45 @@ pub fn search_move(
     history: Vec<GameState>,
     tc: TimeControl,
 ) -> Option<i16> {
-    //1. Prepare itcs (reset things from previous search)
+    prepare_search(&itcs);
+    run_search(itcs, max_depth, game_state, history, tc)
+}
+
+//Resets things from the previous search
+fn prepare_search(itcs: &InterThreadCommunicationSystem) {
     *itcs.best_pv.lock().unwrap() = ScoredPrincipalVariation::default();
-    itcs.stable_pv.store(false, Ordering::Relaxed);
     *itcs.depth_info.lock().unwrap() = [DepthInformation::UnSearched; MAX_SEARCH_DEPTH];
-    itcs.nodes_searched()
+    *itcs.time_to_depth.lock().unwrap() = [None; MAX_SEARCH_DEPTH];
+    itcs.nodes_searched
+        .read()
+        .unwrap()
         .iter()
         .for_each(|x| x.store(0u64, Ordering::Relaxed));
     itcs.seldepth.store(0, Ordering::Relaxed);
-    *itcs.start_time.write().unwrap() = Instant::now();
+    *itcs.start_time.write().unwrap() = itcs.clock.now();
     *itcs.last_cache_status.lock().unwrap() = None;
     itcs.cache_status.store(0, Ordering::Relaxed);
-    *itcs.timeout_flag.write().unwrap() = false;
+    itcs.timeout_flag.store(false, Ordering::Release);
+}
+
+//A ponder search which ended on its own holds back its bestmove
+fn wait_for_ponder_end(itcs: &InterThreadCommunicationSystem) {
+    while itcs.is_pondering() {
+        itcs.poll_input();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+}
 
+fn run_search(
+    itcs: Arc<InterThreadCommunicationSystem>,
+    max_depth: i16,
+    game_state: GameState,
+    history: Vec<GameState>,
+    tc: TimeControl,
+) -> Option<i16> {
     let mut thread = Thread::new(0, Arc::clone(&itcs));
 
-    let time_saved_before = itcs.saved_time.load(Ordering::Relaxed);
-    //Step 1. Check how many legal moves there are
+    //Step 1. Check how many legal moves there are, searchmoves may restrict them
     let mut movelist = MoveList::default();
     generate_moves(
         &game_state,
@@ -552,20 +823,35 @@ pub fn search_move(
         &mut movelist,
         &GameStateAttackContainer::from_state(&game_state),
     );
+    let mut root_moves: Vec<GameMove> = movelist
+        .moves()
+        .iter()
+        .map(|mv| mv.0.to_game_move(&game_state))
+        .collect();
+    {
+        let searchmoves = itcs.searchmoves.lock().unwrap();
+        if root_moves.iter().any(|mv| searchmoves.contains(mv)) {
+            root_moves.retain(|mv| searchmoves.contains(mv));
+        }
+    }
 
-    //Step2. Check legal moves
-    if movelist.move_list.is_empty() {
-        panic!("The root position given does not have any legal move!");
-    } else if movelist.move_list.len() == 1 {
-        writeln!(itcs.output(), "bestmove {:?}", movelist.move_list[0].0)
+    //Step2. Check legal moves. Mated and stalemated positions get the null move
+    if root_moves.is_empty() {
+        wait_for_ponder_end(&itcs);
+        let mut output = itcs.output();
+        writeln!(output, "info string The root position has no legal move")
             .expect("engine output write failed");
-
-        let new_timesaved: u64 = (time_saved_before as i64
-            + tc.time_saved(0, time_saved_before, itcs.uci_options().move_overhead))
-        .max(0) as u64;
-        itcs.saved_time.store(new_timesaved, Ordering::Relaxed);
+        writeln!(output, "bestmove 0000").expect("engine output write failed");
+        output.flush().expect("engine output flush failed");
+        return None;
+    } else if root_moves.len() == 1 {
+        wait_for_ponder_end(&itcs);
+        writeln!(itcs.output(), "bestmove {:?}", root_moves[0])
+            .expect("engine output write failed");
+        itcs.output().flush().expect("engine output flush failed");
         return None;
     }
+    let first_move = root_moves[0];
 
     //Step3. Prepare history
     let mut hist: History = History::default();
@@ -581,47 +867,48 @@ pub fn search_move(
     }
 
     //Step 4. Send search command
-    thread.start_search(
-        max_depth,
-        game_state.clone(),
-        tc,
-        hist.clone(),
-        time_saved_before,
-    );
-    /*for tx in itcs.tx.read().unwrap().iter() {
+    let time_manager = TimeManager::new(tc, &game_state, itcs.uci_options().move_overhead);
+    let max_depth = max_depth.min(time_manager.max_depth);
+    if itcs.uci_options().debug_print {
+        writeln!(
+            itcs.output(),
+            "info String optimum time {} maximum time {} max depth {}",
+            time_manager.optimum_time,
+            time_manager.maximum_time,
+            max_depth
+        )
+        .expect("engine output write failed");
+    }
+    for tx in itcs.tx.read().unwrap().iter() {
         tx.send(ThreadInstruction::StartSearch(
             max_depth,
             game_state.clone(),
-            tc,
+            time_manager,
             hist.clone(),
-            time_saved_before,
+            root_moves.clone(),
         ))
         .expect("Couldn't send search command!");
-    }*/
-
-    // //Step 5. Wait until every thread finished up
-    // for _ in 0..itcs.uci_options().threads {
-    //     std::thread::sleep_ms(10000000);
-    //     // loop
-    //     // match itcs.rx_f
-    //     //     .try_recv() {
-
-    //     //     }
-    //     //     .expect("Could not receive finish flag from channel");
-    // }
+    }
+    thread.start_search(max_depth, game_state, time_manager, hist, root_moves);
+
+    //Step 5. Wait until every helper thread finished up. Thread 0 has set the timeout flag
+    {
+        let helpers = itcs.tx.read().unwrap().len();
+        let rx_f = itcs.rx_f.lock().unwrap();
+        for _ in 0..helpers {
+            rx_f.recv()
+                .expect("Could not receive finish flag from channel");
+        }
+    }
 
-    //Step 6. Report to UCI
+    //Step 6. Report to UCI. If the search was stopped before the first iteration, any legal move is better than none
+    itcs.best_pv.lock().unwrap().pv.pv[0].get_or_insert(first_move);
+    if itcs.uci_options().debug_print {
+        writeln!(itcs.output(), "info String {}", itcs.smp_report())
+            .expect("engine output write failed");
+    }
+    wait_for_ponder_end(&itcs);
     itcs.report_bestmove();
-    //Store new saved time
-    let elapsed_time = itcs.get_time_elapsed();
-    let new_timesaved: u64 = (time_saved_before as i64
-        + tc.time_saved(
-            elapsed_time,
-            time_saved_before,
-            itcs.uci_options().move_overhead,
-        ))
-    .max(0) as u64;
-    itcs.saved_time.store(new_timesaved, Ordering::Relaxed);
     //And return
     let best_score = itcs.best_pv.lock().unwrap().score;
     Some(best_score)
@@ -0,0 +1,276 @@
+use super::cache::Cache;
+use super::heuristics::HISTORY_MAX;
+use super::searcher::InterThreadCommunicationSystem;
+use crate::UCIOptions;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fmt::{Display, Formatter, Result};
+
+//Below this depth, deferring busy moves costs more than it saves
+pub const ABDADA_MIN_DEPTH: i16 = 3;
+//Upper bound of the random value helper threads add to quiet move scores
+pub const ORDERING_NOISE: i32 = 4 * HISTORY_MAX / 100;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SMPStrategy {
+    //Helper threads skip depths which are already searched by enough threads, see SMPSkipRatio
+    SkipDepths,
+    //Every thread deepens on its own, helper threads with odd id start one ply deeper
+    DepthOffset,
+    //Moves another thread is currently searching are deferred, using busy flags in the cache
+    ABDADA,
+    //Helper threads add random noise to the quiet move ordering
+    OrderingNoise,
+}
+
+pub const SMP_STRATEGIES: [SMPStrategy; 4] = [
+    SMPStrategy::SkipDepths,
+    SMPStrategy::DepthOffset,
+    SMPStrategy::ABDADA,
+    SMPStrategy::OrderingNoise,
+];
+pub const DEFAULT_SMP_STRATEGY: SMPStrategy = SMPStrategy::SkipDepths;
+
+impl SMPStrategy {
+    pub fn from_name(name: &str) -> Option<SMPStrategy> {
+        SMP_STRATEGIES
+            .iter()
+            .find(|strategy| strategy.to_string().eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    //Returns the next depth to search and whether the thread is the first one in that depth
+    pub fn next_depth(
+        self,
+        itcs: &InterThreadCommunicationSystem,
+        thread_id: usize,
+        from_depth: usize,
+        options: &UCIOptions,
+    ) -> (usize, bool) {
+        match self {
+            SMPStrategy::SkipDepths => itcs.get_next_depth(from_depth, options),
+            _ => {
+                let next_depth =
+                    if from_depth == 0 && self == SMPStrategy::DepthOffset && thread_id % 2 == 1 {
+                        2
+                    } else {
+                        from_depth + 1
+                    };
+                (next_depth, itcs.claim_depth(from_depth, next_depth))
+            }
+        }
+    }
+
+    //Seeded with the thread id, so a search with a given thread count can be reproduced
+    pub fn ordering_noise(self, thread_id: usize) -> Option<StdRng> {
+        if self == SMPStrategy::OrderingNoise && thread_id > 0 {
+            Some(StdRng::seed_from_u64(thread_id as u64))
+        } else {
+            None
+        }
+    }
+}
+
+//ABDADA: A move is deferred while another thread searches it. The first move of a node is
+//never deferred, and neither are the deferred moves when they are searched at the end
+pub fn abdada_defer(cache: &Cache, hash: u64, index: usize, deferred_pass: bool) -> bool {
+    index > 0 && !deferred_pass && cache.is_busy(hash)
+}
+
+impl Display for SMPStrategy {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        write!(
+            formatter,
+            "{}",
+            match self {
+                SMPStrategy::SkipDepths => "SkipDepths",
+                SMPStrategy::DepthOffset => "DepthOffset",
+                SMPStrategy::ABDADA => "ABDADA",
+                SMPStrategy::OrderingNoise => "OrderingNoise",
+            }
+        )
+    }
+}
+
+//Collected over one search, to compare the strategies
+#[derive(Clone, Debug)]
+pub struct SMPReport {
+    pub strategy: SMPStrategy,
+    //Depth and the milliseconds until any thread completed it
+    pub time_to_depth: Vec<(usize, u64)>,
+    pub thread_nodes: Vec<u64>,
+}
+
+impl Display for SMPReport {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        write!(formatter, "SMP {} time to depth", self.strategy)?;
+        for (depth, time) in self.time_to_depth.iter() {
+            write!(formatter, " {}:{}", depth, time)?;
+        }
+        write!(formatter, " thread nodes")?;
+        for nodes in self.thread_nodes.iter() {
+            write!(formatter, " {}", nodes)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_representation::game_state::GameState;
+    use crate::move_generation::makemove::make_move;
+    use crate::perft::legal_moves;
+    use crate::search::searcher::search_move;
+    use crate::search::timecontrol::TimeControl;
+    use crate::search::CombinedSearchParameters;
+    use rand::Rng;
+    use std::sync::Arc;
+
+    fn options(threads: usize) -> UCIOptions {
+        UCIOptions {
+            threads,
+            skip_ratio: 2,
+            ..UCIOptions::default()
+        }
+    }
+
+    #[test]
+    fn skip_depths() {
+        let itcs = InterThreadCommunicationSystem::default();
+        let options = options(4);
+        let next = |from_depth| SMPStrategy::SkipDepths.next_depth(&itcs, 0, from_depth, &options);
+        assert_eq!(next(0), (1, true));
+        //Depth 1 is done, the first thread to finish it opens depth 2
+        assert_eq!(next(1), (2, true));
+        //threads / skip_ratio = 2 threads share a depth, the third one skips ahead
+        assert_eq!(next(1), (2, false));
+        assert_eq!(next(1), (3, true));
+        assert_eq!(next(2), (3, false));
+        assert_eq!(next(2), (4, true));
+    }
+
+    #[test]
+    fn depth_offset() {
+        let itcs = InterThreadCommunicationSystem::default();
+        let options = options(4);
+        let next = |thread_id, from_depth| {
+            SMPStrategy::DepthOffset.next_depth(&itcs, thread_id, from_depth, &options)
+        };
+        assert_eq!(next(0, 0), (1, true));
+        assert_eq!(next(1, 0), (2, true));
+        assert_eq!(next(2, 0), (1, false));
+        assert_eq!(next(3, 0), (2, false));
+        assert_eq!(next(0, 1), (2, false));
+        assert_eq!(next(1, 2), (3, true));
+        //Without the offset every thread starts at depth 1
+        for thread_id in 0..4 {
+            assert_eq!(
+                SMPStrategy::ABDADA
+                    .next_depth(&itcs, thread_id, 0, &options)
+                    .0,
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn abdada_busy_flags() {
+        let cache = Cache::with_size_threaded(1, 1);
+        let state = GameState::standard();
+        let children: Vec<GameState> = legal_moves(&state)
+            .iter()
+            .map(|&mv| make_move(&state, mv))
+            .collect();
+        let (searched, other) = (&children[0], &children[1]);
+        //Positions without an entry can't be marked
+        cache.set_busy(searched.hash, true);
+        assert!(!cache.is_busy(searched.hash));
+
+        let mv = legal_moves(searched)[0];
+        let p = CombinedSearchParameters::from(-100, 100, 5, searched, -1, 1);
+        cache.insert(&p, mv, 0, -100, 0, None);
+        assert!(!cache.is_busy(searched.hash));
+        cache.set_busy(searched.hash, true);
+        assert!(cache.is_busy(searched.hash));
+        assert!(!cache.is_busy(other.hash));
+        //The entry itself is still intact
+        assert_eq!(cache.probe(searched.hash).unwrap().depth, 5);
+
+        assert!(abdada_defer(&cache, searched.hash, 1, false));
+        assert!(!abdada_defer(&cache, searched.hash, 0, false));
+        assert!(!abdada_defer(&cache, searched.hash, 1, true));
+        assert!(!abdada_defer(&cache, other.hash, 1, false));
+
+        cache.set_busy(searched.hash, false);
+        assert!(!cache.is_busy(searched.hash));
+        assert!(!abdada_defer(&cache, searched.hash, 1, false));
+    }
+
+    #[test]
+    fn ordering_noise_per_seed() {
+        let noise = |strategy: SMPStrategy, thread_id| {
+            strategy.ordering_noise(thread_id).map(|mut rng| {
+                (0..100)
+                    .map(|_| rng.gen_range(0, ORDERING_NOISE))
+                    .collect::<Vec<i32>>()
+            })
+        };
+        assert!(noise(SMPStrategy::OrderingNoise, 0).is_none());
+        for &strategy in &[
+            SMPStrategy::SkipDepths,
+            SMPStrategy::DepthOffset,
+            SMPStrategy::ABDADA,
+        ] {
+            assert!(noise(strategy, 1).is_none());
+        }
+        let first = noise(SMPStrategy::OrderingNoise, 1).unwrap();
+        assert_eq!(noise(SMPStrategy::OrderingNoise, 1), Some(first.clone()));
+        assert_ne!(noise(SMPStrategy::OrderingNoise, 2), Some(first.clone()));
+        assert!(first
+            .iter()
+            .all(|&noise| (0..ORDERING_NOISE).contains(&noise)));
+    }
+
+    #[test]
+    fn every_strategy_finds_a_move() {
+        let state =
+            GameState::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let legal = legal_moves(&state);
+        for &strategy in SMP_STRATEGIES.iter() {
+            let state = state.clone();
+            let legal = legal.clone();
+            std::thread::Builder::new()
+                .stack_size(32 * 1024 * 1024)
+                .spawn(move || {
+                    let itcs = Arc::new(InterThreadCommunicationSystem::default_with_output(
+                        std::io::sink(),
+                    ));
+                    itcs.uci_options_mut().smp_strategy = strategy;
+                    InterThreadCommunicationSystem::update_thread_count(&itcs, 3);
+                    itcs.set_cache(Cache::with_size_threaded(1, 3));
+                    let score = search_move(
+                        Arc::clone(&itcs),
+                        6,
+                        state,
+                        Vec::new(),
+                        TimeControl::Infinite,
+                    );
+                    assert!(score.is_some());
+                    let best_move = itcs.best_pv.lock().unwrap().pv.pv[0];
+                    assert!(
+                        best_move.map(|mv| legal.contains(&mv)).unwrap_or(false),
+                        "{} found no legal move",
+                        strategy
+                    );
+                    let report = itcs.smp_report();
+                    assert_eq!(report.strategy, strategy);
+                    assert!(report.thread_nodes.iter().all(|&nodes| nodes > 0));
+                    InterThreadCommunicationSystem::update_thread_count(&itcs, 1);
+                })
+                .unwrap()
+                .join()
+                .unwrap_or_else(|_| panic!("{} search panicked", strategy));
+        }
+    }
+}
@@ -23,6 +23,9 @@ pub struct SearchStatistics {
     pub iid_nodes: u64,
     pub futil_nodes: u64,
     pub history_pruned: u64,
+    pub singular_searches: u64,
+    pub singular_extensions: u64,
+    pub multi_cuts: u64,
 }
 
 impl Default for SearchStatistics {
@@ -50,6 +53,9 @@ impl Default for SearchStatistics {
             iid_nodes: 0,
             futil_nodes: 0,
             history_pruned: 0,
+            singular_searches: 0,
+            singular_extensions: 0,
+            multi_cuts: 0,
         }
     }
 }
@@ -143,6 +149,18 @@ impl SearchStatistics {
     pub fn add_nm_pruning(&mut self) {
         self.nm_pruned += 1;
     }
+    #[inline(always)]
+    pub fn add_singular_search(&mut self) {
+        self.singular_searches += 1;
+    }
+    #[inline(always)]
+    pub fn add_singular_extension(&mut self) {
+        self.singular_extensions += 1;
+    }
+    #[inline(always)]
+    pub fn add_multi_cut(&mut self) {
+        self.multi_cuts += 1;
+    }
 }
 
 impl Display for SearchStatistics {
@@ -218,6 +236,21 @@ impl Display for SearchStatistics {
             self.history_pruned,
             (self.history_pruned as f64 / self.normal_nodes_searched as f64 * 100.0)
         ));
+        res_str.push_str(&format!(
+            "Normal-Search Singular-Searches : {} ({}%)\n",
+            self.singular_searches,
+            (self.singular_searches as f64 / self.normal_nodes_searched as f64 * 100.0)
+        ));
+        res_str.push_str(&format!(
+            "Normal-Search Singular-Extensions : {} ({}%)\n",
+            self.singular_extensions,
+            (self.singular_extensions as f64 / self.singular_searches as f64 * 100.0)
+        ));
+        res_str.push_str(&format!(
+            "Normal-Search Multi-Cuts : {} ({}%)\n",
+            self.multi_cuts,
+            (self.multi_cuts as f64 / self.singular_searches as f64 * 100.0)
+        ));
 
         res_str.push_str("\n");
         res_str.push_str(&format!(
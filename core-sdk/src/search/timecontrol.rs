@@ -1,24 +1,56 @@
+use crate::board_representation::game_state::{GameMove, GameState};
+use crate::search::MAX_SEARCH_DEPTH;
+#[cfg(target_arch = "wasm32")]
+use fake_instant::FakeClock as Instant;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
 pub const DEFAULT_MOVE_OVERHEAD: u64 = 25;
 pub const MIN_MOVE_OVERHEAD: u64 = 0;
 pub const MAX_MOVE_OVERHEAD: u64 = 20000;
 
-pub struct TimeControlInformation {
-    pub time_saved: u64,
-    pub stable_pv: bool,
-    pub high_score_diff: bool,
+//Without movestogo, the amount of moves left is estimated from the game phase
+pub const OPENING_MOVES_TO_GO: f64 = 45.;
+pub const ENDGAME_MOVES_TO_GO: f64 = 20.;
+pub const INCREMENT_USAGE: f64 = 0.75;
+//Maximum time is bounded both relative to the optimum and to the remaining clock
+pub const MAX_OPTIMUM_SCALE: f64 = 5.;
+pub const MAX_CLOCK_FRACTION: f64 = 0.8;
+//A new iteration is only started if it is likely to finish before the optimum time
+pub const NEW_ITERATION_RATIO: f64 = 0.6;
+pub const BEST_MOVE_CHANGE_DECAY: f64 = 0.5;
+pub const BEST_MOVE_CHANGE_WEIGHT: f64 = 0.4;
+pub const SCORE_DROP_CAP: i32 = 100;
+pub const SCORE_DROP_WEIGHT: f64 = 0.5;
+//The more nodes the best move needed, the clearer it is the best
+pub const NODE_FRACTION_BASE: f64 = 1.6;
+pub const NODE_FRACTION_SCALE: f64 = 1.25;
+//Below this much clock left after the move overhead, the search depth is limited
+pub const EMERGENCY_CLOCK: u64 = 200;
+pub const EMERGENCY_DEPTH: i16 = 4;
+
+//Milliseconds since some fixed point in time. The search reads the time only through a clock,
+//so tests can drive it by hand
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u64;
 }
 
-impl TimeControlInformation {
-    pub fn new(time_saved: u64) -> Self {
-        TimeControlInformation {
-            time_saved,
-            stable_pv: false,
-            high_score_diff: false,
-        }
+//The real time, counted from the creation of the clock
+pub struct SystemClock(Instant);
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock(Instant::now())
     }
 }
 
-#[derive(Clone, Copy)]
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        Instant::now().duration_since(self.0).as_millis() as u64
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum TimeControl {
     Incremental(u64, u64),
     MoveTime(u64),
@@ -50,21 +82,23 @@ impl TimeControl {
     pub fn update(&mut self, time_spent: u64, tournament_info: Option<(usize, u64)>) {
         match self {
             TimeControl::Incremental(left, inc) => {
-                assert!(*left > time_spent);
-                *self = TimeControl::Incremental(*left - time_spent + *inc, *inc);
+                *self = TimeControl::Incremental(left.saturating_sub(time_spent) + *inc, *inc);
             }
             TimeControl::MoveTime(time) => {
                 *self = TimeControl::MoveTime(*time);
             }
-            TimeControl::Infinite => panic!("Should not call updat eon Infinite"),
+            TimeControl::Infinite => {}
             TimeControl::Tournament(left, inc, movestogo) => {
-                assert!(*left > time_spent);
-                let mut new_left = *left - time_spent + *inc;
-                if *movestogo == 0 {
-                    new_left += tournament_info.unwrap().1;
-                    *movestogo = tournament_info.unwrap().0;
+                let mut new_left = left.saturating_sub(time_spent) + *inc;
+                let mut new_movestogo = movestogo.saturating_sub(1);
+                //A new session starts, tournament_info holds its moves and time
+                if new_movestogo == 0 {
+                    if let Some((moves, time)) = tournament_info {
+                        new_left += time;
+                        new_movestogo = moves;
+                    }
                 }
-                *self = TimeControl::Tournament(new_left, *inc, *movestogo);
+                *self = TimeControl::Tournament(new_left, *inc, new_movestogo);
             }
         }
     }
@@ -72,125 +106,433 @@ impl TimeControl {
         match self {
             TimeControl::Incremental(left, _) => *left,
             TimeControl::MoveTime(left) => *left,
-            TimeControl::Infinite => panic!("Should not call time_left on Infinite"),
+            TimeControl::Infinite => u64::MAX,
             TimeControl::Tournament(left, _, _) => *left,
         }
     }
-    pub fn time_over(
-        &self,
-        time_spent: u64,
-        tc_information: &TimeControlInformation,
-        move_overhead: u64,
-    ) -> bool {
-        if let TimeControl::Incremental(mytime, myinc) = self {
-            if time_spent as isize > *mytime as isize - 4 * move_overhead as isize {
-                return true;
-            }
-            let normal_time = ((*mytime as f64 - tc_information.time_saved as f64) / 30.0) as u64
-                + myinc
-                - move_overhead;
-            let time_aspired = if tc_information.time_saved < normal_time {
-                ((normal_time as f64 * 0.85) as u64).max(*myinc)
-            } else {
-                normal_time.max(*myinc)
-            };
-            if time_spent < time_aspired {
-                return false;
+
+    //Moves which still have to be played with the remaining time
+    pub fn moves_to_go(&self, phase: f64) -> f64 {
+        let estimate =
+            ENDGAME_MOVES_TO_GO + (OPENING_MOVES_TO_GO - ENDGAME_MOVES_TO_GO) * phase / 128.;
+        match self {
+            TimeControl::Tournament(_, _, movestogo) => estimate.min((*movestogo).max(1) as f64),
+            _ => estimate,
+        }
+    }
+
+    //Returns the optimum and the maximum time for the next move
+    pub fn time_limits(&self, phase: f64, move_overhead: u64) -> (u64, u64) {
+        match self {
+            TimeControl::Incremental(time, inc) | TimeControl::Tournament(time, inc, _) => {
+                let available = time.saturating_sub(move_overhead) as f64;
+                let optimum = available / self.moves_to_go(phase) + *inc as f64 * INCREMENT_USAGE;
+                let maximum = (optimum * MAX_OPTIMUM_SCALE).min(available * MAX_CLOCK_FRACTION);
+                (optimum.min(maximum) as u64, maximum as u64)
             }
-            if tc_information.stable_pv && !tc_information.high_score_diff {
-                return true;
+            TimeControl::MoveTime(time) => {
+                let time = time.saturating_sub(move_overhead);
+                (time, time)
             }
-            if tc_information.high_score_diff {
-                return time_spent as f64 > 0.85 * (normal_time + tc_information.time_saved) as f64;
+            TimeControl::Infinite => (u64::MAX, u64::MAX),
+        }
+    }
+}
+
+//Decides when the main thread stops searching. The limits are computed once when the search starts,
+//the optimum time is scaled after every iteration
+#[derive(Clone, Copy)]
+pub struct TimeManager {
+    pub tc: TimeControl,
+    pub optimum_time: u64,
+    pub maximum_time: u64,
+    pub scale: f64,
+    //Emergency mode on very low clocks: only a shallow search, with no time left only depth 1
+    pub max_depth: i16,
+    best_move: Option<GameMove>,
+    best_move_changes: f64,
+    previous_score: Option<i16>,
+}
+
+impl TimeManager {
+    pub fn new(tc: TimeControl, state: &GameState, move_overhead: u64) -> Self {
+        let (optimum_time, maximum_time) = tc.time_limits(state.phase.phase, move_overhead);
+        let max_depth = match tc {
+            TimeControl::Incremental(time, _) | TimeControl::Tournament(time, _, _) => {
+                match time.saturating_sub(move_overhead) {
+                    0 => 1,
+                    available if available < EMERGENCY_CLOCK => EMERGENCY_DEPTH,
+                    _ => MAX_SEARCH_DEPTH as i16,
+                }
             }
-            //Non stable pv so we increase time
-            return time_spent as f64 > 1.15 * (normal_time + tc_information.time_saved) as f64;
-        } else if let TimeControl::MoveTime(move_time) = self {
-            return time_spent > move_time - move_overhead || *move_time < move_overhead;
-        } else if let TimeControl::Infinite = self {
-            return false;
-        } else if let TimeControl::Tournament(mytime, myinc, movestogo) = self {
-            if time_spent as isize > *mytime as isize - 4 * move_overhead as isize {
-                return true;
+            _ => MAX_SEARCH_DEPTH as i16,
+        };
+        TimeManager {
+            tc,
+            optimum_time,
+            maximum_time,
+            scale: 1.,
+            max_depth,
+            best_move: None,
+            best_move_changes: 0.,
+            previous_score: None,
+        }
+    }
+
+    fn is_flexible(&self) -> bool {
+        matches!(
+            self.tc,
+            TimeControl::Incremental(..) | TimeControl::Tournament(..)
+        )
+    }
+
+    pub fn scaled_optimum(&self) -> u64 {
+        if self.is_flexible() {
+            ((self.optimum_time as f64 * self.scale) as u64).min(self.maximum_time)
+        } else {
+            self.maximum_time
+        }
+    }
+
+    //best_move_node_fraction is the share of the root nodes the best move needed in this iteration
+    pub fn iteration_completed(
+        &mut self,
+        best_move: GameMove,
+        score: i16,
+        best_move_node_fraction: f64,
+    ) {
+        self.best_move_changes *= BEST_MOVE_CHANGE_DECAY;
+        if self.best_move.map(|mv| mv != best_move).unwrap_or(false) {
+            self.best_move_changes += 1.;
+        }
+        self.best_move = Some(best_move);
+        let score_drop = self
+            .previous_score
+            .map(|previous| (previous as i32 - score as i32).clamp(0, SCORE_DROP_CAP))
+            .unwrap_or(0);
+        self.previous_score = Some(score);
+
+        let instability = 1. + BEST_MOVE_CHANGE_WEIGHT * self.best_move_changes;
+        let falling_score = 1. + SCORE_DROP_WEIGHT * score_drop as f64 / SCORE_DROP_CAP as f64;
+        let node_fraction =
+            (NODE_FRACTION_BASE - best_move_node_fraction.clamp(0., 1.)) * NODE_FRACTION_SCALE;
+        self.scale = instability * falling_score * node_fraction;
+    }
+
+    //Checked after every completed iteration
+    pub fn stop_iterating(&self, elapsed: u64) -> bool {
+        self.is_flexible() && elapsed as f64 >= self.scaled_optimum() as f64 * NEW_ITERATION_RATIO
+    }
+
+    //Checked during the search. The first iteration always finishes, so there is a move to play
+    pub fn time_over(&self, elapsed: u64) -> bool {
+        self.best_move.is_some() && elapsed >= self.scaled_optimum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_representation::game_state::{GameMoveType, PieceType};
+    use crate::search::cache::Cache;
+    use crate::search::searcher::{search_move, InterThreadCommunicationSystem};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    //Only moves when the test advances it
+    #[derive(Clone, Default)]
+    struct ManualClock(Arc<AtomicU64>);
+
+    impl Clock for ManualClock {
+        fn now(&self) -> u64 {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    struct Iteration {
+        duration: u64,
+        best_move: GameMove,
+        score: i16,
+        node_fraction: f64,
+    }
+
+    fn mv(from: u8, to: u8) -> GameMove {
+        GameMove {
+            from,
+            to,
+            move_type: GameMoveType::Quiet,
+            piece_type: PieceType::Knight,
+        }
+    }
+
+    //Every iteration takes twice as long as the one before
+    fn iterations(result: impl Fn(usize) -> (GameMove, i16, f64)) -> Vec<Iteration> {
+        (0..20)
+            .map(|i| {
+                let (best_move, score, node_fraction) = result(i);
+                Iteration {
+                    duration: 1 << i,
+                    best_move,
+                    score,
+                    node_fraction,
+                }
+            })
+            .collect()
+    }
+
+    //Replays a search millisecond by millisecond, checking the time like the main thread does.
+    //Returns the amount of completed iterations and the time used
+    fn replay(mut time_manager: TimeManager, iterations: &[Iteration]) -> (usize, u64) {
+        let mut elapsed = 0;
+        for (completed, iteration) in iterations.iter().enumerate() {
+            for _ in 0..iteration.duration {
+                elapsed += 1;
+                if time_manager.time_over(elapsed) {
+                    return (completed, elapsed);
+                }
             }
-            let normal_time = ((*mytime as f64 - tc_information.time_saved as f64)
-                / *movestogo as f64) as u64
-                + myinc
-                - move_overhead;
-            let time_aspired = if tc_information.time_saved < normal_time {
-                (normal_time as f64 * 0.85) as u64
-            } else {
-                normal_time
-            };
-            if time_spent < time_aspired {
-                return false;
+            time_manager.iteration_completed(
+                iteration.best_move,
+                iteration.score,
+                iteration.node_fraction,
+            );
+            if time_manager.stop_iterating(elapsed) {
+                return (completed + 1, elapsed);
             }
-            if tc_information.stable_pv {
-                return true;
+        }
+        (iterations.len(), elapsed)
+    }
+
+    fn incremental(time: u64, inc: u64) -> TimeManager {
+        TimeManager::new(
+            TimeControl::Incremental(time, inc),
+            &GameState::standard(),
+            DEFAULT_MOVE_OVERHEAD,
+        )
+    }
+
+    #[test]
+    fn unstable_best_move_uses_more_time() {
+        let stable = iterations(|_| (mv(1, 18), 20, 0.9));
+        let unstable = iterations(|i| (mv(1, 16 + i as u8 % 2), 20, 0.3));
+        let (stable_depth, stable_time) = replay(incremental(60000, 0), &stable);
+        let (unstable_depth, unstable_time) = replay(incremental(60000, 0), &unstable);
+        assert!(stable_depth < unstable_depth);
+        assert!(stable_time < incremental(60000, 0).optimum_time);
+        assert!(unstable_time > incremental(60000, 0).optimum_time);
+        assert!(unstable_time <= incremental(60000, 0).maximum_time);
+    }
+
+    #[test]
+    fn score_drop_extends_search() {
+        let steady = iterations(|_| (mv(1, 18), 20, 0.6));
+        let falling = iterations(|i| (mv(1, 18), 20 - 60 * i as i16, 0.6));
+        assert!(
+            replay(incremental(10000, 100), &steady).1
+                < replay(incremental(10000, 100), &falling).1
+        );
+    }
+
+    #[test]
+    fn never_exceeds_maximum_time() {
+        let chaotic = iterations(|i| (mv(1, 16 + i as u8 % 3), 500 - 200 * i as i16, 0.));
+        for &(time, inc) in &[(100, 0), (1000, 10), (60000, 1000), (300_000, 0)] {
+            let time_manager = incremental(time, inc);
+            let (_, used) = replay(time_manager, &chaotic);
+            assert!(used <= time_manager.maximum_time);
+            assert!(time_manager.maximum_time < time);
+        }
+    }
+
+    struct IterationDecision {
+        depth: usize,
+        elapsed: u64,
+        scaled_optimum: u64,
+        stop: bool,
+    }
+
+    //Replays a real single threaded search against a manual clock. Every checkup of the main
+    //thread advances the clock by ms_per_checkup. Returns the decision after every completed
+    //iteration and the time the whole search took
+    fn replay_search(tc: TimeControl, ms_per_checkup: u64) -> (Vec<IterationDecision>, u64) {
+        let path = std::env::temp_dir().join(format!(
+            "timecontrol_replay_{}_{:?}.txt",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        let output = std::fs::File::create(&path).unwrap();
+        let clock = ManualClock::default();
+        let itcs = Arc::new(InterThreadCommunicationSystem::with_output_and_clock(
+            output,
+            Box::new(clock.clone()),
+        ));
+        InterThreadCommunicationSystem::update_thread_count(&itcs, 1);
+        itcs.set_cache(Cache::with_size_threaded(1, 1));
+        itcs.uci_options_mut().debug_print = true;
+        itcs.set_input_callback(Box::new(move || {
+            clock.0.fetch_add(ms_per_checkup, Ordering::Relaxed);
+            None
+        }));
+        search_move(
+            Arc::clone(&itcs),
+            MAX_SEARCH_DEPTH as i16,
+            GameState::standard(),
+            Vec::new(),
+            tc,
+        );
+        let elapsed = itcs.get_time_elapsed();
+        let output = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(output.contains("bestmove"));
+        let decisions = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("info String depth "))
+            .map(|line| {
+                let values: Vec<&str> = line.split_whitespace().collect();
+                IterationDecision {
+                    depth: values[0].parse().unwrap(),
+                    elapsed: values[2].parse().unwrap(),
+                    scaled_optimum: values[5].parse().unwrap(),
+                    stop: values[7].parse().unwrap(),
+                }
+            })
+            .collect();
+        (decisions, elapsed)
+    }
+
+    //A new iteration starts exactly as long as the time manager allows it, and an iteration
+    //which is not allowed to finish is cut at the scaled optimum
+    fn check_decisions(tc: TimeControl, ms_per_checkup: u64) -> (Vec<IterationDecision>, u64) {
+        let (decisions, elapsed) = replay_search(tc, ms_per_checkup);
+        let time_manager = TimeManager::new(tc, &GameState::standard(), DEFAULT_MOVE_OVERHEAD);
+        assert!(!decisions.is_empty());
+        for (i, decision) in decisions.iter().enumerate() {
+            assert_eq!(decision.depth, i + 1);
+            assert_eq!(
+                decision.stop,
+                time_manager.is_flexible()
+                    && decision.elapsed as f64
+                        >= decision.scaled_optimum as f64 * NEW_ITERATION_RATIO
+            );
+            assert!(decision.scaled_optimum <= time_manager.maximum_time);
+            if i + 1 < decisions.len() {
+                assert!(!decision.stop, "iteration {} should have stopped", i + 1);
+                assert!(decision.elapsed < decision.scaled_optimum);
             }
-            //Non stable pv so we increase time
-            return time_spent as f64 > 1.15 * (normal_time + tc_information.time_saved) as f64;
         }
-        panic!("Invalid Timecontrol");
-    }
-
-    pub fn time_saved(&self, time_spent: u64, saved: u64, move_overhead: u64) -> i64 {
-        if let TimeControl::Incremental(mytime, myinc) = self {
-            let normal_timecontrol =
-                ((*mytime as f64 - saved as f64) / 30.0) as u64 + myinc - move_overhead;
-            normal_timecontrol as i64 - time_spent as i64
-        } else if let TimeControl::Tournament(mytime, myinc, movestogo) = self {
-            let normal_timecontrol = ((*mytime as f64 - saved as f64) / *movestogo as f64) as u64
-                + myinc
-                - move_overhead;
-            normal_timecontrol as i64 - time_spent as i64
+        let last = decisions.last().unwrap();
+        if last.stop {
+            assert_eq!(elapsed, last.elapsed);
         } else {
-            0
+            assert!(elapsed >= last.scaled_optimum);
+            assert!(elapsed < last.scaled_optimum + ms_per_checkup);
         }
+        assert!(elapsed < time_manager.maximum_time + ms_per_checkup);
+        (decisions, elapsed)
     }
 
-    pub fn as_string(&self, tc_information: &TimeControlInformation, move_overhead: u64) -> String {
-        let mut res_str: String = String::new();
-        if let TimeControl::Incremental(mytime, myinc) = self {
-            res_str.push_str(&format!("My Time: {}\n", mytime));
-            res_str.push_str(&format!("My Inc: {}\n", myinc));
-            let normal_time = ((*mytime as f64 - tc_information.time_saved as f64) / 30.0) as u64
-                + myinc
-                - move_overhead;
-            let time_aspired = if tc_information.time_saved < normal_time {
-                ((normal_time as f64 * 0.85) as u64).max(*myinc)
-            } else {
-                normal_time.max(*myinc)
-            };
-            res_str.push_str(&format!("My normal time I would spend: {}\n", normal_time));
-            res_str.push_str(&format!(
-                "My aspired time I would spend: {}\n",
-                time_aspired
-            ));
-        } else if let TimeControl::MoveTime(time) = self {
-            res_str.push_str(&format!("Limited movetime: {}\n", time));
-        } else if let TimeControl::Infinite = self {
-            res_str.push_str("Infinite Time!\n");
-        } else if let TimeControl::Tournament(mytime, myinc, movestogo) = self {
-            res_str.push_str(&format!("My Time: {}\n", mytime));
-            res_str.push_str(&format!("My Inc: {}\n", myinc));
-            res_str.push_str(&format!("Moves to go : {}\n", movestogo));
-            let normal_time = ((*mytime as f64 - tc_information.time_saved as f64)
-                / *movestogo as f64) as u64
-                + myinc
-                - move_overhead;
-            let time_aspired = if tc_information.time_saved < normal_time {
-                (normal_time as f64 * 0.85) as u64
-            } else {
-                normal_time
-            };
-            res_str.push_str(&format!("My normal time I would spend: {}\n", normal_time));
-            res_str.push_str(&format!(
-                "My aspired time I would spend: {}\n",
-                time_aspired
-            ));
-        }
+    #[test]
+    fn search_stop_decisions() {
+        //The search runs on the calling thread, which needs a large stack
+        std::thread::Builder::new()
+            .stack_size(32 * 1024 * 1024)
+            .spawn(|| {
+                //A slower search completes fewer iterations in the same time
+                let (fast, _) = check_decisions(TimeControl::Incremental(10000, 100), 5);
+                let (slow, _) = check_decisions(TimeControl::Incremental(10000, 100), 20);
+                assert!(slow.len() < fast.len());
+                assert!(fast.iter().chain(slow.iter()).any(|decision| decision.stop));
+                check_decisions(TimeControl::Tournament(3000, 0, 5), 10);
+                //A fixed move time is never cut short, the search ends exactly when the time is up
+                let (decisions, elapsed) = check_decisions(TimeControl::MoveTime(300), 5);
+                assert!(decisions.iter().all(|decision| !decision.stop));
+                assert_eq!(elapsed, 300 - DEFAULT_MOVE_OVERHEAD);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn moves_to_go_from_phase() {
+        let endgame = GameState::from_fen("8/5k2/3p4/8/8/4P3/3K4/8 w - - 0 60");
+        let opening = incremental(60000, 0);
+        let late = TimeManager::new(TimeControl::Incremental(60000, 0), &endgame, 0);
+        assert!(opening.optimum_time < late.optimum_time);
+        assert_eq!(late.optimum_time, 60000 / 20);
+        //movestogo bounds the estimate
+        let session = TimeManager::new(TimeControl::Tournament(60000, 0, 10), &endgame, 0);
+        assert_eq!(session.optimum_time, 60000 / 10);
+    }
+
+    #[test]
+    fn movetime_uses_full_time() {
+        let time_manager = TimeManager::new(
+            TimeControl::MoveTime(1000),
+            &GameState::standard(),
+            DEFAULT_MOVE_OVERHEAD,
+        );
+        let stable = iterations(|_| (mv(1, 18), 20, 0.9));
+        assert_eq!(
+            replay(time_manager, &stable),
+            (9, 1000 - DEFAULT_MOVE_OVERHEAD)
+        );
+    }
+
+    #[test]
+    fn clock_updates_saturate() {
+        let mut tc = TimeControl::Incremental(100, 50);
+        tc.update(250, None);
+        assert_eq!(tc.time_left(), 50);
+        let mut tc = TimeControl::Tournament(100, 0, 1);
+        tc.update(1000, Some((40, 60000)));
+        assert_eq!(tc.to_go(true), "wtime 60000 winc 0 movestogo 40");
+        let mut tc = TimeControl::Infinite;
+        tc.update(1000, None);
+        assert_eq!(tc.time_left(), u64::MAX);
+    }
+
+    #[test]
+    fn overhead_larger_than_clock() {
+        let phase = GameState::standard().phase.phase;
+        assert_eq!(
+            TimeControl::Incremental(10, 5).time_limits(phase, 100),
+            (0, 0)
+        );
+        assert_eq!(TimeControl::MoveTime(50).time_limits(phase, 100), (0, 0));
+        assert_eq!(
+            TimeControl::Tournament(0, 0, 0).time_limits(phase, 25),
+            (0, 0)
+        );
+        let (optimum, maximum) = TimeControl::Incremental(1000, 10).time_limits(phase, 100);
+        assert!(optimum <= maximum && maximum < 1000 - 100);
+    }
+
+    #[test]
+    fn movestogo_zero_counts_as_one() {
+        let phase = GameState::standard().phase.phase;
+        assert_eq!(TimeControl::Tournament(1000, 0, 0).moves_to_go(phase), 1.);
+        assert_eq!(
+            TimeControl::Tournament(1000, 0, 0).time_limits(phase, 0),
+            TimeControl::Tournament(1000, 0, 1).time_limits(phase, 0)
+        );
+    }
 
-        res_str
+    #[test]
+    fn emergency_mode() {
+        assert_eq!(incremental(60000, 0).max_depth, MAX_SEARCH_DEPTH as i16);
+        assert_eq!(incremental(150, 1000).max_depth, EMERGENCY_DEPTH);
+        assert_eq!(incremental(DEFAULT_MOVE_OVERHEAD, 1000).max_depth, 1);
+        assert_eq!(incremental(0, 0).max_depth, 1);
+        //Without time left the best move of the first iteration is played right away
+        let stable = iterations(|_| (mv(1, 18), 20, 0.9));
+        assert_eq!(replay(incremental(0, 0), &stable), (1, 1));
+        assert_eq!(
+            replay(
+                TimeManager::new(TimeControl::MoveTime(0), &GameState::standard(), 25),
+                &stable
+            ),
+            (1, 2)
+        );
     }
 }
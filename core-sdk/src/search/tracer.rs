@@ -0,0 +1,527 @@
+//Search tracer: with the search-tracer feature the main thread records every node of
+//principal_variation_search and q_search to TRACE_PATH. Without it SearchTracer does nothing
+#[cfg(feature = "search-tracer")]
+use crate::board_representation::game_state::GameState;
+use crate::board_representation::game_state::{GameMove, GameMoveType, PieceType};
+use crate::search::CombinedSearchParameters;
+use std::fmt::{Display, Formatter, Result};
+use std::fs::File;
+#[cfg(feature = "search-tracer")]
+use std::io::BufWriter;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+pub const TRACE_PATH: &str = "search_trace.fct";
+const TRACE_MAGIC: &str = "FCTRACE1";
+const NO_SCORE: i16 = i16::MIN;
+const FLAG_QUIESCENCE: u8 = 1;
+const FLAG_TT_HIT: u8 = 2;
+const FLAG_EXCLUDED: u8 = 4;
+
+//What ended the node, or why a move was not searched at all
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TraceDecision {
+    Searched,
+    Stopped,
+    MaxDepth,
+    Draw,
+    MateDistance,
+    Quiescence,
+    TTCutoff,
+    StaticNullMove,
+    NullMove,
+    MultiCut,
+    BetaCutoff,
+    StandPat,
+    DeltaPruning,
+    FutilityPruned,
+    HistoryPruned,
+    SEEPruned,
+}
+
+pub const TRACE_DECISIONS: [TraceDecision; 16] = [
+    TraceDecision::Searched,
+    TraceDecision::Stopped,
+    TraceDecision::MaxDepth,
+    TraceDecision::Draw,
+    TraceDecision::MateDistance,
+    TraceDecision::Quiescence,
+    TraceDecision::TTCutoff,
+    TraceDecision::StaticNullMove,
+    TraceDecision::NullMove,
+    TraceDecision::MultiCut,
+    TraceDecision::BetaCutoff,
+    TraceDecision::StandPat,
+    TraceDecision::DeltaPruning,
+    TraceDecision::FutilityPruned,
+    TraceDecision::HistoryPruned,
+    TraceDecision::SEEPruned,
+];
+
+impl TraceDecision {
+    pub fn from_name(name: &str) -> Option<TraceDecision> {
+        TRACE_DECISIONS
+            .iter()
+            .find(|decision| format!("{}", decision).eq_ignore_ascii_case(name))
+            .copied()
+    }
+}
+
+impl Display for TraceDecision {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        write!(
+            formatter,
+            "{}",
+            match self {
+                TraceDecision::Searched => "searched",
+                TraceDecision::Stopped => "stopped",
+                TraceDecision::MaxDepth => "max-depth",
+                TraceDecision::Draw => "draw",
+                TraceDecision::MateDistance => "mate-distance",
+                TraceDecision::Quiescence => "quiescence",
+                TraceDecision::TTCutoff => "tt-cutoff",
+                TraceDecision::StaticNullMove => "static-null-move",
+                TraceDecision::NullMove => "null-move",
+                TraceDecision::MultiCut => "multi-cut",
+                TraceDecision::BetaCutoff => "beta-cutoff",
+                TraceDecision::StandPat => "stand-pat",
+                TraceDecision::DeltaPruning => "delta-pruning",
+                TraceDecision::FutilityPruned => "futility-pruned",
+                TraceDecision::HistoryPruned => "history-pruned",
+                TraceDecision::SEEPruned => "see-pruned",
+            }
+        )
+    }
+}
+
+//From, to and promotion piece in 16 bits, 0 is the null move
+pub fn encode_move(mv: Option<GameMove>) -> u16 {
+    match mv {
+        None => 0,
+        Some(mv) => {
+            let promotion = match mv.move_type {
+                GameMoveType::Promotion(PieceType::Queen, _) => 1,
+                GameMoveType::Promotion(PieceType::Rook, _) => 2,
+                GameMoveType::Promotion(PieceType::Bishop, _) => 3,
+                GameMoveType::Promotion(PieceType::Knight, _) => 4,
+                _ => 0,
+            };
+            u16::from(mv.from) | u16::from(mv.to) << 6 | promotion << 12
+        }
+    }
+}
+
+pub fn move_to_string(mv: u16) -> String {
+    if mv == 0 {
+        return "null".to_owned();
+    }
+    let square = |sq: u16| format!("{}{}", (b'a' + (sq % 8) as u8) as char, sq / 8 + 1);
+    format!(
+        "{}{}{}",
+        square(mv & 63),
+        square((mv >> 6) & 63),
+        ["", "q", "r", "b", "n"][(mv >> 12) as usize % 5]
+    )
+}
+
+pub struct TraceRecord {
+    pub iteration: u8,
+    pub path: Vec<u16>,
+    pub quiescence: bool,
+    pub excluded: bool,
+    pub alpha: i16,
+    pub beta: i16,
+    pub depth: i16,
+    pub reduction: i16,
+    pub static_eval: Option<i16>,
+    pub tt_hit: bool,
+    pub decision: TraceDecision,
+    pub score: Option<i16>,
+}
+
+impl TraceRecord {
+    pub fn ply(&self) -> usize {
+        self.path.len()
+    }
+
+    pub fn path_string(&self) -> String {
+        self.path
+            .iter()
+            .map(|mv| move_to_string(*mv))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut flags = 0;
+        if self.quiescence {
+            flags |= FLAG_QUIESCENCE;
+        }
+        if self.tt_hit {
+            flags |= FLAG_TT_HIT;
+        }
+        if self.excluded {
+            flags |= FLAG_EXCLUDED;
+        }
+        let decision = TRACE_DECISIONS
+            .iter()
+            .position(|d| *d == self.decision)
+            .unwrap() as u8;
+        let mut buffer = Vec::with_capacity(16 + 2 * self.path.len());
+        buffer.extend_from_slice(&[self.iteration, self.path.len() as u8, flags, decision]);
+        for value in [
+            self.alpha,
+            self.beta,
+            self.depth,
+            self.reduction,
+            self.static_eval.unwrap_or(NO_SCORE),
+            self.score.unwrap_or(NO_SCORE),
+        ]
+        .iter()
+        {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        for mv in self.path.iter() {
+            buffer.extend_from_slice(&mv.to_le_bytes());
+        }
+        writer.write_all(&buffer)
+    }
+
+    //Returns None at the end of the file
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Option<TraceRecord>> {
+        let mut head = [0u8; 16];
+        match reader.read_exact(&mut head) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let value = |i: usize| i16::from_le_bytes([head[4 + 2 * i], head[5 + 2 * i]]);
+        let optional = |v: i16| if v == NO_SCORE { None } else { Some(v) };
+        let decision = *TRACE_DECISIONS
+            .get(head[3] as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid decision"))?;
+        let mut path_bytes = vec![0u8; 2 * head[1] as usize];
+        reader.read_exact(&mut path_bytes)?;
+        Ok(Some(TraceRecord {
+            iteration: head[0],
+            path: path_bytes
+                .chunks(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect(),
+            quiescence: head[2] & FLAG_QUIESCENCE != 0,
+            tt_hit: head[2] & FLAG_TT_HIT != 0,
+            excluded: head[2] & FLAG_EXCLUDED != 0,
+            alpha: value(0),
+            beta: value(1),
+            depth: value(2),
+            reduction: value(3),
+            static_eval: optional(value(4)),
+            decision,
+            score: optional(value(5)),
+        }))
+    }
+}
+
+impl Display for TraceRecord {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        write!(
+            formatter,
+            "{:indent$}{} it {} {}d {} r {} [{}, {}]",
+            "",
+            if self.path.is_empty() {
+                "root".to_owned()
+            } else {
+                self.path_string()
+            },
+            self.iteration,
+            if self.quiescence { "q" } else { "" },
+            self.depth,
+            self.reduction,
+            self.alpha,
+            self.beta,
+            indent = 2 * self.ply()
+        )?;
+        if let Some(eval) = self.static_eval {
+            write!(formatter, " eval {}", eval)?;
+        }
+        if self.tt_hit {
+            write!(formatter, " tt")?;
+        }
+        if self.excluded {
+            write!(formatter, " excluded")?;
+        }
+        write!(formatter, " {}", self.decision)?;
+        if let Some(score) = self.score {
+            write!(formatter, " -> {}", score)?;
+        }
+        Ok(())
+    }
+}
+
+//Records are written in the order the nodes are left, children before their parents
+pub struct Trace {
+    pub fen: String,
+    pub records: Vec<TraceRecord>,
+}
+
+pub fn read_trace(path: &str) -> io::Result<Trace> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = String::new();
+    reader.read_line(&mut header)?;
+    if header.trim_end() != TRACE_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Not a search trace",
+        ));
+    }
+    let mut fen = String::new();
+    reader.read_line(&mut fen)?;
+    let mut records = Vec::new();
+    while let Some(record) = TraceRecord::read(&mut reader)? {
+        records.push(record);
+    }
+    Ok(Trace {
+        fen: fen.trim_end().to_owned(),
+        records,
+    })
+}
+
+#[cfg(feature = "search-tracer")]
+#[derive(Clone, Copy)]
+struct TraceFrame {
+    ply: usize,
+    quiescence: bool,
+    excluded: bool,
+    alpha: i16,
+    beta: i16,
+    depth: i16,
+    reduction: i16,
+    static_eval: Option<i16>,
+    tt_hit: bool,
+    decision: TraceDecision,
+}
+
+#[cfg(feature = "search-tracer")]
+#[derive(Default)]
+pub struct SearchTracer {
+    writer: Option<BufWriter<File>>,
+    iteration: u8,
+    //Nodes on the current line, verification searches share the ply of their parent
+    frames: Vec<TraceFrame>,
+    pending_reduction: i16,
+}
+
+#[cfg(feature = "search-tracer")]
+impl SearchTracer {
+    pub fn start(&mut self, root: &GameState) {
+        let mut writer = BufWriter::new(File::create(TRACE_PATH).expect("Could not create trace!"));
+        writeln!(writer, "{}\n{}", TRACE_MAGIC, root.to_fen()).expect("Could not write trace!");
+        self.writer = Some(writer);
+        self.frames.clear();
+        self.pending_reduction = 0;
+    }
+
+    pub fn finish(&mut self) {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush().expect("Could not write trace!");
+        }
+    }
+
+    pub fn iteration(&mut self, depth: usize) {
+        self.iteration = depth as u8;
+    }
+
+    #[inline(always)]
+    pub fn enter(&mut self, p: &CombinedSearchParameters, quiescence: bool) {
+        if self.writer.is_none() {
+            return;
+        }
+        self.frames.push(TraceFrame {
+            ply: p.current_depth,
+            quiescence,
+            excluded: p.excluded_move.is_some(),
+            alpha: p.alpha,
+            beta: p.beta,
+            depth: p.depth_left,
+            reduction: self.pending_reduction,
+            static_eval: None,
+            tt_hit: false,
+            decision: TraceDecision::Searched,
+        });
+        self.pending_reduction = 0;
+    }
+
+    #[inline(always)]
+    pub fn static_eval(&mut self, eval: i16) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.static_eval = Some(eval);
+        }
+    }
+
+    #[inline(always)]
+    pub fn tt_hit(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.tt_hit = true;
+        }
+    }
+
+    #[inline(always)]
+    pub fn decision(&mut self, decision: TraceDecision) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.decision = decision;
+        }
+    }
+
+    //Reduction of the next child searched
+    #[inline(always)]
+    pub fn reduce(&mut self, reduction: i16) {
+        self.pending_reduction = reduction;
+    }
+
+    //A move of the current node that is skipped without being searched
+    #[inline(always)]
+    pub fn pruned(
+        &mut self,
+        p: &CombinedSearchParameters,
+        mv: GameMove,
+        decision: TraceDecision,
+        played_moves: &[Option<GameMove>],
+    ) {
+        if self.writer.is_none() {
+            return;
+        }
+        let mut path: Vec<u16> = played_moves[..p.current_depth]
+            .iter()
+            .map(|mv| encode_move(*mv))
+            .collect();
+        path.push(encode_move(Some(mv)));
+        self.write(TraceRecord {
+            iteration: self.iteration,
+            path,
+            quiescence: self.frames.last().map(|f| f.quiescence).unwrap_or(false),
+            excluded: false,
+            alpha: -p.beta,
+            beta: -p.alpha,
+            depth: p.depth_left - 1,
+            reduction: 0,
+            static_eval: None,
+            tt_hit: false,
+            decision,
+            score: None,
+        });
+    }
+
+    #[inline(always)]
+    pub fn exit(&mut self, score: i16, played_moves: &[Option<GameMove>]) {
+        if let Some(frame) = self.frames.pop() {
+            self.write(TraceRecord {
+                iteration: self.iteration,
+                path: played_moves[..frame.ply]
+                    .iter()
+                    .map(|mv| encode_move(*mv))
+                    .collect(),
+                quiescence: frame.quiescence,
+                excluded: frame.excluded,
+                alpha: frame.alpha,
+                beta: frame.beta,
+                depth: frame.depth,
+                reduction: frame.reduction,
+                static_eval: frame.static_eval,
+                tt_hit: frame.tt_hit,
+                decision: frame.decision,
+                score: Some(score),
+            });
+        }
+    }
+
+    fn write(&mut self, record: TraceRecord) {
+        if let Some(writer) = self.writer.as_mut() {
+            record.write(writer).expect("Could not write trace!");
+        }
+    }
+}
+
+#[cfg(not(feature = "search-tracer"))]
+#[derive(Default)]
+pub struct SearchTracer {}
+
+#[cfg(not(feature = "search-tracer"))]
+impl SearchTracer {
+    #[inline(always)]
+    pub fn start(&mut self, _root: &crate::board_representation::game_state::GameState) {}
+    #[inline(always)]
+    pub fn finish(&mut self) {}
+    #[inline(always)]
+    pub fn iteration(&mut self, _depth: usize) {}
+    #[inline(always)]
+    pub fn enter(&mut self, _p: &CombinedSearchParameters, _quiescence: bool) {}
+    #[inline(always)]
+    pub fn static_eval(&mut self, _eval: i16) {}
+    #[inline(always)]
+    pub fn tt_hit(&mut self) {}
+    #[inline(always)]
+    pub fn decision(&mut self, _decision: TraceDecision) {}
+    #[inline(always)]
+    pub fn reduce(&mut self, _reduction: i16) {}
+    #[inline(always)]
+    pub fn pruned(
+        &mut self,
+        _p: &CombinedSearchParameters,
+        _mv: GameMove,
+        _decision: TraceDecision,
+        _played_moves: &[Option<GameMove>],
+    ) {
+    }
+    #[inline(always)]
+    pub fn exit(&mut self, _score: i16, _played_moves: &[Option<GameMove>]) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_round_trip() {
+        let record = TraceRecord {
+            iteration: 7,
+            path: vec![
+                encode_move(Some(GameMove {
+                    from: 12,
+                    to: 28,
+                    move_type: GameMoveType::Quiet,
+                    piece_type: PieceType::Pawn,
+                })),
+                encode_move(None),
+                encode_move(Some(GameMove {
+                    from: 52,
+                    to: 60,
+                    move_type: GameMoveType::Promotion(PieceType::Knight, None),
+                    piece_type: PieceType::Pawn,
+                })),
+            ],
+            quiescence: false,
+            excluded: true,
+            alpha: -30,
+            beta: -29,
+            depth: 3,
+            reduction: 1,
+            static_eval: Some(-12),
+            tt_hit: true,
+            decision: TraceDecision::NullMove,
+            score: None,
+        };
+        let mut buffer = Vec::new();
+        record.write(&mut buffer).unwrap();
+        record.write(&mut buffer).unwrap();
+        let mut reader = &buffer[..];
+        for _ in 0..2 {
+            let read = TraceRecord::read(&mut reader).unwrap().unwrap();
+            assert_eq!(read.path_string(), "e2e4 null e7e8n");
+            assert_eq!(format!("{}", read), format!("{}", record));
+        }
+        assert!(TraceRecord::read(&mut reader).unwrap().is_none());
+        assert_eq!(
+            TraceDecision::from_name("Static-Null-Move"),
+            Some(TraceDecision::StaticNullMove)
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::pgn::pgn_writer::{get_pgn_string, PGNMetadata};
+use core_sdk::board_representation::game_state::*;
+use core_sdk::board_representation::game_state_attack_container::GameStateAttackContainer;
+use core_sdk::board_representation::rules::{game_result, GameEndReason};
+use core_sdk::move_generation::makemove::make_move;
+use core_sdk::move_generation::movegen;
+use std::fmt::{Display, Formatter, Result};
+
+#[derive(PartialEq, Debug)]
+pub enum GameError {
+    InvalidFEN(String),
+    IllegalMove(String),
+    San(SanError),
+    NothingToUndo,
+}
+impl Display for GameError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        match self {
+            GameError::InvalidFEN(fen) => write!(formatter, "Invalid FEN: {}", fen),
+            GameError::IllegalMove(mv) => write!(formatter, "Illegal move: {}", mv),
+            GameError::San(e) => write!(formatter, "{}", e),
+            GameError::NothingToUndo => write!(formatter, "No move to undo"),
+        }
+    }
+}
+impl std::error::Error for GameError {}
+
+//A game from a starting position, owning every position reached so far
+pub struct Game {
+    start_fen: String,
+    states: Vec<GameState>,
+    moves: Vec<GameMove>,
+    movelist: movegen::MoveList,
+    attack_container: GameStateAttackContainer,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game::from_state(GameState::standard())
+    }
+}
+
+impl Game {
+    pub fn from_state(state: GameState) -> Self {
+        Game {
+            start_fen: state.to_fen(),
+            states: vec![state],
+            moves: Vec::new(),
+            movelist: movegen::MoveList::default(),
+            attack_container: GameStateAttackContainer::default(),
+        }
+    }
+
+    pub fn from_fen(fen: &str) -> std::result::Result<Self, GameError> {
+        let state =
+            GameState::try_from_fen(fen).map_err(|_| GameError::InvalidFEN(fen.to_owned()))?;
+        //Pawns can't stand on the first or last rank
+        if (state.pieces[PAWN][WHITE] | state.pieces[PAWN][BLACK]) & 0xFF00_0000_0000_00FF != 0 {
+            return Err(GameError::InvalidFEN(fen.to_owned()));
+        }
+        Ok(Game::from_state(state))
+    }
+
+    pub fn position(&self) -> &GameState {
+        self.states.last().unwrap()
+    }
+
+    pub fn moves(&self) -> &[GameMove] {
+        &self.moves
+    }
+
+    pub fn legal_moves(&mut self) -> Vec<GameMove> {
+        self.generate();
+        let position = self.states.last().unwrap();
+        self.movelist.game_moves(position).collect()
+    }
+
+    pub fn play(&mut self, mv: GameMove) -> std::result::Result<(), GameError> {
+        if !self.legal_moves().contains(&mv) {
+            return Err(GameError::IllegalMove(format!("{:?}", mv)));
+        }
+        let state = make_move(self.position(), mv);
+        self.states.push(state);
+        self.moves.push(mv);
+        Ok(())
+    }
+
+    //Long algebraic notation as used by UCI, e.g. e2e4 or e7e8q
+    pub fn play_uci(&mut self, mv: &str) -> std::result::Result<GameMove, GameError> {
+        let found = GameMove::from_uci(self.position(), mv).map_err(|e| match e {
+            SanError::IllegalMove(mv) => GameError::IllegalMove(mv),
+            e => GameError::San(e),
+        })?;
+        self.play(found)?;
+        Ok(found)
+    }
+
+    pub fn play_san(&mut self, mv: &str) -> std::result::Result<GameMove, GameError> {
+        let found = GameMove::from_san(self.position(), mv).map_err(GameError::San)?;
+        self.play(found)?;
+        Ok(found)
+    }
+
+    pub fn undo(&mut self) -> std::result::Result<GameMove, GameError> {
+        match self.moves.pop() {
+            Some(mv) => {
+                self.states.pop();
+                Ok(mv)
+            }
+            None => Err(GameError::NothingToUndo),
+        }
+    }
+
+    pub fn result(&mut self) -> (GameResult, Option<GameEndReason>) {
+        let agsi = self.generate();
+        let position = self.position();
+        let repetitions = self.states[..self.states.len() - 1]
+            .iter()
+            .filter(|state| state.hash == position.hash)
+            .count();
+        game_result(
+            position,
+            agsi.stm_haslegalmove,
+            agsi.stm_incheck,
+            repetitions,
+        )
+    }
+
+    //The reason the game ended is added as comment to the last move
+    pub fn to_pgn(&mut self) -> String {
+        let (result, reason) = self.result();
+        let mut move_comments = vec![String::new(); self.moves.len()];
+        if let (Some(reason), Some(last)) = (reason, move_comments.last_mut()) {
+            *last = reason.to_string();
+        }
+        let metadata = PGNMetadata {
+            starting_position: self.start_fen.clone(),
+            result: Some(result.to_string()),
+            termination: reason.map(|_| "normal".to_owned()),
+            ..PGNMetadata::default()
+        };
+        get_pgn_string(&metadata, self.moves.clone(), None, Some(&move_comments))
+    }
+
+    fn generate(&mut self) -> movegen::AdditionalGameStateInformation {
+        let position = self.states.last().unwrap();
+        self.attack_container.write_state(position);
+        movegen::generate_moves(position, false, &mut self.movelist, &self.attack_container)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn play_and_undo() {
+        let mut game = Game::default();
+        assert_eq!(game.legal_moves().len(), 20);
+        assert_eq!(format!("{:?}", game.play_uci("e2e4").unwrap()), "e2e4");
+        assert_eq!(format!("{:?}", game.play_san("Nc6").unwrap()), "b8c6");
+        assert_eq!(
+            game.play_uci("e2e4"),
+            Err(GameError::IllegalMove("e2e4".to_owned()))
+        );
+        assert!(game.play_san("Qxf7").is_err());
+        game.undo().unwrap();
+        game.undo().unwrap();
+        assert_eq!(game.undo(), Err(GameError::NothingToUndo));
+        assert_eq!(game.position().hash, GameState::standard().hash);
+        assert!(Game::from_fen("8/8/8/8/8/8/8/8 w - -").is_err());
+        assert!(Game::from_fen("8/8/4k3/8/2B5/8/4K3/8 w - -").is_err());
+        assert!(Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0").is_err());
+    }
+
+    #[test]
+    fn game_results() {
+        let mut game = Game::default();
+        for mv in ["f3", "e5", "g4", "Qh4#"].iter() {
+            game.play_san(mv).unwrap();
+        }
+        assert_eq!(
+            game.result(),
+            (GameResult::BlackWin, Some(GameEndReason::Checkmate))
+        );
+        assert!(game
+            .to_pgn()
+            .contains("1. f3 e5 2. g4 Qh4# {checkmate} 0-1"));
+
+        let mut game = Game::default();
+        for mv in ["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1"].iter() {
+            game.play_san(mv).unwrap();
+            assert_eq!(game.result(), (GameResult::Ingame, None));
+        }
+        game.play_san("Ng8").unwrap();
+        assert_eq!(
+            game.result(),
+            (GameResult::Draw, Some(GameEndReason::ThreefoldRepetition))
+        );
+
+        let mut game = Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(
+            game.result(),
+            (GameResult::Draw, Some(GameEndReason::Stalemate))
+        );
+        let mut game = Game::from_fen("8/8/4k3/8/8/8/4K3/2BB4 w - - 0 1").unwrap();
+        assert_eq!(game.result(), (GameResult::Ingame, None));
+        let mut game = Game::from_fen("8/8/4k3/8/8/8/1B2K3/2B5 w - - 0 1").unwrap();
+        assert_eq!(
+            game.result(),
+            (GameResult::Draw, Some(GameEndReason::DeadPosition))
+        );
+        let mut game = Game::from_fen("8/8/4k3/8/2R5/8/4K3/8 w - - 100 80").unwrap();
+        assert_eq!(
+            game.result(),
+            (GameResult::Draw, Some(GameEndReason::FiftyMoveRule))
+        );
+    }
+}
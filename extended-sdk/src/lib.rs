@@ -1,3 +1,4 @@
+pub mod game;
 pub mod misc;
 pub mod openings;
 pub mod pgn;
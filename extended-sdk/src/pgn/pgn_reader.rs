@@ -1,22 +1,17 @@
-use core_sdk::board_representation::game_state::{
-    char_to_file, char_to_rank, GameMove, GameMoveType, GameState, PieceType, WHITE,
-};
-use core_sdk::board_representation::game_state_attack_container::GameStateAttackContainer;
+use core_sdk::board_representation::game_state::{GameMove, GameState, SanError};
 use core_sdk::move_generation::makemove::make_move;
-use core_sdk::move_generation::movegen;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-pub struct GameParser {
-    pub pgn_parser: PGNParser,
+pub struct GameParser<R: BufRead = BufReader<File>> {
+    pub pgn_parser: PGNParser<R>,
     pub is_opening: bool,
     pub opening_load_untilply: usize,
-    pub move_list: movegen::MoveList,
-    pub attack_container: GameStateAttackContainer,
 }
 
-impl Iterator for GameParser {
+impl<R: BufRead> Iterator for GameParser<R> {
     type Item = (Vec<GameMove>, Vec<GameState>, isize);
+    //Games which can't be parsed are returned with a score of -2
     fn next(&mut self) -> Option<(Vec<GameMove>, Vec<GameState>, isize)> {
         let next = self.pgn_parser.next();
         let mut vec_res: Vec<GameMove> = Vec::new();
@@ -30,262 +25,46 @@ impl Iterator for GameParser {
                     //Invalid state
                     return Some((vec_res, vec_gs, -2));
                 }
-                //log(&format!("{}\n", game));
-                let moves = game.split(' ').collect::<Vec<&str>>();
-                for move_str in moves.iter().take(moves.len() - 2) {
-                    let mut move_str = (*move_str).to_string();
-                    if move_str.contains('.') {
-                        move_str = move_str.rsplit('.').collect::<Vec<&str>>()[0].to_string();
-                    }
+                let moves = game.split_whitespace().collect::<Vec<&str>>();
+                let score = match moves.last() {
+                    Some(&"1-0") => 1,
+                    Some(&"0-1") => -1,
+                    Some(&"1/2-1/2") => 0,
+                    _ => return Some((vec_res, vec_gs, -2)),
+                };
+                for move_str in moves.iter().take(moves.len() - 1) {
+                    let move_str = move_str.rsplit('.').next().unwrap_or("");
                     if move_str.is_empty() {
                         continue;
                     }
-                    //println!("{} || len: {}", move_str, move_str.len());
                     let last_state = &vec_gs[vec_gs.len() - 1];
-                    self.attack_container.write_state(last_state);
-                    let parsed_move = parse_move(
-                        last_state,
-                        &move_str,
-                        &mut self.move_list,
-                        &self.attack_container,
-                    );
-                    vec_gs.push(parsed_move.1);
-                    vec_res.push(parsed_move.0);
+                    match parse_move(last_state, move_str) {
+                        Ok((mv, state)) => {
+                            vec_gs.push(state);
+                            vec_res.push(mv);
+                        }
+                        Err(_) => return Some((vec_res, vec_gs, -2)),
+                    }
                     if self.is_opening && vec_res.len() == self.opening_load_untilply {
                         break;
                     }
                 }
-                let last_elem = moves[moves.len() - 2];
-                let mut score = 0;
-                if last_elem == "1-0" {
-                    score = 1;
-                } else if last_elem == "0-1" {
-                    score = -1;
-                } else {
-                    assert!(last_elem == "1/2-1/2");
-                }
                 Some((vec_res, vec_gs, score))
             }
         }
     }
 }
 
-pub fn find_castle(
-    movelist: &movegen::MoveList,
-    g: &GameState,
-    king_side: bool,
-) -> Result<(GameMove, GameState), ()> {
-    for gmv in movelist.move_list.iter() {
-        let mv = gmv.0;
-        if mv.move_type == GameMoveType::Castle
-            && mv.to as isize - mv.from as isize == 2 * if king_side { 1 } else { -1 }
-        {
-            let state = make_move(g, mv);
-            return Ok((mv, state));
-        }
-    }
-    Err(())
-}
-
-pub fn find_move(
-    movelist: &movegen::MoveList,
-    g: &GameState,
-    ms: MoveSpecification,
-) -> Result<(GameMove, GameState), ()> {
-    for gmv in movelist.move_list.iter() {
-        /*println!("Checking: {:?}", gmv.0);
-        if &format!("{:?}", gmv.0) == "e4d4" {
-            println!("{:?} ", ms.target_square);
-            println!("{:?} ", ms.from_square);
-            println!("{:?} ", ms.from_file);
-            println!("{:?} ", ms.from_rank);
-            println!("{:?} ", ms.moving_piece_type);
-            println!("{:?} ", ms.promotion_piece);
-        }*/
-
-        if ms.matches(&gmv.0) {
-            let state = make_move(g, gmv.0);
-            return Ok((gmv.0, state));
-        }
-    }
-    Err(())
-}
-
-pub fn get_piece_type(my_string: &mut String) -> PieceType {
-    let mut moving_piece_type = PieceType::Pawn;
-    if my_string.starts_with('N') {
-        moving_piece_type = PieceType::Knight;
-        *my_string = String::from(&my_string[1..]);
-    } else if my_string.starts_with('B') {
-        moving_piece_type = PieceType::Bishop;
-        *my_string = String::from(&my_string[1..]);
-    } else if my_string.starts_with('R') {
-        moving_piece_type = PieceType::Rook;
-        *my_string = String::from(&my_string[1..]);
-    } else if my_string.starts_with('Q') {
-        moving_piece_type = PieceType::Queen;
-        *my_string = String::from(&my_string[1..]);
-    } else if my_string.starts_with('K') {
-        moving_piece_type = PieceType::King;
-        *my_string = String::from(&my_string[1..]);
-    }
-    moving_piece_type
-}
-
-pub fn is_promotion(my_string: &mut String) -> Option<PieceType> {
-    let mut promotion_piece = None;
-    if my_string.to_lowercase().ends_with('q') {
-        promotion_piece = Some(PieceType::Queen)
-    } else if my_string.to_lowercase().ends_with('r') {
-        promotion_piece = Some(PieceType::Rook);
-    } else if my_string.to_lowercase().ends_with('b') {
-        promotion_piece = Some(PieceType::Bishop);
-    } else if my_string.to_lowercase().ends_with('n') {
-        promotion_piece = Some(PieceType::Knight);
-    }
-    if promotion_piece.is_some() {
-        *my_string = String::from(&my_string[..my_string.len() - 1]);
-    }
-    promotion_piece
-}
-
-pub struct MoveSpecification {
-    target_square: usize,
-    from_square: Option<usize>,
-    from_file: Option<usize>,
-    from_rank: Option<usize>,
-    moving_piece_type: PieceType,
-    promotion_piece: Option<PieceType>,
-}
-
-impl MoveSpecification {
-    pub fn new(
-        target_square: usize,
-        moving_piece_type: PieceType,
-        promotion_piece: Option<PieceType>,
-    ) -> Self {
-        MoveSpecification {
-            target_square,
-            from_square: None,
-            from_file: None,
-            from_rank: None,
-            moving_piece_type,
-            promotion_piece,
-        }
-    }
-
-    pub fn matches(&self, mv: &GameMove) -> bool {
-        mv.to as usize == self.target_square
-            && (self.from_square.is_none() || self.from_square.unwrap() == mv.from as usize)
-            && (self.from_file.is_none() || self.from_file.unwrap() == mv.from as usize % 8)
-            && (self.from_rank.is_none() || self.from_rank.unwrap() == mv.from as usize / 8)
-            && (mv.piece_type == self.moving_piece_type || self.from_square.is_some())
-            && (self.promotion_piece
-                == match mv.move_type {
-                    GameMoveType::Promotion(piece, _) => Some(piece),
-                    _ => None,
-                })
-    }
-}
-
-pub fn parse_move(
-    g: &GameState,
-    move_str: &str,
-    movelist: &mut movegen::MoveList,
-    attack_container: &GameStateAttackContainer,
-) -> (GameMove, GameState) {
-    let mut my_string = move_str.to_string();
-    my_string = my_string
-        .replace("#", "")
-        .replace("+", "")
-        .replace("=", "")
-        .replace("x", "");
-    movegen::generate_moves(&g, false, movelist, &attack_container);
-    if my_string.contains('-') {
-        //Castle
-        //Kingside
-        if my_string.len() == 3 {
-            if g.color_to_move == WHITE {
-                assert_eq!(true, g.castle_white_kingside);
-            } else {
-                assert_eq!(true, g.castle_black_kingside);
-            }
-            if let Ok(res) = find_castle(movelist, g, true) {
-                return res;
-            }
-        } else {
-            if g.color_to_move == WHITE {
-                assert_eq!(true, g.castle_white_queenside);
-            } else {
-                assert_eq!(true, g.castle_black_queenside);
-            }
-            if let Ok(res) = find_castle(movelist, g, false) {
-                return res;
-            }
-        }
-    } else {
-        let moving_piece_type = get_piece_type(&mut my_string);
-        let promotion_piece = is_promotion(&mut my_string);
-        let target_square = 8 * match_rank(my_string.chars().nth(my_string.len() - 1))
-            + match_file(my_string.chars().nth(my_string.len() - 2));
-        let mut ms = MoveSpecification::new(target_square, moving_piece_type, promotion_piece);
-
-        if my_string.len() == 3 {
-            let first = my_string.chars().nth(0);
-            if is_file(first) {
-                ms.from_file = Some(match_file(first));
-            } else {
-                ms.from_rank = Some(match_rank(first));
-            }
-        } else if my_string.len() == 4 {
-            ms.from_square = Some(
-                8 * match_rank(my_string.chars().nth(1)) + match_file(my_string.chars().nth(0)),
-            );
-        }
-        if let Ok(res) = find_move(movelist, g, ms) {
-            return res;
-        }
-    }
-    println!("{}", move_str);
-    println!("{}", my_string);
-    println!("{}", g);
-
-    for gmv in movelist.move_list.iter() {
-        println!("{:?}", gmv.0);
-    }
-    panic!("Shouldn't get here");
-}
-
-pub fn is_file(c: Option<char>) -> bool {
-    match c {
-        None => panic!("Invalid!"),
-        Some(character) => match character {
-            'a' => true,
-            'b' => true,
-            'c' => true,
-            'd' => true,
-            'e' => true,
-            'f' => true,
-            'g' => true,
-            'h' => true,
-            _ => false,
-        },
-    }
-}
-
-pub fn match_file(c: Option<char>) -> usize {
-    char_to_file(c.expect("Invalid"))
+pub fn parse_move(g: &GameState, move_str: &str) -> Result<(GameMove, GameState), SanError> {
+    let mv = GameMove::from_san(g, move_str)?;
+    Ok((mv, make_move(g, mv)))
 }
 
-pub fn match_rank(c: Option<char>) -> usize {
-    char_to_rank(c.expect("Invalid"))
+pub struct PGNParser<R: BufRead = BufReader<File>> {
+    pub reader: R,
 }
 
-pub struct PGNParser {
-    pub reader: BufReader<File>,
-}
-
-impl Iterator for PGNParser {
+impl<R: BufRead> Iterator for PGNParser<R> {
     type Item = String;
 
     fn next(&mut self) -> Option<String> {
@@ -307,9 +86,11 @@ impl Iterator for PGNParser {
                         break;
                     }
                     line = String::new();
-                    self.reader
-                        .read_line(&mut line)
-                        .expect("Reader had an error reading moves of game!");
+                    //A game cut off by the end of the file has no result
+                    match self.reader.read_line(&mut line) {
+                        Ok(0) | Err(_) => break,
+                        Ok(_) => {}
+                    }
                 }
                 break;
             }
@@ -42,6 +42,7 @@ pub fn get_pgn_string(
     metadata: &PGNMetadata,
     moves: Vec<GameMove>,
     opening_comment: Option<usize>,
+    move_comments: Option<&[String]>,
 ) -> String {
     let mut res_str = String::new();
     let s = if metadata.event_name.is_some() {
@@ -113,6 +114,11 @@ pub fn get_pgn_string(
     let mut current_color = start_pos.color_to_move;
     for (index, mv) in moves.iter().enumerate() {
         move_text.push_str(&format!("{} ", mv.to_san(&start_pos)));
+        if let Some(comment) = move_comments.and_then(|comments| comments.get(index)) {
+            if !comment.is_empty() {
+                move_text.push_str(&format!("{{{}}} ", comment));
+            }
+        }
         if opening_comment.is_some() && (index + 1) == opening_comment.unwrap() {
             move_text.push_str("{Opening book has ended} ");
         }
@@ -185,15 +191,17 @@ mod tests {
                 }
                 break;
             }
-            let mv = movelist.move_list[rng.gen_range(0, movelist.move_list.len())];
-            g = make_move(&g, mv.0);
-            moves.push(mv.0);
+            let mv = movelist.moves()[rng.gen_range(0, movelist.len())]
+                .0
+                .to_game_move(&g);
+            g = make_move(&g, mv);
+            moves.push(mv);
         }
         let mut metadata = PGNMetadata::default();
         metadata.fill_systemdata();
         metadata.result = Some(res.to_string());
         metadata.starting_position =
             "rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq -".to_owned();
-        print!("{}", super::get_pgn_string(&metadata, moves, Some(0)))
+        print!("{}", super::get_pgn_string(&metadata, moves, Some(0), None))
     }
 }
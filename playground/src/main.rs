@@ -9,8 +9,10 @@ fn main() {
 }
 fn go_infinite_from_startpos() {
     let itcs = Arc::new(InterThreadCommunicationSystem::default());
-    *itcs.cache() =
-        Cache::with_size_threaded(itcs.uci_options().hash_size, itcs.uci_options().threads);
+    itcs.set_cache(Cache::with_size_threaded(
+        itcs.uci_options().hash_size,
+        itcs.uci_options().threads,
+    ));
     InterThreadCommunicationSystem::update_thread_count(&itcs, 1);
     search_move(
         itcs,
@@ -0,0 +1,426 @@
+use crate::async_communication::{expect_output, expect_output_and_listen_for_info, write_all};
+use crate::engine::{fetch_info, EndConditionInformation, EngineStatus, UCIInfo};
+use core_sdk::board_representation::game_state::*;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Stdio;
+use tokio::io::{BufReader, BufWriter};
+use tokio::process::Command;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TablebaseConfig {
+    //UCI engine with access to tablebases, e.g. with SyzygyPath set in its options
+    pub engine: (String, HashMap<String, String>),
+    pub max_pieces: u32,
+    //Centipawn scores at least this high count as tablebase wins. Engines usually report them
+    //just below their mate scores, e.g. as 20000 minus the distance to the root
+    #[serde(default = "default_tablebase_win_score")]
+    pub win_score: isize,
+}
+
+fn default_tablebase_win_score() -> isize {
+    10000
+}
+
+impl TablebaseConfig {
+    //Result for the side to move: 1 win, 0 draw, -1 loss. A mate, a tablebase win score or a
+    //decisive WDL decide the game, every other score of a tablebase position is a draw
+    pub fn result_for_side_to_move(&self, info: &UCIInfo) -> isize {
+        if info.positive_mate_found {
+            1
+        } else if info.negative_mate_found {
+            -1
+        } else if let Some((win, _, loss)) = info.wdl {
+            if win == 1000 {
+                1
+            } else if loss == 1000 {
+                -1
+            } else {
+                0
+            }
+        } else {
+            match info.cp_score {
+                Some(score) if score >= self.win_score => 1,
+                Some(score) if score <= -self.win_score => -1,
+                _ => 0,
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AdjudicationConfig {
+    pub resign_score: isize,
+    pub resign_move_count: usize,
+    pub draw_score: isize,
+    pub draw_move_count: usize,
+    pub draw_min_ply: usize,
+    pub max_game_length: Option<usize>,
+    pub tablebase: Option<TablebaseConfig>,
+    //Milliseconds a move may exceed the time left before the engine loses on time. Absorbs the
+    //latency of the pipes and the scheduling of the referee
+    pub time_margin: u64,
+}
+
+impl Default for AdjudicationConfig {
+    fn default() -> Self {
+        AdjudicationConfig {
+            resign_score: 1000,
+            resign_move_count: 10,
+            draw_score: 10,
+            draw_move_count: 20,
+            draw_min_ply: 68,
+            max_game_length: None,
+            tablebase: None,
+            time_margin: 50,
+        }
+    }
+}
+
+impl AdjudicationConfig {
+    pub fn engine_status(&self, info: &UCIInfo) -> EngineStatus {
+        if info.negative_mate_found {
+            EngineStatus::ProclaimsLoss
+        } else if info.positive_mate_found {
+            EngineStatus::ProclaimsWin
+        } else if let Some(score) = info.cp_score {
+            if score < -self.resign_score {
+                EngineStatus::ProclaimsLoss
+            } else if score > self.resign_score {
+                EngineStatus::ProclaimsWin
+            } else if score.abs() <= self.draw_score {
+                EngineStatus::ProclaimsDraw
+            } else {
+                EngineStatus::ProclaimsNothing
+            }
+        } else {
+            EngineStatus::ProclaimsNothing
+        }
+    }
+}
+
+pub struct Adjudicator<'a> {
+    config: &'a AdjudicationConfig,
+    draw_adjudication: usize,
+    win_adjudication: usize,
+    win_adjudication_for_p1: bool,
+}
+
+impl<'a> Adjudicator<'a> {
+    pub fn new(config: &'a AdjudicationConfig) -> Self {
+        Adjudicator {
+            config,
+            draw_adjudication: 0,
+            win_adjudication: 0,
+            win_adjudication_for_p1: true,
+        }
+    }
+
+    //Both engines have to agree on the evaluation for consecutive moves
+    pub fn update(&mut self, status: &EngineStatus, player1_move: bool, ply: usize) {
+        match status {
+            EngineStatus::ProclaimsNothing => {
+                self.draw_adjudication = 0;
+                self.win_adjudication = 0;
+            }
+            EngineStatus::ProclaimsDraw => {
+                self.win_adjudication = 0;
+                self.draw_adjudication += 1;
+            }
+            EngineStatus::ProclaimsWin | EngineStatus::ProclaimsLoss => {
+                self.draw_adjudication = 0;
+                let for_p1 = player1_move == matches!(status, EngineStatus::ProclaimsWin);
+                if for_p1 != self.win_adjudication_for_p1 {
+                    self.win_adjudication = 1;
+                } else {
+                    self.win_adjudication += 1;
+                }
+                self.win_adjudication_for_p1 = for_p1;
+            }
+        }
+        if ply < self.config.draw_min_ply {
+            self.draw_adjudication = 0;
+        }
+    }
+
+    //The side to move ran out of time
+    pub fn time_loss(color_to_move: usize) -> (GameResult, EndConditionInformation) {
+        let result = if color_to_move == WHITE {
+            GameResult::BlackWin
+        } else {
+            GameResult::WhiteWin
+        };
+        (result, EndConditionInformation::TimeLoss)
+    }
+
+    pub fn adjudicate(
+        &self,
+        plies_played: usize,
+        p1_is_white: bool,
+    ) -> Option<(GameResult, EndConditionInformation)> {
+        if self.draw_adjudication >= self.config.draw_move_count {
+            Some((
+                GameResult::Draw,
+                EndConditionInformation::DrawByadjudication,
+            ))
+        } else if self.win_adjudication >= self.config.resign_move_count {
+            let result = if self.win_adjudication_for_p1 == p1_is_white {
+                GameResult::WhiteWin
+            } else {
+                GameResult::BlackWin
+            };
+            Some((result, EndConditionInformation::MateByadjudication))
+        } else if self
+            .config
+            .max_game_length
+            .map(|max| plies_played >= max)
+            .unwrap_or(false)
+        {
+            Some((GameResult::Draw, EndConditionInformation::MaxGameLength))
+        } else {
+            None
+        }
+    }
+}
+
+//Asks the tablebase engine for a shallow search of the position. Only scores backed by tablebase
+//hits are trusted. The engine is started with UCI_ShowWDL, engines supporting it report the WDL
+pub async fn probe_tablebase(
+    tablebase: &TablebaseConfig,
+    position_string: &str,
+    white_to_move: bool,
+) -> Option<GameResult> {
+    let mut cmd = Command::new(&tablebase.engine.0);
+    cmd.stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(msg) => {
+            warn!("Could not start tablebase engine: {}", msg);
+            return None;
+        }
+    };
+    let mut stdin = BufWriter::new(child.stdin.take().unwrap());
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut msg = String::from("uci\nsetoption name UCI_ShowWDL value true\n");
+    for pair in &tablebase.engine.1 {
+        msg.push_str(&format!("setoption name {} value {}\n", pair.0, pair.1));
+    }
+    msg.push_str("isready\n");
+    write_all(&mut stdin, &msg).await;
+    let ready = expect_output("readyok", 10000, &mut stdout).await;
+    let mut res = None;
+    if ready.0.is_some() {
+        write_all(&mut stdin, position_string).await;
+        write_all(&mut stdin, "go depth 1\n").await;
+        let output =
+            expect_output_and_listen_for_info("bestmove", "info", 10000, &mut stdout).await;
+        if output.0.is_some() {
+            let info = output.1.lines().rev().map(fetch_info).find(|info| {
+                info.tbhits.unwrap_or(0) > 0
+                    && (info.cp_score.is_some()
+                        || info.wdl.is_some()
+                        || info.positive_mate_found
+                        || info.negative_mate_found)
+            });
+            if let Some(info) = info {
+                let stm_result = tablebase.result_for_side_to_move(&info);
+                res = Some(match stm_result * if white_to_move { 1 } else { -1 } {
+                    1 => GameResult::WhiteWin,
+                    -1 => GameResult::BlackWin,
+                    _ => GameResult::Draw,
+                });
+            }
+        }
+    }
+    write_all(&mut stdin, "quit\n").await;
+    let _ = child.kill();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AdjudicationConfig {
+        AdjudicationConfig {
+            resign_score: 500,
+            resign_move_count: 4,
+            draw_score: 10,
+            draw_move_count: 6,
+            draw_min_ply: 20,
+            max_game_length: Some(200),
+            tablebase: None,
+            time_margin: 50,
+        }
+    }
+
+    fn status(config: &AdjudicationConfig, info: &str) -> EngineStatus {
+        config.engine_status(&fetch_info(info))
+    }
+
+    #[test]
+    fn engine_status_from_scores() {
+        let config = config();
+        assert!(matches!(
+            status(&config, "info depth 9 score cp 501"),
+            EngineStatus::ProclaimsWin
+        ));
+        assert!(matches!(
+            status(&config, "info depth 9 score mate -3"),
+            EngineStatus::ProclaimsLoss
+        ));
+        assert!(matches!(
+            status(&config, "info depth 9 score cp -10"),
+            EngineStatus::ProclaimsDraw
+        ));
+        assert!(matches!(
+            status(&config, "info depth 9 score cp 300"),
+            EngineStatus::ProclaimsNothing
+        ));
+        assert!(matches!(
+            status(&config, "info depth 9 nodes 100"),
+            EngineStatus::ProclaimsNothing
+        ));
+    }
+
+    #[test]
+    fn resign_streak() {
+        let config = config();
+        let mut adjudicator = Adjudicator::new(&config);
+        //Player 1 sees a win, player 2 agrees by seeing a loss
+        for ply in 0..3 {
+            let player1_move = ply % 2 == 0;
+            let info = if player1_move {
+                "score cp 900"
+            } else {
+                "score cp -900"
+            };
+            adjudicator.update(&status(&config, info), player1_move, 40 + ply);
+            assert!(adjudicator.adjudicate(40 + ply, true).is_none());
+        }
+        //A single disagreeing move restarts the streak
+        adjudicator.update(&status(&config, "score cp 100"), false, 43);
+        for ply in 44..47 {
+            let player1_move = ply % 2 == 0;
+            let info = if player1_move {
+                "score cp 900"
+            } else {
+                "score mate -5"
+            };
+            adjudicator.update(&status(&config, info), player1_move, ply);
+            assert!(adjudicator.adjudicate(ply, false).is_none());
+        }
+        //Player 2 claiming the win for itself starts a streak for player 2
+        adjudicator.update(&status(&config, "score cp 900"), false, 47);
+        assert!(adjudicator.adjudicate(47, false).is_none());
+        for ply in 48..51 {
+            let player1_move = ply % 2 == 0;
+            let info = if player1_move {
+                "score cp -900"
+            } else {
+                "score cp 900"
+            };
+            adjudicator.update(&status(&config, info), player1_move, ply);
+        }
+        assert!(matches!(
+            adjudicator.adjudicate(51, false),
+            Some((
+                GameResult::WhiteWin,
+                EndConditionInformation::MateByadjudication
+            ))
+        ));
+        assert!(matches!(
+            adjudicator.adjudicate(51, true),
+            Some((GameResult::BlackWin, _))
+        ));
+    }
+
+    #[test]
+    fn draw_streak() {
+        let config = config();
+        let mut adjudicator = Adjudicator::new(&config);
+        //Draw scores before draw_min_ply don't count
+        for ply in 10..20 {
+            adjudicator.update(&status(&config, "score cp 0"), ply % 2 == 0, ply);
+            assert!(adjudicator.adjudicate(ply, true).is_none());
+        }
+        for ply in 20..25 {
+            adjudicator.update(&status(&config, "score cp 3"), ply % 2 == 0, ply);
+            assert!(adjudicator.adjudicate(ply, true).is_none());
+        }
+        adjudicator.update(&status(&config, "score cp 50"), false, 25);
+        for ply in 26..31 {
+            adjudicator.update(&status(&config, "score cp -3"), ply % 2 == 0, ply);
+            assert!(adjudicator.adjudicate(ply, true).is_none());
+        }
+        adjudicator.update(&status(&config, "score cp -3"), false, 31);
+        assert!(matches!(
+            adjudicator.adjudicate(31, true),
+            Some((
+                GameResult::Draw,
+                EndConditionInformation::DrawByadjudication
+            ))
+        ));
+    }
+
+    #[test]
+    fn max_game_length() {
+        let config = config();
+        let adjudicator = Adjudicator::new(&config);
+        assert!(adjudicator.adjudicate(199, true).is_none());
+        assert!(matches!(
+            adjudicator.adjudicate(200, true),
+            Some((GameResult::Draw, EndConditionInformation::MaxGameLength))
+        ));
+        let unlimited = AdjudicationConfig {
+            max_game_length: None,
+            ..config
+        };
+        assert!(Adjudicator::new(&unlimited)
+            .adjudicate(100_000, true)
+            .is_none());
+    }
+
+    #[test]
+    fn time_loss() {
+        assert!(matches!(
+            Adjudicator::time_loss(WHITE),
+            (GameResult::BlackWin, EndConditionInformation::TimeLoss)
+        ));
+        assert!(matches!(
+            Adjudicator::time_loss(BLACK),
+            (GameResult::WhiteWin, EndConditionInformation::TimeLoss)
+        ));
+    }
+
+    #[test]
+    fn tablebase_scores() {
+        let tablebase = TablebaseConfig {
+            engine: (String::new(), HashMap::new()),
+            max_pieces: 6,
+            win_score: default_tablebase_win_score(),
+        };
+        let result = |info: &str| tablebase.result_for_side_to_move(&fetch_info(info));
+        //Small evaluations of drawn tablebase positions are no wins
+        assert_eq!(result("info depth 1 score cp 37 tbhits 1"), 0);
+        assert_eq!(result("info depth 1 score cp -250 tbhits 1"), 0);
+        assert_eq!(result("info depth 1 score cp 19985 tbhits 1"), 1);
+        assert_eq!(result("info depth 1 score cp -19985 tbhits 1"), -1);
+        assert_eq!(result("info depth 1 score mate 12 tbhits 1"), 1);
+        assert_eq!(result("info depth 1 score mate -7 tbhits 1"), -1);
+        assert_eq!(result("info depth 1 score cp 80 wdl 1000 0 0 tbhits 1"), 1);
+        assert_eq!(
+            result("info depth 1 score cp -80 wdl 0 0 1000 tbhits 1"),
+            -1
+        );
+        assert_eq!(result("info depth 1 score cp 80 wdl 620 380 0 tbhits 1"), 0);
+        assert_eq!(
+            result("info depth 1 score cp 20000 wdl 0 1000 0 tbhits 1"),
+            0
+        );
+    }
+}
@@ -1,10 +1,11 @@
 use crate::async_communication::{
     expect_output, expect_output_and_listen_for_info, stderr_listener, write_all,
 };
+use crate::timecontrol::EngineTimeControl;
 use core_sdk::board_representation::game_state::*;
-use core_sdk::move_generation::movegen::MoveList;
-use core_sdk::search::timecontrol::TimeControl;
+use core_sdk::board_representation::rules::GameEndReason;
 use log::{info, warn};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter, Result};
 use std::process::Stdio;
@@ -13,6 +14,7 @@ use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
 
 pub enum EngineReaction<T> {
     ContinueGame(T),
+    LoseOnTime,
     DisqualifyEngine,
 }
 #[derive(Debug)]
@@ -24,27 +26,27 @@ pub enum EngineStatus {
 }
 #[derive(Clone, Copy)]
 pub enum EndConditionInformation {
-    HundredMoveDraw,
-    ThreeFoldRepetition,
+    Rules(GameEndReason),
     DrawByadjudication,
-    DrawByMissingPieces,
-    StaleMate,
-    Mate,
     MateByadjudication,
+    MaxGameLength,
+    TablebaseAdjudication,
+    TimeLoss,
 }
 
 impl Display for EndConditionInformation {
     fn fmt(&self, formatter: &mut Formatter) -> Result {
-        let mut res_str: String = String::new();
-        res_str.push_str(match *self {
-            EndConditionInformation::HundredMoveDraw => "Hundred Move Draw",
-            EndConditionInformation::ThreeFoldRepetition => "Draw by Three Fold Repetition",
-            EndConditionInformation::DrawByadjudication => "Draw by adjudication",
-            EndConditionInformation::DrawByMissingPieces => "Draw by missing pieces",
-            EndConditionInformation::StaleMate => "Draw by Stalemate",
-            EndConditionInformation::Mate => "Win by Mate",
-            EndConditionInformation::MateByadjudication => "Win by adjudication",
-        });
+        let res_str = match *self {
+            EndConditionInformation::Rules(GameEndReason::Checkmate) => {
+                "Win by checkmate".to_owned()
+            }
+            EndConditionInformation::Rules(reason) => format!("Draw by {}", reason),
+            EndConditionInformation::DrawByadjudication => "Draw by adjudication".to_owned(),
+            EndConditionInformation::MateByadjudication => "Win by adjudication".to_owned(),
+            EndConditionInformation::MaxGameLength => "Draw by maximum game length".to_owned(),
+            EndConditionInformation::TablebaseAdjudication => "Tablebase adjudication".to_owned(),
+            EndConditionInformation::TimeLoss => "Loss on time".to_owned(),
+        };
         write!(formatter, "{}", res_str)
     }
 }
@@ -53,7 +55,24 @@ pub fn get_elo_gain(p_a: f64) -> f64 {
     -1.0 * (1.0 / p_a - 1.0).ln() * 400.0 / (10.0 as f64).ln()
 }
 
-#[derive(Clone)]
+pub fn get_elo_estimate(wins: usize, draws: usize, losses: usize) -> (f64, f64) {
+    //Derived from 1. E_A= 1/(1+10^(-DeltaElo/400)) and 2. |X/N-p|<=1.96*sqrt(N*p*(1-p))/n
+    let n: f64 = (wins + draws + losses) as f64;
+    let x_a: f64 = wins as f64 + draws as f64 / 2.0;
+    if n >= 1. {
+        let p_a: f64 = x_a / n;
+        let k: f64 = (1.96 * 1.96 + 2.0 * x_a) / (-1.0 * 1.96 * 1.96 - n);
+        let q = -1.0 * x_a * x_a / (n * (-1.96 * 1.96 - n));
+        let root = ((k / 2.0) * (k / 2.0) - q).sqrt();
+        let p_a_upper: f64 = -k / 2.0 + root;
+        let curr = get_elo_gain(p_a);
+        (curr, get_elo_gain(p_a_upper) - curr)
+    } else {
+        (0., 0.)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct EngineStats {
     pub moves_played: usize,
     pub avg_depth: f64,
@@ -96,7 +115,7 @@ pub struct Engine {
     pub draws: usize,
     pub losses: usize,
     pub disqs: usize,
-    pub time_control: TimeControl,
+    pub time_control: EngineTimeControl,
     pub stats: EngineStats,
     pub uci_options: HashMap<String, String>,
 }
@@ -117,20 +136,7 @@ impl Engine {
         self.disqs += other.disqs;
     }
     pub fn get_elo_gain(&self) -> (String, String, f64) {
-        //Derived from 1. E_A= 1/(1+10^(-DeltaElo/400)) and 2. |X/N-p|<=1.96*sqrt(N*p*(1-p))/n
-        let n: f64 = (self.wins + self.draws + self.losses) as f64;
-        let x_a: f64 = self.wins as f64 + self.draws as f64 / 2.0;
-        let (elo_gain, elo_bounds) = if n >= 1. || x_a >= 0. {
-            let p_a: f64 = x_a / n;
-            let k: f64 = (1.96 * 1.96 + 2.0 * x_a) / (-1.0 * 1.96 * 1.96 - n);
-            let q = -1.0 * x_a * x_a / (n * (-1.96 * 1.96 - n));
-            let root = ((k / 2.0) * (k / 2.0) - q).sqrt();
-            let p_a_upper: f64 = -k / 2.0 + root;
-            let curr = get_elo_gain(p_a);
-            (curr, get_elo_gain(p_a_upper) - curr)
-        } else {
-            (0., 0.)
-        };
+        let (elo_gain, elo_bounds) = get_elo_estimate(self.wins, self.draws, self.losses);
         (
             format!(
                 "{:25}{:.2}   +/- {:.2}   +{}   ={}   -{}  sc {:.1}%",
@@ -155,10 +161,30 @@ impl Engine {
         )
     }
 
+    //An engine without a process behind it, for tests of the bookkeeping
+    #[cfg(test)]
+    pub fn dummy(id: usize) -> Self {
+        Engine {
+            name: format!("Engine{}", id),
+            path: String::new(),
+            id,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+            disqs: 0,
+            time_control: EngineTimeControl::new(
+                crate::timecontrol::TimeControlConfig::Depth(1),
+                1.0,
+            ),
+            stats: EngineStats::default(),
+            uci_options: HashMap::new(),
+        }
+    }
+
     pub async fn from_path(
         path: &str,
         id: usize,
-        tc: TimeControl,
+        tc: EngineTimeControl,
         options: HashMap<String, String>,
     ) -> Self {
         let mut res = Engine {
@@ -197,6 +223,8 @@ impl Engine {
         res
     }
 
+    //Returns the move, the info lines sent while searching and the time spent on the move
+    #[allow(clippy::too_many_arguments)]
     pub async fn request_move(
         &mut self,
         position_description: &str,
@@ -204,42 +232,49 @@ impl Engine {
         stdin: &mut BufWriter<ChildStdin>,
         stdout: &mut BufReader<ChildStdout>,
         task_id: usize,
-        movelist: &MoveList,
-    ) -> EngineReaction<(GameMove, EngineStatus)> {
+        game_state: &GameState,
+        time_margin: u64,
+    ) -> EngineReaction<(GameMove, UCIInfo, u64)> {
         write_all(stdin, position_description).await;
         let reaction = self.valid_isready_reaction(stdin, stdout, task_id).await;
         if let EngineReaction::DisqualifyEngine = reaction {
             return EngineReaction::DisqualifyEngine;
         }
         write_all(stdin, go_string).await;
+        //Waiting a millisecond longer than allowed makes sure that a timeout counts as a time loss
+        let deadline = self.time_control.time_left().saturating_add(time_margin);
         let output = expect_output_and_listen_for_info(
             "bestmove",
             "info",
-            self.time_control.time_left(),
+            deadline.saturating_add(1),
             stdout,
         )
         .await;
-        if output.0.is_none() {
+        let time_spent = output.2 as u64;
+        if time_spent > deadline {
             info!(
-                "Engine {} didn't send bestmove in time in game {}! It had {}ms left!\n",
+                "Engine {} lost on time in game {}! It had {}ms left and used {}ms!\n",
                 self.name,
                 task_id,
                 self.time_control.time_left(),
+                time_spent,
             );
-            return EngineReaction::DisqualifyEngine;
+            return EngineReaction::LoseOnTime;
         }
-        if output.2 as u64 > self.time_control.time_left() {
-            warn!("Mistake in Referee! Bestmove found but it took longer than time still left ({}) for engine {}! Disqualifying engine illegitimately in game {}\n",self.time_control.time_left(),self.name ,task_id);
+        if output.0.is_none() {
+            info!(
+                "Engine {} stopped sending output before bestmove in game {}!\n",
+                self.name, task_id,
+            );
             return EngineReaction::DisqualifyEngine;
         }
-        self.time_control.update(output.2 as u64, None);
+        self.time_control.update(time_spent);
 
         //Parse the move
         let line = output.0.unwrap();
         let split_line: Vec<&str> = line.split_whitespace().collect();
-        let game_move: GameMove = if split_line[0] == "bestmove" {
-            let mv = GameMove::string_to_move(split_line[1]);
-            let found_move = find_move(mv.0, mv.1, mv.2, &movelist);
+        let game_move: GameMove = if split_line[0] == "bestmove" && split_line.len() > 1 {
+            let found_move = GameMove::from_uci(game_state, split_line[1]).ok();
             if found_move.is_none() {
                 info!(
                     "Engine {} sent illegal move ({}) in game {}\n",
@@ -257,25 +292,8 @@ impl Engine {
         };
 
         //Get additional info about engine e.g. how deep it saw, nps, and its evaluation
-        let mut status = EngineStatus::ProclaimsNothing;
         self.stats.moves_played += 1;
         let info = fetch_info(&output.1);
-        if info.negative_mate_found {
-            status = EngineStatus::ProclaimsLoss;
-        } else if info.positive_mate_found {
-            status = EngineStatus::ProclaimsWin;
-        } else if info.cp_score.is_some() {
-            let score = info.cp_score.unwrap();
-            if score.abs() <= 10 {
-                status = EngineStatus::ProclaimsDraw;
-            }
-            if score < -1000 {
-                status = EngineStatus::ProclaimsLoss;
-            } else if score > 1000 {
-                status = EngineStatus::ProclaimsWin
-            }
-        }
-
         if let Some(dep) = info.depth {
             self.stats.avg_depth += dep as f64;
         }
@@ -283,7 +301,7 @@ impl Engine {
             self.stats.avg_nps += nps as f64;
         }
 
-        EngineReaction::ContinueGame((game_move, status))
+        EngineReaction::ContinueGame((game_move, info, time_spent))
     }
 
     pub async fn valid_isready_reaction(
@@ -340,38 +358,13 @@ impl Engine {
     }
 }
 
-pub fn find_move(
-    from: usize,
-    to: usize,
-    promo_pieces: Option<PieceType>,
-    move_list: &MoveList,
-) -> Option<GameMove> {
-    for gmv in move_list.move_list.iter() {
-        let mv = gmv.0;
-        if mv.from as usize == from && mv.to as usize == to {
-            if let GameMoveType::Promotion(ps, _) = mv.move_type {
-                match promo_pieces {
-                    Some(piece) => {
-                        if piece != ps {
-                            continue;
-                        }
-                    }
-                    None => {
-                        continue;
-                    }
-                }
-            }
-            return Some(mv);
-        }
-    }
-    None
-}
-
 pub fn fetch_info(info: &str) -> UCIInfo {
     let split_line: Vec<&str> = info.split_whitespace().collect();
     let mut depth = None;
     let mut nps = None;
     let mut cp_score = None;
+    let mut tbhits = None;
+    let mut wdl = None;
     let mut positive_mate_found = false;
     let mut negative_mate_found = false;
     let mut index = 0;
@@ -389,6 +382,20 @@ pub fn fetch_info(info: &str) -> UCIInfo {
                 nps = split_line[index + 1].parse::<usize>().ok();
                 index += 1;
             }
+            "tbhits" => {
+                tbhits = split_line[index + 1].parse::<usize>().ok();
+                index += 1;
+            }
+            "wdl" if index + 3 < split_line.len() => {
+                let permille: Vec<Option<usize>> = split_line[index + 1..index + 4]
+                    .iter()
+                    .map(|value| value.parse().ok())
+                    .collect();
+                if let [Some(win), Some(draw), Some(loss)] = permille[..] {
+                    wdl = Some((win, draw, loss));
+                }
+                index += 3;
+            }
             "mate" => {
                 let mate_score = match split_line[index + 1].parse::<isize>() {
                     Ok(s) => s,
@@ -408,17 +415,22 @@ pub fn fetch_info(info: &str) -> UCIInfo {
         depth,
         nps,
         cp_score,
+        tbhits,
+        wdl,
         positive_mate_found,
         negative_mate_found,
     }
 }
 
 pub struct UCIInfo {
-    depth: Option<usize>,
-    nps: Option<usize>,
-    cp_score: Option<isize>,
-    positive_mate_found: bool,
-    negative_mate_found: bool,
+    pub depth: Option<usize>,
+    pub nps: Option<usize>,
+    pub cp_score: Option<isize>,
+    pub tbhits: Option<usize>,
+    //Win, draw and loss probabilities in permille
+    pub wdl: Option<(usize, usize, usize)>,
+    pub positive_mate_found: bool,
+    pub negative_mate_found: bool,
 }
 
 pub struct PlayTask {
@@ -434,14 +446,29 @@ pub struct TaskResult {
     pub task: PlayTask,
     pub endcondition: Option<EndConditionInformation>,
     pub move_sequence: Vec<GameMove>,
+    //Milliseconds spent by the engine to move on every move of move_sequence
+    pub move_times: Vec<u64>,
     pub final_status: GameResult,
 }
 
 impl TaskResult {
+    //Score of engine1 in half points, a disqualification counts as a loss
+    pub fn engine1_half_points(&self) -> usize {
+        let engine1 = &self.task.engine1;
+        if engine1.disqs > 0 || engine1.losses > 0 {
+            0
+        } else if self.task.engine2.disqs > 0 || engine1.wins > 0 {
+            2
+        } else {
+            1
+        }
+    }
+
     pub fn disq(
         mut task: PlayTask,
         p1: bool,
         move_sequence: Vec<GameMove>,
+        move_times: Vec<u64>,
         final_status: GameResult,
     ) -> Self {
         if p1 {
@@ -455,6 +482,7 @@ impl TaskResult {
             task,
             endcondition: None,
             move_sequence,
+            move_times,
             final_status,
         }
     }
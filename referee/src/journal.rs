@@ -0,0 +1,249 @@
+use crate::engine::{Engine, EngineStats, TaskResult};
+use crate::Config;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+
+pub const JOURNAL_PATH: &str = "referee_journal.json";
+
+//Hash of everything in the config that influences the games. The amount of processors may change between runs
+pub fn hash_config(config: &Config) -> u64 {
+    let mut value = serde_json::to_value(config).expect("Could not serialize config!");
+    if let Some(map) = value.as_object_mut() {
+        map.remove("processors");
+    }
+    let mut hasher = DefaultHasher::new();
+    value.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EngineRecord {
+    pub id: usize,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    pub disqs: usize,
+    pub stats: EngineStats,
+}
+
+impl EngineRecord {
+    pub fn from_engine(engine: &Engine) -> Self {
+        EngineRecord {
+            id: engine.id,
+            wins: engine.wins,
+            draws: engine.draws,
+            losses: engine.losses,
+            disqs: engine.disqs,
+            stats: engine.stats.clone(),
+        }
+    }
+
+    pub fn to_engine(&self, template: &Engine) -> Engine {
+        let mut res = template.clone();
+        res.wins = self.wins;
+        res.draws = self.draws;
+        res.losses = self.losses;
+        res.disqs = self.disqs;
+        res.stats = self.stats.clone();
+        res
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GameRecord {
+    pub task_id: usize,
+    pub engine1: EngineRecord,
+    pub engine2: EngineRecord,
+    pub engine1_half_points: usize,
+}
+
+impl GameRecord {
+    pub fn from_result(result: &TaskResult) -> Self {
+        GameRecord {
+            task_id: result.task.id,
+            engine1: EngineRecord::from_engine(&result.task.engine1),
+            engine2: EngineRecord::from_engine(&result.task.engine2),
+            engine1_half_points: result.engine1_half_points(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Journal {
+    pub config_hash: u64,
+    //Seed of the opening selection, so that a resumed match plays the same openings
+    pub seed: u64,
+    //In the order the results were collected
+    pub completed: Vec<GameRecord>,
+    pub pending: Vec<usize>,
+}
+
+impl Journal {
+    pub fn new(config_hash: u64, seed: u64) -> Self {
+        Journal {
+            config_hash,
+            seed,
+            completed: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &str, config_hash: u64) -> Self {
+        let content = fs::read_to_string(path).expect("Unable to read journal file!");
+        let journal: Journal = serde_json::from_str(&content).expect("Journal file is corrupted!");
+        if journal.config_hash != config_hash {
+            panic!("Journal was written for a different config, can not resume!");
+        }
+        journal
+    }
+
+    //Writes to a temporary file first, so a crash while saving never destroys the old journal
+    pub fn save(&self, path: &str) {
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(
+            &tmp_path,
+            serde_json::to_string(self).expect("Could not serialize journal!"),
+        )
+        .expect("Could not write journal file!");
+        fs::rename(&tmp_path, path).expect("Could not replace journal file!");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::PlayTask;
+    use crate::openings::{build_tasks, pick_openings};
+    use core_sdk::board_representation::game_state::{GameMove, GameState};
+    use core_sdk::move_generation::makemove::make_move;
+    use core_sdk::perft::legal_moves;
+
+    fn config() -> Config {
+        serde_json::from_str(include_str!("../EXAMPLE_CONFIG.json")).unwrap()
+    }
+
+    fn journal_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!(
+                "referee_journal_{}_{}.json",
+                name,
+                std::process::id()
+            ))
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    fn record(task_id: usize, engine1_half_points: usize) -> GameRecord {
+        let (mut engine1, mut engine2) = (Engine::dummy(0), Engine::dummy(1));
+        engine1.wins = engine1_half_points / 2;
+        engine1.draws = engine1_half_points % 2;
+        engine2.losses = engine1.wins;
+        engine2.draws = engine1.draws;
+        engine1.stats.moves_played = 40;
+        engine1.stats.avg_depth = 12.5;
+        GameRecord {
+            task_id,
+            engine1: EngineRecord::from_engine(&engine1),
+            engine2: EngineRecord::from_engine(&engine2),
+            engine1_half_points,
+        }
+    }
+
+    #[test]
+    fn config_hash_ignores_processors() {
+        let mut other = config();
+        other.processors += 1;
+        assert_eq!(hash_config(&config()), hash_config(&other));
+        other.games += 2;
+        assert_ne!(hash_config(&config()), hash_config(&other));
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let path = journal_path("round_trip");
+        let mut journal = Journal::new(hash_config(&config()), 1234);
+        journal.completed.push(record(0, 2));
+        journal.completed.push(record(3, 1));
+        journal.pending = vec![1, 2, 4];
+        journal.save(&path);
+        let loaded = Journal::load(&path, hash_config(&config()));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.config_hash, journal.config_hash);
+        assert_eq!(loaded.seed, 1234);
+        assert_eq!(loaded.pending, vec![1, 2, 4]);
+        assert_eq!(loaded.completed.len(), 2);
+        let (first, second) = (&loaded.completed[0], &loaded.completed[1]);
+        assert_eq!((first.task_id, first.engine1_half_points), (0, 2));
+        assert_eq!((second.task_id, second.engine1_half_points), (3, 1));
+        let engine1 = first.engine1.to_engine(&Engine::dummy(0));
+        assert_eq!((engine1.wins, engine1.draws, engine1.losses), (1, 0, 0));
+        assert_eq!(engine1.stats.moves_played, 40);
+        assert!((engine1.stats.avg_depth - 12.5).abs() < f64::EPSILON);
+        assert_eq!(second.engine2.draws, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "different config")]
+    fn load_rejects_other_config() {
+        let path = journal_path("other_config");
+        Journal::new(hash_config(&config()), 0).save(&path);
+        let mut other = config();
+        other.games += 2;
+        let res = std::panic::catch_unwind(|| Journal::load(&path, hash_config(&other)));
+        std::fs::remove_file(&path).unwrap();
+        if let Err(e) = res {
+            std::panic::resume_unwind(e);
+        }
+    }
+
+    //Positions after the first two plies, as an opening database
+    fn database() -> (Vec<GameState>, Vec<Vec<GameMove>>) {
+        let mut db = Vec::new();
+        let mut sequences = Vec::new();
+        let start = GameState::standard();
+        for first in legal_moves(&start) {
+            let state = make_move(&start, first);
+            for second in legal_moves(&state) {
+                db.push(make_move(&state, second));
+                sequences.push(vec![first, second]);
+            }
+        }
+        (db, sequences)
+    }
+
+    fn assignment(seed: u64) -> Vec<(usize, u64, bool, usize, usize)> {
+        let (mut db, mut sequences) = database();
+        let openings = pick_openings(10, &mut db, &mut sequences, seed);
+        let engines = vec![Engine::dummy(0), Engine::dummy(1), Engine::dummy(2)];
+        build_tasks(&openings, &[(0, 1), (0, 2)], &engines, 0)
+            .iter()
+            .map(|task: &PlayTask| {
+                (
+                    task.id,
+                    task.opening.hash,
+                    task.p1_is_white,
+                    task.engine1.id,
+                    task.engine2.id,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resume_assigns_same_openings() {
+        let path = journal_path("openings");
+        let journal = Journal::new(hash_config(&config()), rand::random());
+        journal.save(&path);
+        let loaded = Journal::load(&path, hash_config(&config()));
+        std::fs::remove_file(&path).unwrap();
+
+        let original = assignment(journal.seed);
+        assert_eq!(original.len(), 40);
+        assert_eq!(assignment(loaded.seed), original);
+        assert_ne!(assignment(journal.seed.wrapping_add(1)), original);
+    }
+}
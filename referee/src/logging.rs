@@ -46,11 +46,10 @@ impl log::Log for FileLogger {
         }
     }
 
+    //Also syncs the file, so what was written survives a crash of the machine
     fn flush(&self) {
-        self.0
-            .lock()
-            .unwrap()
-            .flush()
-            .expect("Could not flush in FileLogger");
+        let mut file = self.0.lock().unwrap();
+        file.flush().expect("Could not flush in FileLogger");
+        file.sync_data().expect("Could not sync in FileLogger");
     }
 }
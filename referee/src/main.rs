@@ -1,15 +1,26 @@
+use crate::adjudication::AdjudicationConfig;
+use crate::sprt::SPRTConfig;
+use crate::spsa::SPSAConfig;
+use crate::timecontrol::{EngineTimeControl, TimeControlConfig};
+use crate::tournament::TournamentMode;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
 use std::fs;
 
+pub mod adjudication;
 pub mod async_communication;
 pub mod engine;
+pub mod journal;
 pub mod logging;
 pub mod openings;
 pub mod queue;
 pub mod selfplay;
 pub mod selfplay_splitter;
+pub mod sprt;
+pub mod spsa;
+pub mod timecontrol;
+pub mod tournament;
 
 //STS
 pub const STS_SUB_SUITS: [&str; 15] = [
@@ -30,18 +41,72 @@ pub const STS_SUB_SUITS: [&str; 15] = [
     "STS(v15.0) AT",
 ];
 
+fn default_time_odds() -> f64 {
+    1.0
+}
+
+//Either ["path", {options}] or an object that may also set the time control of the engine
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EngineEntry {
+    Simple(String, HashMap<String, String>),
+    Detailed {
+        path: String,
+        #[serde(default)]
+        options: HashMap<String, String>,
+        time_control: Option<TimeControlConfig>,
+        #[serde(default = "default_time_odds")]
+        time_odds: f64,
+    },
+}
+
+impl EngineEntry {
+    pub fn path(&self) -> &str {
+        match self {
+            EngineEntry::Simple(path, _) | EngineEntry::Detailed { path, .. } => path,
+        }
+    }
+
+    pub fn options(&self) -> HashMap<String, String> {
+        match self {
+            EngineEntry::Simple(_, options) | EngineEntry::Detailed { options, .. } => {
+                options.clone()
+            }
+        }
+    }
+
+    pub fn time_control(&self, default: TimeControlConfig) -> EngineTimeControl {
+        match self {
+            EngineEntry::Simple(..) => EngineTimeControl::new(default, 1.0),
+            EngineEntry::Detailed {
+                time_control,
+                time_odds,
+                ..
+            } => EngineTimeControl::new(time_control.unwrap_or(default), *time_odds),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub processors: usize,
     pub games: usize,
-    pub engine_path: (String, HashMap<String, String>),
-    pub enemies_paths: Vec<(String, HashMap<String, String>)>,
+    pub engine_path: EngineEntry,
+    pub enemies_paths: Vec<EngineEntry>,
     pub opening_databases: Vec<String>,
     pub opening_load_untilply: usize,
+    //Time controls of engines that don't specify their own
     pub timecontrol_engine_time: u64,
     pub timecontrol_engine_inc: u64,
     pub timecontrol_enemies_time: u64,
     pub timecontrol_enemies_inc: u64,
+    pub sprt: Option<SPRTConfig>,
+    //Tunes the options of engine_path instead of playing a match
+    pub spsa: Option<SPSAConfig>,
+    #[serde(default)]
+    pub tournament: TournamentMode,
+    #[serde(default)]
+    pub adjudication: AdjudicationConfig,
 }
 /*
 Error-Margin in +/- (95% Confidence)
@@ -61,6 +126,7 @@ Win/Elo Gain:-------------------------------------------------------------------
 */
 fn main() {
     let mut config_path = "REFEREE_CONFIG.json";
+    let mut resume = false;
     let args: Vec<String> = env::args().collect();
     let mut index: usize = 1;
     while index < args.len() {
@@ -70,9 +136,13 @@ fn main() {
                 index += 2;
                 continue;
             }
+            "resume" => {
+                resume = true;
+                index += 1;
+            }
             _ => {
                 println!(
-                    "Invalid argument {}, use config CONFIG_FILE to specify",
+                    "Invalid argument {}, use config CONFIG_FILE to specify or resume to continue the last match",
                     &args[index]
                 );
                 index += 1;
@@ -87,5 +157,12 @@ fn main() {
         .enable_all()
         .build()
         .expect("Could not create tokio runtime");
-    runtime.block_on(selfplay_splitter::start_self_play(config));
+    if config.spsa.is_some() {
+        if resume {
+            println!("SPSA can not be resumed, starting from the configured values!");
+        }
+        runtime.block_on(spsa::start_spsa(config));
+    } else {
+        runtime.block_on(selfplay_splitter::start_self_play(config, resume));
+    }
 }
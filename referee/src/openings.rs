@@ -1,18 +1,17 @@
 use crate::engine::{Engine, PlayTask};
-use crate::queue::ThreadSafeQueue;
 use core_sdk::board_representation::game_state::*;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-pub fn load_openings_into_queue(
+//The same seed and databases always result in the same openings
+pub fn pick_openings(
     n: usize,
-    mut db: Vec<GameState>,
-    mut db_sequences: Vec<Vec<GameMove>>,
-    gauntlet_engine: &Engine,
-    enemies: &[Engine],
-) -> ThreadSafeQueue<PlayTask> {
-    let mut rng = rand::thread_rng();
-    let mut res: Vec<PlayTask> = Vec::with_capacity(n);
-    let mut id = 0;
+    db: &mut Vec<GameState>,
+    db_sequences: &mut Vec<Vec<GameMove>>,
+    seed: u64,
+) -> Vec<(GameState, Vec<GameMove>)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut res: Vec<(GameState, Vec<GameMove>)> = Vec::with_capacity(n);
     for _ in 0..n {
         loop {
             if db.is_empty() {
@@ -22,33 +21,42 @@ pub fn load_openings_into_queue(
             let state = db.remove(index);
             let sequence = db_sequences.remove(index);
             if !contains(&res, &state) {
-                for enemy_engine in enemies {
-                    res.push(PlayTask {
-                        opening: state.clone(),
-                        opening_sequence: sequence.clone(),
-                        p1_is_white: true,
-                        id,
-                        engine1: gauntlet_engine.clone(),
-                        engine2: enemy_engine.clone(),
-                    });
-                    id += 1;
-                    res.push(PlayTask {
-                        opening: state.clone(),
-                        opening_sequence: sequence.clone(),
-                        p1_is_white: false,
-                        id,
-                        engine1: gauntlet_engine.clone(),
-                        engine2: enemy_engine.clone(),
-                    });
-                    id += 1;
-                }
+                res.push((state, sequence));
                 break;
             }
         }
     }
-    ThreadSafeQueue::new(res)
+    res
 }
 
-pub fn contains(queue: &[PlayTask], state: &GameState) -> bool {
-    queue.iter().any(|other| other.opening.hash == state.hash)
+//Every opening is played with both colors for every pairing. The two games of an opening
+//always get the ids 2k and 2k+1
+pub fn build_tasks(
+    openings: &[(GameState, Vec<GameMove>)],
+    pairings: &[(usize, usize)],
+    engines: &[Engine],
+    first_id: usize,
+) -> Vec<PlayTask> {
+    let mut res: Vec<PlayTask> = Vec::with_capacity(openings.len() * pairings.len() * 2);
+    let mut id = first_id;
+    for (state, sequence) in openings {
+        for pairing in pairings {
+            for &p1_is_white in [true, false].iter() {
+                res.push(PlayTask {
+                    opening: state.clone(),
+                    opening_sequence: sequence.clone(),
+                    p1_is_white,
+                    id,
+                    engine1: engines[pairing.0].clone(),
+                    engine2: engines[pairing.1].clone(),
+                });
+                id += 1;
+            }
+        }
+    }
+    res
+}
+
+pub fn contains(openings: &[(GameState, Vec<GameMove>)], state: &GameState) -> bool {
+    openings.iter().any(|other| other.0.hash == state.hash)
 }
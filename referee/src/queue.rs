@@ -23,6 +23,11 @@ impl<T> ThreadSafeQueue<T> {
         (*data).push(item);
     }
 
+    pub fn drain(&self) -> Vec<T> {
+        let mut data = self.queue.lock().unwrap();
+        (*data).drain(..).collect()
+    }
+
     pub fn len(&self) -> usize {
         let data = self.queue.lock().unwrap();
         data.len()
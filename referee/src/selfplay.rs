@@ -1,7 +1,9 @@
+use crate::adjudication::{probe_tablebase, AdjudicationConfig, Adjudicator};
 use crate::async_communication::{stderr_listener, write_all};
-use crate::engine::{EndConditionInformation, EngineReaction, EngineStatus, PlayTask, TaskResult};
+use crate::engine::{EndConditionInformation, EngineReaction, PlayTask, TaskResult};
 use core_sdk::board_representation::game_state::*;
 use core_sdk::board_representation::game_state_attack_container::GameStateAttackContainer;
+use core_sdk::board_representation::rules::game_result;
 use core_sdk::move_generation::makemove::make_move;
 use core_sdk::move_generation::movegen;
 use log::warn;
@@ -20,7 +22,7 @@ pub async fn cleanup(mut e1: Child, mut e2: Child, e1_err: JoinHandle<()>, e2_er
         .await
         .unwrap_or_else(|msg| warn!("Could not join e2_err task: {}", msg));
 }
-pub async fn play_game(mut task: PlayTask) -> TaskResult {
+pub async fn play_game(mut task: PlayTask, adjudication: &AdjudicationConfig) -> TaskResult {
     let mut movelist = movegen::MoveList::default();
     let mut attack_container = GameStateAttackContainer::default();
     //-------------------------------------------------------------
@@ -39,6 +41,7 @@ pub async fn play_game(mut task: PlayTask) -> TaskResult {
     assert_eq!(status, GameResult::Ingame);
     history.push(task.opening.clone());
     let mut move_history: Vec<GameMove> = Vec::with_capacity(100);
+    let mut move_times: Vec<u64> = Vec::with_capacity(100);
     let mut endcondition = None;
     //-------------------------------------------------------------
     //Set players up
@@ -56,7 +59,7 @@ pub async fn play_game(mut task: PlayTask) -> TaskResult {
         e1_err.await.unwrap_or_else(|msg| {
             warn!("Could not join err reading task: {:?}", msg);
         });
-        return TaskResult::disq(task, true, move_history, status);
+        return TaskResult::disq(task, true, move_history, move_times, status);
     }
 
     let (e2, mut e2_input, mut e2_output, e2_err) = task.engine2.get_handles().await;
@@ -67,13 +70,12 @@ pub async fn play_game(mut task: PlayTask) -> TaskResult {
         .await;
     if let EngineReaction::DisqualifyEngine = reaction {
         cleanup(e1, e2, e1_err, e2_err).await;
-        return TaskResult::disq(task, false, move_history, status);
+        return TaskResult::disq(task, false, move_history, move_times, status);
     }
     //-------------------------------------------------------------
     //Adjudications
-    let mut draw_adjudication = 0usize;
-    let mut win_adjudication = 0usize;
-    let mut win_adjudication_for_p1 = true;
+    let mut adjudicator = Adjudicator::new(adjudication);
+    let mut tablebase_pieces = 33;
 
     while let GameResult::Ingame = status {
         //Request move
@@ -92,145 +94,92 @@ pub async fn play_game(mut task: PlayTask) -> TaskResult {
         }
         position_string.push_str("\n");
         //Prepare go command
-        let mut go_string = String::new();
-        go_string.push_str(&format!(
-            "go {} {}\n",
-            if task.p1_is_white {
-                task.engine1.time_control.to_go(true)
-            } else {
-                task.engine2.time_control.to_go(true)
-            },
-            if task.p1_is_white {
-                task.engine2.time_control.to_go(false)
-            } else {
-                task.engine1.time_control.to_go(false)
-            }
-        ));
-        let game_move: GameMove;
-        if player1_move {
-            let reaction = task
-                .engine1
+        let (white_tc, black_tc) = if task.p1_is_white {
+            (&task.engine1.time_control, &task.engine2.time_control)
+        } else {
+            (&task.engine2.time_control, &task.engine1.time_control)
+        };
+        let go_string = if latest_state.color_to_move == WHITE {
+            format!("go {}\n", white_tc.go_args(true, black_tc))
+        } else {
+            format!("go {}\n", black_tc.go_args(false, white_tc))
+        };
+        let reaction = if player1_move {
+            task.engine1
                 .request_move(
                     &position_string,
                     &go_string,
                     &mut e1_input,
                     &mut e1_output,
                     task.id,
-                    &movelist,
+                    latest_state,
+                    adjudication.time_margin,
                 )
-                .await;
-            let engine_status;
-            match reaction {
-                EngineReaction::DisqualifyEngine => {
-                    cleanup(e1, e2, e1_err, e2_err).await;
-                    return TaskResult::disq(task, true, move_history, status);
-                }
-                EngineReaction::ContinueGame(temp) => {
-                    game_move = temp.0;
-                    engine_status = temp.1;
-                }
-            }
-            if let EngineStatus::ProclaimsNothing = &engine_status {
-                draw_adjudication = 0;
-                win_adjudication = 0;
-            } else if let EngineStatus::ProclaimsDraw = &engine_status {
-                win_adjudication = 0;
-                draw_adjudication += 1;
-            } else if let EngineStatus::ProclaimsWin = &engine_status {
-                draw_adjudication = 0;
-                if !win_adjudication_for_p1 {
-                    win_adjudication = 1;
-                } else {
-                    win_adjudication += 1;
-                }
-                win_adjudication_for_p1 = true;
-            } else if let EngineStatus::ProclaimsLoss = &engine_status {
-                draw_adjudication = 0;
-                if win_adjudication_for_p1 {
-                    win_adjudication = 1;
-                } else {
-                    win_adjudication += 1;
-                }
-                win_adjudication_for_p1 = false;
-            }
+                .await
         } else {
-            let reaction = task
-                .engine2
+            task.engine2
                 .request_move(
                     &position_string,
                     &go_string,
                     &mut e2_input,
                     &mut e2_output,
                     task.id,
-                    &movelist,
+                    latest_state,
+                    adjudication.time_margin,
                 )
-                .await;
-            let engine_status;
-            match reaction {
-                EngineReaction::DisqualifyEngine => {
-                    cleanup(e1, e2, e1_err, e2_err).await;
-                    return TaskResult::disq(task, false, move_history, status);
-                }
-                EngineReaction::ContinueGame(temp) => {
-                    game_move = temp.0;
-                    engine_status = temp.1;
-                }
+                .await
+        };
+        let (game_move, info, time_spent) = match reaction {
+            EngineReaction::DisqualifyEngine => {
+                cleanup(e1, e2, e1_err, e2_err).await;
+                return TaskResult::disq(task, player1_move, move_history, move_times, status);
             }
-            if let EngineStatus::ProclaimsNothing = &engine_status {
-                draw_adjudication = 0;
-                win_adjudication = 0;
-            } else if let EngineStatus::ProclaimsDraw = &engine_status {
-                win_adjudication = 0;
-                draw_adjudication += 1;
-            } else if let EngineStatus::ProclaimsWin = &engine_status {
-                draw_adjudication = 0;
-                if win_adjudication_for_p1 {
-                    win_adjudication = 1;
-                } else {
-                    win_adjudication += 1;
-                }
-                win_adjudication_for_p1 = false;
-            } else if let EngineStatus::ProclaimsLoss = &engine_status {
-                draw_adjudication = 0;
-                if !win_adjudication_for_p1 {
-                    win_adjudication = 1;
-                } else {
-                    win_adjudication += 1;
-                }
-                win_adjudication_for_p1 = true;
+            EngineReaction::LoseOnTime => {
+                let (result, reason) = Adjudicator::time_loss(latest_state.color_to_move);
+                status = result;
+                endcondition = Some(reason);
+                break;
             }
-        }
+            EngineReaction::ContinueGame(temp) => temp,
+        };
 
         //Make new state with move
         move_history.push(game_move);
+        move_times.push(time_spent);
         let state = make_move(latest_state, game_move);
-        if state.full_moves < 35 {
-            draw_adjudication = 0;
-        }
+        let ply = 2 * (state.full_moves - 1) + state.color_to_move;
+        adjudicator.update(&adjudication.engine_status(&info), player1_move, ply);
         attack_container.write_state(&state);
         let agsi = movegen::generate_moves(&state, false, &mut movelist, &attack_container);
         let check = check_end_condition(&state, agsi.stm_haslegalmove, agsi.stm_incheck, &history);
+        let white_to_move = state.color_to_move == WHITE;
+        let pieces = state.get_all_pieces().count_ones();
         history.push(state);
         status = check.0;
         endcondition = check.1;
         //Check for adjudication
         if let GameResult::Ingame = status {
-            //Check adjudication values
-            if draw_adjudication >= 20 {
-                status = GameResult::Draw;
-                endcondition = Some(EndConditionInformation::DrawByadjudication);
-            } else if win_adjudication >= 10 {
-                endcondition = Some(EndConditionInformation::MateByadjudication);
-                if win_adjudication_for_p1 {
-                    if task.p1_is_white {
-                        status = GameResult::WhiteWin;
-                    } else {
-                        status = GameResult::BlackWin;
-                    }
-                } else if task.p1_is_white {
-                    status = GameResult::BlackWin;
-                } else {
-                    status = GameResult::WhiteWin;
+            if let Some((result, reason)) =
+                adjudicator.adjudicate(move_history.len(), task.p1_is_white)
+            {
+                status = result;
+                endcondition = Some(reason);
+            }
+        }
+        if let (GameResult::Ingame, Some(tablebase)) = (&status, &adjudication.tablebase) {
+            //Only probe again if a piece was captured since the last failed probe
+            if pieces <= tablebase.max_pieces && pieces < tablebase_pieces {
+                tablebase_pieces = pieces;
+                let mut position_string = position_string.trim_end().to_owned();
+                if move_history.len() == 1 {
+                    position_string.push_str(" moves");
+                }
+                position_string.push_str(&format!(" {:?}\n", game_move));
+                if let Some(result) =
+                    probe_tablebase(tablebase, &position_string, white_to_move).await
+                {
+                    status = result;
+                    endcondition = Some(EndConditionInformation::TablebaseAdjudication);
                 }
             }
         }
@@ -259,12 +208,13 @@ pub async fn play_game(mut task: PlayTask) -> TaskResult {
 
     task.engine1.stats.divide(); //Make the mean of nps and deepth
     task.engine2.stats.divide();
-    task.engine1.stats.avg_timeleft = task.engine1.time_control.time_left() as f64; //Set the time left
-    task.engine2.stats.avg_timeleft = task.engine2.time_control.time_left() as f64;
+    task.engine1.stats.avg_timeleft = task.engine1.time_control.clock_left() as f64; //Set the time left
+    task.engine2.stats.avg_timeleft = task.engine2.time_control.clock_left() as f64;
     TaskResult {
         task,
         endcondition,
         move_sequence: move_history,
+        move_times,
         final_status: status,
     }
 }
@@ -275,50 +225,14 @@ pub fn check_end_condition(
     in_check: bool,
     history: &[GameState],
 ) -> (GameResult, Option<EndConditionInformation>) {
-    let enemy_win = if game_state.color_to_move == 0 {
-        GameResult::BlackWin
-    } else {
-        GameResult::WhiteWin
-    };
-    if in_check && !has_legal_moves {
-        return (enemy_win, Some(EndConditionInformation::Mate));
-    }
-    if !in_check && !has_legal_moves {
-        return (GameResult::Draw, Some(EndConditionInformation::StaleMate));
-    }
-
-    //Missing pieces
-    if game_state.pieces[PAWN][WHITE]
-        | game_state.pieces[KNIGHT][WHITE]
-        | game_state.pieces[BISHOP][WHITE]
-        | game_state.pieces[ROOK][WHITE]
-        | game_state.pieces[QUEEN][WHITE]
-        | game_state.pieces[PAWN][BLACK]
-        | game_state.pieces[KNIGHT][BLACK]
-        | game_state.pieces[BISHOP][BLACK]
-        | game_state.pieces[ROOK][BLACK]
-        | game_state.pieces[QUEEN][BLACK]
-        == 0u64
-    {
-        return (
-            GameResult::Draw,
-            Some(EndConditionInformation::DrawByMissingPieces),
-        );
-    }
-    if game_state.half_moves >= 100 {
-        return (
-            GameResult::Draw,
-            Some(EndConditionInformation::HundredMoveDraw),
-        );
-    }
-    if get_occurences(history, game_state) >= 2 {
-        return (
-            GameResult::Draw,
-            Some(EndConditionInformation::ThreeFoldRepetition),
-        );
-    }
-
-    (GameResult::Ingame, None)
+    //The referee claims threefold repetition and the fifty-move rule for the engines
+    let (result, reason) = game_result(
+        game_state,
+        has_legal_moves,
+        in_check,
+        get_occurences(history, game_state),
+    );
+    (result, reason.map(EndConditionInformation::Rules))
 }
 
 pub fn get_occurences(history: &[GameState], state: &GameState) -> usize {
@@ -330,3 +244,64 @@ pub fn get_occurences(history: &[GameState], state: &GameState) -> usize {
     }
     occ
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+    use crate::timecontrol::{EngineTimeControl, TimeControlConfig};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    //Builds FabChess next to the test binary, i.e. in the target directory of this profile
+    fn fabchess_path() -> PathBuf {
+        let status = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned()))
+            .args(["build", "-p", "uci-engine"])
+            .status()
+            .expect("Could not run cargo");
+        assert!(status.success(), "Could not build uci-engine");
+        let mut path = std::env::current_exe().unwrap();
+        path.pop();
+        if path.ends_with("deps") {
+            path.pop();
+        }
+        path.join(format!("uci-engine{}", std::env::consts::EXE_SUFFIX))
+    }
+
+    #[test]
+    fn nodes_limited_game_is_played_to_completion() {
+        let path = fabchess_path();
+        let path = path.to_str().unwrap();
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let result = runtime.block_on(async {
+            let tc = EngineTimeControl::new(TimeControlConfig::Nodes(1000), 1.0);
+            let engine1 = Engine::from_path(path, 0, tc, HashMap::new()).await;
+            let engine2 = Engine::from_path(path, 1, tc, HashMap::new()).await;
+            let task = PlayTask {
+                opening: GameState::standard(),
+                opening_sequence: Vec::new(),
+                p1_is_white: true,
+                id: 0,
+                engine1,
+                engine2,
+            };
+            let adjudication = AdjudicationConfig {
+                max_game_length: Some(300),
+                ..AdjudicationConfig::default()
+            };
+            play_game(task, &adjudication).await
+        });
+        assert_eq!(result.task.engine1.disqs + result.task.engine2.disqs, 0);
+        assert_ne!(result.final_status, GameResult::Ingame);
+        assert!(!matches!(
+            result.endcondition,
+            None | Some(EndConditionInformation::TimeLoss)
+        ));
+        assert_eq!(result.move_sequence.len(), result.move_times.len());
+    }
+}
@@ -1,42 +1,68 @@
+use crate::adjudication::AdjudicationConfig;
 use crate::engine::{EndConditionInformation, Engine};
 use crate::engine::{PlayTask, TaskResult};
+use crate::journal::{hash_config, GameRecord, Journal, JOURNAL_PATH};
 use crate::logging::FileLogger;
-use crate::openings::load_openings_into_queue;
+use crate::openings::{build_tasks, pick_openings};
 use crate::queue::ThreadSafeQueue;
 use crate::selfplay::play_game;
+use crate::sprt::{SPRTStatus, SPRT};
+use crate::timecontrol::TimeControlConfig;
+use crate::tournament::{Tournament, TournamentMode};
 use crate::Config;
 use core_sdk::board_representation::game_state::*;
-use core_sdk::search::timecontrol::TimeControl;
 use extended_sdk::openings::load_db_until;
 use extended_sdk::pgn::pgn_writer::*;
-use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::atomic::{self, AtomicBool};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 use tokio::time::delay_for;
 
-pub async fn start_self_play(config: Config) {
-    FileLogger::new("referee_error_log.txt", false)
+pub async fn start_self_play(config: Config, resume: bool) {
+    FileLogger::new("referee_error_log.txt", resume)
         .init()
         .expect("Could not create File Logger");
-    let tcp1 = TimeControl::Incremental(
-        config.timecontrol_engine_time,
-        config.timecontrol_engine_inc,
-    );
-    let mut gauntlet_engine = Engine::from_path(
-        &config.engine_path.0,
-        999,
-        tcp1,
-        config.engine_path.1.clone(),
-    )
-    .await;
-    let tcp2 = TimeControl::Incremental(
-        config.timecontrol_enemies_time,
-        config.timecontrol_enemies_inc,
-    );
+    let config_hash = hash_config(&config);
+    let mut journal = if resume {
+        let journal = Journal::load(JOURNAL_PATH, config_hash);
+        println!(
+            "Resuming match with {} finished games!",
+            journal.completed.len()
+        );
+        journal
+    } else {
+        Journal::new(config_hash, rand::random())
+    };
+    let tcp1 = TimeControlConfig::Incremental {
+        time: config.timecontrol_engine_time,
+        inc: config.timecontrol_engine_inc,
+    };
     let mut engines: Vec<Engine> = Vec::new();
-    for (index, path) in config.enemies_paths.into_iter().enumerate() {
-        engines.push(Engine::from_path(&path.0, index, tcp2, path.1).await);
+    engines.push(
+        Engine::from_path(
+            config.engine_path.path(),
+            0,
+            config.engine_path.time_control(tcp1),
+            config.engine_path.options(),
+        )
+        .await,
+    );
+    let tcp2 = TimeControlConfig::Incremental {
+        time: config.timecontrol_enemies_time,
+        inc: config.timecontrol_enemies_inc,
+    };
+    for (index, entry) in config.enemies_paths.iter().enumerate() {
+        engines.push(
+            Engine::from_path(
+                entry.path(),
+                index + 1,
+                entry.time_control(tcp2),
+                entry.options(),
+            )
+            .await,
+        );
     }
     let mut db: Vec<GameState> = Vec::with_capacity(100_000);
     let mut db_sequences: Vec<Vec<GameMove>> = Vec::with_capacity(100_000);
@@ -52,35 +78,84 @@ pub async fn start_self_play(config: Config) {
             db.len()
         )
     );
-    let queue: Arc<ThreadSafeQueue<PlayTask>> = Arc::new(load_openings_into_queue(
-        config.games / 2,
-        db,
-        db_sequences,
-        &gauntlet_engine,
-        &engines,
-    ));
-    let games = queue.len();
-    println!("Prepared {} games! Starting...", games);
+    let openings = pick_openings(config.games / 2, &mut db, &mut db_sequences, journal.seed);
+    let mut tournament = Tournament::new(config.tournament, engines);
+    let pairings = tournament.next_round(openings.len() * 2);
+    let mut tasks = build_tasks(&openings, &pairings, &tournament.engines, 0);
+    let mut games = tasks.len();
+    let mut sprt = if config.tournament == TournamentMode::Gauntlet {
+        config.sprt.map(SPRT::new)
+    } else {
+        if config.sprt.is_some() {
+            println!("SPRT is only supported in gauntlet mode, ignoring it!");
+        }
+        None
+    };
+
+    //Replay finished games of the journal, this also recreates the later knockout rounds
+    let mut results_collected = 0;
+    let mut finished_ids: HashSet<usize> = HashSet::new();
+    for record in &journal.completed {
+        results_collected += 1;
+        finished_ids.insert(record.task_id);
+        let engine1 = record
+            .engine1
+            .to_engine(&tournament.engines[record.engine1.id]);
+        let engine2 = record
+            .engine2
+            .to_engine(&tournament.engines[record.engine2.id]);
+        tournament.add_game(&engine1, &engine2, record.engine1_half_points);
+        if let Some(sprt) = sprt.as_mut() {
+            sprt.add_game(record.task_id, record.engine1_half_points);
+        }
+        if results_collected == games && !tournament.is_finished() {
+            let pairings = tournament.next_round(openings.len() * 2);
+            let mut next_tasks = build_tasks(&openings, &pairings, &tournament.engines, games);
+            games += next_tasks.len();
+            tasks.append(&mut next_tasks);
+        }
+    }
+    let sprt_finished = sprt
+        .as_ref()
+        .map(|sprt| sprt.status() != SPRTStatus::Continue)
+        .unwrap_or(false);
+    if sprt_finished {
+        tasks.clear();
+        games = results_collected;
+    }
+    tasks.retain(|task| !finished_ids.contains(&task.id));
+    journal.pending = tasks.iter().map(|task| task.id).collect();
+    journal.save(JOURNAL_PATH);
+    let queue: Arc<ThreadSafeQueue<PlayTask>> = Arc::new(ThreadSafeQueue::new(tasks));
+    println!(
+        "Prepared {} games for round {} of the {}! Starting...",
+        queue.len(),
+        tournament.round,
+        tournament.mode
+    );
 
     let result_queue: Arc<ThreadSafeQueue<TaskResult>> =
         Arc::new(ThreadSafeQueue::new(Vec::with_capacity(100)));
     let pgn_log = FileLogger::new("pgns.pgn", true);
+    let finished = Arc::new(AtomicBool::new(false));
+    let adjudication = Arc::new(config.adjudication);
 
     //Start all childs
     let mut childs = Vec::with_capacity(config.processors);
     for _ in 0..config.processors {
         let queue_clone = queue.clone();
         let res_clone = result_queue.clone();
+        let finished_clone = finished.clone();
+        let adjudication_clone = adjudication.clone();
         childs.push(tokio::spawn(async move {
-            start_self_play_thread(queue_clone, res_clone).await
+            start_self_play_thread(queue_clone, res_clone, finished_clone, adjudication_clone).await
         }));
     }
 
     //Collect results
-    let mut results_collected = 0;
     while results_collected < games {
         delay_for(Duration::from_millis(50)).await;
-        if let Some(mut result) = result_queue.pop() {
+        if let Some(result) = result_queue.pop() {
             results_collected += 1;
             println!("*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*");
             println!("Game {} finished!", result.task.id);
@@ -90,90 +165,143 @@ pub async fn start_self_play(config: Config) {
                 println!("Reason: Disqualification");
             }
             println!("*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*-*");
+            //The PGN is on disk before the journal marks the game as completed. A crash in
+            //between replays the game on resume, at worst writing its PGN twice
+            write_pgn(&pgn_log, &result, tournament.mode);
             //Add engines
-            gauntlet_engine.add(&result.task.engine1);
-            engines[result.task.engine2.id].add(&result.task.engine2);
+            tournament.add_result(&result);
+            journal.completed.push(GameRecord::from_result(&result));
+            journal.pending.retain(|&id| id != result.task.id);
 
             println!("-------------------------------------------------");
-            let (rank, descr, _) = gauntlet_engine.get_elo_gain();
-            println!("{}", rank);
-            let mut other: Vec<(String, String, f64)> = Vec::with_capacity(engines.len());
-            for engine in &engines {
-                other.push(engine.get_elo_gain());
+            let ranking = tournament.get_ranking();
+            for &engine in &ranking {
+                println!("{}", tournament.engines[engine].get_elo_gain().0);
             }
-            other.sort_by(|a, b| {
-                if a.2 > b.2 {
-                    Ordering::Less
-                } else if (a.2 - b.2).abs() < std::f64::EPSILON {
-                    Ordering::Equal
-                } else {
-                    Ordering::Greater
+            println!("-------------------------------------------------");
+            if let Some(sprt) = sprt.as_mut() {
+                let was_running = sprt.status() == SPRTStatus::Continue;
+                sprt.add_game(result.task.id, result.engine1_half_points());
+                println!("{}", sprt.get_description());
+                if was_running && sprt.status() != SPRTStatus::Continue {
+                    let dropped = queue.drain();
+                    games -= dropped.len();
+                    journal
+                        .pending
+                        .retain(|&id| !dropped.iter().any(|task| task.id == id));
+                    println!(
+                        "SPRT finished: {}! Dropped {} queued games, waiting for {} running games",
+                        sprt.status(),
+                        dropped.len(),
+                        games - results_collected
+                    );
                 }
-            });
-            for desc in &other {
-                println!("{}", desc.0);
+                println!("-------------------------------------------------");
             }
-            println!("-------------------------------------------------");
-            if (results_collected + 1) % 5 == 0 {
+            if (results_collected + 1) % 5 == 0 || results_collected == games {
                 println!("+++++++++++++++++++++++++++++++++++++++++++++++++");
-                println!("{}", descr);
-                for desc in &other {
-                    println!("{}", desc.1);
+                for &engine in &ranking {
+                    println!("{}", tournament.engines[engine].get_elo_gain().1);
                 }
+                println!("{}", tournament.get_crosstable());
                 println!("+++++++++++++++++++++++++++++++++++++++++++++++++");
             }
-
-            //Write all fens of game to pgn
-            let opening_moves = Some(result.task.opening_sequence.len());
-            let mut moves = result.task.opening_sequence;
-            if !result.move_sequence.is_empty() {
-                moves.append(&mut result.move_sequence);
-                let mut metadata = PGNMetadata::default();
-                metadata.fill_systemdata();
-                metadata.event_name = Some("FabChess local gauntlet".to_owned());
-                metadata.round = Some(format!("{}", result.task.id));
-                metadata.result = Some(result.final_status.to_string());
-                metadata.termination = Some(if result.endcondition.is_none() {
-                    "rules infraction".to_owned()
-                } else {
-                    let temp = result.endcondition.unwrap();
-                    match temp {
-                        EndConditionInformation::DrawByadjudication
-                        | EndConditionInformation::MateByadjudication => "adjudication",
-                        _ => "normal",
-                    }
-                    .to_owned()
-                });
-                metadata.white = Some(if result.task.p1_is_white {
-                    result.task.engine1.name.clone()
-                } else {
-                    result.task.engine2.name.clone()
-                });
-                metadata.black = Some(if result.task.p1_is_white {
-                    result.task.engine2.name.clone()
-                } else {
-                    result.task.engine1.name.clone()
-                });
-                pgn_log.dump_msg(&get_pgn_string(&metadata, moves, opening_moves));
+            if results_collected == games && !tournament.is_finished() {
+                let pairings = tournament.next_round(openings.len() * 2);
+                let tasks = build_tasks(&openings, &pairings, &tournament.engines, games);
+                games += tasks.len();
+                println!(
+                    "Prepared {} games for round {} of the {}!",
+                    tasks.len(),
+                    tournament.round,
+                    tournament.mode
+                );
+                for task in tasks {
+                    journal.pending.push(task.id);
+                    queue.push(task);
+                }
             }
+            journal.save(JOURNAL_PATH);
         }
     }
+    finished.store(true, atomic::Ordering::SeqCst);
     for child in childs {
         child.await.expect("Couldn't join thread");
     }
+    println!("{}", tournament.get_crosstable());
+    println!("Final ranking:");
+    for (rank, &engine) in tournament.get_ranking().iter().enumerate() {
+        println!(
+            "{:3} {}",
+            rank + 1,
+            tournament.engines[engine].get_elo_gain().0
+        );
+    }
     println!("Testing finished!");
 }
 
+pub fn write_pgn(pgn_log: &FileLogger, result: &TaskResult, mode: TournamentMode) {
+    if result.move_sequence.is_empty() {
+        return;
+    }
+    let opening_length = result.task.opening_sequence.len();
+    let mut moves = result.task.opening_sequence.clone();
+    moves.extend_from_slice(&result.move_sequence);
+    let mut move_comments = vec![String::new(); opening_length];
+    for time in &result.move_times {
+        move_comments.push(format!("{:.3}s", *time as f64 / 1000.));
+    }
+    let mut metadata = PGNMetadata::default();
+    metadata.fill_systemdata();
+    metadata.event_name = Some(format!("FabChess local {}", mode));
+    metadata.round = Some(format!("{}", result.task.id));
+    metadata.result = Some(result.final_status.to_string());
+    metadata.termination = Some(
+        match result.endcondition {
+            None => "rules infraction",
+            Some(EndConditionInformation::DrawByadjudication)
+            | Some(EndConditionInformation::MateByadjudication)
+            | Some(EndConditionInformation::MaxGameLength)
+            | Some(EndConditionInformation::TablebaseAdjudication) => "adjudication",
+            Some(EndConditionInformation::TimeLoss) => "time forfeit",
+            _ => "normal",
+        }
+        .to_owned(),
+    );
+    let (white, black) = if result.task.p1_is_white {
+        (&result.task.engine1, &result.task.engine2)
+    } else {
+        (&result.task.engine2, &result.task.engine1)
+    };
+    metadata.white = Some(white.name.clone());
+    metadata.black = Some(black.name.clone());
+    pgn_log.dump_msg(&get_pgn_string(
+        &metadata,
+        moves,
+        Some(opening_length),
+        Some(&move_comments),
+    ));
+    log::Log::flush(pgn_log);
+}
+
+//Childs keep polling the queue until the collector is finished, since knockout rounds
+//are only queued once the previous round is decided
 pub async fn start_self_play_thread(
     queue: Arc<ThreadSafeQueue<PlayTask>>,
     result_queue: Arc<ThreadSafeQueue<TaskResult>>,
+    finished: Arc<AtomicBool>,
+    adjudication: Arc<AdjudicationConfig>,
 ) {
-    while let Some(task) = queue.pop() {
-        println!("Starting game {}", task.id);
-        let res = play_game(task).await;
-        if res.endcondition.is_none() {
-            thread::sleep(Duration::from_millis(50));
+    while !finished.load(atomic::Ordering::SeqCst) {
+        if let Some(task) = queue.pop() {
+            println!("Starting game {}", task.id);
+            let res = play_game(task, &adjudication).await;
+            if res.endcondition.is_none() {
+                thread::sleep(Duration::from_millis(50));
+            }
+            result_queue.push(res);
+        } else {
+            delay_for(Duration::from_millis(50)).await;
         }
-        result_queue.push(res);
     }
 }
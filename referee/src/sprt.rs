@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum SPRTModel {
+    Trinomial,
+    #[default]
+    Pentanomial,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SPRTConfig {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    #[serde(default)]
+    pub model: SPRTModel,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SPRTStatus {
+    Continue,
+    AcceptH0,
+    AcceptH1,
+}
+
+impl Display for SPRTStatus {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        write!(
+            formatter,
+            "{}",
+            match self {
+                SPRTStatus::Continue => "Continue",
+                SPRTStatus::AcceptH0 => "H0 accepted",
+                SPRTStatus::AcceptH1 => "H1 accepted",
+            }
+        )
+    }
+}
+
+pub fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+//Generalized SPRT: LLR ~ N * (s1 - s0) * (2 * mean - s0 - s1) / (2 * var)
+//where every sample is a normalized score in [0, 1] (a single game or a game pair)
+pub fn gsprt_llr(samples: &[(f64, usize)], elo0: f64, elo1: f64) -> f64 {
+    let n = samples.iter().map(|s| s.1).sum::<usize>() as f64;
+    if n < 1. {
+        return 0.;
+    }
+    let mean = samples.iter().map(|s| s.0 * s.1 as f64).sum::<f64>() / n;
+    let var = samples
+        .iter()
+        .map(|s| (s.0 - mean) * (s.0 - mean) * s.1 as f64)
+        .sum::<f64>()
+        / n;
+    if var <= 0. {
+        return 0.;
+    }
+    let (s0, s1) = (expected_score(elo0), expected_score(elo1));
+    n * (s1 - s0) * (2. * mean - s0 - s1) / (2. * var)
+}
+
+pub struct SPRT {
+    pub config: SPRTConfig,
+    //Losses, draws, wins of engine1
+    pub trinomial: [usize; 3],
+    //Game pairs scoring 0, 0.5, 1, 1.5 and 2 points for engine1
+    pub pentanomial: [usize; 5],
+    open_pairs: HashMap<usize, usize>,
+}
+
+impl SPRT {
+    pub fn new(config: SPRTConfig) -> Self {
+        SPRT {
+            config,
+            trinomial: [0; 3],
+            pentanomial: [0; 5],
+            open_pairs: HashMap::new(),
+        }
+    }
+
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.config.beta / (1. - self.config.alpha)).ln(),
+            ((1. - self.config.beta) / self.config.alpha).ln(),
+        )
+    }
+
+    //Score is given in half points: 0 = loss, 1 = draw, 2 = win
+    //Tasks 2k and 2k+1 are the two colors of the same opening and form a pair
+    pub fn add_game(&mut self, task_id: usize, half_points: usize) {
+        self.trinomial[half_points] += 1;
+        let pair = task_id / 2;
+        if let Some(other) = self.open_pairs.remove(&pair) {
+            self.pentanomial[half_points + other] += 1;
+        } else {
+            self.open_pairs.insert(pair, half_points);
+        }
+    }
+
+    pub fn llr(&self) -> f64 {
+        let samples: Vec<(f64, usize)> = match self.config.model {
+            SPRTModel::Trinomial => self
+                .trinomial
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (i as f64 / 2., *c))
+                .collect(),
+            SPRTModel::Pentanomial => self
+                .pentanomial
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (i as f64 / 4., *c))
+                .collect(),
+        };
+        gsprt_llr(&samples, self.config.elo0, self.config.elo1)
+    }
+
+    pub fn status(&self) -> SPRTStatus {
+        let (lower, upper) = self.bounds();
+        let llr = self.llr();
+        if llr >= upper {
+            SPRTStatus::AcceptH1
+        } else if llr <= lower {
+            SPRTStatus::AcceptH0
+        } else {
+            SPRTStatus::Continue
+        }
+    }
+
+    pub fn get_description(&self) -> String {
+        let (lower, upper) = self.bounds();
+        format!(
+            "SPRT elo0 {:.1} elo1 {:.1} ({:?}): LLR {:.3} [{:.3}, {:.3}] {} | ptnml {:?} | LDW {:?}",
+            self.config.elo0,
+            self.config.elo1,
+            self.config.model,
+            self.llr(),
+            lower,
+            upper,
+            self.status(),
+            self.pentanomial,
+            self.trinomial,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(model: SPRTModel) -> SPRTConfig {
+        SPRTConfig {
+            elo0: 0.,
+            elo1: 5.,
+            alpha: 0.05,
+            beta: 0.05,
+            model,
+        }
+    }
+
+    #[test]
+    fn sprt_accepts_clear_results() {
+        for model in [SPRTModel::Trinomial, SPRTModel::Pentanomial].iter() {
+            let mut winning = SPRT::new(config(*model));
+            let mut losing = SPRT::new(config(*model));
+            let mut id = 0;
+            while winning.status() == SPRTStatus::Continue && id < 100_000 {
+                //Engine1 scores 1.5/2 in three out of four pairs
+                let second = if (id / 2) % 4 == 0 { 0 } else { 1 };
+                winning.add_game(id, 2);
+                winning.add_game(id + 1, second);
+                losing.add_game(id, 0);
+                losing.add_game(id + 1, 2 - second);
+                id += 2;
+            }
+            assert_eq!(winning.status(), SPRTStatus::AcceptH1);
+            assert_eq!(losing.status(), SPRTStatus::AcceptH0);
+        }
+    }
+}
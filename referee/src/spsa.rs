@@ -0,0 +1,304 @@
+use crate::engine::{Engine, PlayTask};
+use crate::logging::FileLogger;
+use crate::selfplay::play_game;
+use crate::timecontrol::TimeControlConfig;
+use crate::Config;
+use core_sdk::board_representation::game_state::*;
+use extended_sdk::openings::load_db_until;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+pub const SPSA_PARAMS_PATH: &str = "spsa_params.json";
+
+fn default_alpha() -> f64 {
+    0.602
+}
+
+fn default_gamma() -> f64 {
+    0.101
+}
+
+fn default_a_ratio() -> f64 {
+    0.1
+}
+
+//A UCI spin option of the engine. The perturbation c and the learning rate r are given for the
+//last iteration, earlier iterations use larger values
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SPSAParam {
+    pub name: String,
+    pub start: f64,
+    pub min: f64,
+    pub max: f64,
+    pub c_end: f64,
+    pub r_end: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SPSAConfig {
+    //Every iteration is a game pair between the two perturbed engines
+    pub iterations: usize,
+    pub params: Vec<SPSAParam>,
+    #[serde(default = "default_alpha")]
+    pub alpha: f64,
+    #[serde(default = "default_gamma")]
+    pub gamma: f64,
+    //Stability constant A of the learning rate as a fraction of the iterations
+    #[serde(default = "default_a_ratio")]
+    pub a_ratio: f64,
+}
+
+pub struct SPSAIteration {
+    pub k: usize,
+    pub flips: Vec<f64>,
+    pub plus: Vec<f64>,
+    pub minus: Vec<f64>,
+}
+
+pub struct SPSA {
+    pub config: SPSAConfig,
+    pub theta: Vec<f64>,
+    pub started: usize,
+    pub finished: usize,
+}
+
+impl SPSA {
+    pub fn new(config: SPSAConfig) -> Self {
+        let theta = config.params.iter().map(|param| param.start).collect();
+        SPSA {
+            config,
+            theta,
+            started: 0,
+            finished: 0,
+        }
+    }
+
+    //Perturbation of iteration k, k starts at 1
+    pub fn c_k(&self, param: &SPSAParam, k: usize) -> f64 {
+        param.c_end * (self.config.iterations as f64 / k as f64).powf(self.config.gamma)
+    }
+
+    //Learning rate of iteration k, k starts at 1
+    pub fn a_k(&self, param: &SPSAParam, k: usize) -> f64 {
+        let n = self.config.iterations as f64;
+        let big_a = self.config.a_ratio * n;
+        let a_end = param.r_end * param.c_end * param.c_end;
+        a_end * ((big_a + n) / (big_a + k as f64)).powf(self.config.alpha)
+    }
+
+    pub fn next_iteration<R: Rng>(&mut self, rng: &mut R) -> Option<SPSAIteration> {
+        if self.started >= self.config.iterations {
+            return None;
+        }
+        self.started += 1;
+        let k = self.started;
+        let mut res = SPSAIteration {
+            k,
+            flips: Vec::with_capacity(self.theta.len()),
+            plus: Vec::with_capacity(self.theta.len()),
+            minus: Vec::with_capacity(self.theta.len()),
+        };
+        for (param, theta) in self.config.params.iter().zip(self.theta.iter()) {
+            let flip = if rng.gen::<bool>() { 1. } else { -1. };
+            let c = self.c_k(param, k);
+            res.flips.push(flip);
+            res.plus
+                .push((theta + c * flip).max(param.min).min(param.max));
+            res.minus
+                .push((theta - c * flip).max(param.min).min(param.max));
+        }
+        Some(res)
+    }
+
+    //Result is wins minus losses of the plus engine in the game pair
+    pub fn update(&mut self, iteration: &SPSAIteration, result: f64) {
+        for (i, param) in self.config.params.iter().enumerate() {
+            let step = self.a_k(param, iteration.k) / self.c_k(param, iteration.k)
+                * result
+                * iteration.flips[i];
+            self.theta[i] = (self.theta[i] + step).max(param.min).min(param.max);
+        }
+        self.finished += 1;
+    }
+
+    pub fn options(&self, values: &[f64]) -> HashMap<String, String> {
+        self.config
+            .params
+            .iter()
+            .zip(values.iter())
+            .map(|(param, value)| (param.name.clone(), format!("{}", value.round() as i64)))
+            .collect()
+    }
+
+    pub fn get_description(&self) -> String {
+        let mut res_str = format!(
+            "SPSA iteration {}/{}:",
+            self.finished, self.config.iterations
+        );
+        for (param, theta) in self.config.params.iter().zip(self.theta.iter()) {
+            res_str.push_str(&format!(" {} {:.2}", param.name, theta));
+        }
+        res_str
+    }
+
+    pub fn save(&self, path: &str) {
+        let values: HashMap<&str, f64> = self
+            .config
+            .params
+            .iter()
+            .zip(self.theta.iter())
+            .map(|(param, theta)| (param.name.as_str(), *theta))
+            .collect();
+        fs::write(
+            path,
+            serde_json::to_string_pretty(&values).expect("Could not serialize SPSA params!"),
+        )
+        .expect("Could not write SPSA params!");
+    }
+}
+
+//Tunes the UCI options of engine_path by playing it against itself with perturbed options
+pub async fn start_spsa(config: Config) {
+    FileLogger::new("referee_error_log.txt", false)
+        .init()
+        .expect("Could not create File Logger");
+    let spsa_config = config.spsa.clone().expect("No SPSA config!");
+    let tc = TimeControlConfig::Incremental {
+        time: config.timecontrol_engine_time,
+        inc: config.timecontrol_engine_inc,
+    };
+    let engine = Engine::from_path(
+        config.engine_path.path(),
+        0,
+        config.engine_path.time_control(tc),
+        config.engine_path.options(),
+    )
+    .await;
+    let mut openings: Vec<(GameState, Vec<GameMove>)> = Vec::with_capacity(100_000);
+    for database in config.opening_databases.iter() {
+        let (states, sequences) = load_db_until(database, config.opening_load_untilply);
+        openings.extend(states.into_iter().zip(sequences));
+    }
+    if openings.is_empty() {
+        panic!("SPSA needs at least one opening!");
+    }
+    println!(
+        "Loaded database with {} games found! Starting {} SPSA iterations...",
+        openings.len(),
+        spsa_config.iterations
+    );
+    let spsa = Arc::new(Mutex::new(SPSA::new(spsa_config)));
+    let openings = Arc::new(openings);
+    let adjudication = Arc::new(config.adjudication);
+
+    let mut childs = Vec::with_capacity(config.processors);
+    for _ in 0..config.processors {
+        let spsa = spsa.clone();
+        let openings = openings.clone();
+        let adjudication = adjudication.clone();
+        let engine = engine.clone();
+        childs.push(tokio::spawn(async move {
+            let mut rng = StdRng::seed_from_u64(rand::random());
+            loop {
+                let (iteration, plus, minus) = {
+                    let mut spsa = spsa.lock().unwrap();
+                    match spsa.next_iteration(&mut rng) {
+                        Some(iteration) => {
+                            let plus = spsa.options(&iteration.plus);
+                            let minus = spsa.options(&iteration.minus);
+                            (iteration, plus, minus)
+                        }
+                        None => break,
+                    }
+                };
+                let (opening, sequence) = &openings[rng.gen_range(0, openings.len())];
+                let mut engine1 = engine.clone();
+                engine1.uci_options.extend(plus);
+                let mut engine2 = engine.clone();
+                engine2.id = 1;
+                engine2.uci_options.extend(minus);
+                //Wins minus losses of the plus engine, in half points a draw scores 1
+                let mut result = 0.;
+                for &p1_is_white in [true, false].iter() {
+                    let task = PlayTask {
+                        opening: opening.clone(),
+                        opening_sequence: sequence.clone(),
+                        p1_is_white,
+                        id: 2 * iteration.k + if p1_is_white { 0 } else { 1 },
+                        engine1: engine1.clone(),
+                        engine2: engine2.clone(),
+                    };
+                    let game = play_game(task, &adjudication).await;
+                    result += game.engine1_half_points() as f64 - 1.;
+                }
+                let mut spsa = spsa.lock().unwrap();
+                spsa.update(&iteration, result);
+                println!("{}", spsa.get_description());
+                spsa.save(SPSA_PARAMS_PATH);
+            }
+        }));
+    }
+    for child in childs {
+        child.await.expect("Couldn't join thread");
+    }
+    println!("{}", spsa.lock().unwrap().get_description());
+    println!("SPSA finished! Parameters written to {}", SPSA_PARAMS_PATH);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SPSAConfig {
+        SPSAConfig {
+            iterations: 100,
+            params: vec![SPSAParam {
+                name: "FutilityMargin".to_owned(),
+                start: 90.,
+                min: 0.,
+                max: 300.,
+                c_end: 10.,
+                r_end: 0.002,
+            }],
+            alpha: default_alpha(),
+            gamma: default_gamma(),
+            a_ratio: default_a_ratio(),
+        }
+    }
+
+    #[test]
+    fn schedule_ends_at_configured_values() {
+        let spsa = SPSA::new(config());
+        let param = &spsa.config.params[0];
+        assert!((spsa.c_k(param, 100) - 10.).abs() < 1e-9);
+        assert!((spsa.a_k(param, 100) - 0.002 * 100.).abs() < 1e-9);
+        assert!(spsa.c_k(param, 1) > spsa.c_k(param, 50));
+        assert!(spsa.a_k(param, 1) > spsa.a_k(param, 50));
+    }
+
+    #[test]
+    fn theta_moves_towards_winning_side() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut spsa = SPSA::new(config());
+        while let Some(iteration) = spsa.next_iteration(&mut rng) {
+            assert_eq!(
+                spsa.options(&iteration.plus)["FutilityMargin"],
+                format!("{}", iteration.plus[0].round() as i64)
+            );
+            //The engine with the larger margin always wins the pair
+            let result = if iteration.plus[0] > iteration.minus[0] {
+                2.
+            } else {
+                -2.
+            };
+            spsa.update(&iteration, result);
+        }
+        assert_eq!(spsa.finished, 100);
+        assert!(spsa.theta[0] > 90.);
+        assert!(spsa.theta[0] <= 300.);
+    }
+}
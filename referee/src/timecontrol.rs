@@ -0,0 +1,133 @@
+use core_sdk::search::timecontrol::TimeControl;
+use serde::{Deserialize, Serialize};
+
+//Engines limited by depth or nodes have no clock, they lose on time if a move takes longer than this
+pub const NO_CLOCK_MOVE_TIMEOUT: u64 = 600_000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum TimeControlConfig {
+    Incremental { time: u64, inc: u64 },
+    //Every session of moves adds time to the clock, e.g. 40 moves in 60s repeating
+    Sessions { moves: usize, time: u64, inc: u64 },
+    MoveTime(u64),
+    Depth(usize),
+    Nodes(u64),
+}
+
+#[derive(Clone, Copy)]
+pub struct EngineTimeControl {
+    pub config: TimeControlConfig,
+    pub clock: TimeControl,
+}
+
+impl EngineTimeControl {
+    //Time odds scale every amount of time the engine is given
+    pub fn new(config: TimeControlConfig, time_odds: f64) -> Self {
+        let scale = |time: u64| (time as f64 * time_odds) as u64;
+        let config = match config {
+            TimeControlConfig::Incremental { time, inc } => TimeControlConfig::Incremental {
+                time: scale(time),
+                inc: scale(inc),
+            },
+            TimeControlConfig::Sessions { moves, time, inc } => TimeControlConfig::Sessions {
+                moves,
+                time: scale(time),
+                inc: scale(inc),
+            },
+            TimeControlConfig::MoveTime(time) => TimeControlConfig::MoveTime(scale(time)),
+            other => other,
+        };
+        let clock = match config {
+            TimeControlConfig::Incremental { time, inc } => TimeControl::Incremental(time, inc),
+            TimeControlConfig::Sessions { moves, time, inc } => {
+                TimeControl::Tournament(time, inc, moves)
+            }
+            TimeControlConfig::MoveTime(time) => TimeControl::MoveTime(time),
+            TimeControlConfig::Depth(_) | TimeControlConfig::Nodes(_) => {
+                TimeControl::MoveTime(NO_CLOCK_MOVE_TIMEOUT)
+            }
+        };
+        EngineTimeControl { config, clock }
+    }
+
+    pub fn has_clock(&self) -> bool {
+        matches!(
+            self.config,
+            TimeControlConfig::Incremental { .. } | TimeControlConfig::Sessions { .. }
+        )
+    }
+
+    //Maximum time the engine may take for its next move
+    pub fn time_left(&self) -> u64 {
+        self.clock.time_left()
+    }
+
+    pub fn clock_left(&self) -> u64 {
+        if self.has_clock() {
+            self.clock.time_left()
+        } else {
+            0
+        }
+    }
+
+    pub fn update(&mut self, time_spent: u64) {
+        if let TimeControlConfig::Sessions { moves, time, .. } = self.config {
+            self.clock.update(time_spent, Some((moves, time)));
+        } else {
+            self.clock.update(time_spent, None);
+        }
+    }
+
+    //Arguments of the go command when this engine is to move. The opponent's clock is only
+    //sent along if both engines play with a clock
+    pub fn go_args(&self, white: bool, opponent: &EngineTimeControl) -> String {
+        match self.config {
+            TimeControlConfig::Depth(depth) => format!("depth {}", depth),
+            TimeControlConfig::Nodes(nodes) => format!("nodes {}", nodes),
+            TimeControlConfig::MoveTime(_) => self.clock.to_go(white),
+            _ => {
+                let mut res = self.clock.to_go(white);
+                if let TimeControl::Incremental(left, inc) | TimeControl::Tournament(left, inc, _) =
+                    opponent.clock
+                {
+                    if opponent.has_clock() {
+                        res.push_str(&format!(
+                            " {}",
+                            TimeControl::Incremental(left, inc).to_go(!white)
+                        ));
+                    }
+                }
+                res
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_refill_clock() {
+        let mut tc = EngineTimeControl::new(
+            TimeControlConfig::Sessions {
+                moves: 2,
+                time: 1000,
+                inc: 10,
+            },
+            2.0,
+        );
+        let opponent = EngineTimeControl::new(TimeControlConfig::Depth(5), 1.0);
+        assert_eq!(tc.go_args(true, &opponent), "wtime 2000 winc 20 movestogo 2");
+        tc.update(500);
+        assert_eq!(tc.go_args(true, &opponent), "wtime 1520 winc 20 movestogo 1");
+        tc.update(500);
+        assert_eq!(tc.go_args(true, &opponent), "wtime 3040 winc 20 movestogo 2");
+        assert_eq!(opponent.go_args(false, &tc), "depth 5");
+        assert_eq!(
+            EngineTimeControl::new(TimeControlConfig::Incremental { time: 100, inc: 1 }, 1.0)
+                .go_args(false, &tc),
+            "btime 100 binc 1 wtime 3040 winc 20"
+        );
+    }
+}
@@ -0,0 +1,373 @@
+use crate::engine::{get_elo_estimate, Engine, TaskResult};
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::fmt::{Display, Formatter, Result};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum TournamentMode {
+    #[default]
+    Gauntlet,
+    RoundRobin,
+    Knockout,
+}
+
+impl Display for TournamentMode {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        write!(
+            formatter,
+            "{}",
+            match self {
+                TournamentMode::Gauntlet => "gauntlet",
+                TournamentMode::RoundRobin => "round robin",
+                TournamentMode::Knockout => "knockout",
+            }
+        )
+    }
+}
+
+pub struct Tournament {
+    pub mode: TournamentMode,
+    //The first engine is the gauntlet engine, the others follow the order of the config
+    pub engines: Vec<Engine>,
+    //results[i][j] holds wins, draws and losses of engine i against engine j, including disqualifications
+    pub results: Vec<Vec<(usize, usize, usize)>>,
+    pub round: usize,
+    //Knockout: engines still in the tournament, in bracket order
+    survivors: Vec<usize>,
+    eliminated_in_round: Vec<Option<usize>>,
+    pairings: Vec<(usize, usize)>,
+    pending_games: usize,
+}
+
+impl Tournament {
+    pub fn new(mode: TournamentMode, engines: Vec<Engine>) -> Self {
+        let n = engines.len();
+        Tournament {
+            mode,
+            engines,
+            results: vec![vec![(0, 0, 0); n]; n],
+            round: 0,
+            survivors: (0..n).collect(),
+            eliminated_in_round: vec![None; n],
+            pairings: Vec::new(),
+            pending_games: 0,
+        }
+    }
+
+    //Returns the pairings of the next round, each is played games_per_pairing times.
+    //Gauntlet and round robin consist of a single round
+    pub fn next_round(&mut self, games_per_pairing: usize) -> Vec<(usize, usize)> {
+        self.pairings = match self.mode {
+            TournamentMode::Gauntlet if self.round == 0 => {
+                (1..self.engines.len()).map(|i| (0, i)).collect()
+            }
+            TournamentMode::RoundRobin if self.round == 0 => {
+                let mut res = Vec::new();
+                for i in 0..self.engines.len() {
+                    for j in i + 1..self.engines.len() {
+                        res.push((i, j));
+                    }
+                }
+                res
+            }
+            TournamentMode::Knockout if self.survivors.len() > 1 => self
+                .survivors
+                .chunks(2)
+                .filter(|chunk| chunk.len() == 2)
+                .map(|chunk| (chunk[0], chunk[1]))
+                .collect(),
+            _ => Vec::new(),
+        };
+        if !self.pairings.is_empty() {
+            self.round += 1;
+        }
+        self.pending_games = self.pairings.len() * games_per_pairing;
+        self.pairings.clone()
+    }
+
+    pub fn add_result(&mut self, result: &TaskResult) {
+        self.add_game(
+            &result.task.engine1,
+            &result.task.engine2,
+            result.engine1_half_points(),
+        );
+    }
+
+    pub fn add_game(&mut self, engine1: &Engine, engine2: &Engine, engine1_half_points: usize) {
+        let (e1, e2) = (engine1.id, engine2.id);
+        self.engines[e1].add(engine1);
+        self.engines[e2].add(engine2);
+        match engine1_half_points {
+            0 => {
+                self.results[e1][e2].2 += 1;
+                self.results[e2][e1].0 += 1;
+            }
+            1 => {
+                self.results[e1][e2].1 += 1;
+                self.results[e2][e1].1 += 1;
+            }
+            _ => {
+                self.results[e1][e2].0 += 1;
+                self.results[e2][e1].2 += 1;
+            }
+        }
+        self.pending_games = self.pending_games.saturating_sub(1);
+        if self.pending_games == 0 && self.mode == TournamentMode::Knockout {
+            self.eliminate_losers();
+        }
+    }
+
+    //The winner of a knockout match is the engine with more points, the higher seed on a tie.
+    //An engine without opponent advances without playing
+    fn eliminate_losers(&mut self) {
+        for &(a, b) in &self.pairings {
+            let (w, _, l) = self.results[a][b];
+            let loser = if w >= l { b } else { a };
+            self.eliminated_in_round[loser] = Some(self.round);
+        }
+        let eliminated_in_round = &self.eliminated_in_round;
+        self.survivors
+            .retain(|&engine| eliminated_in_round[engine].is_none());
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pending_games == 0
+            && (self.mode != TournamentMode::Knockout || self.survivors.len() <= 1)
+    }
+
+    pub fn points(&self, engine: usize) -> (f64, usize) {
+        self.results[engine]
+            .iter()
+            .fold((0., 0), |acc, &(w, d, l)| {
+                (acc.0 + w as f64 + d as f64 / 2., acc.1 + w + d + l)
+            })
+    }
+
+    pub fn get_ranking(&self) -> Vec<usize> {
+        let mut ranking: Vec<usize> = (0..self.engines.len()).collect();
+        let percentage = |engine: usize| {
+            let (points, games) = self.points(engine);
+            if games == 0 {
+                0.
+            } else {
+                points / games as f64
+            }
+        };
+        ranking.sort_by(|&a, &b| {
+            percentage(b)
+                .partial_cmp(&percentage(a))
+                .unwrap_or(Ordering::Equal)
+        });
+        if self.mode == TournamentMode::Knockout {
+            //Engines surviving longer rank higher
+            ranking.sort_by_key(|&engine| {
+                Reverse(self.eliminated_in_round[engine].unwrap_or(usize::MAX))
+            });
+        }
+        ranking
+    }
+
+    pub fn get_crosstable(&self) -> String {
+        let ranking = self.get_ranking();
+        let mut res_str = String::new();
+        res_str.push_str(&format!(
+            "{:3} {:25} {:>16} {:>11}",
+            "#", "Engine", "Elo", "Score"
+        ));
+        for i in 0..ranking.len() {
+            res_str.push_str(&format!(" {:>10}", i + 1));
+        }
+        res_str.push('\n');
+        for (rank, &engine) in ranking.iter().enumerate() {
+            let (points, games) = self.points(engine);
+            let (w, d, l) = self.results[engine]
+                .iter()
+                .fold((0, 0, 0), |acc, r| (acc.0 + r.0, acc.1 + r.1, acc.2 + r.2));
+            let (elo, error) = get_elo_estimate(w, d, l);
+            res_str.push_str(&format!(
+                "{:3} {:25} {:>16} {:>11}",
+                rank + 1,
+                self.engines[engine].name,
+                format!("{:.1} +/- {:.1}", elo, error),
+                format!("{}/{}", points, games),
+            ));
+            for &other in &ranking {
+                let (w, d, l) = self.results[engine][other];
+                if other == engine || w + d + l == 0 {
+                    res_str.push_str(&format!(" {:>10}", "-"));
+                } else {
+                    res_str.push_str(&format!(
+                        " {:>10}",
+                        format!("{}/{}", w as f64 + d as f64 / 2., w + d + l)
+                    ));
+                }
+            }
+            res_str.push('\n');
+        }
+        res_str
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openings::build_tasks;
+    use core_sdk::board_representation::game_state::GameState;
+
+    fn tournament(mode: TournamentMode, n: usize) -> Tournament {
+        Tournament::new(mode, (0..n).map(Engine::dummy).collect())
+    }
+
+    //Adds a game the way a finished task reports it
+    fn play(t: &mut Tournament, e1: usize, e2: usize, half_points: usize) {
+        let (mut engine1, mut engine2) = (Engine::dummy(e1), Engine::dummy(e2));
+        match half_points {
+            0 => {
+                engine1.losses = 1;
+                engine2.wins = 1;
+            }
+            1 => {
+                engine1.draws = 1;
+                engine2.draws = 1;
+            }
+            _ => {
+                engine1.wins = 1;
+                engine2.losses = 1;
+            }
+        }
+        t.add_game(&engine1, &engine2, half_points);
+    }
+
+    #[test]
+    fn round_robin_pairs_everyone_once() {
+        let mut t = tournament(TournamentMode::RoundRobin, 4);
+        let pairings = t.next_round(2);
+        assert_eq!(pairings.len(), 6);
+        for engine in 0..4 {
+            let opponents: Vec<usize> = pairings
+                .iter()
+                .filter_map(|&(a, b)| match engine {
+                    _ if a == engine => Some(b),
+                    _ if b == engine => Some(a),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(opponents.len(), 3);
+            assert!(!opponents.contains(&engine));
+        }
+
+        let openings = vec![(GameState::standard(), Vec::new()); 2];
+        let tasks = build_tasks(&openings, &pairings, &t.engines, 0);
+        assert_eq!(tasks.len(), 2 * 6 * 2);
+        for engine in 0..4 {
+            let white = tasks
+                .iter()
+                .filter(|task| {
+                    (task.engine1.id == engine && task.p1_is_white)
+                        || (task.engine2.id == engine && !task.p1_is_white)
+                })
+                .count();
+            let black = tasks
+                .iter()
+                .filter(|task| task.engine1.id == engine || task.engine2.id == engine)
+                .count()
+                - white;
+            assert_eq!((white, black), (6, 6));
+        }
+        for pair in tasks.chunks(2) {
+            assert_eq!(pair[0].p1_is_white, !pair[1].p1_is_white);
+            assert_eq!(pair[0].opening.hash, pair[1].opening.hash);
+        }
+
+        assert!(t.next_round(2).is_empty());
+        assert_eq!(t.round, 1);
+    }
+
+    #[test]
+    fn gauntlet_pairs_first_engine_with_everyone() {
+        let mut t = tournament(TournamentMode::Gauntlet, 4);
+        assert_eq!(t.next_round(2), vec![(0, 1), (0, 2), (0, 3)]);
+        assert!(t.next_round(2).is_empty());
+    }
+
+    #[test]
+    fn knockout_advances_winners() {
+        let mut t = tournament(TournamentMode::Knockout, 4);
+        assert_eq!(t.next_round(2), vec![(0, 1), (2, 3)]);
+        play(&mut t, 0, 1, 0);
+        play(&mut t, 0, 1, 1);
+        play(&mut t, 2, 3, 1);
+        assert!(!t.is_finished());
+        //A tied match goes to the higher seed
+        play(&mut t, 2, 3, 1);
+        assert!(!t.is_finished());
+        assert_eq!(t.next_round(2), vec![(1, 2)]);
+        play(&mut t, 1, 2, 2);
+        assert!(!t.is_finished());
+        play(&mut t, 1, 2, 0);
+        assert!(t.is_finished());
+        assert!(t.next_round(2).is_empty());
+        let ranking = t.get_ranking();
+        assert_eq!(&ranking[..2], &[1, 2]);
+        assert_eq!(t.eliminated_in_round[0], Some(1));
+        assert_eq!(t.eliminated_in_round[3], Some(1));
+        assert_eq!(t.eliminated_in_round[2], Some(2));
+        assert_eq!(t.eliminated_in_round[1], None);
+    }
+
+    #[test]
+    fn knockout_gives_odd_engine_a_bye() {
+        let mut t = tournament(TournamentMode::Knockout, 3);
+        assert_eq!(t.next_round(1), vec![(0, 1)]);
+        play(&mut t, 0, 1, 2);
+        assert_eq!(t.next_round(1), vec![(0, 2)]);
+        play(&mut t, 0, 2, 0);
+        assert!(t.is_finished());
+        assert_eq!(t.get_ranking(), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn crosstable_totals() {
+        let mut t = tournament(TournamentMode::RoundRobin, 3);
+        t.next_round(2);
+        play(&mut t, 0, 1, 2);
+        play(&mut t, 0, 1, 1);
+        play(&mut t, 0, 2, 2);
+        play(&mut t, 0, 2, 2);
+        play(&mut t, 1, 2, 1);
+        play(&mut t, 1, 2, 0);
+        assert!(t.is_finished());
+        assert_eq!(t.points(0), (3.5, 4));
+        assert_eq!(t.points(1), (1., 4));
+        assert_eq!(t.points(2), (1.5, 4));
+        assert_eq!(t.results[1][0], (0, 1, 1));
+        assert_eq!(t.engines[0].wins, 3);
+        assert_eq!(t.engines[2].losses, 2);
+        assert_eq!(t.get_ranking(), vec![0, 2, 1]);
+
+        let crosstable = t.get_crosstable();
+        let rows: Vec<&str> = crosstable.lines().collect();
+        assert_eq!(rows.len(), 4);
+        let cells = |row: &str| -> Vec<String> {
+            row.split_whitespace().map(|cell| cell.to_owned()).collect()
+        };
+        //Rank, name, elo, "+/-", error, score, then one column per engine in ranking order
+        assert_eq!(cells(rows[1])[5..], ["3.5/4", "-", "2/2", "1.5/2"]);
+        assert_eq!(cells(rows[2])[5..], ["1.5/4", "0/2", "-", "1.5/2"]);
+        assert_eq!(cells(rows[3])[5..], ["1/4", "0.5/2", "0.5/2", "-"]);
+    }
+
+    #[test]
+    fn elo_estimate() {
+        assert_eq!(get_elo_estimate(0, 0, 0), (0., 0.));
+        let (elo, error) = get_elo_estimate(10, 20, 10);
+        assert!(elo.abs() < 1e-9);
+        assert!(error > 0.);
+        let (elo, _) = get_elo_estimate(75, 0, 25);
+        assert!((elo - 190.85).abs() < 0.01);
+        let (elo, _) = get_elo_estimate(25, 0, 75);
+        assert!((elo + 190.85).abs() < 0.01);
+        //More games give a tighter bound
+        assert!(get_elo_estimate(300, 0, 100).1 < get_elo_estimate(75, 0, 25).1);
+    }
+}
@@ -0,0 +1,9 @@
+#!/bin/sh
+#Checks the lockless transposition table with Miri, and the table and a multi-threaded search with
+#ThreadSanitizer. Needs a nightly toolchain with the miri and rust-src components
+set -e
+set -x
+cargo +nightly miri test -p core-sdk cache::
+TARGET=$(rustc +nightly -vV | sed -n 's/^host: //p')
+RUSTFLAGS="-Zsanitizer=thread" cargo +nightly test -Zbuild-std --target "$TARGET" -p core-sdk --lib -- \
+    cache::tests::concurrent_entries_never_mix smp::tests::every_strategy_finds_a_move
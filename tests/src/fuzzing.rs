@@ -0,0 +1,75 @@
+//Entry points of the fuzz targets in fuzz/. Every input has to be handled without panicking,
+//the inputs of tests/corpus are replayed by the fuzz_corpus test
+use core_sdk::board_representation::game_state::{GameMove, GameState};
+use core_sdk::board_representation::incremental_verification::verify_incremental;
+use core_sdk::move_generation::makemove::make_move;
+use core_sdk::perft::legal_moves;
+use extended_sdk::pgn::pgn_reader::{GameParser, PGNParser};
+use std::io::Cursor;
+use uci_engine::uci_command::UCICommand;
+use uci_engine::uci_engine::UCIEngine;
+use uci_engine::uci_parser::position;
+
+pub const TARGETS: [(&str, fn(&[u8])); 4] =
+    [("fen", fen), ("moves", moves), ("pgn", pgn), ("uci", uci)];
+
+//Accepted positions have to survive a round trip and playing every legal move
+pub fn fen(data: &[u8]) {
+    let fen = match std::str::from_utf8(data) {
+        Ok(fen) => fen,
+        Err(_) => return,
+    };
+    if let Ok(state) = GameState::try_from_fen(fen) {
+        let reparsed = GameState::try_from_fen(&state.to_fen()).expect("to_fen output rejected");
+        assert_eq!(reparsed.hash, state.hash);
+        for mv in legal_moves(&state) {
+            let san = mv.to_san(&state);
+            assert_eq!(GameMove::from_san(&state, &san), Ok(mv));
+            verify_incremental(&make_move(&state, mv)).unwrap();
+        }
+    }
+}
+
+//An optional FEN on the first line, then moves in UCI or SAN notation
+pub fn moves(data: &[u8]) {
+    let input = String::from_utf8_lossy(data);
+    let mut lines = input.lines();
+    let mut state = lines
+        .next()
+        .and_then(|line| GameState::try_from_fen(line).ok())
+        .unwrap_or_else(GameState::standard);
+    for token in lines.flat_map(|line| line.split_whitespace()) {
+        let _ = GameMove::string_to_move(token);
+        if let Ok(mv) = GameMove::from_san(&state, token) {
+            state = make_move(&state, mv);
+        }
+    }
+}
+
+pub fn pgn(data: &[u8]) {
+    let parser = GameParser {
+        pgn_parser: PGNParser {
+            reader: Cursor::new(data),
+        },
+        is_opening: false,
+        opening_load_untilply: 0,
+    };
+    for _game in parser {}
+}
+
+//Every line is parsed, positions are set up, but nothing is searched
+pub fn uci(data: &[u8]) {
+    let input = String::from_utf8_lossy(data);
+    let mut engine = UCIEngine::standard();
+    for line in input.lines() {
+        match UCICommand::parse(line) {
+            Ok(UCICommand::Position { fen, moves }) => {
+                let _ = position(&mut engine, fen.as_deref(), &moves);
+            }
+            Ok(UCICommand::Go(params)) => {
+                params.time_control(engine.internal_state.color_to_move);
+            }
+            _ => {}
+        }
+    }
+}
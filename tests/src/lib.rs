@@ -1,7 +1,31 @@
 extern crate rand;
+pub mod fuzzing;
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+//Engine output which stays readable after the engine took ownership of a clone
+#[derive(Clone, Default)]
+pub struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+impl SharedOutput {
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use core_sdk::board_representation::game_state::GameState;
+    use crate::SharedOutput;
+    use core_sdk::board_representation::game_state::{GameMove, GameState, SanError};
     use core_sdk::board_representation::game_state_attack_container::GameStateAttackContainer;
     use core_sdk::evaluation::phase::Phase;
     use core_sdk::evaluation::psqt_evaluation::psqt;
@@ -9,13 +33,21 @@ mod tests {
     use core_sdk::move_generation::movegen;
     use core_sdk::move_generation::movegen::MoveList;
     use core_sdk::perft;
+    use core_sdk::search::cache::Cache;
     use core_sdk::search::reserved_memory::ReservedAttackContainer;
     use core_sdk::search::reserved_memory::ReservedMoveList;
+    use core_sdk::search::searcher::{search_move, InterThreadCommunicationSystem};
+    use core_sdk::search::smp::SMP_STRATEGIES;
+    use core_sdk::search::timecontrol::TimeControl;
     use extended_sdk::misc::KING_BASE_PATH;
     use extended_sdk::pgn::pgn_reader::{parse_move, GameParser, PGNParser};
     use rand::Rng;
     use std::fs::File;
-    use std::io::BufReader;
+    use std::io::{BufReader, Cursor};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Instant;
+    use uci_engine::uci_parser::{parse_loop, PERFT_USAGE};
 
     #[test]
     fn fen_test() {
@@ -190,7 +222,9 @@ mod tests {
                 }
                 g = make_move(
                     &g,
-                    movelist.move_list[rng.gen_range(0, movelist.move_list.len())].0,
+                    movelist.moves()[rng.gen_range(0, movelist.len())]
+                        .0
+                        .to_game_move(&g),
                 )
             }
         }
@@ -214,7 +248,9 @@ mod tests {
                 }
                 g = make_move(
                     &g,
-                    movelist.move_list[rng.gen_range(0, movelist.move_list.len())].0,
+                    movelist.moves()[rng.gen_range(0, movelist.len())]
+                        .0
+                        .to_game_move(&g),
                 );
                 assert!(
                     (g.phase.phase - Phase::from_pieces(&g.pieces).phase).abs() < std::f64::EPSILON
@@ -245,7 +281,9 @@ mod tests {
                 }
                 g = make_move(
                     &g,
-                    movelist.move_list[rng.gen_range(0, movelist.move_list.len())].0,
+                    movelist.moves()[rng.gen_range(0, movelist.len())]
+                        .0
+                        .to_game_move(&g),
                 );
                 let w_psqt = psqt(true, &g.pieces, &mut _eval);
                 let b_psqt = psqt(false, &g.pieces, &mut _eval);
@@ -268,8 +306,6 @@ mod tests {
                 pgn_parser: PGNParser { reader },
                 is_opening: false,
                 opening_load_untilply: 0usize,
-                move_list: movegen::MoveList::default(),
-                attack_container: GameStateAttackContainer::default(),
             };
             for _game in parser.into_iter() {
                 //println!("{}", game.1);
@@ -279,35 +315,416 @@ mod tests {
     #[test]
     fn make_test() {
         let g = GameState::from_fen("4k3/6P1/8/1Pp5/6b1/8/2B5/4K2R w K c6 0 2");
-        let mut movelist = MoveList::default();
-        let agsi = GameStateAttackContainer::from_state(&g);
         assert_eq!(
-            make_move(&g, parse_move(&g, "e1g1", &mut movelist, &agsi).0).hash,
+            make_move(&g, parse_move(&g, "e1g1").unwrap().0).hash,
             GameState::from_fen("4k3/6P1/8/1Pp5/6b1/8/2B5/5RK1 b - - 1 2").hash
         );
         assert_eq!(
-            make_move(&g, parse_move(&g, "g7g8q", &mut movelist, &agsi).0).hash,
+            make_move(&g, parse_move(&g, "g7g8q").unwrap().0).hash,
             GameState::from_fen("4k1Q1/8/8/1Pp5/6b1/8/2B5/4K2R b K - 0 2").hash
         );
         assert_eq!(
-            make_move(&g, parse_move(&g, "g7g8b", &mut movelist, &agsi).0).hash,
+            make_move(&g, parse_move(&g, "g7g8b").unwrap().0).hash,
             GameState::from_fen("4k1B1/8/8/1Pp5/6b1/8/2B5/4K2R b K - 0 2").hash
         );
         assert_eq!(
-            make_move(&g, parse_move(&g, "g7g8n", &mut movelist, &agsi).0).hash,
+            make_move(&g, parse_move(&g, "g7g8n").unwrap().0).hash,
             GameState::from_fen("4k1N1/8/8/1Pp5/6b1/8/2B5/4K2R b K - 0 2").hash
         );
         assert_eq!(
-            make_move(&g, parse_move(&g, "g7g8r", &mut movelist, &agsi).0).hash,
+            make_move(&g, parse_move(&g, "g7g8r").unwrap().0).hash,
             GameState::from_fen("4k1R1/8/8/1Pp5/6b1/8/2B5/4K2R b K - 0 2").hash
         );
         assert_eq!(
-            make_move(&g, parse_move(&g, "b5c6", &mut movelist, &agsi).0).hash,
+            make_move(&g, parse_move(&g, "b5c6").unwrap().0).hash,
             GameState::from_fen("4k3/6P1/2P5/8/6b1/8/2B5/4K2R b K - 0 2").hash
         );
         assert_eq!(
-            make_move(&g, parse_move(&g, "c2d3", &mut movelist, &agsi).0).hash,
+            make_move(&g, parse_move(&g, "c2d3").unwrap().0).hash,
             GameState::from_fen("4k3/6P1/8/1Pp5/6b1/3B4/8/4K2R b K - 1 2").hash
         );
     }
+    fn san_roundtrip(g: &GameState, depth: usize) {
+        if depth == 0 {
+            return;
+        }
+        let mut movelist = MoveList::default();
+        let agsi = GameStateAttackContainer::from_state(g);
+        movegen::generate_moves(g, false, &mut movelist, &agsi);
+        for mv in movelist.game_moves(g) {
+            let san = mv.to_san(g);
+            let figurine = san
+                .replace('K', "\u{2654}")
+                .replace('Q', "\u{2655}")
+                .replace('R', "\u{2656}")
+                .replace('B', "\u{2657}")
+                .replace('N', "\u{2658}");
+            let variants = [
+                san.clone(),
+                san.replace('+', "").replace('#', "").replace('=', ""),
+                format!("{}!?", san),
+                san.replace('O', "0"),
+                figurine,
+                format!("{:?}", mv),
+            ];
+            for variant in variants.iter() {
+                assert_eq!(
+                    GameMove::from_san(g, variant),
+                    Ok(mv),
+                    "{} in {}",
+                    variant,
+                    g.to_fen()
+                );
+            }
+            san_roundtrip(&make_move(g, mv), depth - 1);
+        }
+    }
+
+    #[test]
+    fn san_test() {
+        #[rustfmt::skip]
+        let cases = [
+            (2, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            (2, "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -"),
+            (2, "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"),
+            (2, "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8"),
+            (2, "3r4/6k1/pN1q2p1/Pp6/1PPpp3/4brPP/1Q2R1RK/8 b - c3 0 1"),
+            (3, "6R1/2p2r2/2PP4/2b5/2B3p1/6k1/5p2/4BK2 b - - 0 1"),
+            (4, "8/P1k5/K7/8/8/8/8/8 w - - 0 1"),
+        ];
+        for (depth, fen) in cases.iter() {
+            san_roundtrip(&GameState::from_fen(fen), *depth);
+        }
+
+        let g = GameState::from_fen("1k6/8/8/8/8/8/8/R3R2K w - - 0 1");
+        assert_eq!(
+            GameMove::from_san(&g, "Rc1"),
+            Err(SanError::AmbiguousMove("Rc1".to_owned()))
+        );
+        assert_eq!(
+            format!("{:?}", GameMove::from_san(&g, "Rac1").unwrap()),
+            "a1c1"
+        );
+        assert_eq!(
+            format!("{:?}", GameMove::from_san(&g, "Re1-c1").unwrap()),
+            "e1c1"
+        );
+        assert_eq!(GameMove::from_san(&g, " +!"), Err(SanError::Empty));
+        assert_eq!(
+            GameMove::from_san(&g, "Rc9"),
+            Err(SanError::InvalidSyntax("Rc9".to_owned()))
+        );
+        assert_eq!(
+            GameMove::from_san(&g, "Rxb8"),
+            Err(SanError::IllegalMove("Rxb8".to_owned()))
+        );
+        assert_eq!(
+            GameMove::from_san(&g, "O-O"),
+            Err(SanError::IllegalMove("O-O".to_owned()))
+        );
+    }
+
+    #[test]
+    fn smp_stress_test() {
+        const POSITIONS: [&str; 6] = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "2rr2k1/1p4bp/p1q1p1p1/4Pp1n/2PB4/1PN3P1/P3Q2P/2RR2K1 w - f6 0 20",
+            "6k1/5pp1/8/2bKP2P/2P5/p4PNb/B7/8 b - - 1 44",
+        ];
+        //Several engines search concurrently, each with its own pool of helper threads and SMP strategy
+        let mut handles = Vec::new();
+        for threads in 2..6 {
+            handles.push(
+                std::thread::Builder::new()
+                    .stack_size(32 * 1024 * 1024)
+                    .spawn(move || {
+                        let output = SharedOutput::default();
+                        let itcs = Arc::new(InterThreadCommunicationSystem::default_with_output(
+                            output.clone(),
+                        ));
+                        itcs.uci_options_mut().smp_strategy =
+                            SMP_STRATEGIES[threads % SMP_STRATEGIES.len()];
+                        InterThreadCommunicationSystem::update_thread_count(&itcs, threads);
+                        itcs.set_cache(Cache::with_size_threaded(1, threads));
+                        for position in POSITIONS.iter() {
+                            search_move(
+                                Arc::clone(&itcs),
+                                5,
+                                GameState::from_fen(position),
+                                Vec::new(),
+                                TimeControl::Infinite,
+                            );
+                            let report = itcs.smp_report();
+                            assert!(report.time_to_depth.iter().any(|&(depth, _)| depth == 5));
+                            assert_eq!(report.thread_nodes.len(), threads);
+                        }
+                        InterThreadCommunicationSystem::update_thread_count(&itcs, 1);
+                        let output = output.contents();
+                        assert_eq!(
+                            output
+                                .lines()
+                                .filter(|line| line.starts_with("bestmove"))
+                                .count(),
+                            POSITIONS.len()
+                        );
+                    })
+                    .unwrap(),
+            );
+        }
+        for handle in handles {
+            handle.join().expect("Search thread panicked!");
+        }
+    }
+
+    #[test]
+    fn input_callback_stops_search() {
+        let output = SharedOutput::default();
+        let itcs = Arc::new(InterThreadCommunicationSystem::default_with_output(
+            output.clone(),
+        ));
+        InterThreadCommunicationSystem::update_thread_count(&itcs, 1);
+        itcs.set_cache(Cache::with_size_threaded(1, 1));
+        let mut lines = vec!["stop\n", "position startpos\n", "isready\n"];
+        itcs.set_input_callback(Box::new(move || lines.pop().map(|line| line.to_owned())));
+        let now = Instant::now();
+        search_move(
+            Arc::clone(&itcs),
+            100,
+            GameState::standard(),
+            Vec::new(),
+            TimeControl::MoveTime(60_000),
+        );
+        assert!(now.elapsed().as_secs() < 10, "Search was not stopped!");
+        let output = output.contents();
+        let readyok = output.find("readyok").expect("No readyok during search!");
+        let bestmove = output.find("bestmove").expect("No bestmove after stop!");
+        assert!(readyok < bestmove);
+        //Everything but stop and isready waits for the search to end
+        assert_eq!(
+            itcs.next_deferred_input().as_deref(),
+            Some("position startpos\n")
+        );
+        assert_eq!(itcs.next_deferred_input(), None);
+    }
+
+    //Sends the line once the given time has passed
+    fn ponder_search(
+        line: &'static str,
+        after_ms: u64,
+        depth: i16,
+        tc: TimeControl,
+    ) -> (u64, String) {
+        let output = SharedOutput::default();
+        let itcs = Arc::new(InterThreadCommunicationSystem::default_with_output(
+            output.clone(),
+        ));
+        InterThreadCommunicationSystem::update_thread_count(&itcs, 1);
+        itcs.set_cache(Cache::with_size_threaded(1, 1));
+        let now = Instant::now();
+        let mut sent = false;
+        itcs.set_input_callback(Box::new(move || {
+            if sent || now.elapsed().as_millis() < u128::from(after_ms) {
+                None
+            } else {
+                sent = true;
+                Some(line.to_owned())
+            }
+        }));
+        itcs.pondering.store(true, Ordering::SeqCst);
+        search_move(
+            Arc::clone(&itcs),
+            depth,
+            GameState::standard(),
+            Vec::new(),
+            tc,
+        );
+        assert!(!itcs.is_pondering());
+        (now.elapsed().as_millis() as u64, output.contents())
+    }
+
+    #[test]
+    fn ponder_ignores_clock_until_ponderhit() {
+        let (elapsed, output) = ponder_search("ponderhit", 500, 100, TimeControl::MoveTime(100));
+        assert!(elapsed >= 500);
+        assert!(elapsed < 5000, "Search did not switch to the clock!");
+        assert!(output.contains("bestmove"));
+    }
+
+    #[test]
+    fn ponder_holds_bestmove_until_stop() {
+        let (elapsed, output) = ponder_search("stop", 300, 2, TimeControl::Infinite);
+        assert!(elapsed >= 300);
+        assert_eq!(
+            output
+                .lines()
+                .filter(|line| line.starts_with("bestmove"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn stop_infinite_search() {
+        let output = SharedOutput::default();
+        let input = Cursor::new("position startpos\ngo infinite\nisready\nstop\nquit\n");
+        parse_loop(input, output.clone());
+        let output = output.contents();
+        let readyok = output.find("readyok").expect("No readyok during search!");
+        let bestmove = output.find("bestmove").expect("Search was not stopped!");
+        assert!(readyok < bestmove);
+    }
+
+    #[test]
+    fn commands_stop_infinite_search() {
+        for command in &[
+            "position startpos moves e2e4",
+            "go depth 1",
+            "ucinewgame",
+            "setoption name Hash value 2",
+        ] {
+            let output = SharedOutput::default();
+            let input = format!("position startpos\ngo infinite\n{}\nquit\n", command);
+            parse_loop(Cursor::new(input), output.clone());
+            assert!(
+                output.contents().contains("bestmove"),
+                "{} deadlocked",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn ponder_search_is_stopped_by_position() {
+        let output = SharedOutput::default();
+        let input = Cursor::new("position startpos\ngo ponder wtime 100 btime 100\nposition startpos moves e2e4\nquit\n");
+        parse_loop(input, output.clone());
+        assert_eq!(output.contents().matches("bestmove").count(), 1);
+    }
+
+    #[test]
+    fn bestmove_on_empty_clock() {
+        let output = SharedOutput::default();
+        let input = Cursor::new("position startpos\ngo wtime 0 btime 0\n");
+        parse_loop(input, output.clone());
+        assert!(output.contents().contains("bestmove"));
+    }
+
+    #[test]
+    fn go_nodes_terminates() {
+        let output = SharedOutput::default();
+        let input = Cursor::new("position startpos\ngo nodes 1000\n");
+        parse_loop(input, output.clone());
+        assert!(output.contents().contains("bestmove"));
+    }
+
+    #[test]
+    fn node_limit_stops_search() {
+        let output = SharedOutput::default();
+        let itcs = Arc::new(InterThreadCommunicationSystem::default_with_output(
+            output.clone(),
+        ));
+        InterThreadCommunicationSystem::update_thread_count(&itcs, 2);
+        itcs.set_cache(Cache::with_size_threaded(1, 2));
+        itcs.node_limit.store(20_000, Ordering::Relaxed);
+        search_move(
+            Arc::clone(&itcs),
+            100,
+            GameState::standard(),
+            Vec::new(),
+            TimeControl::Infinite,
+        );
+        let nodes = itcs.get_nodes_sum();
+        assert!(
+            nodes >= 20_000 && nodes < 21_000,
+            "{} nodes searched",
+            nodes
+        );
+        assert!(output.contents().contains("bestmove"));
+    }
+
+    #[test]
+    fn searchmoves_restrict_root() {
+        let output = SharedOutput::default();
+        let input = Cursor::new(
+            "position startpos\ngo depth 4 searchmoves a2a3 e2e5\ngo depth 4 searchmoves h2h4 b1a3\n",
+        );
+        parse_loop(input, output.clone());
+        let output = output.contents();
+        let bestmoves: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("bestmove"))
+            .collect();
+        assert_eq!(bestmoves.len(), 2);
+        assert_eq!(bestmoves[0], "bestmove a2a3");
+        assert!(bestmoves[1] == "bestmove h2h4" || bestmoves[1] == "bestmove b1a3");
+        assert!(output.contains("info string Illegal move: e2e5"));
+    }
+
+    #[test]
+    fn no_legal_move_at_root() {
+        let output = SharedOutput::default();
+        let input = Cursor::new(
+            "position fen 7k/6Q1/6K1/8/8/8/8/8 b - - 0 1\ngo depth 5\nposition fen 7k/5Q2/6K1/8/8/8/8/8 b - - 0 1\ngo infinite\n",
+        );
+        parse_loop(input, output.clone());
+        let output = output.contents();
+        assert_eq!(
+            output
+                .lines()
+                .filter(|line| *line == "bestmove 0000")
+                .count(),
+            2
+        );
+        assert!(output.contains("info string The root position has no legal move"));
+    }
+
+    #[test]
+    fn errors_go_to_engine_output() {
+        let output = SharedOutput::default();
+        let input = Cursor::new(
+            "position startpos moves e2e5\nsetoption name Hash value abc\nnonsense\nisready\n",
+        );
+        parse_loop(input, output.clone());
+        let output = output.contents();
+        assert_eq!(
+            output
+                .lines()
+                .filter(|line| line.starts_with("info string"))
+                .count(),
+            3
+        );
+        assert!(output.contains("Illegal move: e2e5"));
+        assert!(output.ends_with("readyok\n"));
+    }
+
+    #[test]
+    fn responses_go_to_engine_output() {
+        let output = SharedOutput::default();
+        let input = Cursor::new("uci\nd\nstatic\nperft 1\nperft x\nisready\n");
+        parse_loop(input, output.clone());
+        let output = output.contents();
+        assert!(output.starts_with("id name FabChess"));
+        assert!(output.contains("uciok\n"));
+        assert!(output.contains("\ncp "));
+        assert!(output.contains("\n20\n"));
+        assert!(output.contains(PERFT_USAGE));
+        assert!(output.ends_with("readyok\n"));
+    }
+
+    #[test]
+    fn fuzz_corpus() {
+        let corpus = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+        for (target, run) in crate::fuzzing::TARGETS.iter() {
+            for entry in std::fs::read_dir(corpus.join(target)).unwrap() {
+                let path = entry.unwrap().path();
+                let data = std::fs::read(&path).unwrap();
+                if std::panic::catch_unwind(|| run(&data)).is_err() {
+                    panic!("{} target panicked on {}", target, path.display());
+                }
+            }
+        }
+    }
 }
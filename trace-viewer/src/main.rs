@@ -0,0 +1,87 @@
+use core_sdk::search::tracer::{
+    move_to_string, read_trace, TraceDecision, TraceRecord, TRACE_PATH,
+};
+use std::env;
+
+const USAGE: &str = "Usage: trace-viewer [file TRACE_FILE] [path MOVE..] [depth N] [ply N] [iteration N] [decision DECISION]";
+
+#[derive(Default)]
+struct Filter {
+    path: Vec<String>,
+    depth: Option<i16>,
+    ply: Option<usize>,
+    iteration: Option<u8>,
+    decision: Option<TraceDecision>,
+}
+
+impl Filter {
+    //A record matches the path filter if its move path starts with the given moves
+    fn matches(&self, record: &TraceRecord) -> bool {
+        record.path.len() >= self.path.len()
+            && record
+                .path
+                .iter()
+                .zip(self.path.iter())
+                .all(|(mv, filter)| move_to_string(*mv) == *filter)
+            && self.depth.map(|d| d == record.depth).unwrap_or(true)
+            && self.ply.map(|p| p == record.ply()).unwrap_or(true)
+            && self
+                .iteration
+                .map(|i| i == record.iteration)
+                .unwrap_or(true)
+            && self.decision.map(|d| d == record.decision).unwrap_or(true)
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(args: &[String], index: usize) -> T {
+    args.get(index)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or_else(|| panic!("Invalid value for {}\n{}", args[index - 1], USAGE))
+}
+
+fn main() {
+    let mut trace_path = TRACE_PATH.to_owned();
+    let mut filter = Filter::default();
+    let args: Vec<String> = env::args().collect();
+    let mut index: usize = 1;
+    while index < args.len() {
+        match &args[index][..] {
+            "file" => trace_path = parse_value(&args, index + 1),
+            "depth" => filter.depth = Some(parse_value(&args, index + 1)),
+            "ply" => filter.ply = Some(parse_value(&args, index + 1)),
+            "iteration" => filter.iteration = Some(parse_value(&args, index + 1)),
+            "decision" => {
+                let name: String = parse_value(&args, index + 1);
+                filter.decision = Some(TraceDecision::from_name(&name).unwrap_or_else(|| {
+                    panic!("Invalid decision {}\n{}", name, USAGE);
+                }));
+            }
+            "path" => {
+                index += 1;
+                //Moves continue until the next keyword
+                while index < args.len()
+                    && !["file", "depth", "ply", "iteration", "decision"]
+                        .contains(&&args[index][..])
+                {
+                    filter.path.push(args[index].to_lowercase());
+                    index += 1;
+                }
+                continue;
+            }
+            _ => {
+                println!("Invalid argument {}\n{}", &args[index], USAGE);
+                index += 1;
+                continue;
+            }
+        }
+        index += 2;
+    }
+    let trace = read_trace(&trace_path).expect("Could not read trace!");
+    println!("{}", trace.fen);
+    let mut shown = 0;
+    for record in trace.records.iter().filter(|record| filter.matches(record)) {
+        println!("{}", record);
+        shown += 1;
+    }
+    println!("{} of {} records", shown, trace.records.len());
+}
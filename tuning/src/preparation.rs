@@ -7,7 +7,8 @@ use core_sdk::move_generation::makemove::make_move;
 use core_sdk::move_generation::movegen::{self, AdditionalGameStateInformation, MoveList};
 use core_sdk::search::history::History;
 use core_sdk::search::in_check;
-use core_sdk::search::quiescence::{best_move_value, passes_delta_pruning, see, DELTA_PRUNING};
+use core_sdk::search::parameters::SearchParams;
+use core_sdk::search::quiescence::{best_move_value, passes_delta_pruning, see};
 use core_sdk::search::reserved_memory::{ReservedAttackContainer, ReservedMoveList};
 use core_sdk::search::SearchInstruction;
 use core_sdk::search::{check_end_condition, check_for_draw, leaf_score};
@@ -127,7 +128,7 @@ pub fn stripped_q_search(
         alpha = stand_pat;
     }
     //Big Delta Pruning
-    let diff = alpha - stand_pat - DELTA_PRUNING;
+    let diff = alpha - stand_pat - SearchParams::default().delta_pruning;
     if !incheck && diff > 0 && best_move_value(&game_state) < diff {
         return (stand_pat, game_state);
     }
@@ -153,11 +154,11 @@ pub fn stripped_q_search(
             break;
         }
         let (i, capture_move) = capture_move.unwrap();
-        if capture_move.1.unwrap() < 0. {
+        if capture_move.1.unwrap() < 0 {
             continue;
         }
-        let capture_move = capture_move.0;
-        move_list.move_lists[current_depth].move_list.remove(i);
+        let capture_move = capture_move.0.to_game_move(&game_state);
+        move_list.move_lists[current_depth].remove(i);
         let next_g = make_move(&game_state, capture_move);
         let (score, other_state) = stripped_q_search(
             -beta,
@@ -205,24 +206,32 @@ pub fn make_moves(
     incheck: bool,
 ) -> AdditionalGameStateInformation {
     let agsi = movegen::generate_moves(&game_state, !incheck, move_list, attack_container);
-    for gmv in move_list.move_list.iter_mut() {
-        let mv: GameMove = gmv.0;
+    for gmv in move_list.moves_mut().iter_mut() {
+        let mv: GameMove = gmv.0.to_game_move(game_state);
         if let GameMoveType::EnPassant = mv.move_type {
-            gmv.1 = Some(100.0);
+            gmv.1 = Some(100);
         } else {
-            if !incheck && !passes_delta_pruning(mv, phase, stand_pat, alpha) {
-                gmv.1 = Some(-1.);
+            if !incheck
+                && !passes_delta_pruning(
+                    mv,
+                    phase,
+                    stand_pat,
+                    alpha,
+                    SearchParams::default().delta_pruning,
+                )
+            {
+                gmv.1 = Some(-1);
                 continue;
             }
             if !incheck {
                 let score = see(&game_state, mv, true, see_buffer);
                 if score < 0 {
-                    gmv.1 = Some(-1.);
+                    gmv.1 = Some(-1);
                     continue;
                 }
-                gmv.1 = Some(f64::from(score));
+                gmv.1 = Some(i32::from(score));
             } else {
-                gmv.1 = Some(0.);
+                gmv.1 = Some(0);
             }
         }
     }
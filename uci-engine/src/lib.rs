@@ -0,0 +1,3 @@
+pub mod uci_command;
+pub mod uci_engine;
+pub mod uci_parser;
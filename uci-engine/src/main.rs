@@ -3,14 +3,21 @@ use fake_instant::FakeClock as Instant;
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
 
-pub mod uci_engine;
-pub mod uci_parser;
-
+use core_sdk::board_representation::game_state::GameState;
+use core_sdk::board_representation::incremental_verification::{
+    collision_statistics, fuzz_random_games,
+};
+use core_sdk::perft::PerftOptions;
 use std::fs::File;
 use std::io::{self, BufReader, BufWriter};
+use uci_engine::uci_parser;
 
 const ERROR_MSG: &str =
     "Invalid command line, expected: ./app [input] [output]. If omitted, stdin/stdout is assumed.";
+const PERFT_ERROR_MSG: &str =
+    "Invalid command line, expected: ./app perft DEPTH [fen FEN] [threads N] [hash MB] [diff REFERENCE_EPD] [epd PLIES OUTPUT_EPD]";
+const ZOBRIST_ERROR_MSG: &str =
+    "Invalid command line, expected: ./app zobrist [games N] [positions N] [seed S] [hash MB]";
 
 fn main() -> Result<(), String> {
     let now = Instant::now();
@@ -29,7 +36,25 @@ fn main() -> Result<(), String> {
     );
     let args: Vec<_> = std::env::args().skip(1).collect();
     match &args[..] {
-        [] => uci_parser::parse_loop(io::stdin().lock(), io::stdout()),
+        [] => uci_parser::parse_loop(BufReader::new(io::stdin()), io::stdout()),
+        [perft, tail @ ..] if perft == "perft" => {
+            //The FEN is a single argument, the rest is the same as for the UCI perft command
+            let mut state = GameState::standard();
+            let mut cmd: Vec<&str> = Vec::new();
+            let mut index = 0;
+            while index < tail.len() {
+                if tail[index] == "fen" {
+                    let fen = tail.get(index + 1).ok_or(PERFT_ERROR_MSG)?;
+                    state = GameState::try_from_fen(fen).map_err(|e| e.to_string())?;
+                    index += 2;
+                } else {
+                    cmd.push(tail[index].as_str());
+                    index += 1;
+                }
+            }
+            uci_parser::perft(&mut io::stdout(), &state, &cmd, PerftOptions::default())?;
+        }
+        [zobrist, tail @ ..] if zobrist == "zobrist" => zobrist_statistics(tail)?,
         [input, tail @ ..] => {
             let file = File::open(input)
                 .map_err(|e| format!("opening the input file: {}: {}", input, e))?;
@@ -48,3 +73,32 @@ fn main() -> Result<(), String> {
     };
     Ok(())
 }
+
+//Verifies the incremental hash, psqt and phase over random games, then reports TT key collisions
+fn zobrist_statistics(args: &[String]) -> Result<(), String> {
+    let (mut games, mut positions, mut seed, mut hash_size) = (1000, 1_000_000, 0, 256);
+    for pair in args.chunks(2) {
+        let value: usize = match pair {
+            [_, value] => value.parse().map_err(|_| ZOBRIST_ERROR_MSG)?,
+            _ => return Err(ZOBRIST_ERROR_MSG.to_owned()),
+        };
+        match pair[0].as_str() {
+            "games" => games = value,
+            "positions" => positions = value,
+            "seed" => seed = value,
+            "hash" => hash_size = value,
+            _ => return Err(ZOBRIST_ERROR_MSG.to_owned()),
+        }
+    }
+    let checked = fuzz_random_games(seed as u64, games, 300)?;
+    println!("Verified {} positions from {} random games", checked, games);
+    let stats = collision_statistics(seed as u64, positions, 300, hash_size);
+    println!("Distinct positions: {}", stats.positions);
+    println!("Full hash collisions: {}", stats.hash_collisions);
+    println!(
+        "Key collisions (upper 48 bits): {} (expected {:.6})",
+        stats.key_collisions, stats.expected_key_collisions
+    );
+    println!("False TT hits at {}MB: {}", hash_size, stats.false_hits);
+    Ok(())
+}
@@ -0,0 +1,514 @@
+use core_sdk::board_representation::game_state::GameMove;
+use core_sdk::search::cache::{MAX_HASH_SIZE, MIN_HASH_SIZE};
+#[cfg(feature = "tune")]
+use core_sdk::search::parameters::SEARCH_PARAMS;
+use core_sdk::search::searcher::{MAX_SKIP_RATIO, MAX_THREADS, MIN_SKIP_RATIO, MIN_THREADS};
+use core_sdk::search::smp::SMPStrategy;
+use core_sdk::search::timecontrol::{TimeControl, MAX_MOVE_OVERHEAD, MIN_MOVE_OVERHEAD};
+use core_sdk::search::MAX_SEARCH_DEPTH;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum UCICommand {
+    UCI,
+    Debug(bool),
+    IsReady,
+    SetOption(UCIOption),
+    Register,
+    UCINewGame,
+    //Without FEN the position starts from the standard position
+    Position {
+        fen: Option<String>,
+        moves: Vec<String>,
+    },
+    Go(GoParameters),
+    Stop,
+    PonderHit,
+    Quit,
+    //Engine specific commands
+    Display,
+    Perft(Vec<String>),
+    Static,
+}
+
+impl UCICommand {
+    //Unknown tokens are skipped as the UCI protocol demands, "joho debug on" switches debug on
+    pub fn parse(line: &str) -> Result<UCICommand, String> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let start = tokens
+            .iter()
+            .position(|token| UCICommand::is_command(token))
+            .ok_or_else(|| format!("Unknown command: {}", line.trim()))?;
+        let args = &tokens[start + 1..];
+        Ok(match tokens[start] {
+            "uci" => UCICommand::UCI,
+            "debug" => match args.iter().find(|&&arg| arg == "on" || arg == "off") {
+                Some(&arg) => UCICommand::Debug(arg == "on"),
+                None => return Err(format!("Invalid debug command: {}", line.trim())),
+            },
+            "isready" => UCICommand::IsReady,
+            "setoption" => UCICommand::SetOption(UCIOption::parse(args)?),
+            "register" => UCICommand::Register,
+            "ucinewgame" | "newgame" => UCICommand::UCINewGame,
+            "position" => UCICommand::parse_position(args)?,
+            "go" => UCICommand::Go(GoParameters::parse(args)),
+            "stop" => UCICommand::Stop,
+            "ponderhit" => UCICommand::PonderHit,
+            "quit" => UCICommand::Quit,
+            "d" => UCICommand::Display,
+            "perft" => UCICommand::Perft(args.iter().map(|arg| (*arg).to_owned()).collect()),
+            "static" => UCICommand::Static,
+            _ => return Err(format!("Unknown command: {}", line.trim())),
+        })
+    }
+
+    fn is_command(token: &str) -> bool {
+        [
+            "uci",
+            "debug",
+            "isready",
+            "setoption",
+            "register",
+            "ucinewgame",
+            "newgame",
+            "position",
+            "go",
+            "stop",
+            "ponderhit",
+            "quit",
+            "d",
+            "perft",
+            "static",
+        ]
+        .contains(&token)
+    }
+
+    //position [fen <fenstring> | startpos] moves <move1> .... <movei>
+    fn parse_position(args: &[&str]) -> Result<UCICommand, String> {
+        let invalid = || format!("Invalid position command: position {}", args.join(" "));
+        let start = args
+            .iter()
+            .position(|&arg| arg == "fen" || arg == "startpos")
+            .ok_or_else(invalid)?;
+        let moves_start = args
+            .iter()
+            .position(|arg| arg.eq_ignore_ascii_case("moves"))
+            .unwrap_or_else(|| args.len());
+        if moves_start < start {
+            return Err(invalid());
+        }
+        let fen = if args[start] == "fen" {
+            let fen = args[start + 1..moves_start].join(" ");
+            if fen.is_empty() {
+                return Err(invalid());
+            }
+            Some(fen)
+        } else {
+            None
+        };
+        let mut moves = Vec::new();
+        for mv in args.iter().skip(moves_start + 1) {
+            GameMove::string_to_move(mv).map_err(|e| e.to_string())?;
+            moves.push((*mv).to_owned());
+        }
+        Ok(UCICommand::Position { fen, moves })
+    }
+}
+
+//Nodes and searchmoves limit the search on top of the time control and the depth. A ponder
+//search ignores the clock until ponderhit
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct GoParameters {
+    pub searchmoves: Vec<String>,
+    pub ponder: bool,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<usize>,
+    pub depth: Option<usize>,
+    pub nodes: Option<u64>,
+    pub mate: Option<usize>,
+    pub movetime: Option<u64>,
+    pub infinite: bool,
+}
+
+impl GoParameters {
+    //A keyword without valid value is skipped, its value is then parsed as next keyword
+    pub fn parse(args: &[&str]) -> GoParameters {
+        let mut params = GoParameters::default();
+        let mut index = 0;
+        while index < args.len() {
+            let value = args
+                .get(index + 1)
+                .and_then(|value| value.parse::<u64>().ok());
+            let mut consumed_value = value.is_some();
+            match args[index].to_lowercase().as_str() {
+                "searchmoves" => {
+                    consumed_value = false;
+                    while let Some(mv) = args.get(index + 1) {
+                        if GameMove::string_to_move(mv).is_err() {
+                            break;
+                        }
+                        params.searchmoves.push((*mv).to_owned());
+                        index += 1;
+                    }
+                }
+                "ponder" => {
+                    params.ponder = true;
+                    consumed_value = false;
+                }
+                "infinite" => {
+                    params.infinite = true;
+                    consumed_value = false;
+                }
+                "wtime" => params.wtime = value.or(params.wtime),
+                "btime" => params.btime = value.or(params.btime),
+                "winc" => params.winc = value.or(params.winc),
+                "binc" => params.binc = value.or(params.binc),
+                "movestogo" => params.movestogo = value.map(|v| v as usize).or(params.movestogo),
+                "depth" => params.depth = value.map(|v| v as usize).or(params.depth),
+                "nodes" => params.nodes = value.or(params.nodes),
+                "mate" => params.mate = value.map(|v| v as usize).or(params.mate),
+                "movetime" => params.movetime = value.or(params.movetime),
+                _ => consumed_value = false,
+            }
+            index += if consumed_value { 2 } else { 1 };
+        }
+        params
+    }
+
+    //Without any time information the search is infinite. A mate in n needs at most 2n-1 plies
+    pub fn time_control(&self, color_to_move: usize) -> (TimeControl, usize) {
+        let (time, inc) = if color_to_move == 0 {
+            (self.wtime, self.winc.unwrap_or(0))
+        } else {
+            (self.btime, self.binc.unwrap_or(0))
+        };
+        let tc = if self.infinite {
+            TimeControl::Infinite
+        } else if let Some(movetime) = self.movetime {
+            TimeControl::MoveTime(movetime)
+        } else if let Some(time) = time {
+            match self.movestogo {
+                Some(mvs) if mvs > 0 => TimeControl::Tournament(time, inc, mvs),
+                _ => TimeControl::Incremental(time, inc),
+            }
+        } else {
+            TimeControl::Infinite
+        };
+        let depth = match (self.depth, self.mate) {
+            (Some(depth), _) => depth,
+            (None, Some(mate)) => mate.saturating_mul(2).saturating_sub(1),
+            (None, None) => MAX_SEARCH_DEPTH,
+        };
+        (tc, depth.clamp(1, MAX_SEARCH_DEPTH))
+    }
+}
+
+//A parsed setoption command, values are clamped into the ranges announced by uci
+#[derive(Clone, PartialEq, Debug)]
+pub enum UCIOption {
+    Hash(usize),
+    ClearHash,
+    Threads(usize),
+    MoveOverhead(u64),
+    DebugSMPPrint(bool),
+    SMPSkipRatio(usize),
+    SMPStrategy(SMPStrategy),
+    #[cfg(feature = "tune")]
+    SearchParam(String, i64),
+}
+
+impl UCIOption {
+    //Expects the tokens after setoption: name <id> [value <x>]
+    pub fn parse(cmd: &[&str]) -> Result<UCIOption, String> {
+        let name_start = cmd
+            .iter()
+            .position(|token| token.eq_ignore_ascii_case("name"))
+            .map(|index| index + 1)
+            .unwrap_or(0);
+        let value_start = cmd
+            .iter()
+            .position(|token| token.eq_ignore_ascii_case("value"))
+            .unwrap_or_else(|| cmd.len());
+        if name_start > value_start {
+            return Err(format!("Invalid setoption command: {}", cmd.join(" ")));
+        }
+        let name = cmd[name_start..value_start].join(" ");
+        let value = cmd.get(value_start + 1..).unwrap_or(&[]).join(" ");
+        fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("Invalid {} value: {}", name, value))
+        }
+        match name.to_lowercase().as_str() {
+            "hash" => Ok(UCIOption::Hash(
+                parse_value::<usize>(&name, &value)?.clamp(MIN_HASH_SIZE, MAX_HASH_SIZE),
+            )),
+            "clearhash" => Ok(UCIOption::ClearHash),
+            "threads" => Ok(UCIOption::Threads(
+                parse_value::<usize>(&name, &value)?.clamp(MIN_THREADS, MAX_THREADS),
+            )),
+            "moveoverhead" => Ok(UCIOption::MoveOverhead(
+                parse_value::<u64>(&name, &value)?.clamp(MIN_MOVE_OVERHEAD, MAX_MOVE_OVERHEAD),
+            )),
+            "debugsmpprint" => Ok(UCIOption::DebugSMPPrint(parse_value(&name, &value)?)),
+            "smpskipratio" => Ok(UCIOption::SMPSkipRatio(
+                parse_value::<usize>(&name, &value)?.clamp(MIN_SKIP_RATIO, MAX_SKIP_RATIO),
+            )),
+            "smpstrategy" => SMPStrategy::from_name(&value)
+                .map(UCIOption::SMPStrategy)
+                .ok_or_else(|| format!("Invalid {} value: {}", name, value)),
+            #[cfg(feature = "tune")]
+            _ if SEARCH_PARAMS
+                .iter()
+                .any(|range| range.name.eq_ignore_ascii_case(&name)) =>
+            {
+                Ok(UCIOption::SearchParam(
+                    name.clone(),
+                    parse_value(&name, &value)?,
+                ))
+            }
+            _ => Err(format!("Unknown option: {}", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GoParameters, UCICommand, UCIOption};
+    use core_sdk::search::smp::SMPStrategy;
+    use core_sdk::search::timecontrol::TimeControl;
+    use core_sdk::search::MAX_SEARCH_DEPTH;
+
+    fn position(fen: Option<&str>, moves: &[&str]) -> UCICommand {
+        UCICommand::Position {
+            fen: fen.map(|fen| fen.to_owned()),
+            moves: moves.iter().map(|mv| (*mv).to_owned()).collect(),
+        }
+    }
+
+    #[test]
+    fn parse_commands() {
+        //None is a parse error
+        let cases = vec![
+            ("uci", Some(UCICommand::UCI)),
+            ("  isready \r\n", Some(UCICommand::IsReady)),
+            ("debug off", Some(UCICommand::Debug(false))),
+            ("joho debug on", Some(UCICommand::Debug(true))),
+            ("debug", None),
+            ("register later", Some(UCICommand::Register)),
+            ("ucinewgame", Some(UCICommand::UCINewGame)),
+            ("newgame", Some(UCICommand::UCINewGame)),
+            ("stop", Some(UCICommand::Stop)),
+            ("ponderhit", Some(UCICommand::PonderHit)),
+            ("quit", Some(UCICommand::Quit)),
+            ("d", Some(UCICommand::Display)),
+            ("static", Some(UCICommand::Static)),
+            (
+                "perft 5 threads 2",
+                Some(UCICommand::Perft(vec![
+                    "5".to_owned(),
+                    "threads".to_owned(),
+                    "2".to_owned(),
+                ])),
+            ),
+            ("position startpos", Some(position(None, &[]))),
+            (
+                "position startpos moves e2e4 e7e5 e1g1 a7a8q",
+                Some(position(None, &["e2e4", "e7e5", "e1g1", "a7a8q"])),
+            ),
+            (
+                "position fen 4k3/8/8/8/8/8/8/4K3 w - - 0 1 moves e1e2",
+                Some(position(Some("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), &["e1e2"])),
+            ),
+            (
+                "position fen 8/8/8 w - -",
+                Some(position(Some("8/8/8 w - -"), &[])),
+            ),
+            ("position", None),
+            ("position fen", None),
+            ("position fen moves e2e4", None),
+            ("position moves e2e4 startpos", None),
+            ("position startpos moves e2", None),
+            ("position startpos moves e2e4 Nf3", None),
+            (
+                "setoption name Hash value 64",
+                Some(UCICommand::SetOption(UCIOption::Hash(64))),
+            ),
+            (
+                "setoption name Threads value 0",
+                Some(UCICommand::SetOption(UCIOption::Threads(1))),
+            ),
+            (
+                "setoption name MoveOverhead value 1000000",
+                Some(UCICommand::SetOption(UCIOption::MoveOverhead(20000))),
+            ),
+            (
+                "setoption name SMPSkipRatio value 0",
+                Some(UCICommand::SetOption(UCIOption::SMPSkipRatio(1))),
+            ),
+            (
+                "setoption name DebugSMPPrint value true",
+                Some(UCICommand::SetOption(UCIOption::DebugSMPPrint(true))),
+            ),
+            (
+                "setoption name SMPStrategy value abdada",
+                Some(UCICommand::SetOption(UCIOption::SMPStrategy(
+                    SMPStrategy::ABDADA,
+                ))),
+            ),
+            (
+                "setoption name ClearHash",
+                Some(UCICommand::SetOption(UCIOption::ClearHash)),
+            ),
+            ("setoption name Hash", None),
+            ("setoption name Hash value x", None),
+            ("setoption value 3 name Hash", None),
+            ("setoption name Unknown value 1", None),
+            ("setoption name SMPStrategy value x", None),
+            (
+                "go infinite",
+                Some(UCICommand::Go(GoParameters {
+                    infinite: true,
+                    ..GoParameters::default()
+                })),
+            ),
+            ("", None),
+            ("hello world", None),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(UCICommand::parse(line).ok(), expected, "{}", line);
+        }
+    }
+
+    #[test]
+    fn parse_go() {
+        let cases = vec![
+            ("", GoParameters::default()),
+            (
+                "infinite searchmoves e2e4 d2d4 ponder",
+                GoParameters {
+                    searchmoves: vec!["e2e4".to_owned(), "d2d4".to_owned()],
+                    ponder: true,
+                    infinite: true,
+                    ..GoParameters::default()
+                },
+            ),
+            (
+                "ponder wtime 100 winc 10 movestogo 3 depth 5",
+                GoParameters {
+                    ponder: true,
+                    wtime: Some(100),
+                    winc: Some(10),
+                    movestogo: Some(3),
+                    depth: Some(5),
+                    ..GoParameters::default()
+                },
+            ),
+            (
+                "wtime 1000 btime 2000 winc 10 binc 20 movestogo 5 depth 7 nodes 100 mate 3 movetime 50",
+                GoParameters {
+                    wtime: Some(1000),
+                    btime: Some(2000),
+                    winc: Some(10),
+                    binc: Some(20),
+                    movestogo: Some(5),
+                    depth: Some(7),
+                    nodes: Some(100),
+                    mate: Some(3),
+                    movetime: Some(50),
+                    ..GoParameters::default()
+                },
+            ),
+            (
+                "searchmoves e2e4 nodes 5000",
+                GoParameters {
+                    searchmoves: vec!["e2e4".to_owned()],
+                    nodes: Some(5000),
+                    ..GoParameters::default()
+                },
+            ),
+            (
+                "wtime infinite",
+                GoParameters {
+                    infinite: true,
+                    ..GoParameters::default()
+                },
+            ),
+            (
+                "depth x mate 2 foo btime 10 bar",
+                GoParameters {
+                    mate: Some(2),
+                    btime: Some(10),
+                    ..GoParameters::default()
+                },
+            ),
+            (
+                "WTIME 10 Infinite",
+                GoParameters {
+                    wtime: Some(10),
+                    infinite: true,
+                    ..GoParameters::default()
+                },
+            ),
+        ];
+        for (line, expected) in cases {
+            let args: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(GoParameters::parse(&args), expected, "{}", line);
+        }
+    }
+
+    #[test]
+    fn go_time_control() {
+        let cases = vec![
+            ("", 0, TimeControl::Infinite, MAX_SEARCH_DEPTH),
+            ("wtime", 0, TimeControl::Infinite, MAX_SEARCH_DEPTH),
+            ("depth x", 0, TimeControl::Infinite, MAX_SEARCH_DEPTH),
+            ("depth 0", 0, TimeControl::Infinite, 1),
+            ("depth 100000", 0, TimeControl::Infinite, MAX_SEARCH_DEPTH),
+            ("mate 3", 0, TimeControl::Infinite, 5),
+            ("mate 3 depth 2", 0, TimeControl::Infinite, 2),
+            (
+                "wtime 0 btime 0 movestogo 0",
+                0,
+                TimeControl::Incremental(0, 0),
+                MAX_SEARCH_DEPTH,
+            ),
+            (
+                "ponder wtime 100 winc 10 movestogo 3 depth 5",
+                0,
+                TimeControl::Tournament(100, 10, 3),
+                5,
+            ),
+            (
+                "wtime 100 btime 200 winc 1 binc 2",
+                1,
+                TimeControl::Incremental(200, 2),
+                MAX_SEARCH_DEPTH,
+            ),
+            ("wtime 100", 1, TimeControl::Infinite, MAX_SEARCH_DEPTH),
+            (
+                "wtime 100 movetime 50",
+                0,
+                TimeControl::MoveTime(50),
+                MAX_SEARCH_DEPTH,
+            ),
+            (
+                "wtime 100 movetime 50 infinite",
+                0,
+                TimeControl::Infinite,
+                MAX_SEARCH_DEPTH,
+            ),
+        ];
+        for (line, color_to_move, tc, depth) in cases {
+            let args: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(
+                GoParameters::parse(&args).time_control(color_to_move),
+                (tc, depth),
+                "{}",
+                line
+            );
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use core_sdk::board_representation::game_state::GameState;
+use std::io::{self, Write};
 
 pub struct UCIEngine<'a> {
     pub name: &'a str,
@@ -17,9 +18,9 @@ impl<'a> UCIEngine<'a> {
         }
     }
 
-    pub fn id_command(&self) {
-        println!("id name {}", self.name);
-        println!("id author {}", self.author);
-        println!("id contributors {}", self.contributors.join(", "))
+    pub fn id_command<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        writeln!(output, "id name {}", self.name)?;
+        writeln!(output, "id author {}", self.author)?;
+        writeln!(output, "id contributors {}", self.contributors.join(", "))
     }
 }
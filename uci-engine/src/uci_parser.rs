@@ -1,358 +1,470 @@
+use super::uci_command::{UCICommand, UCIOption};
 use super::uci_engine::UCIEngine;
-use core_sdk::board_representation::game_state::{GameMove, GameMoveType, GameState, PieceType};
-use core_sdk::board_representation::game_state_attack_container::GameStateAttackContainer;
+use core_sdk::board_representation::game_state::{GameMove, GameState};
 use core_sdk::move_generation::makemove::make_move;
-use core_sdk::move_generation::movegen;
+use core_sdk::perft::{PerftOptions, PerftReference};
 use core_sdk::search::cache::{Cache, MAX_HASH_SIZE, MIN_HASH_SIZE};
+#[cfg(feature = "tune")]
+use core_sdk::search::parameters::SEARCH_PARAMS;
+#[cfg(target_arch = "wasm32")]
+use core_sdk::search::searcher::search_move;
+#[cfg(not(target_arch = "wasm32"))]
+use core_sdk::search::searcher::search_move_in_background;
 use core_sdk::search::searcher::{
-    search_move, InterThreadCommunicationSystem, MAX_SKIP_RATIO, MAX_THREADS, MIN_SKIP_RATIO,
+    InputCallback, InterThreadCommunicationSystem, MAX_SKIP_RATIO, MAX_THREADS, MIN_SKIP_RATIO,
     MIN_THREADS,
 };
-use core_sdk::search::timecontrol::{TimeControl, MAX_MOVE_OVERHEAD, MIN_MOVE_OVERHEAD};
+use core_sdk::search::smp::SMP_STRATEGIES;
+use core_sdk::search::timecontrol::{MAX_MOVE_OVERHEAD, MIN_MOVE_OVERHEAD};
 use core_sdk::search::MAX_SEARCH_DEPTH;
+#[cfg(target_arch = "wasm32")]
+use fake_instant::FakeClock as Instant;
 use std::io::{BufRead, Write};
-use std::sync::{atomic::Ordering, Arc};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 use std::u64;
 
-pub fn parse_loop<I: BufRead, O: Write + 'static>(mut stdin: I, stdout: O) {
+pub fn parse_loop<I: BufRead + Send + 'static, O: Write + Send + 'static>(stdin: I, stdout: O) {
+    let stdin = Arc::new(Mutex::new(stdin));
     let mut history: Vec<GameState> = vec![];
 
     let mut us = UCIEngine::standard();
 
     let itcs = Arc::new(InterThreadCommunicationSystem::default_with_output(stdout));
-    *itcs.cache() =
-        Cache::with_size_threaded(itcs.uci_options().hash_size, itcs.uci_options().threads);
-    let mut movelist = movegen::MoveList::default();
-    let mut attack_container = GameStateAttackContainer::default();
+    itcs.set_cache(Cache::with_size_threaded(
+        itcs.uci_options().hash_size,
+        itcs.uci_options().threads,
+    ));
+    //On wasm the search polls the input itself
+    #[cfg(target_arch = "wasm32")]
+    itcs.set_input_callback(queued_lines(Arc::clone(&stdin)));
 
+    //On native targets the search runs in the background, so stop, isready and quit are read while it runs
+    let mut search: Option<thread::JoinHandle<Option<i16>>> = None;
     //let mut stdin = io::stdin();
     let mut line = String::new();
     loop {
         line.clear();
-        stdin.read_line(&mut line).unwrap();
-        println!("Read line: {}", line.trim());
-        let arg: Vec<&str> = line.split_whitespace().collect();
-        if arg.is_empty() {
+        if let Some(deferred) = itcs.next_deferred_input() {
+            line.push_str(&deferred);
+        } else {
+            let read = stdin.lock().unwrap().read_line(&mut line);
+            match read {
+                //Lines which aren't UTF-8 are skipped
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => continue,
+                //End of input, a running search may still finish up. Nothing can end pondering anymore
+                Ok(0) | Err(_) => {
+                    itcs.ponderhit();
+                    wait_for_search(&mut search);
+                    break;
+                }
+                Ok(_) => {}
+            }
+        }
+        if line.trim().is_empty() {
             continue;
         }
-        let cmd = arg[0];
-        match cmd.trim() {
-            "" => continue,
-            "uci" => {
+        let command = match UCICommand::parse(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                report_error(&itcs, &e);
+                continue;
+            }
+        };
+        match command {
+            UCICommand::UCI => {
                 uci(&us, &itcs);
             }
-            "setoption" => setoption(&arg[1..], &itcs),
-
-            "ucinewgame" | "newgame" => {
+            //Neither debug mode nor registration exist
+            UCICommand::Debug(_) | UCICommand::Register => {}
+            UCICommand::SetOption(option) => {
+                stop_search(&itcs, &mut search);
+                if let Err(e) = setoption(option, &itcs) {
+                    report_error(&itcs, &e);
+                }
+            }
+            UCICommand::UCINewGame => {
+                stop_search(&itcs, &mut search);
                 newgame(&mut us);
                 itcs.cache().clear_threaded(itcs.uci_options().threads);
-                itcs.saved_time.store(0, Ordering::Relaxed);
             }
-            "isready" => isready(&itcs, true),
-            "position" => {
-                history = position(&mut us, &arg[1..], &mut movelist, &mut attack_container);
+            UCICommand::IsReady => isready(&itcs, search.is_none(), true),
+            UCICommand::Position { fen, moves } => {
+                stop_search(&itcs, &mut search);
+                match position(&mut us, fen.as_deref(), &moves) {
+                    Ok(new_history) => history = new_history,
+                    Err(e) => report_error(&itcs, &e),
+                }
             }
-            "go" => {
-                isready(&itcs, false);
-                let (tc, depth) = go(&us, &arg[1..]);
+            UCICommand::Go(params) => {
+                stop_search(&itcs, &mut search);
+                isready(&itcs, true, false);
+                let (tc, depth) = params.time_control(us.internal_state.color_to_move);
+                itcs.pondering.store(params.ponder, Ordering::SeqCst);
+                itcs.node_limit
+                    .store(params.nodes.unwrap_or(u64::MAX), Ordering::Relaxed);
+                let mut searchmoves = Vec::new();
+                for mv in &params.searchmoves {
+                    match GameMove::from_uci(&us.internal_state, mv) {
+                        Ok(mv) => searchmoves.push(mv),
+                        Err(e) => report_error(&itcs, &e),
+                    }
+                }
+                *itcs.searchmoves.lock().unwrap() = searchmoves;
                 let mut new_history = vec![];
                 for gs in &history {
                     new_history.push(gs.clone());
                 }
                 let new_state = us.internal_state.clone();
                 let itcs = Arc::clone(&itcs);
-                search_move(itcs, depth as i16, new_state, new_history, tc); // ???
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    search = Some(search_move_in_background(
+                        itcs,
+                        depth as i16,
+                        new_state,
+                        new_history,
+                        tc,
+                    ));
+                }
+                #[cfg(target_arch = "wasm32")]
+                search_move(itcs, depth as i16, new_state, new_history, tc);
             }
-            "stop" => {
-                *itcs.timeout_flag.write().unwrap() = true;
-                thread::sleep(Duration::from_millis(5));
+            //The ponder search switches over to the clock of the game
+            UCICommand::PonderHit => itcs.ponderhit(),
+            UCICommand::Stop => {
+                itcs.stop();
+                wait_for_search(&mut search);
             }
-            "quit" => {
+            UCICommand::Quit => {
+                itcs.stop();
+                wait_for_search(&mut search);
                 break;
             }
-            "d" => {
-                print_internal_state(&us);
+            UCICommand::Display => {
+                print_internal_state(&us, &itcs);
+            }
+            UCICommand::Perft(args) => {
+                let options = PerftOptions {
+                    threads: itcs.uci_options().threads,
+                    ..PerftOptions::default()
+                };
+                let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+                let mut output = itcs.output();
+                if let Err(e) = perft(&mut *output, &us.internal_state, &args, options) {
+                    writeln!(output, "{}", e).expect("engine output write failed");
+                }
+                output.flush().expect("engine output flush failed");
             }
-            "perft" => perft(&us.internal_state, &arg[1..]),
-            "static" => {
-                println!(
+            UCICommand::Static => {
+                let mut output = itcs.output();
+                writeln!(
+                    output,
                     "cp {}",
                     core_sdk::evaluation::eval_game_state_from_null(&us.internal_state).final_eval
-                );
-            }
-            _ => {
-                println!("Unknown command {}", line);
+                )
+                .expect("engine output write failed");
+                output.flush().expect("engine output flush failed");
             }
         }
     }
 }
 
-pub fn perft(game_state: &GameState, cmd: &[&str]) {
-    let depth = cmd[0].parse::<usize>().unwrap();
-    core_sdk::perft_div(&game_state, depth);
+//Returns the lines which are already available on the input. A read without data, which is
+//what a wasm host's stdin gives when its queue is empty, ends the polling
+pub fn queued_lines<I: BufRead + Send + 'static>(input: Arc<Mutex<I>>) -> InputCallback {
+    Box::new(move || {
+        let mut line = String::new();
+        match input.lock().unwrap().read_line(&mut line) {
+            Ok(n) if n > 0 => Some(line),
+            _ => None,
+        }
+    })
+}
+
+//Errors go to the engine output, which isn't necessarily stdout
+pub fn report_error<E: std::fmt::Display>(itcs: &InterThreadCommunicationSystem, e: &E) {
+    let mut output = itcs.output();
+    writeln!(output, "info string {}", e).expect("engine output write failed");
+    output.flush().expect("engine output flush failed");
+}
+
+pub fn wait_for_search(search: &mut Option<thread::JoinHandle<Option<i16>>>) {
+    if let Some(handle) = search.take() {
+        handle.join().expect("Search thread panicked!");
+    }
 }
 
-pub fn print_internal_state(engine: &UCIEngine) {
-    println!("{}", engine.internal_state);
+//A search still running when the GUI moves on, e.g. go infinite or ponder without a stop, is
+//stopped implicitly. Only waiting for it would block the one thread reading the input
+pub fn stop_search(
+    itcs: &InterThreadCommunicationSystem,
+    search: &mut Option<thread::JoinHandle<Option<i16>>>,
+) {
+    if search.is_some() {
+        itcs.stop();
+        wait_for_search(search);
+    }
 }
 
-pub fn go(engine: &UCIEngine, cmd: &[&str]) -> (TimeControl, usize) {
-    let mut wtime: u64 = 0;
-    let mut btime: u64 = 0;
-    let mut winc: u64 = 0;
-    let mut binc: u64 = 0;
-    let mut depth = MAX_SEARCH_DEPTH;
-    if cmd[0].to_lowercase() == "infinite" {
-        return (TimeControl::Infinite, depth);
-    } else if cmd[0].to_lowercase() == "depth" {
-        depth = cmd[1].parse::<usize>().unwrap();
-        return (TimeControl::Infinite, depth);
+pub const PERFT_USAGE: &str =
+    "Usage: perft DEPTH [threads N] [hash MB] [diff REFERENCE_EPD] [epd PLIES OUTPUT_EPD]";
+
+//Writes the divide, or compares it against reference counts, or writes reference counts
+pub fn perft<W: Write>(
+    output: &mut W,
+    game_state: &GameState,
+    cmd: &[&str],
+    mut options: PerftOptions,
+) -> Result<(), String> {
+    fn value<T: std::str::FromStr>(cmd: &[&str], index: usize) -> Result<T, String> {
+        cmd.get(index)
+            .and_then(|arg| arg.parse().ok())
+            .ok_or_else(|| PERFT_USAGE.to_owned())
+    }
+    let depth: usize = value(cmd, 0)?;
+    if depth >= MAX_SEARCH_DEPTH {
+        return Err(PERFT_USAGE.to_owned());
     }
-    let mut index = 0;
-    let mut movestogo: Option<usize> = None;
+    let mut reference_file: Option<String> = None;
+    let mut epd_output: Option<(usize, String)> = None;
+    let mut index = 1;
     while index < cmd.len() {
         match cmd[index] {
-            "wtime" => {
-                wtime = cmd[index + 1].parse::<u64>().unwrap_or(0);
-            }
-            "btime" => {
-                btime = cmd[index + 1].parse::<u64>().unwrap_or(0);
-            }
-            "winc" => {
-                winc = cmd[index + 1].parse::<u64>().unwrap_or(0);
-            }
-            "binc" => {
-                binc = cmd[index + 1].parse::<u64>().unwrap_or(0);
-            }
-            "movetime" => {
-                let mvtime = cmd[index + 1].parse::<u64>().unwrap_or(0);
-                return (TimeControl::MoveTime(mvtime), depth);
+            "threads" => options.threads = value(cmd, index + 1)?,
+            "hash" => options.hash_size = value(cmd, index + 1)?,
+            "diff" => reference_file = Some(value(cmd, index + 1)?),
+            "epd" => {
+                epd_output = Some((value(cmd, index + 1)?, value(cmd, index + 2)?));
+                index += 1;
             }
-            "movestogo" => movestogo = Some(cmd[index + 1].parse::<usize>().unwrap_or(1)),
-            _ => println!("Some parts of the go command weren't recognized well."),
-        };
+            _ => return Err(PERFT_USAGE.to_owned()),
+        }
         index += 2;
     }
-    if movestogo.is_none() {
-        if engine.internal_state.color_to_move == 0 {
-            (TimeControl::Incremental(wtime, winc), depth)
-        } else {
-            (TimeControl::Incremental(btime, binc), depth)
+
+    let now = Instant::now();
+    if let Some(reference_file) = reference_file {
+        let epd = std::fs::read_to_string(&reference_file)
+            .map_err(|e| format!("Can't read {}: {}", reference_file, e))?;
+        let reference = PerftReference::parse(&epd)?;
+        match core_sdk::perft::diff(game_state, depth, &reference, options)? {
+            None => writeln!(output, "No difference at depth {}", depth)
+                .expect("engine output write failed"),
+            Some(difference) => {
+                let path: Vec<String> = difference
+                    .path
+                    .iter()
+                    .map(|mv| format!("{:?}", mv))
+                    .collect();
+                writeln!(output, "First difference after: {}", path.join(" "))
+                    .expect("engine output write failed");
+                writeln!(output, "Position: {}", difference.fen)
+                    .expect("engine output write failed");
+                writeln!(
+                    output,
+                    "Depth {}: expected {}, found {}",
+                    difference.depth, difference.expected, difference.found
+                )
+                .expect("engine output write failed");
+                for (mv, nodes) in difference.divide.iter() {
+                    writeln!(output, "{:?}: {}", mv, nodes).expect("engine output write failed");
+                }
+            }
         }
-    } else if let Some(mvs) = movestogo {
-        if mvs == 0 {
-            panic!("movestogo = 0");
+    } else if let Some((plies, path)) = epd_output {
+        let epd = core_sdk::perft::reference_epd(game_state, depth, plies, options);
+        std::fs::write(&path, &epd).map_err(|e| format!("Can't write {}: {}", path, e))?;
+        writeln!(
+            output,
+            "Wrote {} positions to {}",
+            epd.lines().count(),
+            path
+        )
+        .expect("engine output write failed");
+    } else {
+        let divide = core_sdk::perft::divide(game_state, depth, options);
+        for (mv, nodes) in divide.iter() {
+            writeln!(output, "{:?}: {}", mv, nodes).expect("engine output write failed");
         }
-        if engine.internal_state.color_to_move == 0 {
-            (TimeControl::Tournament(wtime, winc, mvs), depth)
+        let count: u64 = if depth == 0 {
+            1
         } else {
-            (TimeControl::Tournament(btime, binc, mvs), depth)
-        }
-    } else {
-        panic!("Something went wrong in go!");
+            divide.iter().map(|(_, nodes)| nodes).sum()
+        };
+        let secs = Instant::now().duration_since(now).as_secs_f64();
+        writeln!(output, "{}", count).expect("engine output write failed");
+        writeln!(output, "Time {} ({} nps)", secs, count as f64 / secs)
+            .expect("engine output write failed");
     }
+    Ok(())
 }
 
+pub fn print_internal_state(engine: &UCIEngine, itcs: &InterThreadCommunicationSystem) {
+    let mut output = itcs.output();
+    writeln!(output, "{}", engine.internal_state).expect("engine output write failed");
+    output.flush().expect("engine output flush failed");
+}
+
+//On errors the engine keeps its previous position
 pub fn position(
     engine: &mut UCIEngine,
-    cmd: &[&str],
-    movelist: &mut movegen::MoveList,
-    attack_container: &mut GameStateAttackContainer,
-) -> Vec<GameState> {
-    let mut move_index = 1;
-    match cmd[0] {
-        "fen" => {
-            let mut fen_string = String::new();
-            while move_index < cmd.len() && cmd[move_index].to_lowercase() != "moves" {
-                fen_string.push_str(cmd[move_index]);
-                fen_string.push_str(" ");
-                move_index += 1;
-            }
-            engine.internal_state = GameState::from_fen(fen_string.trim_end());
-        }
-        "startpos" => {
-            engine.internal_state = GameState::standard();
-        }
-        _ => {
-            panic!("Illegal position cmd");
-        }
-    }
+    fen: Option<&str>,
+    moves: &[String],
+) -> Result<Vec<GameState>, String> {
+    let mut state = match fen {
+        Some(fen) => GameState::try_from_fen(fen).map_err(|e| e.to_string())?,
+        None => GameState::standard(),
+    };
     let mut history: Vec<GameState> = vec![];
-    history.push(engine.internal_state.clone());
-    if move_index < cmd.len() && cmd[move_index].to_lowercase() == "moves" {
-        move_index += 1;
-        while move_index < cmd.len() {
-            //Parse the move and make it
-            let mv = cmd[move_index];
-            let (from, to, promo) = GameMove::string_to_move(mv);
-            engine.internal_state = scout_and_make_draftmove(
-                from,
-                to,
-                promo,
-                &engine.internal_state,
-                movelist,
-                attack_container,
-            );
-            history.push(engine.internal_state.clone());
-            move_index += 1;
-        }
-    }
-    history.pop();
-    history
-}
-
-pub fn scout_and_make_draftmove(
-    from: usize,
-    to: usize,
-    promo_pieces: Option<PieceType>,
-    game_state: &GameState,
-    movelist: &mut movegen::MoveList,
-    attack_container: &mut GameStateAttackContainer,
-) -> GameState {
-    attack_container.write_state(game_state);
-    movegen::generate_moves(&game_state, false, movelist, attack_container);
-    for gmv in movelist.move_list.iter() {
-        let mv = gmv.0;
-        if mv.from as usize == from && mv.to as usize == to {
-            if let GameMoveType::Promotion(ps, _) = mv.move_type {
-                match promo_pieces {
-                    Some(piece) => {
-                        if piece != ps {
-                            continue;
-                        }
-                    }
-                    None => {
-                        continue;
-                    }
-                }
-            }
-            return make_move(&game_state, mv);
-        }
+    for mv in moves {
+        let mv = GameMove::from_uci(&state, mv).map_err(|e| e.to_string())?;
+        let next = make_move(&state, mv);
+        history.push(std::mem::replace(&mut state, next));
     }
-    panic!("Invalid move; not found in list!");
+    engine.internal_state = state;
+    Ok(history)
 }
 
-pub fn isready(itcs: &Arc<InterThreadCommunicationSystem>, print_rdy: bool) {
-    if itcs.tx.read().unwrap().len() == 0 {
+//Threads can not be updated while a search is running, the engine is ready anyway
+pub fn isready(itcs: &Arc<InterThreadCommunicationSystem>, update_threads: bool, print_rdy: bool) {
+    if update_threads && itcs.nodes_searched().len() != itcs.uci_options().threads {
         let threads = itcs.uci_options().threads;
         InterThreadCommunicationSystem::update_thread_count(itcs, threads);
     }
     if print_rdy {
-        println!("readyok");
+        let mut output = itcs.output();
+        writeln!(output, "readyok").expect("engine output write failed");
+        output.flush().expect("engine output flush failed");
     }
 }
 
 pub fn uci(engine: &UCIEngine, itcs: &InterThreadCommunicationSystem) {
-    engine.id_command();
-    println!(
+    let mut output = itcs.output();
+    write_uci(engine, itcs, &mut *output).expect("engine output write failed");
+    output.flush().expect("engine output flush failed");
+}
+
+fn write_uci<W: Write>(
+    engine: &UCIEngine,
+    itcs: &InterThreadCommunicationSystem,
+    output: &mut W,
+) -> std::io::Result<()> {
+    engine.id_command(output)?;
+    writeln!(
+        output,
         "option name Hash type spin default {} min {} max {}",
         itcs.uci_options().hash_size,
         MIN_HASH_SIZE,
         MAX_HASH_SIZE
-    );
-    println!("option name ClearHash type button");
-    println!(
+    )?;
+    writeln!(output, "option name ClearHash type button")?;
+    writeln!(
+        output,
         "option name Threads type spin default {} min {} max {}",
         itcs.uci_options().threads,
         MIN_THREADS,
         MAX_THREADS
-    );
-    println!(
+    )?;
+    writeln!(
+        output,
         "option name MoveOverhead type spin default {} min {} max {}",
         itcs.uci_options().move_overhead,
         MIN_MOVE_OVERHEAD,
         MAX_MOVE_OVERHEAD
-    );
-    println!(
+    )?;
+    writeln!(
+        output,
         "option name DebugSMPPrint type check default {}",
         itcs.uci_options().debug_print
-    );
-    println!(
+    )?;
+    writeln!(
+        output,
         "option name SMPSkipRatio type spin default {} min {} max {}",
         itcs.uci_options().skip_ratio,
         MIN_SKIP_RATIO,
         MAX_SKIP_RATIO
-    );
-    println!("uciok");
+    )?;
+    let mut strategies = String::new();
+    for strategy in SMP_STRATEGIES.iter() {
+        strategies.push_str(&format!(" var {}", strategy));
+    }
+    writeln!(
+        output,
+        "option name SMPStrategy type combo default {}{}",
+        itcs.uci_options().smp_strategy,
+        strategies
+    )?;
+    #[cfg(feature = "tune")]
+    {
+        let params = itcs.uci_options().search_params;
+        for range in SEARCH_PARAMS.iter() {
+            writeln!(
+                output,
+                "option name {} type spin default {} min {} max {}",
+                range.name,
+                params.get(range.name).unwrap(),
+                range.min,
+                range.max
+            )?;
+        }
+    }
+    writeln!(output, "uciok")
 }
 
-pub fn setoption(cmd: &[&str], itcs: &Arc<InterThreadCommunicationSystem>) {
-    let mut index = 0;
-    while index < cmd.len() {
-        let arg = cmd[index];
-        match arg.to_lowercase().as_str() {
-            "hash" => {
-                let num = cmd[index + 2]
-                    .parse::<usize>()
-                    .expect("Invalid Hash value!");
-                itcs.uci_options().hash_size = num;
-                let num_threads = itcs.uci_options().threads;
-                *itcs.cache() = Cache::with_size_threaded(num, num_threads);
-                writeln!(itcs.output(), "info String Succesfully set Hash to {}", num)
-                    .expect("engine output write failed");
-                return;
-            }
-            "clearhash" => {
-                itcs.cache().clear_threaded(itcs.uci_options().threads);
-                writeln!(itcs.output(), "info String Succesfully cleared hash!")
-                    .expect("engine output write failed");
-                return;
-            }
-            "threads" => {
-                eprintln!("Error: threads unsupported in WASI build!");
-                // let num = cmd[index + 2]
-                //     .parse::<usize>()
-                //     .expect("Invalid Threads value!");
-                // InterThreadCommunicationSystem::update_thread_count(&itcs, num);
-                // println!("info String Succesfully set Threads to {}", num);
-                return;
-            }
-            "moveoverhead" => {
-                let num = cmd[index + 2]
-                    .parse::<u64>()
-                    .expect("Invalid MoveOverhead value!");
-                itcs.uci_options().move_overhead = num;
-                writeln!(
-                    itcs.output(),
-                    "info String Succesfully set MoveOverhad to {}",
-                    num
-                )
-                .expect("engine output write failed");
-                return;
-            }
-            "debugsmpprint" => {
-                let val = cmd[index + 2]
-                    .parse::<bool>()
-                    .expect("Invalid DebugSMPPrint value!");
-                itcs.uci_options().debug_print = val;
-                writeln!(
-                    itcs.output(),
-                    "info String Succesfully set DebugSMPPrint to {}",
-                    val
-                )
-                .expect("engine output write failed");
-                return;
-            }
-            "smpskipratio" => {
-                let num = cmd[index + 2]
-                    .parse::<usize>()
-                    .expect("Invalid SMPSkipRatio value!");
-                itcs.uci_options().skip_ratio = num;
-                writeln!(
-                    itcs.output(),
-                    "info String Succesfully set SMPSkipRatio to {}",
-                    num
-                )
-                .expect("engine output write failed");
-                return;
-            }
-            _ => {
-                index += 1;
+pub fn setoption(
+    option: UCIOption,
+    itcs: &Arc<InterThreadCommunicationSystem>,
+) -> Result<(), String> {
+    let message = match option {
+        UCIOption::Hash(num) => {
+            itcs.uci_options_mut().hash_size = num;
+            let num_threads = itcs.uci_options().threads;
+            itcs.set_cache(Cache::with_size_threaded(num, num_threads));
+            format!("Succesfully set Hash to {}", num)
+        }
+        UCIOption::ClearHash => {
+            itcs.cache().clear_threaded(itcs.uci_options().threads);
+            "Succesfully cleared hash!".to_owned()
+        }
+        UCIOption::Threads(num) => {
+            if cfg!(target_arch = "wasm32") {
+                return Err("Error: threads unsupported in WASI build!".to_owned());
             }
+            InterThreadCommunicationSystem::update_thread_count(&itcs, num);
+            format!("Succesfully set Threads to {}", num)
         }
-    }
+        UCIOption::MoveOverhead(num) => {
+            itcs.uci_options_mut().move_overhead = num;
+            format!("Succesfully set MoveOverhad to {}", num)
+        }
+        UCIOption::DebugSMPPrint(val) => {
+            itcs.uci_options_mut().debug_print = val;
+            format!("Succesfully set DebugSMPPrint to {}", val)
+        }
+        UCIOption::SMPSkipRatio(num) => {
+            itcs.uci_options_mut().skip_ratio = num;
+            format!("Succesfully set SMPSkipRatio to {}", num)
+        }
+        UCIOption::SMPStrategy(strategy) => {
+            itcs.uci_options_mut().smp_strategy = strategy;
+            format!("Succesfully set SMPStrategy to {}", strategy)
+        }
+        #[cfg(feature = "tune")]
+        UCIOption::SearchParam(name, num) => {
+            let value = itcs
+                .uci_options_mut()
+                .search_params
+                .set(&name, num)
+                .ok_or_else(|| format!("Unknown option: {}", name))?;
+            format!("Succesfully set {} to {}", name, value)
+        }
+    };
+    writeln!(itcs.output(), "info String {}", message).expect("engine output write failed");
+    Ok(())
 }
 
 pub fn newgame(engine: &mut UCIEngine) {